@@ -7,20 +7,32 @@ use std::env;
 use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{BufReader, Read, Seek, Write};
+#[cfg(feature = "images")]
+use std::path::Path;
 
 use anyhow::{anyhow, bail, Context, Result};
 use ntfs::attribute_value::NtfsAttributeValue;
+#[cfg(feature = "images")]
+use ntfs::images::{NtfsEwfReader, NtfsSplitRawReader, NtfsVhdReader, NtfsVhdxReader};
 use ntfs::indexes::NtfsFileNameIndex;
 use ntfs::structured_values::{
-    NtfsAttributeList, NtfsFileName, NtfsFileNamespace, NtfsStandardInformation,
+    NtfsAttributeList, NtfsFileName, NtfsFileNamespace, NtfsReparsePoint, NtfsReparsePointData,
+    NtfsStandardInformation,
 };
-use ntfs::{Ntfs, NtfsAttribute, NtfsAttributeType, NtfsFile, NtfsReadSeek};
+#[cfg(feature = "images")]
+use ntfs::NtfsError;
+use ntfs::{Ntfs, NtfsAttribute, NtfsAttributeType, NtfsFile, NtfsPartitionTable, NtfsReadSeek};
 use time::format_description::FormatItem;
 use time::macros::format_description;
 use time::OffsetDateTime;
 
 use sector_reader::SectorReader;
 
+/// Any reader that can be passed to the `ntfs` crate, used to abstract over the supported image formats.
+trait ReadSeek: Read + Seek {}
+
+impl<T> ReadSeek for T where T: Read + Seek {}
+
 struct CommandInfo<'n, T>
 where
     T: Read + Seek,
@@ -36,15 +48,22 @@ fn main() -> Result<()> {
     if args.len() != 2 {
         eprintln!("Usage: ntfs-shell FILESYSTEM");
         eprintln!();
-        eprintln!("FILESYSTEM can be a path to any NTFS filesystem image.");
+        eprintln!("FILESYSTEM can be a path to any NTFS filesystem image or disk image.");
+        eprintln!("For a disk image, the first NTFS partition is opened.");
+        #[cfg(feature = "images")]
+        {
+            eprintln!(
+                "EnCase (.E01) and split raw (.001) images are opened along with all their segments."
+            );
+            eprintln!("E01 images are verified against their stored hashes before opening them.");
+            eprintln!("Differencing VHD and VHDX images are opened along with their parents.");
+        }
         eprintln!("Under Windows and when run with administrative privileges, FILESYSTEM can also");
         eprintln!("be the special path \\\\.\\C: to access the filesystem of the C: partition.");
         bail!("Aborted");
     }
 
-    let f = File::open(&args[1])?;
-    let sr = SectorReader::new(f, 4096)?;
-    let mut fs = BufReader::new(sr);
+    let mut fs = open_filesystem(&args[1])?;
     let mut ntfs = Ntfs::new(&mut fs)?;
     ntfs.read_upcase_table(&mut fs)?;
     let current_directory = vec![ntfs.root_directory(&mut fs)?];
@@ -349,10 +368,63 @@ where
             Ok(NtfsAttributeType::StandardInformation) => fileinfo_std(attribute)?,
             Ok(NtfsAttributeType::FileName) => fileinfo_filename(info, attribute)?,
             Ok(NtfsAttributeType::Data) => fileinfo_data(attribute)?,
+            Ok(NtfsAttributeType::ReparsePoint) => fileinfo_reparse_point(info, attribute)?,
             _ => continue,
         }
     }
 
+    fileinfo_security(info, &file)?;
+
+    Ok(())
+}
+
+fn fileinfo_security<T>(info: &mut CommandInfo<T>, file: &NtfsFile) -> Result<()>
+where
+    T: Read + Seek,
+{
+    let security_descriptor = match file.security_descriptor(&mut info.fs) {
+        Some(security_descriptor) => security_descriptor?,
+        None => return Ok(()),
+    };
+
+    println!();
+    println!("{:=^72}", " SECURITY DESCRIPTOR ");
+
+    println!("{:34}{}", "Control:", security_descriptor.control());
+    if let Some(owner) = security_descriptor.owner() {
+        println!("{:34}{}", "Owner:", owner?);
+    }
+    if let Some(group) = security_descriptor.group() {
+        println!("{:34}{}", "Group:", group?);
+    }
+
+    for (name, acl) in [
+        ("DACL", security_descriptor.dacl()),
+        ("SACL", security_descriptor.sacl()),
+    ] {
+        let acl = match acl {
+            Some(acl) => acl?,
+            None => continue,
+        };
+
+        for ace in acl.aces() {
+            let ace = ace?;
+            let sid = match ace.sid() {
+                Some(sid) => sid?.to_string(),
+                None => "<NONE>".to_string(),
+            };
+
+            println!(
+                "{:34}{} {} {:#010x} {}",
+                format!("{name} ACE:"),
+                ace.ace_type()?,
+                sid,
+                ace.access_mask().bits(),
+                ace.flags()
+            );
+        }
+    }
+
     Ok(())
 }
 
@@ -454,6 +526,64 @@ fn fileinfo_data(attribute: NtfsAttribute) -> Result<()> {
     Ok(())
 }
 
+fn fileinfo_reparse_point<T>(info: &mut CommandInfo<T>, attribute: NtfsAttribute) -> Result<()>
+where
+    T: Read + Seek,
+{
+    println!();
+    println!("{:=^72}", " REPARSE POINT ");
+
+    let reparse_point = attribute.structured_value::<_, NtfsReparsePoint>(&mut info.fs)?;
+
+    println!("{:34}{:#010x}", "Tag:", reparse_point.tag());
+    if let Some(guid) = reparse_point.guid() {
+        println!("{:34}{}", "GUID:", guid);
+    }
+
+    match reparse_point.data()? {
+        NtfsReparsePointData::SymbolicLink(link) => {
+            println!("{:34}Symbolic Link", "Type:");
+            println!(
+                "{:34}\"{}\"",
+                "Substitute Name:",
+                link.substitute_name().to_string_lossy()
+            );
+            println!(
+                "{:34}\"{}\"",
+                "Print Name:",
+                link.print_name().to_string_lossy()
+            );
+            println!("{:34}{}", "Is Relative:", link.is_relative());
+        }
+        NtfsReparsePointData::MountPoint(mount_point) => {
+            println!("{:34}Mount Point", "Type:");
+            println!(
+                "{:34}\"{}\"",
+                "Substitute Name:",
+                mount_point.substitute_name().to_string_lossy()
+            );
+            println!(
+                "{:34}\"{}\"",
+                "Print Name:",
+                mount_point.print_name().to_string_lossy()
+            );
+        }
+        NtfsReparsePointData::Wof(wof) => {
+            println!("{:34}Windows Overlay Filter", "Type:");
+            println!("{:34}{}", "Provider:", wof.provider());
+        }
+        NtfsReparsePointData::AppExecLink(link) => {
+            println!("{:34}AppExecLink", "Type:");
+            if let Some(target_path) = link.target_path() {
+                println!("{:34}\"{}\"", "Target Path:", target_path.to_string_lossy());
+            }
+        }
+        data => println!("{:34}{:?}", "Data:", data),
+    }
+
+    Ok(())
+}
+
 fn fsinfo<T>(info: &mut CommandInfo<T>) -> Result<()>
 where
     T: Read + Seek,
@@ -509,6 +639,21 @@ where
 
     // Open the desired file and find the $DATA attribute we are looking for.
     let file = parse_file_arg(file_name, info)?;
+
+    // Files compressed by the Windows Overlay Filter keep their data in a separate stream.
+    if data_stream_name.is_empty() {
+        if let Some(wof_data) = file.wof_compressed_data(&mut info.fs) {
+            let wof_data = wof_data?;
+            println!(
+                "Saving {} bytes of {}-compressed data in \"{}\"...",
+                wof_data.len(),
+                wof_data.algorithm(),
+                output_file_name
+            );
+            return write_data(wof_data, &mut info.fs, &mut output_file);
+        }
+    }
+
     let data_item = match file.data(&mut info.fs, data_stream_name) {
         Some(data_item) => data_item,
         None => {
@@ -518,25 +663,14 @@ where
     };
     let data_item = data_item?;
     let data_attribute = data_item.to_attribute()?;
-    let mut data_value = data_attribute.value(&mut info.fs)?;
+    let data_value = data_attribute.value(&mut info.fs)?;
 
     println!(
         "Saving {} bytes of data in \"{}\"...",
         data_value.len(),
         output_file_name
     );
-    let mut buf = [0u8; 4096];
-
-    loop {
-        let bytes_read = data_value.read(&mut info.fs, &mut buf)?;
-        if bytes_read == 0 {
-            break;
-        }
-
-        output_file.write_all(&buf[..bytes_read])?;
-    }
-
-    Ok(())
+    write_data(data_value, &mut info.fs, &mut output_file)
 }
 
 fn help(arg: &str) -> Result<()> {
@@ -623,6 +757,70 @@ fn help_file(command: &str) {
     println!("      ○ {command} /0xa299");
 }
 
+fn open_filesystem(path: &str) -> Result<Box<dyn ReadSeek>> {
+    let mut fs: Box<dyn ReadSeek> = open_image(path)?;
+
+    // A disk image starts with a partition table instead of an NTFS boot sector.
+    if let Err(e) = Ntfs::new(&mut fs) {
+        let partition_table = match NtfsPartitionTable::new(&mut fs) {
+            Ok(partition_table) => partition_table,
+            Err(_) => return Err(e.into()),
+        };
+        let partition = partition_table
+            .ntfs_partitions()
+            .next()
+            .ok_or_else(|| anyhow!("Found a partition table, but no NTFS partition."))?
+            .clone();
+
+        println!(
+            "Using NTFS partition {} at byte offset {:#x}.",
+            partition.number(),
+            partition.offset()
+        );
+        fs = Box::new(partition.reader(fs));
+    }
+
+    Ok(fs)
+}
+
+fn open_image(path: &str) -> Result<Box<dyn ReadSeek>> {
+    #[cfg(feature = "images")]
+    {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match extension.as_str() {
+            "e01" => return Ok(Box::new(open_ewf_image(path)?)),
+            "001" => return Ok(Box::new(BufReader::new(NtfsSplitRawReader::open(path)?))),
+            "vhd" => return Ok(Box::new(BufReader::new(NtfsVhdReader::open(path)?))),
+            "vhdx" => return Ok(Box::new(BufReader::new(NtfsVhdxReader::open(path)?))),
+            _ => (),
+        }
+    }
+
+    let f = File::open(path)?;
+    let sr = SectorReader::new(f, 4096)?;
+    Ok(Box::new(BufReader::new(sr)))
+}
+
+#[cfg(feature = "images")]
+fn open_ewf_image(path: &str) -> Result<NtfsEwfReader<File>> {
+    let mut reader = NtfsEwfReader::open(path)?;
+
+    // A damaged image can still be explored, but the user needs to know that it is damaged.
+    println!("Verifying the E01 image against its stored hashes...");
+    match reader.verify() {
+        Ok(()) => println!("The E01 image matches its stored hashes."),
+        Err(NtfsError::MissingImageHash) => println!("The E01 image has no stored hashes."),
+        Err(e) => println!("WARNING: The E01 image failed verification: {e}"),
+    }
+
+    Ok(reader)
+}
+
 #[allow(clippy::from_str_radix_10)]
 fn parse_file_arg<'n, T>(arg: &str, info: &mut CommandInfo<'n, T>) -> Result<NtfsFile<'n>>
 where
@@ -664,3 +862,22 @@ where
         }
     }
 }
+
+fn write_data<D, T>(mut data: D, fs: &mut T, output_file: &mut File) -> Result<()>
+where
+    D: NtfsReadSeek,
+    T: Read + Seek,
+{
+    let mut buf = [0u8; 4096];
+
+    loop {
+        let bytes_read = data.read(fs, &mut buf)?;
+        if bytes_read == 0 {
+            break;
+        }
+
+        output_file.write_all(&buf[..bytes_read])?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,636 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Asynchronous reading of NTFS filesystems via [`AsyncRead`] and [`AsyncSeek`].
+//!
+//! All parsing code of this crate is synchronous and reads through [`Read`] and [`Seek`].
+//! [`NtfsAsyncReader`] runs this code against the blocks of the filesystem that have been fetched so far.
+//! Whenever it needs bytes of a block that has not been fetched yet, the reader fetches that block
+//! asynchronously and runs the code again.
+//! As the parsing code has no side effects, the result is the same as if it had read the filesystem directly,
+//! but no call ever blocks on I/O.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use futures_io::{AsyncRead, AsyncSeek};
+
+use crate::attribute_value::{seek_contiguous, NtfsAttributeValue};
+use crate::error::Result;
+use crate::file::NtfsFile;
+use crate::ntfs::Ntfs;
+use crate::traits::NtfsReadSeek;
+use crate::walker::NtfsDirectoryWalkerEntry;
+
+const DEFAULT_BLOCK_SIZE: u32 = 64 * 1024;
+const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Asynchronous access to an NTFS filesystem read via [`AsyncRead`] and [`AsyncSeek`].
+///
+/// The filesystem is fetched in blocks, which are kept in memory to serve further requests.
+/// The async functions of this reader cover the main entry points of this crate.
+/// Any other synchronous function can be called asynchronously via [`NtfsAsyncReader::run`].
+///
+/// The reader never evicts blocks while a function is running, so a single call may exceed the memory limit.
+/// All blocks are dropped before the next call if the limit has been exceeded.
+/// [`NtfsAsyncReader::read_value`] and [`NtfsAsyncReader::seek_value`] proceed in block-sized steps and
+/// also drop the blocks between two steps.
+///
+/// ```ignore
+/// let mut reader = NtfsAsyncReader::new(async_fs);
+/// let ntfs = reader.open_ntfs().await?;
+/// let file = reader.file_by_path(&ntfs, "Windows/System32/notepad.exe").await?;
+/// let item = reader.run(|fs| file.data(fs, "").unwrap()).await?;
+/// let attribute = item.to_attribute()?;
+/// let mut value = reader.run(|fs| attribute.value(fs)).await?;
+/// let bytes_read = reader.read_value(&mut value, &mut buf).await?;
+/// ```
+#[derive(Debug)]
+pub struct NtfsAsyncReader<R> {
+    inner: R,
+    blocks: HashMap<u64, Vec<u8>>,
+    block_size: u32,
+    memory_limit: usize,
+    size: Option<u64>,
+    #[cfg(test)]
+    runs: usize,
+}
+
+impl<R> NtfsAsyncReader<R>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    /// Creates a new [`NtfsAsyncReader`] for the NTFS filesystem read by `inner`.
+    ///
+    /// The filesystem is fetched in blocks of 64 KiB, keeping up to 64 MiB in memory.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            blocks: HashMap::new(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            size: None,
+            #[cfg(test)]
+            runs: 0,
+        }
+    }
+
+    /// Sets the size of the blocks in which the filesystem is fetched, in bytes.
+    ///
+    /// Larger blocks need fewer fetches, but also fetch more unneeded data.
+    /// For high-latency sources like object storage, a block size of several hundred KiB may be best.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn block_size(mut self, block_size: u32) -> Self {
+        assert!(block_size > 0);
+        self.block_size = block_size;
+        self.blocks.clear();
+        self
+    }
+
+    /// Returns the entries of the given directory, like an [`NtfsFile::walk`] with a
+    /// [`max_depth`][crate::NtfsDirectoryWalker::max_depth] of 1.
+    pub async fn directory_entries(
+        &mut self,
+        directory: &NtfsFile<'_>,
+    ) -> Result<Vec<NtfsDirectoryWalkerEntry>> {
+        self.run(|fs| directory.walk().max_depth(1).attach(fs).collect())
+            .await
+    }
+
+    /// Drops all blocks if they exceed the memory limit.
+    fn evict_blocks(&mut self) {
+        if self.blocks.len() * self.block_size as usize > self.memory_limit {
+            self.blocks.clear();
+        }
+    }
+
+    /// Fetches all blocks that were missing during the last run.
+    async fn fetch_blocks(&mut self, missing_blocks: Vec<u64>) -> io::Result<()> {
+        for block in missing_blocks {
+            let mut data = vec![0; self.block_size as usize];
+            let position = block * self.block_size as u64;
+            seek(&mut self.inner, SeekFrom::Start(position)).await?;
+
+            let mut filled = 0;
+            while filled < data.len() {
+                match read(&mut self.inner, &mut data[filled..]).await {
+                    Ok(0) => break,
+                    Ok(bytes_read) => filled += bytes_read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            data.truncate(filled);
+            self.blocks.insert(block, data);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the [`NtfsFile`] for the given NTFS File Record Number.
+    ///
+    /// See [`Ntfs::file`].
+    pub async fn file<'n>(
+        &mut self,
+        ntfs: &'n Ntfs,
+        file_record_number: u64,
+    ) -> Result<NtfsFile<'n>> {
+        self.run(|fs| ntfs.file(fs, file_record_number)).await
+    }
+
+    /// Returns the [`NtfsFile`] for the given absolute path.
+    ///
+    /// See [`Ntfs::file_by_path`].
+    pub async fn file_by_path<'n>(&mut self, ntfs: &'n Ntfs, path: &str) -> Result<NtfsFile<'n>> {
+        self.run(|fs| ntfs.file_by_path(fs, path)).await
+    }
+
+    /// Consumes this reader and returns the underlying asynchronous reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Sets the number of bytes to keep in memory between calls.
+    pub fn memory_limit(mut self, memory_limit: usize) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    /// Creates a new [`Ntfs`] object for the filesystem and reads its $UpCase table.
+    ///
+    /// See [`Ntfs::new`] and [`Ntfs::read_upcase_table`].
+    pub async fn open_ntfs(&mut self) -> Result<Ntfs> {
+        self.run(|fs| {
+            let mut ntfs = Ntfs::new(fs)?;
+            ntfs.read_upcase_table(fs)?;
+            Ok(ntfs)
+        })
+        .await
+    }
+
+    /// Reads from the given attribute value into `buf`, advancing its stream position.
+    ///
+    /// The value is read in steps up to the end of the current block, fetching that block beforehand.
+    /// This way, no step needs to be run again, and the memory limit is applied between two steps.
+    ///
+    /// See [`NtfsAttributeValue::read`][NtfsReadSeek::read].
+    pub async fn read_value(
+        &mut self,
+        value: &mut NtfsAttributeValue<'_, '_>,
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        let block_size = self.block_size as u64;
+        let mut bytes_read = 0;
+
+        while bytes_read < buf.len() {
+            self.evict_blocks();
+
+            // Compressed values and sparse Data Runs have no data position, and their steps may need to be run again.
+            let step_length = match value.data_position().value() {
+                Some(position) => {
+                    let block = position.get() / block_size;
+                    if !self.blocks.contains_key(&block) {
+                        self.fetch_blocks(vec![block]).await?;
+                    }
+
+                    block_size - position.get() % block_size
+                }
+                None => block_size,
+            };
+            let remaining = buf.len() - bytes_read;
+            let step_length = u64::min(step_length, remaining as u64) as usize;
+            let step_buf = &mut buf[bytes_read..bytes_read + step_length];
+
+            // Every run must start from the same state, so only the value of the successful run is kept.
+            let (new_value, step_bytes_read) = self
+                .run_fetching(|fs| {
+                    let mut new_value = value.clone();
+                    let step_bytes_read = new_value.read(fs, step_buf)?;
+                    Ok((new_value, step_bytes_read))
+                })
+                .await?;
+
+            *value = new_value;
+            if step_bytes_read == 0 {
+                break;
+            }
+
+            bytes_read += step_bytes_read;
+        }
+
+        Ok(bytes_read)
+    }
+
+    /// Returns the root directory of the filesystem as an [`NtfsFile`].
+    ///
+    /// See [`Ntfs::root_directory`].
+    pub async fn root_directory<'n>(&mut self, ntfs: &'n Ntfs) -> Result<NtfsFile<'n>> {
+        self.run(|fs| ntfs.root_directory(fs)).await
+    }
+
+    /// Calls the synchronous function `f` with a filesystem reader that serves all reads from the fetched blocks.
+    ///
+    /// If `f` needs a block that has not been fetched yet, the reader fails with [`io::ErrorKind::WouldBlock`].
+    /// The block is then fetched asynchronously and `f` is called again, until it completes without
+    /// needing further blocks.
+    /// Hence, `f` must not have side effects beyond its return value, and it must not rely on the position of
+    /// the reader when being called.
+    pub async fn run<F, T>(&mut self, f: F) -> Result<T>
+    where
+        F: FnMut(&mut NtfsFetchedBlocks<'_>) -> Result<T>,
+    {
+        self.evict_blocks();
+        self.run_fetching(f).await
+    }
+
+    /// Calls `f` like [`NtfsAsyncReader::run`], but without evicting any blocks beforehand.
+    async fn run_fetching<F, T>(&mut self, mut f: F) -> Result<T>
+    where
+        F: FnMut(&mut NtfsFetchedBlocks<'_>) -> Result<T>,
+    {
+        loop {
+            if let Some(result) = self.run_once(&mut f).await? {
+                return result;
+            }
+        }
+    }
+
+    /// Calls `f` once and returns its result, or fetches the blocks it was missing and returns `None`.
+    async fn run_once<F, T>(&mut self, f: &mut F) -> Result<Option<Result<T>>>
+    where
+        F: FnMut(&mut NtfsFetchedBlocks<'_>) -> Result<T>,
+    {
+        let size = match self.size {
+            Some(size) => size,
+            None => {
+                let size = seek(&mut self.inner, SeekFrom::End(0)).await?;
+                self.size = Some(size);
+                size
+            }
+        };
+
+        let mut fetched_blocks = NtfsFetchedBlocks {
+            blocks: &self.blocks,
+            block_size: self.block_size as u64,
+            size,
+            missing_blocks: Vec::new(),
+            stream_position: 0,
+        };
+
+        #[cfg(test)]
+        {
+            self.runs += 1;
+        }
+
+        // `f` may have ignored an error of a missing block, so its result is only valid if no block was missing.
+        let result = f(&mut fetched_blocks);
+        let missing_blocks = fetched_blocks.missing_blocks;
+        if missing_blocks.is_empty() {
+            return Ok(Some(result));
+        }
+
+        self.fetch_blocks(missing_blocks).await?;
+        Ok(None)
+    }
+
+    /// Seeks the given attribute value.
+    ///
+    /// Seeking only reads from the filesystem to load the File Records of connected attributes.
+    /// If any of them has not been fetched yet, the value is seeked in block-sized steps, so that every step
+    /// keeps the progress of the previous ones and the memory limit is applied between two steps.
+    ///
+    /// See [`NtfsAttributeValue::seek`][NtfsReadSeek::seek].
+    pub async fn seek_value(
+        &mut self,
+        value: &mut NtfsAttributeValue<'_, '_>,
+        pos: SeekFrom,
+    ) -> Result<u64> {
+        self.evict_blocks();
+
+        // Try to seek in a single run first, which always succeeds for values without connected attributes.
+        // An invalid `pos` also fails here.
+        let target = match self
+            .run_once(&mut |fs| seeked_value(value, fs, pos))
+            .await?
+        {
+            Some(result) => {
+                let (new_value, position) = result?;
+                *value = new_value;
+                return Ok(position);
+            }
+            None => seek_target(value, pos),
+        };
+        let target = match target {
+            Some(target) => target,
+            None => {
+                let (new_value, position) =
+                    self.run_fetching(|fs| seeked_value(value, fs, pos)).await?;
+                *value = new_value;
+                return Ok(position);
+            }
+        };
+
+        let mut position = value.stream_position();
+        if target < position {
+            // Seeking backwards has to rewind the value.
+            position = 0;
+        }
+
+        loop {
+            let step_position = u64::min(target, position.saturating_add(self.block_size as u64));
+            let (new_value, new_position) = self
+                .run_fetching(|fs| seeked_value(value, fs, SeekFrom::Start(step_position)))
+                .await?;
+
+            *value = new_value;
+            position = new_position;
+            if position >= target {
+                return Ok(position);
+            }
+
+            self.evict_blocks();
+        }
+    }
+}
+
+/// Synchronous filesystem reader over the blocks fetched by an [`NtfsAsyncReader`].
+///
+/// This is passed to the function given to [`NtfsAsyncReader::run`].
+/// Reading from a block that has not been fetched yet fails with [`io::ErrorKind::WouldBlock`], and the block
+/// is fetched before the function is called again.
+#[derive(Debug)]
+pub struct NtfsFetchedBlocks<'a> {
+    blocks: &'a HashMap<u64, Vec<u8>>,
+    block_size: u64,
+    size: u64,
+    missing_blocks: Vec<u64>,
+    stream_position: u64,
+}
+
+impl<'a> Read for NtfsFetchedBlocks<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stream_position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let block = self.stream_position / self.block_size;
+        let data = match self.blocks.get(&block) {
+            Some(data) => data,
+            None => {
+                if !self.missing_blocks.contains(&block) {
+                    self.missing_blocks.push(block);
+                }
+
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "block has not been fetched yet",
+                ));
+            }
+        };
+
+        let offset_in_block = (self.stream_position % self.block_size) as usize;
+        let remaining = data.len().saturating_sub(offset_in_block);
+        let bytes_to_read = usize::min(buf.len(), remaining);
+        buf[..bytes_to_read]
+            .copy_from_slice(&data[offset_in_block..offset_in_block + bytes_to_read]);
+
+        self.stream_position += bytes_to_read as u64;
+        Ok(bytes_to_read)
+    }
+}
+
+impl<'a> Seek for NtfsFetchedBlocks<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_contiguous(&mut self.stream_position, self.size, pos).map_err(io::Error::from)
+    }
+}
+
+/// Returns a copy of `value` seeked to `pos`, along with its new position.
+///
+/// Every run must start from the same state, so only the value of the successful run is kept.
+fn seeked_value<'n, 'f>(
+    value: &NtfsAttributeValue<'n, 'f>,
+    fs: &mut NtfsFetchedBlocks<'_>,
+    pos: SeekFrom,
+) -> Result<(NtfsAttributeValue<'n, 'f>, u64)> {
+    let mut new_value = value.clone();
+    let position = new_value.seek(fs, pos)?;
+    Ok((new_value, position))
+}
+
+/// Returns the absolute position within `value` that `pos` refers to, or `None` if it is out of range.
+fn seek_target(value: &NtfsAttributeValue<'_, '_>, pos: SeekFrom) -> Option<u64> {
+    let (base, n) = match pos {
+        SeekFrom::Start(n) => return Some(n),
+        SeekFrom::End(n) => (value.len(), n),
+        SeekFrom::Current(n) => (value.stream_position(), n),
+    };
+
+    if n >= 0 {
+        base.checked_add(n as u64)
+    } else {
+        base.checked_sub(n.unsigned_abs())
+    }
+}
+
+/// Future polling the given function until it returns [`Poll::Ready`].
+struct PollFn<F>(F);
+
+impl<F, T> Future for PollFn<F>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        (self.0)(cx)
+    }
+}
+
+async fn read<R>(inner: &mut R, buf: &mut [u8]) -> io::Result<usize>
+where
+    R: AsyncRead + Unpin,
+{
+    PollFn(|cx: &mut Context<'_>| Pin::new(&mut *inner).poll_read(cx, buf)).await
+}
+
+async fn seek<R>(inner: &mut R, pos: SeekFrom) -> io::Result<u64>
+where
+    R: AsyncSeek + Unpin,
+{
+    PollFn(|cx: &mut Context<'_>| Pin::new(&mut *inner).poll_seek(cx, pos)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asynchronous reader over a byte vector, which returns [`Poll::Pending`] before every operation
+    /// and counts its reads.
+    struct PendingReader {
+        data: Vec<u8>,
+        position: u64,
+        pending: bool,
+        reads: usize,
+    }
+
+    impl PendingReader {
+        fn new(data: Vec<u8>) -> Self {
+            Self {
+                data,
+                position: 0,
+                pending: false,
+                reads: 0,
+            }
+        }
+
+        fn poll_pending(&mut self, cx: &mut Context<'_>) -> bool {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+            }
+
+            self.pending
+        }
+    }
+
+    impl AsyncRead for PendingReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            if self.poll_pending(cx) {
+                return Poll::Pending;
+            }
+
+            let start = usize::min(self.position as usize, self.data.len());
+            let bytes_to_read = usize::min(buf.len(), self.data.len() - start);
+            buf[..bytes_to_read].copy_from_slice(&self.data[start..start + bytes_to_read]);
+
+            self.position += bytes_to_read as u64;
+            self.reads += 1;
+            Poll::Ready(Ok(bytes_to_read))
+        }
+    }
+
+    impl AsyncSeek for PendingReader {
+        fn poll_seek(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            pos: SeekFrom,
+        ) -> Poll<io::Result<u64>> {
+            if self.poll_pending(cx) {
+                return Poll::Pending;
+            }
+
+            let length = self.data.len() as u64;
+            let result = seek_contiguous(&mut self.position, length, pos).map_err(io::Error::from);
+            Poll::Ready(result)
+        }
+    }
+
+    #[test]
+    fn test_async_reader() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let mut reader = NtfsAsyncReader::new(PendingReader::new(testfs1.clone())).block_size(4096);
+
+        pollster::block_on(async {
+            let ntfs = reader.open_ntfs().await.unwrap();
+            assert_eq!(ntfs.cluster_size(), 512);
+
+            // Read a file.
+            let file = reader.file_by_path(&ntfs, "1000-bytes-file").await.unwrap();
+            assert_eq!(file.file_record_number(), 66);
+
+            let item = reader.run(|fs| file.data(fs, "").unwrap()).await.unwrap();
+            let attribute = item.to_attribute().unwrap();
+            let mut value = reader.run(|fs| attribute.value(fs)).await.unwrap();
+            let mut buf = [0u8; 1000];
+            assert_eq!(reader.read_value(&mut value, &mut buf).await.unwrap(), 1000);
+            assert_eq!(&buf[..], &b"12345".repeat(200)[..]);
+
+            reader
+                .seek_value(&mut value, SeekFrom::Start(995))
+                .await
+                .unwrap();
+            assert_eq!(reader.read_value(&mut value, &mut buf).await.unwrap(), 5);
+            assert_eq!(&buf[..5], b"12345");
+
+            // List a directory.
+            let directory = reader.file_by_path(&ntfs, "many_subdirs").await.unwrap();
+            let entries = reader.directory_entries(&directory).await.unwrap();
+            assert_eq!(entries.len(), 512);
+            assert_eq!(entries[0].path(), "1");
+
+            // Everything has been fetched already, so the same calls don't read again.
+            let reads = reader.inner.reads;
+            let root_dir = reader.root_directory(&ntfs).await.unwrap();
+            assert!(root_dir.is_directory());
+            let paths = |entries: &[NtfsDirectoryWalkerEntry]| {
+                entries
+                    .iter()
+                    .map(|entry| entry.path().to_string())
+                    .collect::<Vec<_>>()
+            };
+            let cached_entries = reader.directory_entries(&directory).await.unwrap();
+            assert_eq!(paths(&cached_entries), paths(&entries));
+            assert_eq!(reader.inner.reads, reads);
+        });
+
+        // Without any memory to spare, the blocks are dropped before every call and fetched again.
+        let mut reader = reader.memory_limit(0);
+        pollster::block_on(async {
+            let ntfs = reader.open_ntfs().await.unwrap();
+            let reads = reader.inner.reads;
+            reader.root_directory(&ntfs).await.unwrap();
+            assert!(reader.inner.reads > reads);
+        });
+    }
+
+    #[test]
+    fn test_async_reader_steps() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let mut reader = NtfsAsyncReader::new(PendingReader::new(testfs1))
+            .block_size(64)
+            .memory_limit(128);
+
+        pollster::block_on(async {
+            let ntfs = reader.open_ntfs().await.unwrap();
+            let file = reader.file_by_path(&ntfs, "1000-bytes-file").await.unwrap();
+            let item = reader.run(|fs| file.data(fs, "").unwrap()).await.unwrap();
+            let attribute = item.to_attribute().unwrap();
+            let mut value = reader.run(|fs| attribute.value(fs)).await.unwrap();
+
+            // The 1000 bytes span 16 blocks, each of them is fetched before the step reading it.
+            // Hence, every step succeeds in its first run, and at most 3 blocks are kept in memory.
+            let runs = reader.runs;
+            let mut buf = [0u8; 1000];
+            assert_eq!(reader.read_value(&mut value, &mut buf).await.unwrap(), 1000);
+            assert_eq!(&buf[..], &b"12345".repeat(200)[..]);
+            assert_eq!(reader.runs - runs, 16);
+            assert!(reader.blocks.len() <= 3);
+
+            // Seeking a value without connected attributes never reads from the filesystem and needs a single run.
+            let runs = reader.runs;
+            assert_eq!(
+                reader
+                    .seek_value(&mut value, SeekFrom::Start(10))
+                    .await
+                    .unwrap(),
+                10
+            );
+            assert_eq!(reader.runs - runs, 1);
+            assert_eq!(reader.read_value(&mut value, &mut buf).await.unwrap(), 990);
+            assert_eq!(&buf[..990], &b"12345".repeat(200)[10..]);
+        });
+    }
+}
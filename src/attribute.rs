@@ -28,6 +28,9 @@ use crate::types::{NtfsPosition, Vcn};
 /// Size of all [`NtfsAttributeHeader`] fields.
 const ATTRIBUTE_HEADER_SIZE: usize = 16;
 
+/// Largest supported compression unit size of a compressed attribute value, in bytes.
+const MAX_COMPRESSION_UNIT_SIZE: u64 = 65536;
+
 /// On-disk structure of the generic header of an NTFS Attribute.
 #[repr(C, packed)]
 struct NtfsAttributeHeader {
@@ -288,9 +291,44 @@ impl<'n, 'f> NtfsAttribute<'n, 'f> {
             data,
             position,
             self.non_resident_value_data_size(),
+            self.non_resident_value_initialized_size(),
+            self.non_resident_value_compression_unit_size()?,
+            self.ty().ok(),
+            self.flags(),
         )
     }
 
+    /// Returns the size of a compression unit in bytes if this attribute value is compressed,
+    /// or `None` otherwise.
+    pub(crate) fn non_resident_value_compression_unit_size(&self) -> Result<Option<u32>> {
+        debug_assert!(!self.is_resident());
+
+        if !self.flags().contains(NtfsAttributeFlags::COMPRESSED) {
+            return Ok(None);
+        }
+
+        let start =
+            self.offset + offset_of!(NtfsNonResidentAttributeHeader, compression_unit_exponent);
+        let compression_unit_exponent = self.file.record_data()[start];
+        if compression_unit_exponent == 0 {
+            return Ok(None);
+        }
+
+        // Windows only compresses with 16 clusters per compression unit and cluster sizes up to 4 KiB.
+        // Refuse anything larger than that instead of allocating huge buffers for corrupted values.
+        let cluster_size = self.file.ntfs().cluster_size() as u64;
+        if compression_unit_exponent >= u64::BITS as u8
+            || MAX_COMPRESSION_UNIT_SIZE >> compression_unit_exponent < cluster_size
+        {
+            return Err(NtfsError::UnsupportedCompressionUnitExponent {
+                position: self.position(),
+                exponent: compression_unit_exponent,
+            });
+        }
+
+        Ok(Some((cluster_size << compression_unit_exponent) as u32))
+    }
+
     pub(crate) fn non_resident_value_data_and_position(&self) -> Result<(&'f [u8], NtfsPosition)> {
         debug_assert!(!self.is_resident());
         let start = self.offset + self.non_resident_value_data_runs_offset() as usize;
@@ -318,6 +356,12 @@ impl<'n, 'f> NtfsAttribute<'n, 'f> {
         LittleEndian::read_u16(&self.file.record_data()[start..])
     }
 
+    fn non_resident_value_initialized_size(&self) -> u64 {
+        debug_assert!(!self.is_resident());
+        let start = self.offset + offset_of!(NtfsNonResidentAttributeHeader, initialized_size);
+        LittleEndian::read_u64(&self.file.record_data()[start..])
+    }
+
     pub(crate) fn offset(&self) -> usize {
         self.offset
     }
@@ -356,9 +400,7 @@ impl<'n, 'f> NtfsAttribute<'n, 'f> {
         debug_assert!(self.is_resident());
         self.validate_resident_value_sizes()?;
 
-        let start = self.offset + self.resident_value_offset() as usize;
-        let end = start + self.resident_value_length() as usize;
-        let data = &self.file.record_data()[start..end];
+        let data = &self.file.record_data()[self.resident_value_range()];
 
         Ok(NtfsResidentAttributeValue::new(data, self.position()))
     }
@@ -369,6 +411,16 @@ impl<'n, 'f> NtfsAttribute<'n, 'f> {
         LittleEndian::read_u32(&self.file.record_data()[start..])
     }
 
+    /// Returns the range of the resident value data within the File Record.
+    ///
+    /// The caller must have validated the resident value sizes before.
+    pub(crate) fn resident_value_range(&self) -> Range<usize> {
+        debug_assert!(self.is_resident());
+        let start = self.offset + self.resident_value_offset() as usize;
+        let end = start + self.resident_value_length() as usize;
+        start..end
+    }
+
     fn resident_value_offset(&self) -> u16 {
         debug_assert!(self.is_resident());
         let start = self.offset + offset_of!(NtfsResidentAttributeHeader, value_offset);
@@ -512,6 +564,7 @@ impl<'n, 'f> NtfsAttribute<'n, 'f> {
                 self.instance(),
                 self.ty()?,
                 data_size,
+                self.non_resident_value_compression_unit_size()?,
             )?;
             Ok(NtfsAttributeValue::AttributeListNonResident(value))
         } else if self.is_resident() {
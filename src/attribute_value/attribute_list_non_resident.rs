@@ -8,7 +8,8 @@
 
 use binrw::io::{Read, Seek, SeekFrom};
 
-use super::{DataRunsState, NtfsDataRuns, StreamState};
+use super::compressed::CompressedStream;
+use super::{DataRunsState, NtfsDataRun, NtfsDataRuns, StreamState};
 use crate::attribute::{NtfsAttribute, NtfsAttributeType};
 use crate::error::{NtfsError, Result};
 use crate::file::NtfsFile;
@@ -36,6 +37,8 @@ pub struct NtfsAttributeListNonResidentAttributeValue<'n, 'f> {
     attribute_state: Option<AttributeState<'n>>,
     /// Iteration state of the current Data Run.
     stream_state: StreamState,
+    /// Decompression state if this value is compressed.
+    compressed_stream: Option<CompressedStream>,
 }
 
 impl<'n, 'f> NtfsAttributeListNonResidentAttributeValue<'n, 'f> {
@@ -46,6 +49,7 @@ impl<'n, 'f> NtfsAttributeListNonResidentAttributeValue<'n, 'f> {
         instance: u16,
         ty: NtfsAttributeType,
         data_size: u64,
+        compression_unit_size: Option<u32>,
     ) -> Result<Self>
     where
         T: Read + Seek,
@@ -54,6 +58,17 @@ impl<'n, 'f> NtfsAttributeListNonResidentAttributeValue<'n, 'f> {
             AttributeListConnectedEntries::new(attribute_list_entries.clone(), instance, ty);
         let stream_state = StreamState::new(data_size);
 
+        let compressed_stream = match compression_unit_size {
+            Some(compression_unit_size) => Some(Self::compressed_stream(
+                ntfs,
+                fs,
+                connected_entries.clone(),
+                compression_unit_size,
+                data_size,
+            )?),
+            None => None,
+        };
+
         let mut value = Self {
             ntfs,
             initial_attribute_list_entries: attribute_list_entries,
@@ -61,26 +76,80 @@ impl<'n, 'f> NtfsAttributeListNonResidentAttributeValue<'n, 'f> {
             data_size,
             attribute_state: None,
             stream_state,
+            compressed_stream,
         };
         value.next_attribute(fs)?;
 
         Ok(value)
     }
 
+    /// Collects the data runs of all connected attributes for reading a compressed value.
+    fn compressed_stream<T>(
+        ntfs: &'n Ntfs,
+        fs: &mut T,
+        connected_entries: AttributeListConnectedEntries<'n, 'f>,
+        compression_unit_size: u32,
+        data_size: u64,
+    ) -> Result<CompressedStream>
+    where
+        T: Read + Seek,
+    {
+        let mut compressed_stream = CompressedStream::new(compression_unit_size, data_size);
+        connected_entries.for_each_data_run(ntfs, fs, |data_run| {
+            compressed_stream.push_data_run(data_run)
+        })?;
+
+        Ok(compressed_stream)
+    }
+
+    /// Calls `f` for every Data Run of all connected attributes, in order of their VCNs.
+    pub(crate) fn for_each_data_run<T, F>(&self, fs: &mut T, f: F) -> Result<()>
+    where
+        T: Read + Seek,
+        F: FnMut(&NtfsDataRun),
+    {
+        let connected_entries = AttributeListConnectedEntries::new(
+            self.initial_attribute_list_entries.clone(),
+            self.connected_entries.instance,
+            self.connected_entries.ty,
+        );
+        connected_entries.for_each_data_run(self.ntfs, fs, f)
+    }
+
     /// Returns the absolute current data seek position within the filesystem, in bytes.
     /// This may be `None` if:
     ///   * The current seek position is outside the valid range, or
-    ///   * The current Data Run is a "sparse" Data Run.
+    ///   * The current Data Run is a "sparse" Data Run, or
+    ///   * The value is compressed (and therefore has no 1:1 mapping to filesystem positions).
     pub fn data_position(&self) -> NtfsPosition {
+        if self.compressed_stream.is_some() {
+            return NtfsPosition::none();
+        }
+
         self.stream_state.data_position()
     }
 
+    /// Consumes this value and returns its decompression state if it is compressed.
+    #[cfg(feature = "std")]
+    pub(crate) fn into_compressed_stream(self) -> Option<CompressedStream> {
+        self.compressed_stream
+    }
+
+    /// Returns `true` if the non-resident attribute value is compressed.
+    ///
+    /// Compressed values are transparently decompressed when reading.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed_stream.is_some()
+    }
+
     /// Returns `true` if the non-resident attribute value contains no data.
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
     /// Returns the total length of the non-resident attribute value data, in bytes.
+    ///
+    /// For a compressed value, this is the length of the uncompressed data.
     pub fn len(&self) -> u64 {
         self.data_size
     }
@@ -195,6 +264,10 @@ impl<'n, 'f> NtfsReadSeek for NtfsAttributeListNonResidentAttributeValue<'n, 'f>
     where
         T: Read + Seek,
     {
+        if let Some(compressed_stream) = &mut self.compressed_stream {
+            return compressed_stream.read(fs, buf);
+        }
+
         let mut bytes_read = 0usize;
 
         while bytes_read < buf.len() {
@@ -227,6 +300,10 @@ impl<'n, 'f> NtfsReadSeek for NtfsAttributeListNonResidentAttributeValue<'n, 'f>
     where
         T: Read + Seek,
     {
+        if let Some(compressed_stream) = &mut self.compressed_stream {
+            return compressed_stream.seek(pos);
+        }
+
         let pos = self.stream_state.optimize_seek(pos, self.len())?;
 
         let mut bytes_left_to_seek = match pos {
@@ -277,6 +354,10 @@ impl<'n, 'f> NtfsReadSeek for NtfsAttributeListNonResidentAttributeValue<'n, 'f>
     }
 
     fn stream_position(&self) -> u64 {
+        if let Some(compressed_stream) = &self.compressed_stream {
+            return compressed_stream.stream_position();
+        }
+
         self.stream_state.stream_position()
     }
 }
@@ -301,6 +382,31 @@ impl<'n, 'f> AttributeListConnectedEntries<'n, 'f> {
         }
     }
 
+    fn for_each_data_run<T, F>(mut self, ntfs: &'n Ntfs, fs: &mut T, mut f: F) -> Result<()>
+    where
+        T: Read + Seek,
+        F: FnMut(&NtfsDataRun),
+    {
+        while let Some(entry) = self.next(fs) {
+            let entry = entry?;
+            let file = entry.to_file(ntfs, fs)?;
+            let attribute = entry.to_attribute(&file)?;
+
+            if attribute.is_resident() {
+                return Err(NtfsError::UnexpectedResidentAttribute {
+                    position: attribute.position(),
+                });
+            }
+
+            let (data, position) = attribute.non_resident_value_data_and_position()?;
+            for data_run in NtfsDataRuns::new(ntfs, data, position) {
+                f(&data_run?);
+            }
+        }
+
+        Ok(())
+    }
+
     fn next<T>(&mut self, fs: &mut T) -> Option<Result<NtfsAttributeListEntry>>
     where
         T: Read + Seek,
@@ -0,0 +1,222 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! This module implements the stream logic for reading LZNT1-compressed non-resident attribute values.
+//!
+//! A compressed value is divided into compression units of (usually) 16 clusters.
+//! Each compression unit is stored in one of three ways, which can only be told apart by looking at the data runs:
+//!   * If all clusters of the unit are allocated, the unit is stored uncompressed.
+//!   * If no cluster of the unit is allocated, the unit is sparse and reads as zeros.
+//!   * Otherwise, the allocated clusters at the beginning of the unit contain LZNT1-compressed data,
+//!     and the remaining clusters are sparse.
+//!
+//! Seeking within a compressed value is therefore only possible in multiples of a compression unit.
+//! This reader keeps the current compression unit decompressed in memory and serves all reads from that buffer.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use binrw::io::{Read, Seek, SeekFrom};
+
+use super::{seek_contiguous, NtfsDataRun};
+use crate::compression::lznt1;
+use crate::error::Result;
+use crate::types::NtfsPosition;
+
+/// A contiguous range of the value that is described by a single Data Run.
+#[derive(Clone, Debug)]
+struct CompressedExtent {
+    /// Offset of this extent within the (uncompressed) value, in bytes.
+    offset: u64,
+    /// Absolute position of this extent within the filesystem, in bytes (`None` for a sparse extent).
+    position: NtfsPosition,
+    /// Size of this extent, in bytes.
+    size: u64,
+}
+
+/// Stream state for reading a compressed non-resident attribute value.
+#[derive(Clone, Debug)]
+pub(crate) struct CompressedStream {
+    /// All extents of the value, sorted by their offset.
+    extents: Vec<CompressedExtent>,
+    /// Size of a single compression unit, in bytes.
+    compression_unit_size: u64,
+    /// Total length of the uncompressed value data, in bytes.
+    data_size: u64,
+    /// Current seek position within the uncompressed value data, in bytes.
+    stream_position: u64,
+    /// Index of the compression unit currently held in `unit_data`.
+    unit_index: Option<u64>,
+    /// Uncompressed data of the current compression unit.
+    unit_data: Vec<u8>,
+}
+
+impl CompressedStream {
+    pub(crate) fn new(compression_unit_size: u32, data_size: u64) -> Self {
+        Self {
+            extents: Vec::new(),
+            compression_unit_size: compression_unit_size as u64,
+            data_size,
+            stream_position: 0,
+            unit_index: None,
+            unit_data: Vec::new(),
+        }
+    }
+
+    /// Appends the next Data Run of the value.
+    pub(crate) fn push_data_run(&mut self, data_run: &NtfsDataRun) {
+        let offset = match self.extents.last() {
+            Some(extent) => extent.offset + extent.size,
+            None => 0,
+        };
+
+        self.extents.push(CompressedExtent {
+            offset,
+            position: data_run.data_position(),
+            size: data_run.allocated_size(),
+        });
+    }
+
+    /// Reads the compression unit with the given index from the filesystem into `unit_data`.
+    fn load_unit<T>(&mut self, fs: &mut T, unit_index: u64) -> Result<()>
+    where
+        T: Read + Seek,
+    {
+        if self.unit_index == Some(unit_index) {
+            return Ok(());
+        }
+
+        let unit_size = self.compression_unit_size;
+        let unit_start = unit_index * unit_size;
+        let unit_end = unit_start + unit_size;
+
+        // Gather the allocated bytes of this compression unit.
+        let mut compressed_data = Vec::new();
+        let mut compressed_position = NtfsPosition::none();
+        let first_extent = self
+            .extents
+            .partition_point(|extent| extent.offset + extent.size <= unit_start);
+
+        for extent in &self.extents[first_extent..] {
+            if extent.offset >= unit_end {
+                break;
+            }
+
+            let position = match extent.position.value() {
+                Some(position) => position.get(),
+                None => continue,
+            };
+
+            let start = u64::max(extent.offset, unit_start);
+            let end = u64::min(extent.offset + extent.size, unit_end);
+            let position = position + (start - extent.offset);
+
+            if compressed_position.value().is_none() {
+                compressed_position = NtfsPosition::new(position);
+            }
+
+            let buf_start = compressed_data.len();
+            compressed_data.resize(buf_start + (end - start) as usize, 0);
+            fs.seek(SeekFrom::Start(position))?;
+            fs.read_exact(&mut compressed_data[buf_start..])?;
+        }
+
+        if compressed_data.len() as u64 == unit_size {
+            // All clusters are allocated, so this compression unit is stored uncompressed.
+            self.unit_data = compressed_data;
+        } else {
+            // Sparse compression units are all zeros, and so is any part of a compression unit
+            // that the compressed data does not cover.
+            self.unit_data = vec![0; unit_size as usize];
+            lznt1::decompress(&compressed_data, &mut self.unit_data, compressed_position)?;
+        }
+
+        self.unit_index = Some(unit_index);
+        Ok(())
+    }
+
+    pub(crate) fn read<T>(&mut self, fs: &mut T, buf: &mut [u8]) -> Result<usize>
+    where
+        T: Read + Seek,
+    {
+        let mut bytes_read = 0usize;
+
+        while bytes_read < buf.len() && self.stream_position < self.data_size {
+            let unit_index = self.stream_position / self.compression_unit_size;
+            self.load_unit(fs, unit_index)?;
+
+            // Copy up to the end of the compression unit, the end of the buffer, or the end of the value,
+            // whatever comes first.
+            let offset_in_unit = (self.stream_position % self.compression_unit_size) as usize;
+            let remaining_in_unit = self.unit_data.len() - offset_in_unit;
+            let remaining_in_buf = buf.len() - bytes_read;
+            let remaining_in_value = self.data_size - self.stream_position;
+            let bytes_to_copy = usize::min(remaining_in_unit, remaining_in_buf);
+            let bytes_to_copy = u64::min(bytes_to_copy as u64, remaining_in_value) as usize;
+
+            buf[bytes_read..bytes_read + bytes_to_copy]
+                .copy_from_slice(&self.unit_data[offset_in_unit..offset_in_unit + bytes_to_copy]);
+            bytes_read += bytes_to_copy;
+            self.stream_position += bytes_to_copy as u64;
+        }
+
+        Ok(bytes_read)
+    }
+
+    pub(crate) fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        seek_contiguous(&mut self.stream_position, self.data_size, pos)
+    }
+
+    pub(crate) fn stream_position(&self) -> u64 {
+        self.stream_position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binrw::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_compressed_stream() {
+        const UNIT_SIZE: u32 = 8192;
+
+        // Unit 0 is compressed into the first 512 bytes, unit 1 is stored uncompressed, and unit 2 is sparse.
+        // Position 0 is reserved for "no position", so the test data starts at 512.
+        let mut fs = vec![0u8; 1024 + UNIT_SIZE as usize];
+        let compressed = [0x05, 0xb0, 0x08, b'a', b'b', b'c', 0x06, 0x20];
+        fs[512..512 + compressed.len()].copy_from_slice(&compressed);
+        fs[1024..].fill(b'u');
+        let mut fs = Cursor::new(fs);
+
+        let data_size = 3 * UNIT_SIZE as u64 - 100;
+        let mut stream = CompressedStream::new(UNIT_SIZE, data_size);
+        stream.push_data_run(&NtfsDataRun::new(Some(512), 512));
+        stream.push_data_run(&NtfsDataRun::new(None, UNIT_SIZE as u64 - 512));
+        stream.push_data_run(&NtfsDataRun::new(Some(1024), UNIT_SIZE as u64));
+        stream.push_data_run(&NtfsDataRun::new(None, UNIT_SIZE as u64));
+
+        let mut buf = vec![0xccu8; 4 * UNIT_SIZE as usize];
+        let bytes_read = stream.read(&mut fs, &mut buf).unwrap();
+        assert_eq!(bytes_read as u64, data_size);
+        assert_eq!(stream.stream_position(), data_size);
+
+        let (unit0, rest) = buf.split_at(UNIT_SIZE as usize);
+        let (unit1, unit2) = rest.split_at(UNIT_SIZE as usize);
+        assert_eq!(&unit0[..12], b"abcabcabcabc");
+        assert!(unit0[12..].iter().all(|&b| b == 0));
+        assert!(unit1.iter().all(|&b| b == b'u'));
+        assert!(unit2[..UNIT_SIZE as usize - 100].iter().all(|&b| b == 0));
+
+        // Seek into the middle of the first unit and read across the unit boundary.
+        stream.seek(SeekFrom::Start(9)).unwrap();
+        let mut buf = [0u8; 4];
+        stream.read(&mut fs, &mut buf).unwrap();
+        assert_eq!(&buf, b"abc\0");
+
+        stream.seek(SeekFrom::Start(UNIT_SIZE as u64 - 2)).unwrap();
+        stream.read(&mut fs, &mut buf).unwrap();
+        assert_eq!(&buf, b"\0\0uu");
+    }
+}
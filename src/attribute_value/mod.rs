@@ -4,6 +4,7 @@
 //! Readers for attribute value types.
 
 mod attribute_list_non_resident;
+mod compressed;
 mod non_resident;
 mod resident;
 
@@ -11,6 +12,9 @@ pub use attribute_list_non_resident::*;
 pub use non_resident::*;
 pub use resident::*;
 
+#[cfg(feature = "std")]
+pub(crate) use compressed::CompressedStream;
+
 use binrw::io;
 use binrw::io::{Read, Seek, SeekFrom};
 
@@ -46,7 +50,8 @@ impl<'n, 'f> NtfsAttributeValue<'n, 'f> {
     /// This may be `None` if:
     ///   * The current seek position is outside the valid range, or
     ///   * The attribute does not have a Data Run, or
-    ///   * The current Data Run is a "sparse" Data Run.
+    ///   * The current Data Run is a "sparse" Data Run, or
+    ///   * The value is compressed.
     pub fn data_position(&self) -> NtfsPosition {
         match self {
             Self::Resident(inner) => inner.data_position(),
@@ -122,7 +127,8 @@ where
     /// This may be `None` if:
     ///   * The current seek position is outside the valid range, or
     ///   * The attribute does not have a Data Run, or
-    ///   * The current Data Run is a "sparse" Data Run.
+    ///   * The current Data Run is a "sparse" Data Run, or
+    ///   * The value is compressed.
     pub fn data_position(&self) -> NtfsPosition {
         self.value.data_position()
     }
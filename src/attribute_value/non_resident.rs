@@ -10,10 +10,12 @@ use core::mem;
 
 use binrw::io;
 use binrw::io::Cursor;
-use binrw::io::{Read, Seek, SeekFrom};
+use binrw::io::{Read, Seek, SeekFrom, Write};
 use binrw::BinRead;
 
+use super::compressed::CompressedStream;
 use super::seek_contiguous;
+use crate::attribute::{NtfsAttributeFlags, NtfsAttributeType};
 use crate::error::{NtfsError, Result};
 use crate::ntfs::Ntfs;
 use crate::traits::NtfsReadSeek;
@@ -32,24 +34,55 @@ pub struct NtfsNonResidentAttributeValue<'n, 'f> {
     stream_data_runs: NtfsDataRuns<'n, 'f>,
     /// Iteration state of the current Data Run.
     stream_state: StreamState,
+    /// Size of the initialized part of the value data, in bytes.
+    initialized_size: u64,
+    /// Decompression state if this value is compressed.
+    compressed_stream: Option<CompressedStream>,
+    /// Type of the attribute, or `None` if it is unknown.
+    ty: Option<NtfsAttributeType>,
+    /// Flags of the attribute.
+    flags: NtfsAttributeFlags,
 }
 
 impl<'n, 'f> NtfsNonResidentAttributeValue<'n, 'f> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         ntfs: &'n Ntfs,
         data: &'f [u8],
         position: NtfsPosition,
         data_size: u64,
+        initialized_size: u64,
+        compression_unit_size: Option<u32>,
+        ty: Option<NtfsAttributeType>,
+        flags: NtfsAttributeFlags,
     ) -> Result<Self> {
         let stream_data_runs = NtfsDataRuns::new(ntfs, data, position);
         let stream_state = StreamState::new(data_size);
 
+        // Compressed values need to know all data runs upfront to locate the compression units.
+        let compressed_stream = match compression_unit_size {
+            Some(compression_unit_size) => {
+                let mut compressed_stream = CompressedStream::new(compression_unit_size, data_size);
+
+                for data_run in stream_data_runs.clone() {
+                    compressed_stream.push_data_run(&data_run?);
+                }
+
+                Some(compressed_stream)
+            }
+            None => None,
+        };
+
         let mut value = Self {
             ntfs,
             data,
             position,
             stream_data_runs,
             stream_state,
+            initialized_size,
+            compressed_stream,
+            ty,
+            flags,
         };
         value.next_data_run()?;
 
@@ -72,8 +105,13 @@ impl<'n, 'f> NtfsNonResidentAttributeValue<'n, 'f> {
     /// This may be `None` if:
     ///   * The current seek position is outside the valid range, or
     ///   * The attribute does not have a Data Run, or
-    ///   * The current Data Run is a "sparse" Data Run
+    ///   * The current Data Run is a "sparse" Data Run, or
+    ///   * The value is compressed (and therefore has no 1:1 mapping to filesystem positions).
     pub fn data_position(&self) -> NtfsPosition {
+        if self.compressed_stream.is_some() {
+            return NtfsPosition::none();
+        }
+
         self.stream_state.data_position()
     }
 
@@ -82,12 +120,35 @@ impl<'n, 'f> NtfsNonResidentAttributeValue<'n, 'f> {
         NtfsDataRuns::new(self.ntfs, self.data, self.position)
     }
 
+    /// Returns the length of the initialized part of the non-resident attribute value data, in bytes.
+    ///
+    /// This is usually the same as [`NtfsNonResidentAttributeValue::len`].
+    /// Windows reads any data beyond the initialized length as zeros.
+    pub fn initialized_len(&self) -> u64 {
+        self.initialized_size
+    }
+
+    /// Consumes this value and returns its decompression state if it is compressed.
+    #[cfg(feature = "std")]
+    pub(crate) fn into_compressed_stream(self) -> Option<CompressedStream> {
+        self.compressed_stream
+    }
+
+    /// Returns `true` if the non-resident attribute value is compressed.
+    ///
+    /// Compressed values are transparently decompressed when reading.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed_stream.is_some()
+    }
+
     /// Returns `true` if the non-resident attribute value contains no data.
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
     /// Returns the total length of the non-resident attribute value data, in bytes.
+    ///
+    /// For a compressed value, this is the length of the uncompressed data.
     pub fn len(&self) -> u64 {
         self.stream_state.data_size()
     }
@@ -117,6 +178,114 @@ impl<'n, 'f> NtfsNonResidentAttributeValue<'n, 'f> {
 
         Ok(())
     }
+
+    /// Checks that every byte in the range `start..end` of the value data is stored in a non-sparse Data Run.
+    fn validate_write_range(&self, start: u64, end: u64) -> Result<()> {
+        let mut data_run_start = 0u64;
+
+        for data_run in self.data_runs() {
+            let data_run = data_run?;
+            let data_run_end = data_run_start + data_run.allocated_size();
+
+            if data_run_end > start && data_run.is_sparse() {
+                return Err(NtfsError::UnsupportedWriteToSparseDataRun {
+                    position: self.position,
+                    offset: u64::max(start, data_run_start),
+                });
+            }
+
+            if data_run_end >= end {
+                break;
+            }
+
+            data_run_start = data_run_end;
+        }
+
+        Ok(())
+    }
+
+    /// Overwrites the value data at the current seek position with the bytes of `buf`
+    /// and advances the seek position by the number of bytes written.
+    ///
+    /// This writes the existing clusters of the value in place and never allocates, frees, or resizes anything.
+    /// Writing stops at the end of the value data (see [`NtfsNonResidentAttributeValue::len`]),
+    /// so fewer bytes than requested may be written, and nothing is written at or beyond the end.
+    ///
+    /// Nothing is written and an error is returned if the value does not belong to a $DATA attribute, if it is
+    /// encrypted or compressed, or if any of the bytes to write lies in a sparse Data Run or beyond the
+    /// initialized length (see [`NtfsNonResidentAttributeValue::initialized_len`]).
+    ///
+    /// Use [`Ntfs::set_volume_dirty`] and [`NtfsFile::set_modification_time`] to additionally update
+    /// the volume flags and file times.
+    /// The USN change journal is not updated.
+    ///
+    /// [`Ntfs::set_volume_dirty`]: crate::Ntfs::set_volume_dirty
+    /// [`NtfsFile::set_modification_time`]: crate::NtfsFile::set_modification_time
+    pub fn write<T>(&mut self, fs: &mut T, buf: &[u8]) -> Result<usize>
+    where
+        T: Read + Seek + Write,
+    {
+        // Other attributes hold filesystem metadata, which cannot be overwritten without keeping it consistent.
+        if self.ty != Some(NtfsAttributeType::Data) {
+            return Err(NtfsError::UnsupportedWriteToNonDataAttribute {
+                position: self.position,
+            });
+        }
+
+        // Encrypted values would need to be encrypted with the file's key first.
+        if self.flags.contains(NtfsAttributeFlags::ENCRYPTED) {
+            return Err(NtfsError::UnsupportedWriteToEncryptedValue {
+                position: self.position,
+            });
+        }
+
+        if self.compressed_stream.is_some() {
+            return Err(NtfsError::UnsupportedWriteToCompressedValue {
+                position: self.position,
+            });
+        }
+
+        let start = self.stream_position();
+        let end = u64::min(start.saturating_add(buf.len() as u64), self.len());
+        if start >= end {
+            return Ok(0);
+        }
+
+        if end > self.initialized_size {
+            return Err(NtfsError::UnsupportedWriteToUninitializedData {
+                position: self.position,
+                offset: u64::max(start, self.initialized_size),
+                initialized_size: self.initialized_size,
+            });
+        }
+
+        self.validate_write_range(start, end)?;
+
+        let buf = &buf[..(end - start) as usize];
+        let mut bytes_written = 0usize;
+
+        while bytes_written < buf.len() {
+            // Write to the current Data Run if there is one.
+            if self
+                .stream_state
+                .write_data_run(fs, buf, &mut bytes_written, self.position)?
+            {
+                // We wrote something, so check the loop condition again if we need to write more.
+                continue;
+            }
+
+            // Move to the next Data Run.
+            if self.next_data_run()? {
+                // We got another Data Run, so write again.
+                continue;
+            } else {
+                // We wrote everything we could.
+                break;
+            }
+        }
+
+        Ok(bytes_written)
+    }
 }
 
 impl<'n, 'f> NtfsReadSeek for NtfsNonResidentAttributeValue<'n, 'f> {
@@ -124,6 +293,10 @@ impl<'n, 'f> NtfsReadSeek for NtfsNonResidentAttributeValue<'n, 'f> {
     where
         T: Read + Seek,
     {
+        if let Some(compressed_stream) = &mut self.compressed_stream {
+            return compressed_stream.read(fs, buf);
+        }
+
         let mut bytes_read = 0usize;
 
         while bytes_read < buf.len() {
@@ -150,6 +323,10 @@ impl<'n, 'f> NtfsReadSeek for NtfsNonResidentAttributeValue<'n, 'f> {
     where
         T: Read + Seek,
     {
+        if let Some(compressed_stream) = &mut self.compressed_stream {
+            return compressed_stream.seek(pos);
+        }
+
         let pos = self.stream_state.optimize_seek(pos, self.len())?;
 
         let mut bytes_left_to_seek = match pos {
@@ -194,6 +371,10 @@ impl<'n, 'f> NtfsReadSeek for NtfsNonResidentAttributeValue<'n, 'f> {
     }
 
     fn stream_position(&self) -> u64 {
+        if let Some(compressed_stream) = &self.compressed_stream {
+            return compressed_stream.stream_position();
+        }
+
         self.stream_state.stream_position()
     }
 }
@@ -218,7 +399,8 @@ where
     /// This may be `None` if:
     ///   * The current seek position is outside the valid range, or
     ///   * The attribute does not have a Data Run, or
-    ///   * The current Data Run is a "sparse" Data Run.
+    ///   * The current Data Run is a "sparse" Data Run, or
+    ///   * The value is compressed.
     pub fn data_position(&self) -> NtfsPosition {
         self.value.data_position()
     }
@@ -257,6 +439,20 @@ where
     }
 }
 
+/// Overwrites the value data in place via [`NtfsNonResidentAttributeValue::write`].
+impl<'n, 'f, 'a, T> Write for NtfsNonResidentAttributeValueAttached<'n, 'f, 'a, T>
+where
+    T: Read + Seek + Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.value.write(self.fs, buf).map_err(io::Error::from)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.fs.flush()
+    }
+}
+
 /// Iterator over
 ///   all data runs of a non-resident attribute,
 ///   returning an [`NtfsDataRun`] for each entry,
@@ -400,8 +596,9 @@ impl<'n, 'f> Iterator for NtfsDataRuns<'n, 'f> {
             self.read_variable_length_signed_integer(&mut cursor, vcn_byte_count)
         ));
 
-        // The VCN may either indicate "real" data or a sparse Data Run.
-        let position = if vcn.value() != 0 {
+        // A Data Run without a VCN is a sparse Data Run.
+        // Note that a VCN of zero is valid and refers to the same LCN as the previous Data Run (or LCN 0).
+        let position = if vcn_byte_count != 0 {
             // This Data Run contains "real" data.
             // Turn the read VCN into an absolute LCN.
             let new_lcn = iter_try!(self.state.previous_lcn.checked_add(vcn).ok_or(
@@ -412,10 +609,16 @@ impl<'n, 'f> Iterator for NtfsDataRuns<'n, 'f> {
                 }
             ));
             self.state.previous_lcn = new_lcn;
-            iter_try!(new_lcn.position(self.ntfs))
+            // The entire Data Run must be addressable, so that no position within it can overflow.
+            let position = iter_try!(new_lcn
+                .value()
+                .checked_mul(self.ntfs.cluster_size() as u64)
+                .filter(|position| position.checked_add(allocated_size).is_some())
+                .ok_or(NtfsError::LcnTooBig { lcn: new_lcn }));
+            Some(position)
         } else {
             // This is a sparse Data Run.
-            NtfsPosition::none()
+            None
         };
 
         // Only advance after having checked for success.
@@ -444,8 +647,9 @@ pub(crate) struct DataRunsState {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct NtfsDataRun {
     /// Absolute position of the Data Run within the filesystem, in bytes.
-    /// This may be `NtfsPosition(None)` if this is a "sparse" Data Run.
-    position: NtfsPosition,
+    /// This is `None` if this is a "sparse" Data Run.
+    /// Unlike an [`NtfsPosition`], this can also represent a Data Run starting at LCN 0.
+    position: Option<u64>,
     /// Total allocated size of the Data Run, in bytes.
     /// The actual size used by data may be lower, but a Data Run does not know about that.
     allocated_size: u64,
@@ -454,7 +658,7 @@ pub struct NtfsDataRun {
 }
 
 impl NtfsDataRun {
-    pub(crate) fn new(position: NtfsPosition, allocated_size: u64) -> Self {
+    pub(crate) fn new(position: Option<u64>, allocated_size: u64) -> Self {
         Self {
             position,
             allocated_size,
@@ -472,16 +676,68 @@ impl NtfsDataRun {
     ///   * The current seek position is outside the valid range, or
     ///   * The Data Run is a "sparse" Data Run
     pub fn data_position(&self) -> NtfsPosition {
-        if self.stream_position <= self.allocated_size() {
-            self.position + self.stream_position
-        } else {
-            NtfsPosition::none()
+        match self.position {
+            Some(position) if self.stream_position <= self.allocated_size() => {
+                NtfsPosition::new(position + self.stream_position)
+            }
+            _ => NtfsPosition::none(),
         }
     }
 
+    /// Returns `true` if this is a "sparse" Data Run, which occupies no clusters and reads as zeros.
+    pub fn is_sparse(&self) -> bool {
+        self.position.is_none()
+    }
+
     pub(crate) fn remaining_len(&self) -> u64 {
         self.allocated_size().saturating_sub(self.stream_position)
     }
+
+    /// Returns the absolute position of the first byte of the Data Run within the filesystem, in bytes.
+    /// This is `None` for a "sparse" Data Run.
+    ///
+    /// Unlike [`NtfsDataRun::data_position`], this does not depend on the current seek position and
+    /// also returns the position of a Data Run starting at LCN 0 (which is `Some(0)`).
+    pub fn start_position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Overwrites the Data Run at its current seek position with the bytes of `buf`.
+    ///
+    /// `value_position` and `value_offset` are the Data Run information position of the value and the current
+    /// offset within the value, which are reported if this is a "sparse" Data Run that cannot be written.
+    pub(crate) fn write<T>(
+        &mut self,
+        fs: &mut T,
+        buf: &[u8],
+        value_position: NtfsPosition,
+        value_offset: u64,
+    ) -> Result<usize>
+    where
+        T: Seek + Write,
+    {
+        if self.remaining_len() == 0 {
+            return Ok(0);
+        }
+
+        let position = match self.position {
+            Some(position) => position,
+            None => {
+                return Err(NtfsError::UnsupportedWriteToSparseDataRun {
+                    position: value_position,
+                    offset: value_offset,
+                })
+            }
+        };
+
+        // Write everything at once, so that a short write cannot make us continue in the next Data Run.
+        let bytes_to_write = usize::min(buf.len(), self.remaining_len() as usize);
+        fs.seek(SeekFrom::Start(position + self.stream_position))?;
+        fs.write_all(&buf[..bytes_to_write])?;
+
+        self.stream_position += bytes_to_write as u64;
+        Ok(bytes_to_write)
+    }
 }
 
 impl NtfsReadSeek for NtfsDataRun {
@@ -496,9 +752,9 @@ impl NtfsReadSeek for NtfsDataRun {
         let bytes_to_read = usize::min(buf.len(), self.remaining_len() as usize);
         let work_slice = &mut buf[..bytes_to_read];
 
-        let bytes_read = if let Some(position) = self.position.value() {
+        let bytes_read = if let Some(position) = self.position {
             // This Data Run contains "real" data.
-            fs.seek(SeekFrom::Start(position.get() + self.stream_position))?;
+            fs.seek(SeekFrom::Start(position + self.stream_position))?;
             fs.read(work_slice)?
         } else {
             // This is a sparse Data Run.
@@ -719,12 +975,59 @@ impl StreamState {
     pub(crate) fn stream_position(&self) -> u64 {
         self.stream_position
     }
+
+    /// Returns whether we wrote some bytes.
+    pub(crate) fn write_data_run<T>(
+        &mut self,
+        fs: &mut T,
+        buf: &[u8],
+        bytes_written: &mut usize,
+        value_position: NtfsPosition,
+    ) -> Result<bool>
+    where
+        T: Seek + Write,
+    {
+        // Is there a Data Run to write to?
+        let data_run = match &mut self.stream_data_run {
+            Some(data_run) => data_run,
+            None => return Ok(false),
+        };
+
+        // Have we already seeked past the size of the Data Run?
+        if data_run.stream_position() >= data_run.allocated_size() {
+            return Ok(false);
+        }
+
+        // We also must not write past the (used) data size of the entire value.
+        let remaining_data_size = self.data_size.saturating_sub(self.stream_position);
+        if remaining_data_size == 0 {
+            return Ok(false);
+        }
+
+        // Write up to the buffer length or up to the (used) data size, whatever comes first.
+        let start = *bytes_written;
+        let remaining_buf_len = buf.len() - start;
+        let end = start + usize::min(remaining_buf_len, remaining_data_size as usize);
+
+        // Perform the actual write.
+        let bytes_written_in_data_run =
+            data_run.write(fs, &buf[start..end], value_position, self.stream_position)?;
+        if bytes_written_in_data_run == 0 {
+            return Ok(false);
+        }
+
+        *bytes_written += bytes_written_in_data_run;
+        self.stream_position += bytes_written_in_data_run as u64;
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use binrw::io::SeekFrom;
+    use binrw::io::{Read, Seek, SeekFrom, Write};
 
+    use crate::attribute::NtfsAttributeType;
+    use crate::error::NtfsError;
     use crate::indexes::NtfsFileNameIndex;
     use crate::ntfs::Ntfs;
     use crate::traits::NtfsReadSeek;
@@ -839,9 +1142,9 @@ mod tests {
         let third_data_run = data_runs.next().unwrap().unwrap();
         assert!(data_runs.next().is_none());
 
-        assert!(first_data_run.data_position().value().is_some());
-        assert!(second_data_run.data_position().value().is_none());
-        assert!(third_data_run.data_position().value().is_some());
+        assert!(!first_data_run.is_sparse());
+        assert!(second_data_run.is_sparse());
+        assert!(!third_data_run.is_sparse());
 
         // Read the data and validate it.
         let mut data_attribute_value = data_attribute.value(&mut testfs1).unwrap();
@@ -855,4 +1158,107 @@ mod tests {
         assert_eq!(buf[5..500000], [0u8].repeat(499995));
         assert_eq!(buf[500000..500005], [b'1', b'1', b'1', b'1', b'1']);
     }
+
+    #[test]
+    fn test_write() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let mut ntfs = Ntfs::new(&mut testfs1).unwrap();
+        ntfs.read_upcase_table(&mut testfs1).unwrap();
+
+        // Overwrite the end of the "1000-bytes-file".
+        let file = ntfs.file_by_path(&mut testfs1, "1000-bytes-file").unwrap();
+        let data_attribute_item = file.data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_attribute_item.to_attribute().unwrap();
+        let mut value = data_attribute.non_resident_value().unwrap();
+        assert_eq!(value.initialized_len(), 1000);
+
+        value.seek(&mut testfs1, SeekFrom::Start(995)).unwrap();
+        assert_eq!(value.write(&mut testfs1, b"abcdefghij").unwrap(), 5);
+        assert_eq!(value.stream_position(), 1000);
+
+        // Nothing is written beyond the end of the value data.
+        assert_eq!(value.write(&mut testfs1, b"abcdefghij").unwrap(), 0);
+
+        // Overwrite its beginning via the `Write` implementation.
+        let mut value_attached = value.attach(&mut testfs1);
+        value_attached.seek(SeekFrom::Start(0)).unwrap();
+        value_attached.write_all(b"XYZ").unwrap();
+        value_attached.flush().unwrap();
+
+        // Check the data with a new value reader.
+        let mut data = Vec::new();
+        let mut value_attached = data_attribute
+            .value(&mut testfs1)
+            .unwrap()
+            .attach(&mut testfs1);
+        value_attached.read_to_end(&mut data).unwrap();
+
+        let mut expected = b"12345".repeat(200);
+        expected[..3].copy_from_slice(b"XYZ");
+        expected[995..].copy_from_slice(b"abcde");
+        assert_eq!(data, expected);
+
+        // Bytes in a sparse Data Run cannot be overwritten.
+        let file = ntfs.file_by_path(&mut testfs1, "sparse-file").unwrap();
+        let data_attribute_item = file.data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_attribute_item.to_attribute().unwrap();
+        let mut value = data_attribute.non_resident_value().unwrap();
+        let first_data_run_size = value.data_runs().next().unwrap().unwrap().allocated_size();
+
+        value
+            .seek(&mut testfs1, SeekFrom::Start(first_data_run_size - 2))
+            .unwrap();
+        let result = value.write(&mut testfs1, b"abcd");
+        assert!(matches!(
+            result,
+            Err(NtfsError::UnsupportedWriteToSparseDataRun { offset, .. }) if offset == first_data_run_size
+        ));
+
+        // Nothing has been written in that case, but the non-sparse Data Runs can still be overwritten.
+        value.seek(&mut testfs1, SeekFrom::Start(0)).unwrap();
+        assert_eq!(value.write(&mut testfs1, b"abcde").unwrap(), 5);
+        value.seek(&mut testfs1, SeekFrom::Start(500000)).unwrap();
+        assert_eq!(value.write(&mut testfs1, b"fghij").unwrap(), 5);
+
+        let mut data = Vec::new();
+        let mut value_attached = data_attribute
+            .value(&mut testfs1)
+            .unwrap()
+            .attach(&mut testfs1);
+        value_attached.read_to_end(&mut data).unwrap();
+        assert_eq!(data.len(), 500005);
+        assert_eq!(data[..5], *b"abcde");
+        assert!(data[5..500000].iter().all(|&byte| byte == 0));
+        assert_eq!(data[500000..], *b"fghij");
+
+        // Values of other attributes than $DATA cannot be overwritten.
+        let directory = ntfs.file_by_path(&mut testfs1, "many_subdirs").unwrap();
+        let index_allocation_attribute = directory
+            .attributes_raw()
+            .map(|attribute| attribute.unwrap())
+            .find(|attribute| attribute.ty().unwrap() == NtfsAttributeType::IndexAllocation)
+            .unwrap();
+        let mut value = index_allocation_attribute.non_resident_value().unwrap();
+        assert!(matches!(
+            value.write(&mut testfs1, b"abcd"),
+            Err(NtfsError::UnsupportedWriteToNonDataAttribute { .. })
+        ));
+
+        // Neither can encrypted values.
+        let mut file = ntfs.file_by_path(&mut testfs1, "1000-bytes-file").unwrap();
+        let data_attribute_offset = {
+            let data_attribute_item = file.data(&mut testfs1, "").unwrap().unwrap();
+            data_attribute_item.to_attribute().unwrap().offset()
+        };
+
+        // The flags are a little-endian u16 at offset 12 of the attribute header, and ENCRYPTED is 0x4000.
+        file.record_data_mut()[data_attribute_offset + 13] |= 0x40;
+        let data_attribute_item = file.data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_attribute_item.to_attribute().unwrap();
+        let mut value = data_attribute.non_resident_value().unwrap();
+        assert!(matches!(
+            value.write(&mut testfs1, b"abcd"),
+            Err(NtfsError::UnsupportedWriteToEncryptedValue { .. })
+        ));
+    }
 }
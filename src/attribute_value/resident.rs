@@ -31,6 +31,18 @@ impl<'f> NtfsResidentAttributeValue<'f> {
         }
     }
 
+    /// Creates an [`NtfsResidentAttributeValue`] for value data in memory.
+    ///
+    /// This allows to parse a structured value straight from a buffer via
+    /// [`NtfsStructuredValueFromResidentAttributeValue`], e.g. a $FILE_NAME attribute from a memory-mapped
+    /// filesystem image.
+    /// `position` is the absolute position of `data` within the filesystem, in bytes, and may be 0 if unknown.
+    ///
+    /// [`NtfsStructuredValueFromResidentAttributeValue`]: crate::structured_values::NtfsStructuredValueFromResidentAttributeValue
+    pub fn from_bytes(data: &'f [u8], position: u64) -> Self {
+        Self::new(data, NtfsPosition::new(position))
+    }
+
     /// Returns a slice of the entire value data.
     ///
     /// Remember that a resident attribute fits entirely inside the NTFS File Record
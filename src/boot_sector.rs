@@ -80,6 +80,11 @@ impl BiosParameterBlock {
         }
     }
 
+    /// Returns the Logical Cluster Number (LCN) to the beginning of the MFT mirror ($MFTMirr).
+    pub(crate) fn mft_mirror_lcn(&self) -> Lcn {
+        self.mft_mirror_lcn
+    }
+
     /// Source: https://en.wikipedia.org/wiki/NTFS#Partition_Boot_Sector_(VBR)
     fn record_size(&self, size_info: i8) -> Result<u32> {
         // The usual exponent of `BiosParameterBlock::file_record_size_info` is 10 (2^10 = 1024 bytes).
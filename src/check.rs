@@ -0,0 +1,761 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Read-only consistency checks of an NTFS filesystem, similar to what `chkdsk` does without `/f`.
+//!
+//! Call [`Ntfs::check`] to run all checks and get a list of [`NtfsCheckFinding`]s.
+//! The filesystem is never modified.
+
+use core::cmp::Ordering;
+use core::ops::Range;
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek, SeekFrom};
+use displaydoc::Display;
+
+use crate::attribute::NtfsAttributeType;
+use crate::cluster_owners::NtfsClusterOwners;
+use crate::error::{NtfsError, Result};
+use crate::file::{KnownNtfsFileRecordNumber, NtfsFile};
+use crate::file_reference::NtfsFileReference;
+use crate::ntfs::Ntfs;
+use crate::structured_values::NtfsFileName;
+use crate::types::{Lcn, NtfsPosition};
+use crate::upcase_table::UpcaseOrd;
+
+/// Number of File Records that are mirrored in $MFTMirr if its size cannot be determined.
+const DEFAULT_MFT_MIRROR_RECORD_COUNT: u64 = 4;
+
+/// A single inconsistency found by [`Ntfs::check`].
+#[derive(Debug, Display)]
+#[non_exhaustive]
+pub enum NtfsCheckFinding {
+    /// The backup boot sector at byte position {position:#x} differs from the boot sector
+    BackupBootSectorMismatch { position: NtfsPosition },
+    /// The backup boot sector at byte position {position:#x} cannot be read: {error}
+    BackupBootSectorUnreadable {
+        position: NtfsPosition,
+        error: NtfsError,
+    },
+    /// The clusters {lcns:?} are claimed by both File Record {first_file_record_number} and File Record {second_file_record_number}
+    ClustersAllocatedTwice {
+        lcns: Range<Lcn>,
+        first_file_record_number: u64,
+        second_file_record_number: u64,
+    },
+    /// The clusters {lcns:?} are marked as allocated in $Bitmap, but not claimed by any file
+    ClustersLeaked { lcns: Range<Lcn> },
+    /// The clusters {lcns:?} are claimed by File Record {file_record_number}, but not marked as allocated in $Bitmap
+    ClustersNotAllocated {
+        lcns: Range<Lcn>,
+        file_record_number: u64,
+    },
+    /// The clusters claimed by File Record {file_record_number} cannot be determined: {error}
+    ClustersUnreadable {
+        file_record_number: u64,
+        error: NtfsError,
+    },
+    /// The File Record {file_record_number} cannot be read: {error}
+    FileRecordUnreadable {
+        file_record_number: u64,
+        error: NtfsError,
+    },
+    /// The File Record {file_record_number} at byte position {position:#x} has a hard link count of {actual}, but {expected} $FILE_NAME attributes
+    HardLinkCountMismatch {
+        position: NtfsPosition,
+        file_record_number: u64,
+        expected: u16,
+        actual: u16,
+    },
+    /// A check could not be completed: {error}
+    Incomplete { error: NtfsError },
+    /// The Index Entry at byte position {position:#x} in the directory index of File Record {file_record_number} is not sorted after its predecessor
+    IndexEntryOutOfOrder {
+        position: NtfsPosition,
+        file_record_number: u64,
+    },
+    /// The File Record {file_record_number} differs from its copy at byte position {position:#x} in $MFTMirr
+    MftMirrorMismatch {
+        position: NtfsPosition,
+        file_record_number: u64,
+    },
+    /// The copy of File Record {file_record_number} in $MFTMirr cannot be read: {error}
+    MftMirrorRecordUnreadable {
+        file_record_number: u64,
+        error: NtfsError,
+    },
+    /// The $FILE_NAME attribute at byte position {position:#x} of File Record {file_record_number} references File Record {parent_file_record_number} as its parent, which is not a directory in use
+    ParentNotADirectory {
+        position: NtfsPosition,
+        file_record_number: u64,
+        parent_file_record_number: u64,
+    },
+    /// The $FILE_NAME attribute at byte position {position:#x} of File Record {file_record_number} references its parent directory with sequence number {actual}, but the parent has sequence number {expected}
+    ParentSequenceNumberMismatch {
+        position: NtfsPosition,
+        file_record_number: u64,
+        expected: u16,
+        actual: u16,
+    },
+}
+
+/// A parent directory reference of a $FILE_NAME attribute, which is checked after all directories are known.
+struct ParentReference {
+    position: NtfsPosition,
+    file_record_number: u64,
+    parent: NtfsFileReference,
+}
+
+pub(crate) fn check<T>(ntfs: &Ntfs, fs: &mut T) -> Vec<NtfsCheckFinding>
+where
+    T: Read + Seek,
+{
+    let mut findings = Vec::new();
+
+    let results = [
+        check_boot_sectors(ntfs, fs, &mut findings),
+        check_mft_mirror(ntfs, fs, &mut findings),
+        check_files(ntfs, fs, &mut findings),
+        check_cluster_allocation(ntfs, fs, &mut findings),
+    ];
+
+    for result in results {
+        if let Err(error) = result {
+            findings.push(NtfsCheckFinding::Incomplete { error });
+        }
+    }
+
+    findings
+}
+
+/// Compares the boot sector with the backup boot sector in the last sector of the volume.
+fn check_boot_sectors<T>(
+    ntfs: &Ntfs,
+    fs: &mut T,
+    findings: &mut Vec<NtfsCheckFinding>,
+) -> Result<()>
+where
+    T: Read + Seek,
+{
+    let mut boot_sector = vec![0u8; ntfs.sector_size() as usize];
+    fs.seek(SeekFrom::Start(0))?;
+    fs.read_exact(&mut boot_sector)?;
+
+    // The sector count in the boot sector doesn't include the backup boot sector.
+    let position = NtfsPosition::new(ntfs.size());
+    let mut backup_boot_sector = vec![0u8; boot_sector.len()];
+    let result = fs
+        .seek(SeekFrom::Start(ntfs.size()))
+        .and_then(|_| fs.read_exact(&mut backup_boot_sector));
+
+    match result {
+        Ok(()) if backup_boot_sector != boot_sector => {
+            findings.push(NtfsCheckFinding::BackupBootSectorMismatch { position })
+        }
+        Ok(()) => (),
+        Err(error) => findings.push(NtfsCheckFinding::BackupBootSectorUnreadable {
+            position,
+            error: error.into(),
+        }),
+    }
+
+    Ok(())
+}
+
+/// Compares the first File Records of the MFT with their copies in $MFTMirr.
+fn check_mft_mirror<T>(ntfs: &Ntfs, fs: &mut T, findings: &mut Vec<NtfsCheckFinding>) -> Result<()>
+where
+    T: Read + Seek,
+{
+    let mirror_position = ntfs.mft_mirror_position();
+    if mirror_position.value().is_none() {
+        return Err(NtfsError::InvalidMftMirrorLcn);
+    }
+
+    // If $MFTMirr itself is damaged, fall back to the number of File Records that NTFS mirrors by default.
+    let record_count = mft_mirror_record_count(ntfs, fs).unwrap_or(DEFAULT_MFT_MIRROR_RECORD_COUNT);
+    let file_record_size = ntfs.file_record_size() as u64;
+
+    for file_record_number in 0..record_count {
+        // An unreadable File Record in the MFT is reported when checking all files.
+        // Never use the $MFTMirr fallback of `Ntfs::new_with_recovery` here, which would compare the copy with itself.
+        let file = match ntfs.mft_file(fs, file_record_number) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+
+        // A corrupted $MFTMirr size may make the position overflow.
+        let position = match file_record_number
+            .checked_mul(file_record_size)
+            .and_then(|offset| mirror_position.value()?.get().checked_add(offset))
+        {
+            Some(position) => NtfsPosition::new(position),
+            None => {
+                findings.push(NtfsCheckFinding::MftMirrorRecordUnreadable {
+                    file_record_number,
+                    error: NtfsError::InvalidMftMirrorLcn,
+                });
+                break;
+            }
+        };
+
+        // This unwrap is safe, because adding an offset to a nonzero position cannot result in zero.
+        let mirror_file =
+            match NtfsFile::new(ntfs, fs, position.value().unwrap(), file_record_number) {
+                Ok(mirror_file) => mirror_file,
+                Err(error) => {
+                    findings.push(NtfsCheckFinding::MftMirrorRecordUnreadable {
+                        file_record_number,
+                        error,
+                    });
+                    continue;
+                }
+            };
+
+        if file.record_data() != mirror_file.record_data() {
+            findings.push(NtfsCheckFinding::MftMirrorMismatch {
+                position,
+                file_record_number,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn mft_mirror_record_count<T>(ntfs: &Ntfs, fs: &mut T) -> Result<u64>
+where
+    T: Read + Seek,
+{
+    let mft_mirror = ntfs.file(fs, KnownNtfsFileRecordNumber::MFTMirr as u64)?;
+    let data_item = mft_mirror.find_attribute(fs, NtfsAttributeType::Data, Some(""))?;
+    let data_size = data_item.to_attribute()?.value_length();
+
+    Ok(data_size / ntfs.file_record_size() as u64)
+}
+
+/// Checks the File Records of all files in use, their $FILE_NAME attributes, and their directory indexes.
+fn check_files<T>(ntfs: &Ntfs, fs: &mut T, findings: &mut Vec<NtfsCheckFinding>) -> Result<()>
+where
+    T: Read + Seek,
+{
+    let mut directories = BTreeMap::<u64, u16>::new();
+    let mut parent_references = Vec::new();
+    let mut mft_records = ntfs.mft_records(fs)?.lenient(true);
+
+    while let Some(record) = mft_records.next(fs) {
+        let record = record?;
+        let file_record_number = record.file_record_number();
+        let file = match record.into_file() {
+            Ok(file) => file,
+            Err(error) => {
+                findings.push(NtfsCheckFinding::FileRecordUnreadable {
+                    file_record_number,
+                    error,
+                });
+                continue;
+            }
+        };
+
+        // Extension File Records are checked as part of their base File Record.
+        if file.base_file_reference().file_record_number() != 0 {
+            continue;
+        }
+
+        if let Err(error) = check_file_names(fs, &file, &mut parent_references, findings) {
+            findings.push(NtfsCheckFinding::FileRecordUnreadable {
+                file_record_number,
+                error,
+            });
+        }
+
+        if file.is_directory() {
+            directories.insert(file_record_number, file.sequence_number());
+
+            if let Err(error) = check_directory_index(ntfs, fs, &file, findings) {
+                findings.push(NtfsCheckFinding::FileRecordUnreadable {
+                    file_record_number,
+                    error,
+                });
+            }
+        }
+    }
+
+    for reference in parent_references {
+        let parent_file_record_number = reference.parent.file_record_number();
+
+        match directories.get(&parent_file_record_number) {
+            Some(&expected) if expected != reference.parent.sequence_number() => {
+                findings.push(NtfsCheckFinding::ParentSequenceNumberMismatch {
+                    position: reference.position,
+                    file_record_number: reference.file_record_number,
+                    expected,
+                    actual: reference.parent.sequence_number(),
+                });
+            }
+            Some(_) => (),
+            None => findings.push(NtfsCheckFinding::ParentNotADirectory {
+                position: reference.position,
+                file_record_number: reference.file_record_number,
+                parent_file_record_number,
+            }),
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares the hard link count of a file with its $FILE_NAME attributes and collects their parent references.
+///
+/// Every $FILE_NAME attribute counts as a hard link, including a separate DOS name accompanying a Win32 name.
+fn check_file_names<T>(
+    fs: &mut T,
+    file: &NtfsFile,
+    parent_references: &mut Vec<ParentReference>,
+    findings: &mut Vec<NtfsCheckFinding>,
+) -> Result<()>
+where
+    T: Read + Seek,
+{
+    let mut hard_link_count = 0u16;
+    let mut attributes = file.attributes();
+
+    while let Some(item) = attributes.next(fs) {
+        let item = item?;
+        let attribute = item.to_attribute()?;
+        if attribute.ty()? != NtfsAttributeType::FileName {
+            continue;
+        }
+
+        let file_name = attribute.structured_value::<_, NtfsFileName>(fs)?;
+        hard_link_count = hard_link_count.saturating_add(1);
+
+        parent_references.push(ParentReference {
+            position: attribute.position(),
+            file_record_number: file.file_record_number(),
+            parent: file_name.parent_directory_reference(),
+        });
+    }
+
+    if hard_link_count != file.hard_link_count() {
+        findings.push(NtfsCheckFinding::HardLinkCountMismatch {
+            position: file.position(),
+            file_record_number: file.file_record_number(),
+            expected: hard_link_count,
+            actual: file.hard_link_count(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the entries of a directory index are sorted according to the $UpCase table.
+fn check_directory_index<T>(
+    ntfs: &Ntfs,
+    fs: &mut T,
+    directory: &NtfsFile,
+    findings: &mut Vec<NtfsCheckFinding>,
+) -> Result<()>
+where
+    T: Read + Seek,
+{
+    let index = directory.directory_index(fs)?;
+    let mut entries = index.entries();
+    let mut previous_file_name: Option<NtfsFileName> = None;
+
+    while let Some(entry) = entries.next(fs) {
+        let entry = entry?;
+        let file_name = match entry.key() {
+            Some(file_name) => file_name?,
+            None => continue,
+        };
+
+        if let Some(previous_file_name) = &previous_file_name {
+            if previous_file_name
+                .name()
+                .upcase_cmp(ntfs, &file_name.name())
+                == Ordering::Greater
+            {
+                findings.push(NtfsCheckFinding::IndexEntryOutOfOrder {
+                    position: entry.position(),
+                    file_record_number: directory.file_record_number(),
+                });
+            }
+        }
+
+        previous_file_name = Some(file_name);
+    }
+
+    Ok(())
+}
+
+/// Compares the clusters marked as allocated in $Bitmap with the clusters claimed by the Data Runs of all files.
+fn check_cluster_allocation<T>(
+    ntfs: &Ntfs,
+    fs: &mut T,
+    findings: &mut Vec<NtfsCheckFinding>,
+) -> Result<()>
+where
+    T: Read + Seek,
+{
+    let bitmap = ntfs.cluster_bitmap(fs)?;
+
+    // Unreadable File Records are reported when checking all files.
+    // Report files whose attributes cannot be read here, and check the clusters of all other files.
+    let owners =
+        NtfsClusterOwners::new_with_error_handler(ntfs, fs, |file_record_number, error| {
+            findings.push(NtfsCheckFinding::ClustersUnreadable {
+                file_record_number,
+                error,
+            })
+        })?;
+
+    // Merge the claimed ranges and report those claimed more than once.
+    // `last_owner` is the File Record Number that claims the end of the last merged range.
+    let mut claimed_ranges: Vec<Range<u64>> = Vec::new();
+    let mut last_owner = 0;
+    for owner in owners.iter() {
+        let lcns = owner.lcns().start.value()..owner.lcns().end.value();
+
+        let last = match claimed_ranges.last_mut() {
+            Some(last) => last,
+            None => {
+                claimed_ranges.push(lcns);
+                last_owner = owner.file_record_number();
+                continue;
+            }
+        };
+
+        if lcns.start < last.end {
+            findings.push(NtfsCheckFinding::ClustersAllocatedTwice {
+                lcns: Lcn::from(lcns.start)..Lcn::from(u64::min(lcns.end, last.end)),
+                first_file_record_number: last_owner,
+                second_file_record_number: owner.file_record_number(),
+            });
+        }
+
+        if lcns.start <= last.end {
+            if lcns.end > last.end {
+                last.end = lcns.end;
+                last_owner = owner.file_record_number();
+            }
+        } else {
+            claimed_ranges.push(lcns);
+            last_owner = owner.file_record_number();
+        }
+    }
+
+    // Report clusters claimed by a file, but marked as free.
+    let free_ranges: Vec<Range<u64>> = bitmap
+        .free_ranges()
+        .map(|range| range.start.value()..range.end.value())
+        .collect();
+
+    for owner in owners.iter() {
+        let lcns = owner.lcns().start.value()..owner.lcns().end.value();
+
+        for range in intersection(&lcns, &free_ranges) {
+            findings.push(NtfsCheckFinding::ClustersNotAllocated {
+                lcns: Lcn::from(range.start)..Lcn::from(range.end),
+                file_record_number: owner.file_record_number(),
+            });
+        }
+    }
+
+    // Report clusters marked as allocated, but not claimed by any file.
+    for allocated_range in bitmap.allocated_ranges() {
+        let allocated_range = allocated_range.start.value()..allocated_range.end.value();
+        let mut start = allocated_range.start;
+
+        for claimed_range in intersection(&allocated_range, &claimed_ranges) {
+            if start < claimed_range.start {
+                findings.push(NtfsCheckFinding::ClustersLeaked {
+                    lcns: Lcn::from(start)..Lcn::from(claimed_range.start),
+                });
+            }
+
+            start = claimed_range.end;
+        }
+
+        if start < allocated_range.end {
+            findings.push(NtfsCheckFinding::ClustersLeaked {
+                lcns: Lcn::from(start)..Lcn::from(allocated_range.end),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the parts of `range` that are covered by the sorted and non-overlapping `ranges`.
+fn intersection(range: &Range<u64>, ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+    let first = ranges.partition_point(|other| other.end <= range.start);
+
+    ranges[first..]
+        .iter()
+        .take_while(|other| other.start < range.end)
+        .map(|other| u64::max(other.start, range.start)..u64::min(other.end, range.end))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::Record;
+    use crate::structured_values::NtfsFileNamespace;
+    use binrw::io::Cursor;
+    use byteorder::{ByteOrder, LittleEndian};
+
+    fn check(testfs1: &mut Cursor<Vec<u8>>) -> Vec<NtfsCheckFinding> {
+        let mut ntfs = Ntfs::new(testfs1).unwrap();
+        ntfs.read_upcase_table(testfs1).unwrap();
+        ntfs.check(testfs1)
+    }
+
+    #[test]
+    fn test_check() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        assert!(check(&mut testfs1).is_empty());
+
+        let mut ntfs = Ntfs::new(&mut testfs1).unwrap();
+        ntfs.read_upcase_table(&mut testfs1).unwrap();
+        let mut image = testfs1.get_ref().clone();
+
+        // Change the OEM name in the backup boot sector.
+        image[ntfs.size() as usize + 3] ^= 0xff;
+
+        // Change an unused byte of the copy of File Record 3 in $MFTMirr.
+        let file_record_size = ntfs.file_record_size() as usize;
+        let mirror_position = ntfs.mft_mirror_position().value().unwrap().get() as usize;
+        image[mirror_position + 3 * file_record_size + 0x100] ^= 0xff;
+
+        // Increment the hard link count of "1000-bytes-file" (File Record 66).
+        let file = ntfs.file(&mut testfs1, 66).unwrap();
+        let file_position = file.position().value().unwrap().get() as usize;
+        image[file_position + 0x12] += 1;
+
+        // Change the parent sequence number in its $FILE_NAME attribute.
+        let file_name_attribute = file
+            .attributes_raw()
+            .map(|attribute| attribute.unwrap())
+            .find(|attribute| attribute.ty().unwrap() == NtfsAttributeType::FileName)
+            .unwrap();
+        let attribute_position = file_name_attribute.position().value().unwrap().get() as usize;
+        let value_offset = LittleEndian::read_u16(&image[attribute_position + 0x14..]) as usize;
+        image[attribute_position + value_offset + 6] ^= 0xff;
+
+        // Free its second cluster and allocate the first free cluster in $Bitmap.
+        let bitmap = ntfs.cluster_bitmap(&mut testfs1).unwrap();
+        let free_lcn = bitmap.free_ranges().next().unwrap().start.value();
+        let bitmap_file = ntfs
+            .file(&mut testfs1, KnownNtfsFileRecordNumber::Bitmap as u64)
+            .unwrap();
+        let bitmap_item = bitmap_file.data(&mut testfs1, "").unwrap().unwrap();
+        let bitmap_value = bitmap_item
+            .to_attribute()
+            .unwrap()
+            .value(&mut testfs1)
+            .unwrap();
+        let bitmap_position = bitmap_value.data_position().value().unwrap().get() as usize;
+        image[bitmap_position + 2568 / 8] &= !(1 << (2568 % 8));
+        image[bitmap_position + (free_lcn / 8) as usize] |= 1 << (free_lcn % 8);
+
+        // Rename the first entry of the root directory index to sort after its successor.
+        let root_directory = ntfs.root_directory(&mut testfs1).unwrap();
+        let root_directory_index = root_directory.directory_index(&mut testfs1).unwrap();
+        let mut entries = root_directory_index.entries();
+        let entry = entries.next(&mut testfs1).unwrap().unwrap();
+        let name_position = entry.position().value().unwrap().get() as usize + 0x10 + 0x42;
+        assert!(name_position % 512 < 510);
+        image[name_position] = b'z';
+
+        let findings = check(&mut Cursor::new(image));
+        assert_eq!(findings.len(), 7, "{findings:?}");
+
+        assert!(matches!(
+            findings[0],
+            NtfsCheckFinding::BackupBootSectorMismatch { .. }
+        ));
+        assert!(matches!(
+            findings[1],
+            NtfsCheckFinding::MftMirrorMismatch {
+                file_record_number: 3,
+                ..
+            }
+        ));
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            NtfsCheckFinding::HardLinkCountMismatch {
+                file_record_number: 66,
+                expected: 1,
+                actual: 2,
+                ..
+            }
+        )));
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            NtfsCheckFinding::ParentSequenceNumberMismatch {
+                file_record_number: 66,
+                ..
+            }
+        )));
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            NtfsCheckFinding::IndexEntryOutOfOrder {
+                file_record_number: 5,
+                ..
+            }
+        )));
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            NtfsCheckFinding::ClustersNotAllocated {
+                lcns,
+                file_record_number: 66,
+            } if lcns.start.value() == 2568 && lcns.end.value() == 2569
+        )));
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            NtfsCheckFinding::ClustersLeaked { lcns }
+                if lcns.start.value() == free_lcn && lcns.end.value() == free_lcn + 1
+        )));
+    }
+
+    /// Returns "1000-bytes-file" (File Record 66) with the given hard link count and one $FILE_NAME attribute
+    /// per given namespace, all copied from its original $FILE_NAME attribute.
+    fn file_with_names<'n>(
+        ntfs: &'n Ntfs,
+        testfs1: &mut Cursor<Vec<u8>>,
+        hard_link_count: u16,
+        namespaces: &[NtfsFileNamespace],
+    ) -> NtfsFile<'n> {
+        let file = ntfs.file(testfs1, 66).unwrap();
+        let file_name_attribute = file
+            .attributes_raw()
+            .map(|attribute| attribute.unwrap())
+            .find(|attribute| attribute.ty().unwrap() == NtfsAttributeType::FileName)
+            .unwrap();
+        let start = file_name_attribute.offset();
+        let end = start + file_name_attribute.attribute_length() as usize;
+        let value_offset = LittleEndian::read_u16(&file.record_data()[start + 0x14..]) as usize;
+
+        // Replace the original $FILE_NAME attribute by the copies.
+        let data = file.record_data();
+        let mut new_data = data[..start].to_vec();
+        for &namespace in namespaces {
+            let copy_start = new_data.len();
+            new_data.extend_from_slice(&data[start..end]);
+            new_data[copy_start + value_offset + 0x41] = namespace as u8;
+        }
+        new_data.extend_from_slice(&data[end..file.data_size() as usize]);
+        let data_size = new_data.len() as u32;
+        new_data.resize(data.len(), 0);
+
+        LittleEndian::write_u16(&mut new_data[0x12..], hard_link_count);
+        LittleEndian::write_u32(&mut new_data[0x18..], data_size);
+        let record = Record::new(new_data, file.position());
+        NtfsFile::from_record(ntfs, record, 66)
+    }
+
+    fn file_name_findings(fs: &mut Cursor<Vec<u8>>, file: &NtfsFile) -> Vec<NtfsCheckFinding> {
+        let mut parent_references = Vec::new();
+        let mut findings = Vec::new();
+        check_file_names(fs, file, &mut parent_references, &mut findings).unwrap();
+        findings
+    }
+
+    #[test]
+    fn test_check_file_names() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // A single name in the Win32AndDos namespace is a single hard link.
+        let namespaces = [NtfsFileNamespace::Win32AndDos];
+        let file = file_with_names(&ntfs, &mut testfs1, 1, &namespaces);
+        assert!(file_name_findings(&mut testfs1, &file).is_empty());
+
+        // A separate DOS name accompanying a Win32 name counts as a hard link of its own.
+        let namespaces = [NtfsFileNamespace::Win32, NtfsFileNamespace::Dos];
+        let file = file_with_names(&ntfs, &mut testfs1, 2, &namespaces);
+        assert!(file_name_findings(&mut testfs1, &file).is_empty());
+
+        let file = file_with_names(&ntfs, &mut testfs1, 1, &namespaces);
+        let findings = file_name_findings(&mut testfs1, &file);
+        assert_eq!(findings.len(), 1, "{findings:?}");
+        assert!(matches!(
+            findings[0],
+            NtfsCheckFinding::HardLinkCountMismatch {
+                file_record_number: 66,
+                expected: 2,
+                actual: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_check_unreadable_data_runs() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // Make the Data Runs of "1000-bytes-file" (File Record 66) undecodable by announcing a 15-byte cluster count.
+        let file = ntfs.file(&mut testfs1, 66).unwrap();
+        let data_item = file.data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_item.to_attribute().unwrap();
+        let attribute_position = data_attribute.position().value().unwrap().get() as usize;
+        let mut image = testfs1.get_ref().clone();
+        let data_runs_offset = LittleEndian::read_u16(&image[attribute_position + 0x20..]) as usize;
+        image[attribute_position + data_runs_offset] = 0x1f;
+
+        // The file is reported, and the rest of the filesystem is still checked.
+        let findings = check(&mut Cursor::new(image));
+        assert!(
+            !findings
+                .iter()
+                .any(|finding| matches!(finding, NtfsCheckFinding::Incomplete { .. })),
+            "{findings:?}"
+        );
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            NtfsCheckFinding::ClustersUnreadable {
+                file_record_number: 66,
+                ..
+            }
+        )));
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            NtfsCheckFinding::ClustersLeaked { lcns }
+                if lcns.start.value() == 2567 && lcns.end.value() == 2569
+        )));
+    }
+
+    #[test]
+    fn test_check_with_recovery() {
+        let testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1.clone()).unwrap();
+        let mft_position = ntfs.mft_position().value().unwrap().get() as usize;
+        let file_record_size = ntfs.file_record_size() as usize;
+        let sector_size = ntfs.sector_size() as usize;
+
+        // Damage the File Record of $Volume in the MFT, so that `Ntfs::file` falls back to its copy in $MFTMirr.
+        let mut image = testfs1.into_inner();
+        image[mft_position + 3 * file_record_size + sector_size - 1] ^= 0xff;
+        let volume_size = image.len() as u64;
+        let mut fs = Cursor::new(image);
+        let mut ntfs = Ntfs::new_with_recovery(&mut fs, volume_size).unwrap();
+        ntfs.read_upcase_table(&mut fs).unwrap();
+        assert!(ntfs.file(&mut fs, 3).is_ok());
+
+        // The damaged File Record is reported once, and never compared with itself.
+        let findings = ntfs.check(&mut fs);
+        assert_eq!(findings.len(), 1, "{findings:?}");
+        assert!(matches!(
+            findings[0],
+            NtfsCheckFinding::FileRecordUnreadable {
+                file_record_number: 3,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Cluster allocation information from the $Bitmap file.
+
+use core::iter::FusedIterator;
+use core::ops::Range;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek};
+
+use crate::attribute::NtfsAttributeType;
+use crate::error::{NtfsError, Result};
+use crate::file::KnownNtfsFileRecordNumber;
+use crate::ntfs::Ntfs;
+use crate::traits::NtfsReadSeek;
+use crate::types::Lcn;
+
+/// The allocation state of all clusters of an NTFS filesystem, as recorded in the $Bitmap file.
+///
+/// This structure is returned from the [`Ntfs::cluster_bitmap`] function.
+/// The entire bitmap is read upfront (one bit per cluster), so all queries are answered from memory.
+///
+/// Together with [`Ntfs::size`] and [`NtfsVolumeInformation`], this provides the numbers for a `df`-style
+/// overview of a filesystem.
+/// The allocated cluster ranges are also what an imaging tool needs to copy to preserve all file data.
+///
+/// [`NtfsVolumeInformation`]: crate::structured_values::NtfsVolumeInformation
+#[derive(Clone, Debug)]
+pub struct NtfsClusterBitmap {
+    bitmap: Vec<u8>,
+    cluster_count: u64,
+    cluster_size: u32,
+    allocated_cluster_count: u64,
+}
+
+impl NtfsClusterBitmap {
+    pub(crate) fn new<T>(ntfs: &Ntfs, fs: &mut T) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let bitmap_file = ntfs.file(fs, KnownNtfsFileRecordNumber::Bitmap as u64)?;
+        let bitmap_item = bitmap_file
+            .data(fs, "")
+            .ok_or(NtfsError::AttributeNotFound {
+                position: bitmap_file.position(),
+                ty: NtfsAttributeType::Data,
+            })??;
+        let bitmap_attribute = bitmap_item.to_attribute()?;
+        let mut bitmap_value = bitmap_attribute.value(fs)?;
+
+        // The bitmap is padded to a multiple of 8 bytes, so ignore all bits beyond the last cluster.
+        // Never read more than one bit per cluster of the volume, even if a corrupted $Bitmap claims a larger size.
+        let cluster_size = ntfs.cluster_size();
+        let volume_cluster_count = ntfs.size() / cluster_size as u64;
+        let bitmap_length = u64::min(
+            bitmap_attribute.value_length(),
+            (volume_cluster_count + 7) / 8,
+        );
+        let mut bitmap = vec![0u8; bitmap_length as usize];
+        bitmap_value.read_exact(fs, &mut bitmap)?;
+
+        let cluster_count = u64::min(volume_cluster_count, bitmap_length * 8);
+
+        Ok(Self::from_bitmap(bitmap, cluster_count, cluster_size))
+    }
+
+    fn from_bitmap(bitmap: Vec<u8>, cluster_count: u64, cluster_size: u32) -> Self {
+        let full_bytes = (cluster_count / 8) as usize;
+        let mut allocated_cluster_count = bitmap[..full_bytes]
+            .iter()
+            .map(|byte| byte.count_ones() as u64)
+            .sum();
+
+        let remaining_bits = cluster_count % 8;
+        if remaining_bits > 0 {
+            let mask = (1u8 << remaining_bits) - 1;
+            allocated_cluster_count += (bitmap[full_bytes] & mask).count_ones() as u64;
+        }
+
+        Self {
+            bitmap,
+            cluster_count,
+            cluster_size,
+            allocated_cluster_count,
+        }
+    }
+
+    /// Returns the number of clusters that are marked as allocated.
+    pub fn allocated_cluster_count(&self) -> u64 {
+        self.allocated_cluster_count
+    }
+
+    /// Returns an iterator over all ranges of consecutive allocated clusters, in ascending order.
+    pub fn allocated_ranges(&self) -> NtfsClusterRanges<'_> {
+        NtfsClusterRanges::new(self, true, 0..self.cluster_count)
+    }
+
+    /// Returns an iterator over the ranges of consecutive allocated clusters within `lcns`, in ascending order.
+    pub(crate) fn allocated_ranges_within(&self, lcns: Range<u64>) -> NtfsClusterRanges<'_> {
+        let end = u64::min(lcns.end, self.cluster_count);
+        NtfsClusterRanges::new(self, true, lcns.start..end)
+    }
+
+    /// Returns the total number of clusters of the filesystem.
+    pub fn cluster_count(&self) -> u64 {
+        self.cluster_count
+    }
+
+    /// Finds the first cluster in `lcn..end` whose allocation state equals `allocated`.
+    /// Returns `end` if there is no such cluster.
+    fn find(&self, mut lcn: u64, end: u64, allocated: bool) -> u64 {
+        // A byte whose clusters all have the other state can be skipped as a whole.
+        let skip_byte = if allocated { 0x00 } else { 0xff };
+
+        while lcn < end {
+            let byte = self.bitmap[(lcn / 8) as usize];
+
+            if lcn % 8 == 0 && byte == skip_byte {
+                lcn += 8;
+            } else if (byte & (1 << (lcn % 8)) != 0) == allocated {
+                return lcn;
+            } else {
+                lcn += 1;
+            }
+        }
+
+        end
+    }
+
+    /// Returns the number of clusters that are not allocated.
+    pub fn free_cluster_count(&self) -> u64 {
+        self.cluster_count - self.allocated_cluster_count
+    }
+
+    /// Returns an iterator over all ranges of consecutive free clusters, in ascending order.
+    pub fn free_ranges(&self) -> NtfsClusterRanges<'_> {
+        NtfsClusterRanges::new(self, false, 0..self.cluster_count)
+    }
+
+    /// Returns the total size of all free clusters, in bytes.
+    pub fn free_space(&self) -> u64 {
+        self.free_cluster_count() * self.cluster_size as u64
+    }
+
+    /// Returns whether the cluster at the given [`Lcn`] is marked as allocated.
+    ///
+    /// Clusters beyond the end of the filesystem are never allocated.
+    pub fn is_allocated(&self, lcn: Lcn) -> bool {
+        let lcn = lcn.value();
+        lcn < self.cluster_count && self.bitmap[(lcn / 8) as usize] & (1 << (lcn % 8)) != 0
+    }
+}
+
+/// Iterator over
+///   ranges of consecutive clusters that share the same allocation state,
+///   returning a [`Range`] of [`Lcn`]s for each entry.
+///
+/// This iterator is returned from the [`NtfsClusterBitmap::allocated_ranges`] and
+/// [`NtfsClusterBitmap::free_ranges`] functions.
+#[derive(Clone, Debug)]
+pub struct NtfsClusterRanges<'b> {
+    bitmap: &'b NtfsClusterBitmap,
+    allocated: bool,
+    next_lcn: u64,
+    end_lcn: u64,
+}
+
+impl<'b> NtfsClusterRanges<'b> {
+    fn new(bitmap: &'b NtfsClusterBitmap, allocated: bool, lcns: Range<u64>) -> Self {
+        Self {
+            bitmap,
+            allocated,
+            next_lcn: lcns.start,
+            end_lcn: lcns.end,
+        }
+    }
+}
+
+impl<'b> Iterator for NtfsClusterRanges<'b> {
+    type Item = Range<Lcn>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self
+            .bitmap
+            .find(self.next_lcn, self.end_lcn, self.allocated);
+        if start >= self.end_lcn {
+            self.next_lcn = start;
+            return None;
+        }
+
+        let end = self.bitmap.find(start, self.end_lcn, !self.allocated);
+        self.next_lcn = end;
+
+        Some(Lcn::from(start)..Lcn::from(end))
+    }
+}
+
+impl<'b> FusedIterator for NtfsClusterRanges<'b> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(ranges: NtfsClusterRanges) -> Vec<Range<u64>> {
+        ranges
+            .map(|range| range.start.value()..range.end.value())
+            .collect()
+    }
+
+    #[test]
+    fn test_cluster_ranges() {
+        // 20 clusters, followed by padding bits that must be ignored.
+        let bitmap = NtfsClusterBitmap::from_bitmap(vec![0b1000_0111, 0xff, 0b1111_0010], 20, 4096);
+        assert_eq!(bitmap.cluster_count(), 20);
+        assert_eq!(bitmap.allocated_cluster_count(), 13);
+        assert_eq!(bitmap.free_cluster_count(), 7);
+        assert_eq!(bitmap.free_space(), 7 * 4096);
+
+        assert_eq!(ranges(bitmap.allocated_ranges()), [0..3, 7..16, 17..18]);
+        assert_eq!(ranges(bitmap.free_ranges()), [3..7, 16..17, 18..20]);
+
+        assert!(bitmap.is_allocated(Lcn::from(8)));
+        assert!(!bitmap.is_allocated(Lcn::from(16)));
+        assert!(!bitmap.is_allocated(Lcn::from(20)));
+        assert!(!bitmap.is_allocated(Lcn::from(u64::MAX)));
+
+        let within = |lcns| ranges(bitmap.allocated_ranges_within(lcns));
+        assert_eq!(within(1..9), [1..3, 7..9]);
+        assert_eq!(within(3..7), []);
+        assert_eq!(within(13..100), [13..16, 17..18]);
+        assert_eq!(within(30..40), []);
+    }
+
+    #[test]
+    fn test_cluster_bitmap() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let bitmap = ntfs.cluster_bitmap(&mut testfs1).unwrap();
+        assert_eq!(
+            bitmap.cluster_count(),
+            ntfs.size() / ntfs.cluster_size() as u64
+        );
+
+        // The boot sector and the data of "1000-bytes-file" are allocated.
+        assert!(bitmap.is_allocated(Lcn::from(0)));
+        assert!(bitmap.is_allocated(Lcn::from(2567)));
+
+        // The allocated and free ranges alternate and cover the entire filesystem.
+        let mut all_ranges = bitmap
+            .allocated_ranges()
+            .chain(bitmap.free_ranges())
+            .collect::<Vec<_>>();
+        all_ranges.sort_by_key(|range| range.start);
+
+        let mut next_lcn = Lcn::from(0);
+        for range in &all_ranges {
+            assert_eq!(range.start, next_lcn);
+            assert!(range.start < range.end);
+            next_lcn = range.end;
+        }
+        assert_eq!(next_lcn.value(), bitmap.cluster_count());
+
+        let free_cluster_count: u64 = bitmap
+            .free_ranges()
+            .map(|range| range.end.value() - range.start.value())
+            .sum();
+        assert_eq!(free_cluster_count, bitmap.free_cluster_count());
+        assert!(free_cluster_count > 0);
+        assert!(bitmap
+            .free_ranges()
+            .all(|range| !bitmap.is_allocated(range.start)));
+    }
+}
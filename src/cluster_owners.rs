@@ -0,0 +1,322 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Reverse mapping from clusters to the attributes that own them.
+
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek};
+use nt_string::u16strle::U16StrLe;
+
+use crate::attribute::NtfsAttributeType;
+use crate::attribute_value::NtfsAttributeValue;
+use crate::error::{NtfsError, Result};
+use crate::file::NtfsFile;
+use crate::ntfs::Ntfs;
+use crate::types::{Lcn, Vcn};
+
+/// An index from Logical Cluster Numbers (LCNs) to the file and attribute that own these clusters.
+///
+/// This structure is returned from the [`Ntfs::cluster_owners`] function.
+/// It answers which file a cluster belongs to, e.g. when a disk reports a bad sector or a
+/// carving tool finds interesting data at some filesystem position.
+///
+/// Each entry corresponds to one Data Run of a non-resident attribute.
+/// Sparse Data Runs occupy no clusters and are therefore not part of the index.
+/// File Records and attributes that cannot be read are skipped, so a single corrupted File Record
+/// doesn't prevent building the index for the rest of the filesystem.
+#[derive(Clone, Debug)]
+pub struct NtfsClusterOwners {
+    owners: Vec<NtfsClusterOwner>,
+    /// The highest end LCN of all entries up to the same index in `owners`.
+    max_lcn_ends: Vec<Lcn>,
+    cluster_size: u32,
+}
+
+impl NtfsClusterOwners {
+    pub(crate) fn new<T>(ntfs: &Ntfs, fs: &mut T) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        Self::new_with_error_handler(ntfs, fs, |_, _| ())
+    }
+
+    /// Builds the index like [`NtfsClusterOwners::new`], but passes the File Record Number and the error
+    /// of every file whose attributes cannot be read to `on_error`.
+    ///
+    /// File Records that cannot be read at all are skipped without calling `on_error`.
+    pub(crate) fn new_with_error_handler<T, F>(
+        ntfs: &Ntfs,
+        fs: &mut T,
+        mut on_error: F,
+    ) -> Result<Self>
+    where
+        T: Read + Seek,
+        F: FnMut(u64, NtfsError),
+    {
+        let cluster_size = ntfs.cluster_size();
+        let mut owners = Vec::new();
+        let mut mft_records = ntfs.mft_records(fs)?.lenient(true);
+
+        while let Some(record) = mft_records.next(fs) {
+            let file = match record?.into_file() {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+
+            // Attributes in extension File Records are visited through the Attribute List of their base File Record.
+            if file.base_file_reference().file_record_number() != 0 {
+                continue;
+            }
+
+            if let Err(e) = Self::add_file(fs, &file, cluster_size, &mut owners) {
+                on_error(file.file_record_number(), e);
+            }
+        }
+
+        Ok(Self::from_owners(owners, cluster_size))
+    }
+
+    fn from_owners(mut owners: Vec<NtfsClusterOwner>, cluster_size: u32) -> Self {
+        owners.sort_unstable_by_key(|owner| owner.lcns.start);
+
+        let mut max_lcn_end = Lcn::from(0);
+        let max_lcn_ends = owners
+            .iter()
+            .map(|owner| {
+                max_lcn_end = Lcn::max(max_lcn_end, owner.lcns.end);
+                max_lcn_end
+            })
+            .collect();
+
+        Self {
+            owners,
+            max_lcn_ends,
+            cluster_size,
+        }
+    }
+
+    /// Adds an entry for each non-sparse Data Run of each non-resident attribute of `file`.
+    fn add_file<T>(
+        fs: &mut T,
+        file: &NtfsFile,
+        cluster_size: u32,
+        owners: &mut Vec<NtfsClusterOwner>,
+    ) -> Result<()>
+    where
+        T: Read + Seek,
+    {
+        let mut attributes = file.attributes();
+        while let Some(item) = attributes.next(fs) {
+            let item = item?;
+            let attribute = item.to_attribute()?;
+            if attribute.is_resident() {
+                continue;
+            }
+
+            let mut data_runs = Vec::new();
+            match attribute.value(fs)? {
+                NtfsAttributeValue::Resident(_) => continue,
+                NtfsAttributeValue::NonResident(value) => {
+                    for data_run in value.data_runs() {
+                        data_runs.push(data_run?);
+                    }
+                }
+                NtfsAttributeValue::AttributeListNonResident(value) => {
+                    value.for_each_data_run(fs, |data_run| data_runs.push(data_run.clone()))?;
+                }
+            }
+
+            let attribute_type = attribute.ty()?;
+            let name = attribute.name()?.0.to_vec();
+
+            let mut vcn = 0;
+            for data_run in data_runs {
+                let cluster_count = data_run.allocated_size() / cluster_size as u64;
+
+                if let Some(position) = data_run.start_position() {
+                    let lcn = position / cluster_size as u64;
+                    owners.push(NtfsClusterOwner {
+                        lcns: Lcn::from(lcn)..Lcn::from(lcn + cluster_count),
+                        file_record_number: file.file_record_number(),
+                        attribute_type,
+                        name: name.clone(),
+                        vcn: Vcn::from(vcn as i64),
+                    });
+                }
+
+                vcn += cluster_count;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the [`NtfsClusterOwner`] of the cluster at the given [`Lcn`],
+    /// or `None` if the cluster doesn't belong to any non-resident attribute.
+    ///
+    /// On a consistent filesystem, every cluster has at most one owner.
+    /// If a damaged filesystem assigns a cluster to multiple attributes, the owner with the highest
+    /// starting LCN is returned.
+    pub fn find(&self, lcn: Lcn) -> Option<&NtfsClusterOwner> {
+        let end = self.owners.partition_point(|owner| owner.lcns.start <= lcn);
+
+        // Go back through all entries starting at or before `lcn`, until no earlier entry can reach `lcn` anymore.
+        (0..end)
+            .rev()
+            .take_while(|&index| self.max_lcn_ends[index] > lcn)
+            .map(|index| &self.owners[index])
+            .find(|owner| owner.lcns.contains(&lcn))
+    }
+
+    /// Returns the [`NtfsClusterOwner`] of the cluster at the given absolute filesystem position (in bytes),
+    /// along with the corresponding byte offset within the attribute value.
+    ///
+    /// See [`NtfsClusterOwners::find`] for details.
+    pub fn find_by_position(&self, position: u64) -> Option<(&NtfsClusterOwner, u64)> {
+        let cluster_size = self.cluster_size as u64;
+        let lcn = Lcn::from(position / cluster_size);
+        let owner = self.find(lcn)?;
+
+        let cluster_in_run = lcn.value() - owner.lcns.start.value();
+        let vcn = owner.vcn.value() as u64 + cluster_in_run;
+        let offset = vcn * cluster_size + position % cluster_size;
+
+        Some((owner, offset))
+    }
+
+    /// Returns `true` if no cluster is owned by any attribute.
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    /// Returns an iterator over all entries of the index, in ascending order of their LCNs.
+    pub fn iter(&self) -> core::slice::Iter<'_, NtfsClusterOwner> {
+        self.owners.iter()
+    }
+
+    /// Returns the number of entries of the index.
+    pub fn len(&self) -> usize {
+        self.owners.len()
+    }
+}
+
+/// A range of clusters owned by an attribute, returned by the [`NtfsClusterOwners`] functions.
+#[derive(Clone, Debug)]
+pub struct NtfsClusterOwner {
+    lcns: Range<Lcn>,
+    file_record_number: u64,
+    attribute_type: NtfsAttributeType,
+    name: Vec<u8>,
+    vcn: Vcn,
+}
+
+impl NtfsClusterOwner {
+    /// Returns the type of the attribute owning the clusters.
+    pub fn attribute_type(&self) -> NtfsAttributeType {
+        self.attribute_type
+    }
+
+    /// Returns the File Record Number of the file owning the clusters.
+    ///
+    /// This is always the base File Record of a file, even if the attribute is stored in an extension File Record.
+    pub fn file_record_number(&self) -> u64 {
+        self.file_record_number
+    }
+
+    /// Returns the range of Logical Cluster Numbers (LCNs) owned by the attribute.
+    pub fn lcns(&self) -> Range<Lcn> {
+        self.lcns.clone()
+    }
+
+    /// Returns the name of the attribute owning the clusters, which is empty for the unnamed $DATA stream.
+    pub fn name(&self) -> U16StrLe<'_> {
+        U16StrLe(&self.name)
+    }
+
+    /// Returns the Virtual Cluster Number (VCN) of the first cluster of [`NtfsClusterOwner::lcns`]
+    /// within the attribute value.
+    pub fn vcn(&self) -> Vcn {
+        self.vcn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::KnownNtfsFileRecordNumber;
+    use alloc::vec;
+
+    #[test]
+    fn test_cluster_owners() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let owners = ntfs.cluster_owners(&mut testfs1).unwrap();
+        assert!(!owners.is_empty());
+
+        // The entries are sorted and don't overlap.
+        for (previous, next) in owners.iter().zip(owners.iter().skip(1)) {
+            assert!(previous.lcns().end <= next.lcns().start);
+        }
+
+        // "1000-bytes-file" (File Record 66) occupies 2 clusters starting at LCN 2567.
+        let owner = owners.find(Lcn::from(2568)).unwrap();
+        assert_eq!(owner.file_record_number(), 66);
+        assert_eq!(owner.attribute_type(), NtfsAttributeType::Data);
+        assert_eq!(owner.name(), "");
+        assert_eq!(owner.lcns(), Lcn::from(2567)..Lcn::from(2569));
+        assert_eq!(owner.vcn(), Vcn::from(0));
+
+        // The second byte of its second cluster is at offset 512 + 1 of its data.
+        let cluster_size = ntfs.cluster_size() as u64;
+        let (owner, offset) = owners.find_by_position(2568 * cluster_size + 1).unwrap();
+        assert_eq!(owner.file_record_number(), 66);
+        assert_eq!(offset, cluster_size + 1);
+
+        // The MFT itself is owned by File Record 0, starting at VCN 0.
+        let mft_lcn = ntfs.mft_position().value().unwrap().get() / cluster_size;
+        let owner = owners.find(Lcn::from(mft_lcn)).unwrap();
+        assert_eq!(
+            owner.file_record_number(),
+            KnownNtfsFileRecordNumber::MFT as u64
+        );
+        assert_eq!(owner.vcn(), Vcn::from(0));
+
+        // The Data Run of $Boot starts at LCN 0.
+        let owner = owners.find(Lcn::from(0)).unwrap();
+        assert_eq!(
+            owner.file_record_number(),
+            KnownNtfsFileRecordNumber::Boot as u64
+        );
+        assert_eq!(owner.lcns().start, Lcn::from(0));
+    }
+
+    #[test]
+    fn test_overlapping_cluster_owners() {
+        let owner = |start: u64, end: u64, file_record_number| NtfsClusterOwner {
+            lcns: Lcn::from(start)..Lcn::from(end),
+            file_record_number,
+            attribute_type: NtfsAttributeType::Data,
+            name: Vec::new(),
+            vcn: Vcn::from(0),
+        };
+
+        // File Record 1 claims a long range that overlaps the ranges of File Records 2 and 3.
+        let owners = NtfsClusterOwners::from_owners(
+            vec![owner(20, 25, 3), owner(0, 100, 1), owner(10, 15, 2)],
+            512,
+        );
+        let find = |lcn| {
+            owners
+                .find(Lcn::from(lcn))
+                .map(|owner| owner.file_record_number())
+        };
+        assert_eq!(find(5), Some(1));
+        assert_eq!(find(12), Some(2));
+        assert_eq!(find(17), Some(1));
+        assert_eq!(find(22), Some(3));
+        assert_eq!(find(50), Some(1));
+        assert_eq!(find(100), None);
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Canonical Huffman decoder shared by the XPRESS Huffman and LZX decompressors.
+
+use alloc::vec::Vec;
+
+/// Maximum codeword length supported by this decoder, in bits.
+pub(crate) const MAX_CODEWORD_LENGTH: usize = 16;
+
+/// Decoder for a canonical Huffman code, which is fully defined by the codeword length of each symbol.
+///
+/// Codewords are assigned in order of increasing length, and symbols of the same length get
+/// consecutive codewords in order of increasing symbol value.
+#[derive(Clone, Debug)]
+pub(crate) struct HuffmanDecoder {
+    /// Number of symbols for each codeword length.
+    counts: [u16; MAX_CODEWORD_LENGTH + 1],
+    /// All used symbols, sorted by codeword length and then by symbol value.
+    symbols: Vec<u16>,
+}
+
+impl HuffmanDecoder {
+    /// Builds a decoder from the codeword length of each symbol, where 0 denotes an unused symbol.
+    ///
+    /// Returns `None` if the lengths are invalid or describe an over-subscribed code.
+    /// Incomplete codes are accepted, because compressors emit them for blocks with very few symbols.
+    pub(crate) fn new(lengths: &[u8]) -> Option<Self> {
+        let mut counts = [0u16; MAX_CODEWORD_LENGTH + 1];
+        for &length in lengths {
+            *counts.get_mut(length as usize)? += 1;
+        }
+        counts[0] = 0;
+
+        // Every codeword length doubles the number of available codewords.
+        // More symbols than available codewords mean that the code is over-subscribed.
+        let mut available: i32 = 1;
+        for &count in &counts[1..] {
+            available = (available << 1) - count as i32;
+            if available < 0 {
+                return None;
+            }
+        }
+
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..=MAX_CODEWORD_LENGTH as u8 {
+            for (symbol, &symbol_length) in lengths.iter().enumerate() {
+                if symbol_length == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+
+        Some(Self { counts, symbols })
+    }
+
+    /// Decodes the next symbol.
+    ///
+    /// `bits` contains the next [`MAX_CODEWORD_LENGTH`] bits of the input stream, with the next bit being the
+    /// most significant one.
+    /// Returns the decoded symbol and the length of its codeword, or `None` if `bits` do not start with a valid codeword.
+    pub(crate) fn decode(&self, bits: u32) -> Option<(u16, u32)> {
+        let mut code = 0u32;
+        let mut first = 0u32;
+        let mut index = 0u32;
+
+        for length in 1..=MAX_CODEWORD_LENGTH {
+            code |= (bits >> (MAX_CODEWORD_LENGTH - length)) & 1;
+            let count = self.counts[length] as u32;
+
+            if code < first + count {
+                let symbol = self.symbols[(index + code - first) as usize];
+                return Some((symbol, length as u32));
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_huffman() {
+        // Symbol 1 gets codeword 0, symbol 0 gets codeword 10, symbols 2 and 3 get codewords 110 and 111.
+        let decoder = HuffmanDecoder::new(&[2, 1, 3, 3]).unwrap();
+        assert_eq!(decoder.decode(0b0000_0000_0000_0000), Some((1, 1)));
+        assert_eq!(decoder.decode(0b1000_0000_0000_0000), Some((0, 2)));
+        assert_eq!(decoder.decode(0b1100_0000_0000_0000), Some((2, 3)));
+        assert_eq!(decoder.decode(0b1110_0000_0000_0000), Some((3, 3)));
+
+        // Three codewords of length 1 can't exist.
+        assert!(HuffmanDecoder::new(&[1, 1, 1]).is_none());
+
+        // An incomplete code is accepted, but unassigned codewords can't be decoded.
+        let decoder = HuffmanDecoder::new(&[0, 1]).unwrap();
+        assert_eq!(decoder.decode(0), Some((1, 1)));
+        assert_eq!(decoder.decode(0x8000), None);
+    }
+}
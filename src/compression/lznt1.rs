@@ -0,0 +1,175 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Decompressor for the LZNT1 algorithm, which NTFS uses for compressed attribute values.
+//!
+//! LZNT1 data consists of chunks, each of them representing up to 4096 bytes of uncompressed data.
+//! Every chunk starts with a 2-byte header:
+//!   * Bits 0-11 contain the size of the chunk data (excluding the header) minus 1.
+//!   * Bits 12-14 contain a signature (always 3).
+//!   * Bit 15 is set if the chunk data is compressed and unset if it is stored as-is.
+//!
+//! A zero header marks the end of the compressed data.
+//!
+//! Compressed chunk data is a sequence of flag bytes, each followed by 8 tokens.
+//! A token is either a single literal byte (if the corresponding flag bit is unset) or a 2-byte back-reference
+//! (if the corresponding flag bit is set).
+//! The split of a back-reference between offset and length bits depends on the current position in the chunk.
+//!
+//! Reference: [MS-XCA] 2.5 "LZNT1 Algorithm Details"
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::error::{NtfsError, Result};
+use crate::types::NtfsPosition;
+
+/// Number of uncompressed bytes represented by a single LZNT1 chunk.
+const CHUNK_SIZE: usize = 4096;
+
+/// Size of an LZNT1 chunk header, in bytes.
+const CHUNK_HEADER_SIZE: usize = 2;
+
+/// Mask for the bits of a chunk header that specify the chunk data size.
+const CHUNK_SIZE_MASK: u16 = 0x0fff;
+
+/// Flag in a chunk header that is set if the chunk data is compressed.
+const CHUNK_COMPRESSED_FLAG: u16 = 0x8000;
+
+/// Decompresses the LZNT1-compressed `input` into `output` and returns the number of bytes written.
+///
+/// Every chunk fills exactly 4096 bytes of `output` (chunks decompressing to less data are zero-padded),
+/// except for the last one, which may be truncated at the end of `output`.
+/// Decompression stops at the end marker, at the end of `input`, or when `output` is full, whatever comes first.
+///
+/// `position` is the absolute position of `input` within the filesystem and only used for error reporting.
+pub(crate) fn decompress(input: &[u8], output: &mut [u8], position: NtfsPosition) -> Result<usize> {
+    let mut input_offset = 0;
+    let mut output_offset = 0;
+
+    while output_offset < output.len() {
+        let header_end = input_offset + CHUNK_HEADER_SIZE;
+        let header = match input.get(input_offset..header_end) {
+            Some(header) => LittleEndian::read_u16(header),
+            None => break,
+        };
+
+        // A zero header marks the end of the compressed data.
+        if header == 0 {
+            break;
+        }
+
+        let chunk_end = header_end + (header & CHUNK_SIZE_MASK) as usize + 1;
+        let chunk = input
+            .get(header_end..chunk_end)
+            .ok_or(NtfsError::InvalidCompressedData {
+                position: position + input_offset,
+            })?;
+
+        let output_end = usize::min(output_offset + CHUNK_SIZE, output.len());
+        let output_chunk = &mut output[output_offset..output_end];
+
+        let bytes_written = if header & CHUNK_COMPRESSED_FLAG != 0 {
+            decompress_chunk(chunk, output_chunk).ok_or(NtfsError::InvalidCompressedData {
+                position: position + header_end,
+            })?
+        } else {
+            let bytes_to_copy = usize::min(chunk.len(), output_chunk.len());
+            output_chunk[..bytes_to_copy].copy_from_slice(&chunk[..bytes_to_copy]);
+            bytes_to_copy
+        };
+
+        // A chunk that decompresses to less than `CHUNK_SIZE` bytes is implicitly zero-padded.
+        output_chunk[bytes_written..].fill(0);
+
+        input_offset = chunk_end;
+        output_offset = output_end;
+    }
+
+    Ok(output_offset)
+}
+
+/// Decompresses the data of a single compressed chunk and returns the number of bytes written to `output`,
+/// or `None` if the chunk data is corrupted.
+fn decompress_chunk(chunk: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut input_offset = 0;
+    let mut output_offset = 0;
+
+    while input_offset < chunk.len() {
+        let flags = chunk[input_offset];
+        input_offset += 1;
+
+        for bit in 0..8 {
+            if input_offset >= chunk.len() || output_offset >= output.len() {
+                break;
+            }
+
+            if flags & (1 << bit) == 0 {
+                // This token is a literal byte.
+                output[output_offset] = chunk[input_offset];
+                input_offset += 1;
+                output_offset += 1;
+            } else {
+                // This token is a back-reference to previously decompressed data.
+                let token = LittleEndian::read_u16(chunk.get(input_offset..input_offset + 2)?);
+                input_offset += 2;
+
+                // The further we are in the chunk, the more bits are needed for the offset and
+                // the less bits remain for the length.
+                // The offset needs at least 4 bits, leaving at most 12 bits for the length.
+                let mut length_bits = 12;
+                let mut remaining = output_offset.checked_sub(1)?;
+                while remaining >= 0x10 {
+                    length_bits -= 1;
+                    remaining >>= 1;
+                }
+
+                let length_mask = (1u16 << length_bits) - 1;
+                let offset = (token >> length_bits) as usize + 1;
+                let length = (token & length_mask) as usize + 3;
+
+                if offset > output_offset {
+                    return None;
+                }
+
+                // Copy byte by byte, because the source and destination ranges may overlap.
+                let end = usize::min(output_offset + length, output.len());
+                while output_offset < end {
+                    output[output_offset] = output[output_offset - offset];
+                    output_offset += 1;
+                }
+            }
+        }
+    }
+
+    Some(output_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lznt1() {
+        // A compressed chunk with the literals "abc" followed by a back-reference
+        // (offset 3, length 9), and a stored chunk with the literals "xyz".
+        let input = [
+            0x05, 0xb0, 0x08, b'a', b'b', b'c', 0x06, 0x20, 0x02, 0x30, b'x', b'y', b'z', 0x00,
+            0x00,
+        ];
+        let mut output = [0xccu8; 2 * CHUNK_SIZE];
+
+        let bytes_written = decompress(&input, &mut output, NtfsPosition::none()).unwrap();
+        assert_eq!(bytes_written, 2 * CHUNK_SIZE);
+        assert_eq!(&output[..12], b"abcabcabcabc");
+        assert!(output[12..CHUNK_SIZE].iter().all(|&b| b == 0));
+        assert_eq!(&output[CHUNK_SIZE..CHUNK_SIZE + 3], b"xyz");
+        assert!(output[CHUNK_SIZE + 3..].iter().all(|&b| b == 0));
+
+        // A back-reference before any literal is invalid.
+        let input = [0x01, 0xb0, 0x01, 0x00, 0x00];
+        assert!(matches!(
+            decompress(&input, &mut output, NtfsPosition::none()),
+            Err(NtfsError::InvalidCompressedData { .. })
+        ));
+    }
+}
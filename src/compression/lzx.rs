@@ -0,0 +1,767 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Decompressor for the LZX variant that the Windows Overlay Filter (WOF) uses for its LZX compression format.
+//!
+//! This variant matches the one used in WIM files:
+//!   * Each chunk of up to 32768 bytes is compressed independently, with a window of the same size.
+//!   * The bitstream does not start with an E8 translation header, but E8 translation is always performed
+//!     with a fixed translation size.
+//!
+//! The compressed data is a bitstream of 16-bit little-endian words and consists of verbatim, aligned offset,
+//! and uncompressed blocks.
+//! Codeword lengths of the main and length codes are delta-coded against those of the previous block.
+//!
+//! Reference: [MS-PATCH] "LZX DELTA Compression and Decompression", and the WIM LZX implementation of wimlib.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::huffman::{HuffmanDecoder, MAX_CODEWORD_LENGTH};
+use crate::error::{NtfsError, Result};
+use crate::types::NtfsPosition;
+
+/// Block size used when the block header does not specify one, in bytes.
+const DEFAULT_BLOCK_SIZE: usize = 32768;
+
+const BLOCK_TYPE_VERBATIM: u32 = 1;
+const BLOCK_TYPE_ALIGNED: u32 = 2;
+const BLOCK_TYPE_UNCOMPRESSED: u32 = 3;
+
+/// Number of literal symbols of the main code.
+const LITERAL_COUNT: usize = 256;
+
+/// Number of offset slots for a 32768-byte window.
+const OFFSET_SLOT_COUNT: usize = 30;
+
+/// Number of length headers per offset slot in the main code.
+const LENGTH_HEADER_COUNT: usize = 8;
+
+/// Number of symbols of the main code.
+const MAIN_SYMBOL_COUNT: usize = LITERAL_COUNT + OFFSET_SLOT_COUNT * LENGTH_HEADER_COUNT;
+
+/// Number of symbols of the length code.
+const LENGTH_SYMBOL_COUNT: usize = 249;
+
+/// Number of symbols of the aligned offset code.
+const ALIGNED_SYMBOL_COUNT: usize = 8;
+
+/// Number of offset bits that are encoded by the aligned offset code in aligned offset blocks.
+const ALIGNED_OFFSET_BITS: u32 = 3;
+
+/// Number of symbols of the pretree, which encodes the codeword lengths of the main and length codes.
+const PRETREE_SYMBOL_COUNT: usize = 20;
+
+/// Minimum length of a match, in bytes.
+const MIN_MATCH_LENGTH: usize = 2;
+
+/// Number of recently used match offsets that can be referenced through offset slots 0-2.
+const RECENT_OFFSET_COUNT: usize = 3;
+
+/// Difference between a "formatted offset" (as encoded through the offset slots) and the actual match offset.
+const OFFSET_ADJUSTMENT: usize = 2;
+
+/// Translation size used for E8 translation in WIM and WOF LZX.
+const E8_TRANSLATION_SIZE: i32 = 12_000_000;
+
+/// Base formatted offset of each offset slot.
+const OFFSET_SLOT_BASES: [u32; OFFSET_SLOT_COUNT] = [
+    0, 1, 2, 3, 4, 6, 8, 12, 16, 24, 32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024, 1536,
+    2048, 3072, 4096, 6144, 8192, 12288, 16384, 24576,
+];
+
+/// Number of extra offset bits of each offset slot.
+const OFFSET_SLOT_EXTRA_BITS: [u32; OFFSET_SLOT_COUNT] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Decompresses the LZX-compressed `input` into `output`, filling `output` entirely.
+///
+/// `output` must not be larger than 32768 bytes, the window size of WOF LZX.
+/// `position` is the absolute position of `input` within the filesystem and only used for error reporting.
+pub(crate) fn decompress(input: &[u8], output: &mut [u8], position: NtfsPosition) -> Result<()> {
+    decompress_blocks(input, output).ok_or(NtfsError::InvalidCompressedData { position })?;
+    undo_e8_translation(output);
+    Ok(())
+}
+
+fn decompress_blocks(input: &[u8], output: &mut [u8]) -> Option<()> {
+    let mut reader = BitReader {
+        input,
+        offset: 0,
+        bit_buffer: 0,
+        bits_left: 0,
+    };
+    let mut main_lengths = [0u8; MAIN_SYMBOL_COUNT];
+    let mut length_lengths = [0u8; LENGTH_SYMBOL_COUNT];
+    let mut recent_offsets = [1usize; RECENT_OFFSET_COUNT];
+    let mut output_offset = 0;
+
+    while output_offset < output.len() {
+        reader.ensure_bits(4);
+        let block_type = reader.pop_bits(3);
+        let block_size = if reader.pop_bits(1) == 1 {
+            DEFAULT_BLOCK_SIZE
+        } else {
+            reader.read_bits(16) as usize
+        };
+
+        // The last block may be declared larger than the remaining uncompressed data.
+        let block_end = usize::min(output_offset + block_size, output.len());
+
+        match block_type {
+            BLOCK_TYPE_VERBATIM | BLOCK_TYPE_ALIGNED => {
+                let aligned_decoder = if block_type == BLOCK_TYPE_ALIGNED {
+                    let mut aligned_lengths = [0u8; ALIGNED_SYMBOL_COUNT];
+                    for length in aligned_lengths.iter_mut() {
+                        *length = reader.read_bits(3) as u8;
+                    }
+                    Some(HuffmanDecoder::new(&aligned_lengths)?)
+                } else {
+                    None
+                };
+
+                read_codeword_lengths(&mut reader, &mut main_lengths[..LITERAL_COUNT])?;
+                read_codeword_lengths(&mut reader, &mut main_lengths[LITERAL_COUNT..])?;
+                let main_decoder = HuffmanDecoder::new(&main_lengths)?;
+
+                read_codeword_lengths(&mut reader, &mut length_lengths)?;
+                let length_decoder = HuffmanDecoder::new(&length_lengths)?;
+
+                let codes = BlockCodes {
+                    main: main_decoder,
+                    length: length_decoder,
+                    aligned: aligned_decoder,
+                };
+
+                while output_offset < block_end {
+                    output_offset = decode_symbol(
+                        &mut reader,
+                        &codes,
+                        &mut recent_offsets,
+                        output,
+                        output_offset,
+                    )?;
+                }
+            }
+            BLOCK_TYPE_UNCOMPRESSED => {
+                // The recent offsets and the uncompressed data are aligned to the next 16-bit word.
+                // If the bitstream is already aligned, an entire word is skipped.
+                reader.ensure_bits(1);
+                reader.align();
+
+                for recent_offset in recent_offsets.iter_mut() {
+                    *recent_offset = reader.read_u32()? as usize;
+                    if *recent_offset == 0 {
+                        return None;
+                    }
+                }
+
+                let data = reader.read_bytes(block_size)?;
+                output[output_offset..block_end]
+                    .copy_from_slice(&data[..block_end - output_offset]);
+                output_offset = block_end;
+
+                // Uncompressed data is padded to 16-bit alignment.
+                if block_size % 2 == 1 {
+                    reader.read_bytes(1)?;
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    Some(())
+}
+
+/// Huffman codes of a verbatim or aligned offset block.
+struct BlockCodes {
+    main: HuffmanDecoder,
+    length: HuffmanDecoder,
+    aligned: Option<HuffmanDecoder>,
+}
+
+/// Decodes the next literal or match and returns the new output offset.
+fn decode_symbol(
+    reader: &mut BitReader,
+    codes: &BlockCodes,
+    recent_offsets: &mut [usize; RECENT_OFFSET_COUNT],
+    output: &mut [u8],
+    output_offset: usize,
+) -> Option<usize> {
+    let main_symbol = reader.read_symbol(&codes.main)? as usize;
+    if main_symbol < LITERAL_COUNT {
+        output[output_offset] = main_symbol as u8;
+        return Some(output_offset + 1);
+    }
+
+    let main_symbol = main_symbol - LITERAL_COUNT;
+    let length_header = main_symbol % LENGTH_HEADER_COUNT;
+    let offset_slot = main_symbol / LENGTH_HEADER_COUNT;
+
+    let mut match_length = length_header;
+    if length_header == LENGTH_HEADER_COUNT - 1 {
+        match_length += reader.read_symbol(&codes.length)? as usize;
+    }
+    match_length += MIN_MATCH_LENGTH;
+
+    let match_offset = if offset_slot < RECENT_OFFSET_COUNT {
+        // Slots 0-2 reference a recently used offset, which is swapped with the most recent one.
+        recent_offsets.swap(offset_slot, 0);
+        recent_offsets[0]
+    } else {
+        let extra_bit_count = OFFSET_SLOT_EXTRA_BITS[offset_slot];
+        let mut formatted_offset = OFFSET_SLOT_BASES[offset_slot];
+
+        match &codes.aligned {
+            Some(aligned) if extra_bit_count >= ALIGNED_OFFSET_BITS => {
+                let verbatim_bits = reader.read_bits(extra_bit_count - ALIGNED_OFFSET_BITS);
+                formatted_offset += verbatim_bits << ALIGNED_OFFSET_BITS;
+                formatted_offset += reader.read_symbol(aligned)? as u32;
+            }
+            _ => formatted_offset += reader.read_bits(extra_bit_count),
+        }
+
+        let match_offset = formatted_offset as usize - OFFSET_ADJUSTMENT;
+        recent_offsets[2] = recent_offsets[1];
+        recent_offsets[1] = recent_offsets[0];
+        recent_offsets[0] = match_offset;
+        match_offset
+    };
+
+    if match_offset > output_offset || match_length > output.len() - output_offset {
+        return None;
+    }
+
+    // Copy byte by byte, because the source and destination ranges may overlap.
+    for i in output_offset..output_offset + match_length {
+        output[i] = output[i - match_offset];
+    }
+
+    Some(output_offset + match_length)
+}
+
+/// Reads the codeword lengths of a part of the main code or the length code.
+///
+/// The lengths are encoded through a pretree, and each length is stored as a delta to the previous length
+/// of the same symbol (which is why `lengths` must contain the lengths of the previous block).
+fn read_codeword_lengths(reader: &mut BitReader, lengths: &mut [u8]) -> Option<()> {
+    let mut pretree_lengths = [0u8; PRETREE_SYMBOL_COUNT];
+    for length in pretree_lengths.iter_mut() {
+        *length = reader.read_bits(4) as u8;
+    }
+    let pretree = HuffmanDecoder::new(&pretree_lengths)?;
+
+    let delta = |previous: u8, symbol: u16| -> Option<u8> {
+        if symbol > 16 {
+            return None;
+        }
+
+        Some(((previous as u16 + 17 - symbol) % 17) as u8)
+    };
+
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = reader.read_symbol(&pretree)?;
+
+        let (run_length, length) = match symbol {
+            0..=16 => (1, delta(lengths[i], symbol)?),
+            17 => (4 + reader.read_bits(4) as usize, 0),
+            18 => (20 + reader.read_bits(5) as usize, 0),
+            _ => {
+                let run_length = 4 + reader.read_bits(1) as usize;
+                let symbol = reader.read_symbol(&pretree)?;
+                (run_length, delta(lengths[i], symbol)?)
+            }
+        };
+
+        let end = usize::min(i + run_length, lengths.len());
+        lengths[i..end].fill(length);
+        i = end;
+    }
+
+    Some(())
+}
+
+/// Reverts the E8 translation, which the compressor applies to make the relative targets of x86 CALL
+/// instructions (opcode E8) absolute and therefore better compressible.
+fn undo_e8_translation(data: &mut [u8]) {
+    if data.len() <= 10 {
+        return;
+    }
+
+    let end = data.len() - 10;
+    let mut i = 0;
+
+    while i < end {
+        if data[i] != 0xe8 {
+            i += 1;
+            continue;
+        }
+
+        let position = i as i32;
+        let target = &mut data[i + 1..i + 5];
+        let absolute_offset = LittleEndian::read_i32(target);
+
+        if absolute_offset >= 0 {
+            if absolute_offset < E8_TRANSLATION_SIZE {
+                LittleEndian::write_i32(target, absolute_offset - position);
+            }
+        } else if absolute_offset >= -position {
+            LittleEndian::write_i32(target, absolute_offset + E8_TRANSLATION_SIZE);
+        }
+
+        i += 5;
+    }
+}
+
+/// Reader for a bitstream of 16-bit little-endian words, returning the most significant bits first.
+struct BitReader<'a> {
+    input: &'a [u8],
+    offset: usize,
+    bit_buffer: u64,
+    bits_left: u32,
+}
+
+impl<'a> BitReader<'a> {
+    /// Discards all buffered bits to continue at the next 16-bit word.
+    fn align(&mut self) {
+        self.bit_buffer = 0;
+        self.bits_left = 0;
+    }
+
+    /// Refills the bit buffer word by word until it holds at least `bit_count` (at most 32) bits.
+    ///
+    /// Compressors don't pad the input up to the last word the reader may prefetch, so any word past
+    /// the end of the input reads as zero.
+    fn ensure_bits(&mut self, bit_count: u32) {
+        while self.bits_left < bit_count {
+            let word = match self.input.get(self.offset..self.offset + 2) {
+                Some(word) => LittleEndian::read_u16(word),
+                None => 0,
+            };
+            self.offset += 2;
+
+            self.bit_buffer = (self.bit_buffer << 16) | word as u64;
+            self.bits_left += 16;
+        }
+    }
+
+    fn peek_bits(&self, bit_count: u32) -> u32 {
+        ((self.bit_buffer >> (self.bits_left - bit_count)) & ((1u64 << bit_count) - 1)) as u32
+    }
+
+    fn pop_bits(&mut self, bit_count: u32) -> u32 {
+        let bits = self.peek_bits(bit_count);
+        self.bits_left -= bit_count;
+        bits
+    }
+
+    fn read_bits(&mut self, bit_count: u32) -> u32 {
+        self.ensure_bits(bit_count);
+        self.pop_bits(bit_count)
+    }
+
+    /// Reads `length` bytes directly from the input, which must be aligned via [`BitReader::align`] before.
+    fn read_bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .input
+            .get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn read_symbol(&mut self, decoder: &HuffmanDecoder) -> Option<u16> {
+        self.ensure_bits(MAX_CODEWORD_LENGTH as u32);
+        let (symbol, length) = decoder.decode(self.peek_bits(MAX_CODEWORD_LENGTH as u32))?;
+        self.bits_left -= length;
+        Some(symbol)
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        self.read_bytes(4).map(LittleEndian::read_u32)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Writer for a bitstream of 16-bit little-endian words, writing the most significant bits first.
+    struct BitWriter {
+        output: Vec<u8>,
+        word: u16,
+        bit_count: u32,
+    }
+
+    impl BitWriter {
+        fn new() -> Self {
+            Self {
+                output: Vec::new(),
+                word: 0,
+                bit_count: 0,
+            }
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            if self.bit_count > 0 {
+                self.write_bits(0, 16 - self.bit_count);
+            }
+
+            self.output
+        }
+
+        fn write_bits(&mut self, value: u32, bit_count: u32) {
+            for i in (0..bit_count).rev() {
+                self.word = (self.word << 1) | ((value >> i) & 1) as u16;
+                self.bit_count += 1;
+
+                if self.bit_count == 16 {
+                    self.output.extend_from_slice(&self.word.to_le_bytes());
+                    self.word = 0;
+                    self.bit_count = 0;
+                }
+            }
+        }
+
+        fn write_symbol(&mut self, codes: &Codes, symbol: usize) {
+            let (code, length) = codes[symbol];
+            assert!(length > 0);
+            self.write_bits(code, length);
+        }
+    }
+
+    /// Codeword and codeword length of each symbol of a Huffman code.
+    type Codes = Vec<(u32, u32)>;
+
+    /// Assigns canonical Huffman codewords to the given codeword lengths.
+    fn canonical_codes(lengths: &[u8]) -> Codes {
+        let mut codes = vec![(0, 0); lengths.len()];
+        let mut code = 0u32;
+
+        for length in 1..=MAX_CODEWORD_LENGTH as u8 {
+            for (symbol, &symbol_length) in lengths.iter().enumerate() {
+                if symbol_length == length {
+                    codes[symbol] = (code, length as u32);
+                    code += 1;
+                }
+            }
+
+            code <<= 1;
+        }
+
+        codes
+    }
+
+    /// Writes `lengths` as deltas to `previous_lengths` through a pretree that assigns 5-bit codewords to all symbols.
+    /// Runs of zero lengths are written via the run-length symbols 17 and 18.
+    fn write_codeword_lengths(writer: &mut BitWriter, previous_lengths: &[u8], lengths: &[u8]) {
+        for _ in 0..PRETREE_SYMBOL_COUNT {
+            writer.write_bits(5, 4);
+        }
+
+        let mut i = 0;
+        while i < lengths.len() {
+            let zero_run = lengths[i..]
+                .iter()
+                .take_while(|&&length| length == 0)
+                .count();
+
+            if zero_run >= 20 {
+                let run_length = usize::min(zero_run, 51);
+                writer.write_bits(18, 5);
+                writer.write_bits((run_length - 20) as u32, 5);
+                i += run_length;
+            } else if zero_run >= 4 {
+                writer.write_bits(17, 5);
+                writer.write_bits((zero_run - 4) as u32, 4);
+                i += zero_run;
+            } else {
+                let symbol = (previous_lengths[i] + 17 - lengths[i]) % 17;
+                writer.write_bits(symbol as u32, 5);
+                i += 1;
+            }
+        }
+    }
+
+    /// Writes the block header and all codeword lengths of a verbatim or aligned offset block
+    /// and returns the main and length codes.
+    fn write_block_header(
+        writer: &mut BitWriter,
+        block_type: u32,
+        block_size: usize,
+        main_lengths: &mut [u8; MAIN_SYMBOL_COUNT],
+        length_lengths: &mut [u8; LENGTH_SYMBOL_COUNT],
+        new_main_lengths: &[(usize, u8)],
+        new_length_lengths: &[(usize, u8)],
+    ) -> (Codes, Codes) {
+        writer.write_bits(block_type, 3);
+        writer.write_bits(0, 1);
+        writer.write_bits(block_size as u32, 16);
+
+        if block_type == BLOCK_TYPE_ALIGNED {
+            for _ in 0..ALIGNED_SYMBOL_COUNT {
+                writer.write_bits(3, 3);
+            }
+        }
+
+        let previous_main_lengths = *main_lengths;
+        main_lengths.fill(0);
+        for &(symbol, length) in new_main_lengths {
+            main_lengths[symbol] = length;
+        }
+        write_codeword_lengths(
+            writer,
+            &previous_main_lengths[..LITERAL_COUNT],
+            &main_lengths[..LITERAL_COUNT],
+        );
+        write_codeword_lengths(
+            writer,
+            &previous_main_lengths[LITERAL_COUNT..],
+            &main_lengths[LITERAL_COUNT..],
+        );
+
+        let previous_length_lengths = *length_lengths;
+        length_lengths.fill(0);
+        for &(symbol, length) in new_length_lengths {
+            length_lengths[symbol] = length;
+        }
+        write_codeword_lengths(writer, &previous_length_lengths, length_lengths);
+
+        (
+            canonical_codes(main_lengths),
+            canonical_codes(length_lengths),
+        )
+    }
+
+    const fn match_symbol(offset_slot: usize, length_header: usize) -> usize {
+        LITERAL_COUNT + offset_slot * LENGTH_HEADER_COUNT + length_header
+    }
+
+    /// Returns a single LZX verbatim block with `length` bytes of "abc" repetitions.
+    pub(crate) fn compress_abc(length: usize) -> Vec<u8> {
+        assert!(length >= 3);
+
+        let mut writer = BitWriter::new();
+        let mut main_lengths = [0u8; MAIN_SYMBOL_COUNT];
+        let mut length_lengths = [0u8; LENGTH_SYMBOL_COUNT];
+
+        // Offset slot 4 has 1 extra bit and a base formatted offset of 4, which is a match offset of 3 with bit 1.
+        // Offset slot 0 repeats that offset.
+        let mut new_main_lengths = vec![(b'a' as usize, 5), (b'b' as usize, 5), (b'c' as usize, 5)];
+        for length_header in 0..LENGTH_HEADER_COUNT {
+            new_main_lengths.push((match_symbol(4, length_header), 5));
+            new_main_lengths.push((match_symbol(0, length_header), 5));
+        }
+        let new_length_lengths = (0..LENGTH_SYMBOL_COUNT)
+            .map(|symbol| (symbol, 8))
+            .collect::<Vec<_>>();
+
+        let (main_codes, length_codes) = write_block_header(
+            &mut writer,
+            BLOCK_TYPE_VERBATIM,
+            length,
+            &mut main_lengths,
+            &mut length_lengths,
+            &new_main_lengths,
+            &new_length_lengths,
+        );
+
+        for &literal in b"abc" {
+            writer.write_symbol(&main_codes, literal as usize);
+        }
+
+        let mut remaining = length - 3;
+        let mut offset_slot = 4;
+
+        while remaining > 0 {
+            if remaining == 1 {
+                writer.write_symbol(&main_codes, b'a' as usize);
+                break;
+            }
+
+            let match_length =
+                usize::min(remaining, MIN_MATCH_LENGTH + 7 + LENGTH_SYMBOL_COUNT - 1);
+            let length_header = usize::min(match_length - MIN_MATCH_LENGTH, 7);
+            writer.write_symbol(&main_codes, match_symbol(offset_slot, length_header));
+
+            if length_header == 7 {
+                writer.write_symbol(&length_codes, match_length - MIN_MATCH_LENGTH - 7);
+            }
+
+            if offset_slot == 4 {
+                writer.write_bits(1, 1);
+                offset_slot = 0;
+            }
+
+            remaining -= match_length;
+        }
+
+        writer.finish()
+    }
+
+    #[test]
+    fn test_lzx_verbatim_and_aligned_blocks() {
+        let mut writer = BitWriter::new();
+        let mut main_lengths = [0u8; MAIN_SYMBOL_COUNT];
+        let mut length_lengths = [0u8; LENGTH_SYMBOL_COUNT];
+
+        // Verbatim block.
+        // Offset slot 5 has 1 extra bit and a base formatted offset of 6, which is a match offset of 4.
+        let explicit_match = match_symbol(5, 6);
+        let recent_match = match_symbol(1, 1);
+        let long_recent_match = match_symbol(1, 7);
+        let (main_codes, length_codes) = write_block_header(
+            &mut writer,
+            BLOCK_TYPE_VERBATIM,
+            31,
+            &mut main_lengths,
+            &mut length_lengths,
+            &[
+                (b'a' as usize, 3),
+                (b'b' as usize, 3),
+                (b'c' as usize, 3),
+                (b'd' as usize, 3),
+                (b'x' as usize, 3),
+                (b'y' as usize, 3),
+                (explicit_match, 3),
+                (recent_match, 4),
+                (long_recent_match, 4),
+            ],
+            &[(5, 1)],
+        );
+
+        for &literal in b"abcd" {
+            writer.write_symbol(&main_codes, literal as usize);
+        }
+
+        // "abcd" repeated at offset 4 with length 8.
+        writer.write_symbol(&main_codes, explicit_match);
+        writer.write_bits(0, 1);
+
+        // "x" repeated at the second most recent offset (the initial one) with length 3.
+        writer.write_symbol(&main_codes, b'x' as usize);
+        writer.write_symbol(&main_codes, recent_match);
+
+        // "xxxy" repeated at the second most recent offset (4 again) with length 7 + 5 + 2.
+        writer.write_symbol(&main_codes, b'y' as usize);
+        writer.write_symbol(&main_codes, long_recent_match);
+        writer.write_symbol(&length_codes, 5);
+
+        // Aligned offset block, whose codeword lengths are deltas to the ones of the verbatim block.
+        // Offset slot 8 has 3 extra bits and a base formatted offset of 16, which are all encoded by the aligned code.
+        // Offset slot 10 has 4 extra bits and a base formatted offset of 32, with 1 verbatim bit.
+        // Offset slot 5 has less than 3 extra bits, which are still read verbatim.
+        let aligned_match = match_symbol(8, 2);
+        let verbatim_and_aligned_match = match_symbol(10, 0);
+        let (main_codes, _) = write_block_header(
+            &mut writer,
+            BLOCK_TYPE_ALIGNED,
+            17,
+            &mut main_lengths,
+            &mut length_lengths,
+            &[
+                (b'z' as usize, 2),
+                (aligned_match, 2),
+                (verbatim_and_aligned_match, 2),
+                (explicit_match, 2),
+            ],
+            &[],
+        );
+
+        writer.write_symbol(&main_codes, b'z' as usize);
+
+        // Match offset 16 + 2 - 2 with length 4.
+        writer.write_symbol(&main_codes, aligned_match);
+        writer.write_bits(2, 3);
+
+        // Match offset 32 + 3 - 2 with length 2.
+        writer.write_symbol(&main_codes, verbatim_and_aligned_match);
+        writer.write_bits(0, 1);
+        writer.write_bits(3, 3);
+
+        writer.write_symbol(&main_codes, b'z' as usize);
+        writer.write_symbol(&main_codes, b'z' as usize);
+
+        // Match offset 4 with length 8.
+        writer.write_symbol(&main_codes, explicit_match);
+        writer.write_bits(0, 1);
+
+        let input = writer.finish();
+        let mut output = [0u8; 48];
+        decompress(&input, &mut output, NtfsPosition::none()).unwrap();
+        assert_eq!(&output, b"abcdabcdabcdxxxxyxxxyxxxyxxxyxxzyxxxdazzdazzdazz");
+
+        // A match offset beyond the start of the output is invalid.
+        let mut output = [0u8; 3];
+        let mut writer = BitWriter::new();
+        let mut main_lengths = [0u8; MAIN_SYMBOL_COUNT];
+        let mut length_lengths = [0u8; LENGTH_SYMBOL_COUNT];
+        let (main_codes, _) = write_block_header(
+            &mut writer,
+            BLOCK_TYPE_VERBATIM,
+            3,
+            &mut main_lengths,
+            &mut length_lengths,
+            &[(b'a' as usize, 1), (explicit_match, 1)],
+            &[],
+        );
+        writer.write_symbol(&main_codes, b'a' as usize);
+        writer.write_symbol(&main_codes, explicit_match);
+        writer.write_bits(0, 1);
+
+        let input = writer.finish();
+        assert!(matches!(
+            decompress(&input, &mut output, NtfsPosition::none()),
+            Err(NtfsError::InvalidCompressedData { .. })
+        ));
+    }
+
+    #[test]
+    fn test_lzx_uncompressed_block() {
+        // Uncompressed block (type 3) of 5 bytes: block type bits 011, default size bit 0, size 5 in 16 bits.
+        // Header bits: 011 0 0000000000000101, padded to 32 bits, followed by the recent offsets.
+        let header: u32 = 0b0110_0000_0000_0000_0101_0000_0000_0000;
+        let mut input = alloc::vec::Vec::new();
+        input.extend_from_slice(&((header >> 16) as u16).to_le_bytes());
+        input.extend_from_slice(&(header as u16).to_le_bytes());
+        for _ in 0..RECENT_OFFSET_COUNT {
+            input.extend_from_slice(&1u32.to_le_bytes());
+        }
+        input.extend_from_slice(b"hello\0");
+
+        let mut output = [0u8; 5];
+        decompress(&input, &mut output, NtfsPosition::none()).unwrap();
+        assert_eq!(&output, b"hello");
+    }
+
+    #[test]
+    fn test_e8_translation() {
+        // A CALL at position 1 with the absolute target 0x20 becomes a relative target of 0x1f.
+        let mut data = [0u8; 16];
+        data[1] = 0xe8;
+        data[2] = 0x20;
+        undo_e8_translation(&mut data);
+        assert_eq!(LittleEndian::read_i32(&data[2..]), 0x1f);
+    }
+
+    #[test]
+    fn test_lzx_long_matches() {
+        for length in [3, 4, 100, 32768] {
+            let input = compress_abc(length);
+            let mut output = vec![0u8; length];
+            decompress(&input, &mut output, NtfsPosition::none()).unwrap();
+
+            let expected = b"abc"
+                .iter()
+                .cycle()
+                .take(length)
+                .copied()
+                .collect::<Vec<_>>();
+            assert_eq!(output, expected);
+        }
+    }
+}
@@ -0,0 +1,13 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Decompressors for the compression algorithms used by NTFS.
+//!
+//! All decompressors work on in-memory buffers.
+//! Reading the compressed data from the filesystem and splitting it up into independently
+//! compressed units is the job of the respective attribute value reader.
+
+mod huffman;
+pub(crate) mod lznt1;
+pub(crate) mod lzx;
+pub(crate) mod xpress_huffman;
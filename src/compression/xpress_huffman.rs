@@ -0,0 +1,247 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Decompressor for the XPRESS Huffman algorithm, which the Windows Overlay Filter (WOF) uses for
+//! its XPRESS4K, XPRESS8K, and XPRESS16K compression formats.
+//!
+//! The compressed data is a sequence of blocks, each representing up to 65536 bytes of uncompressed data.
+//! Every block starts with a 256-byte table of 4-bit codeword lengths for the 512 symbols of a canonical
+//! Huffman code, followed by a bitstream of 16-bit little-endian words.
+//! Symbols 0-255 are literal bytes, symbols 256-511 encode a match length and the number of offset bits.
+//! Long match lengths are continued in extra bytes that are interleaved with the bitstream words.
+//!
+//! Reference: [MS-XCA] 2.2 "LZ77+Huffman Decompression Algorithm Details"
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::huffman::HuffmanDecoder;
+use crate::error::{NtfsError, Result};
+use crate::types::NtfsPosition;
+
+/// Number of uncompressed bytes represented by a single block.
+const BLOCK_SIZE: usize = 65536;
+
+/// Number of symbols of the Huffman code.
+const SYMBOL_COUNT: usize = 512;
+
+/// Size of the codeword length table at the beginning of each block, in bytes.
+const TABLE_SIZE: usize = SYMBOL_COUNT / 2;
+
+/// Minimum length of a match, in bytes.
+const MIN_MATCH_LENGTH: usize = 3;
+
+/// Decompresses the XPRESS Huffman-compressed `input` into `output`, filling `output` entirely.
+///
+/// `position` is the absolute position of `input` within the filesystem and only used for error reporting.
+pub(crate) fn decompress(input: &[u8], output: &mut [u8], position: NtfsPosition) -> Result<()> {
+    decompress_blocks(input, output).ok_or(NtfsError::InvalidCompressedData { position })
+}
+
+fn decompress_blocks(input: &[u8], output: &mut [u8]) -> Option<()> {
+    let mut reader = BitReader {
+        input,
+        offset: 0,
+        next_bits: 0,
+        extra_bits: 0,
+    };
+    let mut output_offset = 0;
+
+    while output_offset < output.len() {
+        // Every block starts with the codeword lengths of all symbols, two 4-bit lengths per byte.
+        let table = input.get(reader.offset..reader.offset + TABLE_SIZE)?;
+        let mut lengths = [0u8; SYMBOL_COUNT];
+        for (i, byte) in table.iter().enumerate() {
+            lengths[2 * i] = byte & 0x0f;
+            lengths[2 * i + 1] = byte >> 4;
+        }
+        let decoder = HuffmanDecoder::new(&lengths)?;
+
+        reader.offset += TABLE_SIZE;
+        reader.start();
+
+        let block_end = usize::min(output_offset + BLOCK_SIZE, output.len());
+
+        while output_offset < block_end {
+            let (symbol, length) = decoder.decode(reader.next_bits >> 16)?;
+            reader.consume(length);
+
+            if symbol < 256 {
+                output[output_offset] = symbol as u8;
+                output_offset += 1;
+                continue;
+            }
+
+            // The lower 4 bits of the match symbol contain the length, the upper 4 bits the number of offset bits.
+            let symbol = symbol as usize - 256;
+            let mut match_length = symbol & 0x0f;
+            let offset_bit_count = (symbol >> 4) as u32;
+
+            if match_length == 15 {
+                match_length = reader.read_byte()? as usize;
+
+                if match_length == 255 {
+                    match_length = reader.read_u16()? as usize;
+                    match_length = match_length.checked_sub(15)?;
+                }
+
+                match_length += 15;
+            }
+
+            match_length += MIN_MATCH_LENGTH;
+
+            let match_offset = if offset_bit_count > 0 {
+                let offset_bits = reader.next_bits >> (32 - offset_bit_count);
+                reader.consume(offset_bit_count);
+                (offset_bits as usize) | (1 << offset_bit_count)
+            } else {
+                1
+            };
+
+            if match_offset > output_offset || match_length > output.len() - output_offset {
+                return None;
+            }
+
+            // Copy byte by byte, because the source and destination ranges may overlap.
+            for _ in 0..match_length {
+                output[output_offset] = output[output_offset - match_offset];
+                output_offset += 1;
+            }
+        }
+    }
+
+    Some(())
+}
+
+/// Bitstream reader following the [MS-XCA] pseudocode.
+///
+/// `next_bits` contains the next `16 + extra_bits` bits of the bitstream, aligned to the most significant bit.
+/// Bytes for long match lengths are read from the same input offset as the bitstream words.
+struct BitReader<'a> {
+    input: &'a [u8],
+    offset: usize,
+    next_bits: u32,
+    extra_bits: i32,
+}
+
+impl<'a> BitReader<'a> {
+    /// Initializes the bitstream state at the current input offset.
+    fn start(&mut self) {
+        self.next_bits = (self.read_word() as u32) << 16;
+        self.next_bits |= self.read_word() as u32;
+        self.extra_bits = 16;
+    }
+
+    /// Removes the next `bit_count` (at most 16) bits from the bitstream.
+    fn consume(&mut self, bit_count: u32) {
+        self.next_bits <<= bit_count;
+        self.extra_bits -= bit_count as i32;
+
+        if self.extra_bits < 0 {
+            self.next_bits |= (self.read_word() as u32) << (-self.extra_bits);
+            self.extra_bits += 16;
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = *self.input.get(self.offset)?;
+        self.offset += 1;
+        Some(byte)
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        let value = LittleEndian::read_u16(self.input.get(self.offset..self.offset + 2)?);
+        self.offset += 2;
+        Some(value)
+    }
+
+    /// Reads the next bitstream word.
+    ///
+    /// Compressors don't pad the input up to the last word the reader may prefetch, so any word past
+    /// the end of the input reads as zero.
+    fn read_word(&mut self) -> u16 {
+        self.read_u16().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Returns a single XPRESS Huffman block with `length` bytes of "abc" repetitions,
+    /// encoded as 3 literals followed by a single match that uses the extended match length bytes.
+    pub(crate) fn compress_abc(length: usize) -> Vec<u8> {
+        let match_length = length - 3 - MIN_MATCH_LENGTH;
+        assert!(match_length >= 15);
+
+        // 'a' = 00, 'b' = 01, 'c' = 10, and the match symbol with length 15 and 1 offset bit
+        // (256 + 0x10 + 15 = 287) = 11.
+        let mut output = vec![0u8; TABLE_SIZE];
+        output[b'a' as usize / 2] |= 2 << 4;
+        output[b'b' as usize / 2] |= 2;
+        output[b'c' as usize / 2] |= 2 << 4;
+        output[287 / 2] |= 2 << 4;
+
+        // Bitstream: 00 01 10 11 + offset bit 1 for offset 3, padded with zeros.
+        let bits: u16 = 0b0001_1011_1000_0000;
+        output.extend_from_slice(&bits.to_le_bytes());
+        output.extend_from_slice(&[0, 0]);
+
+        // The match length continues in the bytes following the words read so far.
+        if match_length - 15 < 255 {
+            output.push((match_length - 15) as u8);
+        } else {
+            output.push(255);
+            output.extend_from_slice(&(match_length as u16).to_le_bytes());
+        }
+
+        output
+    }
+
+    #[test]
+    fn test_xpress_huffman() {
+        // Build a Huffman code where the symbols 'a', 'b', 'c', and the match symbol for
+        // "length 9 (6 + 3), offset 3 (1 offset bit)" (256 + 0x10 + 6 = 278) all have 2-bit codewords:
+        // 'a' = 00, 'b' = 01, 'c' = 10, 278 = 11
+        let mut input = vec![0u8; TABLE_SIZE];
+        input[b'a' as usize / 2] |= 2 << 4;
+        input[b'b' as usize / 2] |= 2;
+        input[b'c' as usize / 2] |= 2 << 4;
+        input[278 / 2] |= 2;
+
+        // Bitstream: 00 01 10 11 + offset bit 1, padded with zeros.
+        // Words are stored little-endian.
+        let bits: u16 = 0b0001_1011_1000_0000;
+        input.extend_from_slice(&bits.to_le_bytes());
+        input.extend_from_slice(&[0, 0]);
+
+        let mut output = [0u8; 12];
+        decompress(&input, &mut output, NtfsPosition::none()).unwrap();
+        assert_eq!(&output, b"abcabcabcabc");
+
+        // A match before any literal is invalid.
+        let mut output = [0u8; 12];
+        input[TABLE_SIZE] = 0;
+        input[TABLE_SIZE + 1] = 0b1100_0000;
+        assert!(decompress(&input, &mut output, NtfsPosition::none()).is_err());
+    }
+
+    #[test]
+    fn test_xpress_huffman_long_matches() {
+        for length in [21, 100, 275, 276, 65536] {
+            let input = compress_abc(length);
+            let mut output = vec![0u8; length];
+            decompress(&input, &mut output, NtfsPosition::none()).unwrap();
+
+            let expected = b"abc"
+                .iter()
+                .cycle()
+                .take(length)
+                .copied()
+                .collect::<Vec<_>>();
+            assert_eq!(output, expected);
+        }
+    }
+}
@@ -3,9 +3,12 @@
 
 use core::ops::Range;
 
+use alloc::string::String;
+use alloc::vec::Vec;
 use displaydoc::Display;
 
 use crate::attribute::NtfsAttributeType;
+use crate::guid::NtfsGuid;
 use crate::types::NtfsPosition;
 use crate::types::{Lcn, Vcn};
 
@@ -29,6 +32,14 @@ pub enum NtfsError {
     },
     /// The given buffer should have at least {expected} bytes, but it only has {actual} bytes
     BufferTooSmall { expected: usize, actual: usize },
+    /// The Extended Boot Record at byte position {position:#x} has already been visited, so the chain of logical partitions contains a loop
+    ExtendedBootRecordLoop { position: NtfsPosition },
+    /// The {algorithm} hash of the image data should be {expected}, but it is {actual}
+    ImageHashMismatch {
+        algorithm: &'static str,
+        expected: String,
+        actual: String,
+    },
     /// The NTFS Attribute at byte position {position:#x} has a length of {expected} bytes, but only {actual} bytes are left in the record
     InvalidAttributeLength {
         position: NtfsPosition,
@@ -58,6 +69,23 @@ pub enum NtfsError {
         position: NtfsPosition,
         cluster_count: u64,
     },
+    /// The compressed data at byte position {position:#x} is corrupted
+    InvalidCompressedData { position: NtfsPosition },
+    /// The EWF chunk {chunk} is corrupted
+    InvalidEwfChunk { chunk: u64 },
+    /// The EWF section at byte position {position:#x} of segment file {segment} is invalid
+    InvalidEwfSection {
+        segment: u16,
+        position: NtfsPosition,
+    },
+    /// The EWF segment file {expected} has segment number {actual} in its header
+    InvalidEwfSegmentNumber { expected: u16, actual: u16 },
+    /// The EWF segment file {segment} should have signature {expected:?}, but it has signature {actual:?}
+    InvalidEwfSignature {
+        segment: u16,
+        expected: &'static [u8],
+        actual: [u8; 8],
+    },
     /// The NTFS File Record at byte position {position:#x} indicates an allocated size of {expected} bytes, but the record only has a size of {actual} bytes
     InvalidFileAllocatedSize {
         position: NtfsPosition,
@@ -78,6 +106,25 @@ pub enum NtfsError {
         expected: u32,
         actual: u32,
     },
+    /// The GPT {structure} at byte position {position:#x} should have CRC32 checksum {expected:#010x}, but it has checksum {actual:#010x}
+    InvalidGptChecksum {
+        structure: &'static str,
+        position: NtfsPosition,
+        expected: u32,
+        actual: u32,
+    },
+    /// The GPT header at byte position {position:#x} specifies {count} partition entries of {size} bytes each, which is invalid
+    InvalidGptPartitionEntries {
+        position: NtfsPosition,
+        count: u32,
+        size: u32,
+    },
+    /// The GPT header at byte position {position:#x} should have signature {expected:?}, but it has signature {actual:?}
+    InvalidGptSignature {
+        position: NtfsPosition,
+        expected: &'static [u8],
+        actual: [u8; 8],
+    },
     /// The NTFS Index Record at byte position {position:#x} indicates an allocated size of {expected} bytes, but the record only has a size of {actual} bytes
     InvalidIndexAllocatedSize {
         position: NtfsPosition,
@@ -120,14 +167,68 @@ pub enum NtfsError {
         expected: u32,
         actual: u32,
     },
+    /// The $LogFile page at byte position {position:#x} should have signature {expected:?}, but it has signature {actual:?}
+    InvalidLogFileSignature {
+        position: NtfsPosition,
+        expected: &'static [u8],
+        actual: [u8; 4],
+    },
+    /// The $LogFile restart page at byte position {position:#x} specifies a system page size of {system_page_size} bytes and a log page size of {log_page_size} bytes, which is invalid
+    InvalidLogPageSize {
+        position: NtfsPosition,
+        system_page_size: u32,
+        log_page_size: u32,
+    },
+    /// The $LogFile record at byte position {position:#x} indicates a client data length of {length} bytes, which exceeds the size of the $LogFile
+    InvalidLogRecordLength { position: NtfsPosition, length: u32 },
+    /// The $LogFile record at byte position {position:#x} should have Log Sequence Number (LSN) {expected:#x}, but it has LSN {actual:#x}
+    InvalidLogRecordLsn {
+        position: NtfsPosition,
+        expected: u64,
+        actual: u64,
+    },
+    /// The $LogFile record at byte position {position:#x} references client data in the range {range:?}, but the client data only has a size of {size} bytes
+    InvalidLogRecordRange {
+        position: NtfsPosition,
+        range: Range<usize>,
+        size: usize,
+    },
+    /// The $LogFile restart page at byte position {position:#x} references data in the range {range:?}, but the page only has a size of {size} bytes
+    InvalidLogRestartAreaRange {
+        position: NtfsPosition,
+        range: Range<usize>,
+        size: usize,
+    },
+    /// The $LogFile restart page at byte position {position:#x} reserves {sequence_number_bits} bits of each Log Sequence Number (LSN) for a sequence number, which is invalid
+    InvalidLogSequenceNumberBits {
+        position: NtfsPosition,
+        sequence_number_bits: u32,
+    },
+    /// The Log Sequence Number (LSN) {lsn:#x} does not point into a log record page of the $LogFile
+    InvalidLsn { lsn: u64 },
+    /// The $BITMAP attribute of the MFT at byte position {position:#x} has a size of {actual} bytes, but its {file_record_count} File Records need at most {expected} bytes
+    InvalidMftBitmapSize {
+        position: NtfsPosition,
+        file_record_count: u64,
+        expected: u64,
+        actual: u64,
+    },
     /// The MFT LCN in the BIOS Parameter Block of the NTFS filesystem is invalid.
     InvalidMftLcn,
+    /// The MFT mirror LCN in the BIOS Parameter Block of the NTFS filesystem is invalid.
+    InvalidMftMirrorLcn,
     /// The NTFS Non Resident Value Data at byte position {position:#x} references a data field in the range {range:?}, but the entry only has a size of {size} bytes
     InvalidNonResidentValueDataRange {
         position: NtfsPosition,
         range: Range<usize>,
         size: usize,
     },
+    /// The NTFS Reparse Point at byte position {position:#x} references a name in the range {range:?}, but the path buffer only has a size of {size} bytes
+    InvalidReparsePointNameRange {
+        position: NtfsPosition,
+        range: Range<usize>,
+        size: usize,
+    },
     /// The resident NTFS Attribute at byte position {position:#x} indicates a value length of {length} starting at offset {offset}, but the attribute only has a size of {actual} bytes
     InvalidResidentAttributeValueLength {
         position: NtfsPosition,
@@ -145,6 +246,12 @@ pub enum NtfsError {
     InvalidRecordSizeInfo { size_info: i8, cluster_size: u32 },
     /// The sectors per cluster field in the BIOS Parameter Block denotes {sectors_per_cluster:#04x}, which is invalid
     InvalidSectorsPerCluster { sectors_per_cluster: u8 },
+    /// The security descriptor at byte position {position:#x} references a field in the range {range:?}, but it only has a size of {size} bytes
+    InvalidSecurityDescriptorRange {
+        position: NtfsPosition,
+        range: Range<usize>,
+        size: usize,
+    },
     /// The NTFS structured value at byte position {position:#x} of type {ty:?} has {actual} bytes where {expected} bytes were expected
     InvalidStructuredValueSize {
         position: NtfsPosition,
@@ -173,20 +280,57 @@ pub enum NtfsError {
         range: Range<usize>,
         size: usize,
     },
+    /// The Update Sequence Number (USN) {usn:#x} is not aligned to 8 bytes
+    InvalidUsn { usn: u64 },
+    /// The USN record at byte position {position:#x} has an invalid length of {length} bytes
+    InvalidUsnRecordLength { position: NtfsPosition, length: u32 },
+    /// The USN record at byte position {position:#x} references data in the range {range:?}, but the record only has a size of {size} bytes
+    InvalidUsnRecordRange {
+        position: NtfsPosition,
+        range: Range<usize>,
+        size: usize,
+    },
     /// The VCN {vcn} read from the NTFS Data Run header at byte position {position:#x} cannot be added to the LCN {previous_lcn} calculated from previous data runs
     InvalidVcnInDataRunHeader {
         position: NtfsPosition,
         vcn: Vcn,
         previous_lcn: Lcn,
     },
+    /// The virtual disk structure at byte position {position:#x} should have signature {expected:?}, but it has signature {actual:?}
+    InvalidVirtualDiskSignature {
+        position: NtfsPosition,
+        expected: &'static [u8],
+        actual: [u8; 8],
+    },
+    /// The {structure} of the virtual disk at byte position {position:#x} is invalid
+    InvalidVirtualDiskStructure {
+        structure: &'static str,
+        position: NtfsPosition,
+    },
     /// I/O error: {0:?}
     Io(binrw::io::Error),
     /// The Logical Cluster Number (LCN) {lcn} is too big to be multiplied by the cluster size
     LcnTooBig { lcn: Lcn },
+    /// The EWF image has no "{ty}" section
+    MissingEwfSection { ty: &'static str },
+    /// The image contains no stored hash to verify its data
+    MissingImageHash,
     /// The index root at byte position {position:#x} is a large index, but no matching index allocation attribute was provided
     MissingIndexAllocation { position: NtfsPosition },
+    /// The differencing virtual disk has no parent, but data needs to be read from it
+    MissingVirtualDiskParent,
     /// The NTFS file at byte position {position:#x} is not a directory
     NotADirectory { position: NtfsPosition },
+    /// The path component "{path}" is not a directory
+    PathComponentNotADirectory { path: String },
+    /// The path component "{path}" does not exist
+    PathComponentNotFound { path: String },
+    /// The security descriptor at byte position {position:#x} should have Security ID {expected}, but it has Security ID {actual}
+    SecurityIdMismatch {
+        position: NtfsPosition,
+        expected: u32,
+        actual: u32,
+    },
     /// The total sector count is too big to be multiplied by the sector size
     TotalSectorsTooBig { total_sectors: u64 },
     /// The NTFS Attribute at byte position {position:#x} should not belong to an Attribute List, but it does
@@ -195,14 +339,59 @@ pub enum NtfsError {
     UnexpectedNonResidentAttribute { position: NtfsPosition },
     /// The NTFS Attribute at byte position {position:#x} should be non-resident, but it is resident
     UnexpectedResidentAttribute { position: NtfsPosition },
+    /// A parent was given for a virtual disk that is not a differencing disk
+    UnexpectedVirtualDiskParent,
+    /// The ACE at byte position {position:#x} has type {actual:#04x}, which is not supported
+    UnsupportedAceType { position: NtfsPosition, actual: u8 },
     /// The type of the NTFS Attribute at byte position {position:#x} is {actual:#010x}, which is not supported
     UnsupportedAttributeType { position: NtfsPosition, actual: u32 },
     /// The cluster size is {actual} bytes, but it needs to be between {min} and {max}
     UnsupportedClusterSize { min: u32, max: u32, actual: u32 },
+    /// The NTFS Attribute at byte position {position:#x} has a compression unit of 2^{exponent} clusters, which is not supported
+    UnsupportedCompressionUnitExponent {
+        position: NtfsPosition,
+        exponent: u8,
+    },
     /// The namespace of the NTFS file name starting at byte position {position:#x} is {actual}, which is not supported
     UnsupportedFileNamespace { position: NtfsPosition, actual: u8 },
+    /// The $LogFile restart page at byte position {position:#x} has version {major}.{minor}, which is not supported
+    UnsupportedLogFileVersion {
+        position: NtfsPosition,
+        major: i16,
+        minor: i16,
+    },
+    /// The $LogFile record at byte position {position:#x} has operation code {actual:#06x}, which is not supported
+    UnsupportedLogOperation { position: NtfsPosition, actual: u16 },
+    /// The $LogFile record at byte position {position:#x} has record type {actual}, which is not supported
+    UnsupportedLogRecordType { position: NtfsPosition, actual: u32 },
     /// The sector size is {actual} bytes, but it needs to be between {min} and {max}
     UnsupportedSectorSize { min: u16, max: u16, actual: u16 },
+    /// The USN record at byte position {position:#x} has the unsupported major version {actual}
+    UnsupportedUsnRecordVersion { position: NtfsPosition, actual: u16 },
+    /// The VHDX image contains the required item {guid}, which is not supported
+    UnsupportedVhdxItem { guid: NtfsGuid },
+    /// The VHDX image has a log that needs to be replayed, which is not supported
+    UnsupportedVhdxLog,
+    /// The VHD image has disk type {actual}, which is not supported
+    UnsupportedVirtualDiskType { actual: u32 },
+    /// The Windows Overlay Filter reparse point at byte position {position:#x} specifies compression algorithm {actual}, which is not supported
+    UnsupportedWofAlgorithm { position: NtfsPosition, actual: u32 },
+    /// The Windows Overlay Filter reparse point at byte position {position:#x} specifies provider {actual}, which is not supported
+    UnsupportedWofProvider { position: NtfsPosition, actual: u32 },
+    /// The non-resident attribute value with Data Run information at byte position {position:#x} is compressed and cannot be written in place
+    UnsupportedWriteToCompressedValue { position: NtfsPosition },
+    /// The non-resident attribute value with Data Run information at byte position {position:#x} is encrypted and cannot be written in place
+    UnsupportedWriteToEncryptedValue { position: NtfsPosition },
+    /// The non-resident attribute value with Data Run information at byte position {position:#x} does not belong to a $DATA attribute and cannot be written in place
+    UnsupportedWriteToNonDataAttribute { position: NtfsPosition },
+    /// Offset {offset:#x} of the non-resident attribute value with Data Run information at byte position {position:#x} lies in a sparse Data Run and cannot be written in place
+    UnsupportedWriteToSparseDataRun { position: NtfsPosition, offset: u64 },
+    /// Offset {offset:#x} of the non-resident attribute value with Data Run information at byte position {position:#x} is beyond its initialized size of {initialized_size} bytes and cannot be written in place
+    UnsupportedWriteToUninitializedData {
+        position: NtfsPosition,
+        offset: u64,
+        initialized_size: u64,
+    },
     /// The Update Sequence Array (USA) of the record at byte position {position:#x} has entries for {array_count} blocks of 512 bytes, but the record is only {record_size} bytes long
     UpdateSequenceArrayExceedsRecordSize {
         position: NtfsPosition,
@@ -225,15 +414,25 @@ pub enum NtfsError {
     VcnOutOfBoundsInIndexAllocation { position: NtfsPosition, vcn: Vcn },
     /// The Virtual Cluster Number (VCN) {vcn} is too big to be multiplied by the cluster size
     VcnTooBig { vcn: Vcn },
+    /// The parent chain of the differencing virtual disk contains "{path}" more than once
+    VirtualDiskParentLoop { path: String },
+    /// The differencing virtual disk should have a parent with ID {expected}, but the given parent has ID {actual}
+    VirtualDiskParentMismatch {
+        expected: NtfsGuid,
+        actual: NtfsGuid,
+    },
+    /// None of the parent locations {locations:?} of the differencing virtual disk exists
+    VirtualDiskParentNotFound { locations: Vec<String> },
 }
 
 impl From<binrw::error::Error> for NtfsError {
     fn from(error: binrw::error::Error) -> Self {
-        if let binrw::error::Error::Io(io_error) = error {
-            Self::Io(io_error)
-        } else {
+        match error {
+            binrw::error::Error::Io(io_error) => Self::Io(io_error),
+            // I/O errors when reading a field of a structure are wrapped into a backtrace.
+            binrw::error::Error::Backtrace(backtrace) => Self::from(*backtrace.error),
             // We don't use any binrw attributes that result in other errors.
-            unreachable!("Got a binrw error of unexpected type: {:?}", error);
+            error => unreachable!("Got a binrw error of unexpected type: {:?}", error),
         }
     }
 }
@@ -259,3 +458,50 @@ impl From<NtfsError> for binrw::io::Error {
 #[cfg(feature = "std")]
 #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 impl std::error::Error for NtfsError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::{Read, Seek, SeekFrom};
+
+    use super::*;
+    use crate::ntfs::Ntfs;
+
+    /// Reader that fails with an I/O error once the given number of bytes has been read.
+    struct FailingReader {
+        inner: io::Cursor<Vec<u8>>,
+        fail_at: u64,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let remaining = self.fail_at.saturating_sub(self.inner.position());
+            if remaining == 0 {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "device removed"));
+            }
+
+            let bytes_to_read = usize::min(buf.len(), remaining as usize);
+            self.inner.read(&mut buf[..bytes_to_read])
+        }
+    }
+
+    impl Seek for FailingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_io_error_in_structure() {
+        // Fail in the middle of the BIOS Parameter Block, a field of the boot sector structure.
+        let mut fs = FailingReader {
+            inner: crate::helpers::tests::testfs1(),
+            fail_at: 20,
+        };
+
+        match Ntfs::new(&mut fs) {
+            Err(NtfsError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::BrokenPipe),
+            other => panic!("Expected an I/O error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
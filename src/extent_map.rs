@@ -0,0 +1,173 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek, SeekFrom, Write};
+
+use crate::attribute::NtfsAttribute;
+use crate::attribute_value::{NtfsAttributeValue, NtfsDataRun};
+use crate::error::{NtfsError, Result};
+use crate::types::NtfsPosition;
+
+/// A contiguous range of a non-resident attribute value on the filesystem.
+#[derive(Clone, Debug)]
+struct Extent {
+    /// Offset of this extent within the attribute value, in bytes.
+    offset: u64,
+    /// Absolute position of this extent on the filesystem, in bytes.
+    /// This is `None` for a sparse Data Run.
+    position: NtfsPosition,
+    /// Length of this extent, in bytes.
+    length: u64,
+}
+
+/// Decoded data runs of a non-resident attribute value, including all connected attributes of an Attribute List.
+///
+/// This translates offsets within the attribute value into filesystem positions via a binary search,
+/// without any further I/O.
+/// It is used where an attribute value is accessed so often that walking its data runs every time would be too slow,
+/// like for the Master File Table (MFT) itself.
+///
+/// Compressed attribute values cannot be read through an [`ExtentMap`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExtentMap {
+    extents: Vec<Extent>,
+}
+
+impl ExtentMap {
+    /// Collects the data runs of the given non-resident attribute.
+    ///
+    /// If `attribute` is part of an Attribute List, the data runs of all connected attributes are collected.
+    pub(crate) fn from_attribute<T>(fs: &mut T, attribute: &NtfsAttribute) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let value = attribute.value(fs)?;
+        Self::from_value(fs, value, attribute.position())
+    }
+
+    /// Collects the data runs of the given non-resident attribute value.
+    ///
+    /// `position` is the position of the attribute, which is only used for errors.
+    pub(crate) fn from_value<T>(
+        fs: &mut T,
+        value: NtfsAttributeValue,
+        position: NtfsPosition,
+    ) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let mut extent_map = Self::default();
+
+        match value {
+            NtfsAttributeValue::NonResident(value) => {
+                for data_run in value.data_runs() {
+                    extent_map.push_data_run(&data_run?);
+                }
+            }
+            NtfsAttributeValue::AttributeListNonResident(value) => {
+                value.for_each_data_run(fs, |data_run| extent_map.push_data_run(data_run))?;
+            }
+            NtfsAttributeValue::Resident(_) => {
+                return Err(NtfsError::UnexpectedResidentAttribute { position });
+            }
+        }
+
+        Ok(extent_map)
+    }
+
+    /// Returns the total allocated size of all extents, in bytes.
+    pub(crate) fn len(&self) -> u64 {
+        self.extents
+            .last()
+            .map_or(0, |extent| extent.offset + extent.length)
+    }
+
+    /// Returns the absolute filesystem position of the given offset along with the number of bytes
+    /// that can be read contiguously from there.
+    /// The position is `None` if the offset lies in a sparse Data Run.
+    ///
+    /// Returns `None` if the offset is beyond the end of the allocated extents.
+    pub(crate) fn lookup(&self, offset: u64) -> Option<(NtfsPosition, u64)> {
+        let index = self
+            .extents
+            .partition_point(|extent| extent.offset + extent.length <= offset);
+        let extent = self.extents.get(index)?;
+
+        let offset_in_extent = offset - extent.offset;
+        let position = extent.position + offset_in_extent;
+        let remaining = extent.length - offset_in_extent;
+
+        Some((position, remaining))
+    }
+
+    pub(crate) fn push_data_run(&mut self, data_run: &NtfsDataRun) {
+        let length = data_run.allocated_size();
+        if length == 0 {
+            return;
+        }
+
+        self.extents.push(Extent {
+            offset: self.len(),
+            position: data_run.data_position(),
+            length,
+        });
+    }
+
+    /// Reads `buf.len()` bytes starting at the given offset, crossing extent boundaries as necessary.
+    ///
+    /// Returns `None` if any of these bytes is beyond the end of the allocated extents or in a sparse Data Run.
+    pub(crate) fn read_exact<T>(
+        &self,
+        fs: &mut T,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Option<Result<()>>
+    where
+        T: Read + Seek,
+    {
+        let mut bytes_read = 0;
+
+        while bytes_read < buf.len() {
+            let (position, remaining) = self.lookup(offset + bytes_read as u64)?;
+            let position = position.value()?;
+            let length = u64::min((buf.len() - bytes_read) as u64, remaining) as usize;
+
+            iter_try!(fs.seek(SeekFrom::Start(position.get())));
+            iter_try!(fs.read_exact(&mut buf[bytes_read..bytes_read + length]));
+
+            bytes_read += length;
+        }
+
+        Some(Ok(()))
+    }
+
+    /// Writes all bytes of `buf` starting at the given offset, crossing extent boundaries as necessary.
+    ///
+    /// Returns `None` if any of these bytes is beyond the end of the allocated extents or in a sparse Data Run.
+    /// Nothing is written in that case.
+    pub(crate) fn write_all<T>(&self, fs: &mut T, offset: u64, buf: &[u8]) -> Option<Result<()>>
+    where
+        T: Seek + Write,
+    {
+        // Check the entire range first to never write only a part of `buf`.
+        let mut pieces = Vec::new();
+        let mut bytes_checked = 0;
+
+        while bytes_checked < buf.len() {
+            let (position, remaining) = self.lookup(offset + bytes_checked as u64)?;
+            let position = position.value()?;
+            let length = u64::min((buf.len() - bytes_checked) as u64, remaining) as usize;
+
+            pieces.push((position.get(), bytes_checked..bytes_checked + length));
+            bytes_checked += length;
+        }
+
+        for (position, range) in pieces {
+            iter_try!(fs.seek(SeekFrom::Start(position)));
+            iter_try!(fs.write_all(&buf[range]));
+        }
+
+        Some(Ok(()))
+    }
+}
@@ -3,10 +3,12 @@
 
 use core::cmp::Ordering;
 use core::fmt;
+use core::mem;
 use core::num::NonZeroU64;
 
 use alloc::vec;
-use binrw::io::{Read, Seek, SeekFrom};
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek, SeekFrom, Write};
 use bitflags::bitflags;
 use byteorder::{ByteOrder, LittleEndian};
 use memoffset::offset_of;
@@ -18,15 +20,20 @@ use crate::attribute::{
 use crate::error::{NtfsError, Result};
 use crate::file_reference::NtfsFileReference;
 use crate::index::NtfsIndex;
-use crate::indexes::NtfsFileNameIndex;
+use crate::indexes::{NtfsFileNameIndex, NtfsIndexEntryType};
 use crate::ntfs::Ntfs;
 use crate::record::{Record, RecordHeader};
 use crate::structured_values::{
-    NtfsFileName, NtfsFileNamespace, NtfsIndexRoot, NtfsStandardInformation,
-    NtfsStructuredValueFromResidentAttributeValue,
+    NtfsFileName, NtfsFileNamespace, NtfsIndexRoot, NtfsSecurityDescriptor,
+    NtfsStandardInformation, NtfsStructuredValueFromResidentAttributeValue,
+    STANDARD_INFORMATION_MFT_RECORD_MODIFICATION_TIME_OFFSET,
+    STANDARD_INFORMATION_MODIFICATION_TIME_OFFSET,
 };
+use crate::time::NtfsTime;
 use crate::types::NtfsPosition;
 use crate::upcase_table::UpcaseOrd;
+use crate::walker::NtfsDirectoryWalker;
+use crate::wof::NtfsWofCompressedData;
 
 /// A list of standardized NTFS File Record Numbers.
 ///
@@ -140,11 +147,63 @@ impl<'n> NtfsFile<'n> {
     where
         T: Read + Seek,
     {
-        let mut data = vec![0; ntfs.file_record_size() as usize];
-        fs.seek(SeekFrom::Start(position.get()))?;
-        fs.read_exact(&mut data)?;
+        let record = ntfs.read_record(position.into(), || {
+            let mut data = vec![0; ntfs.file_record_size() as usize];
+            fs.seek(SeekFrom::Start(position.get()))?;
+            fs.read_exact(&mut data)?;
 
-        let mut record = Record::new(data, position.into());
+            Self::from_vec(ntfs, data, position.get(), file_record_number).map(Self::into_record)
+        })?;
+
+        Ok(Self::from_record(ntfs, record, file_record_number))
+    }
+
+    /// Parses an [`NtfsFile`] from File Record data in memory, e.g. from a memory-mapped filesystem image.
+    ///
+    /// Only the first [`Ntfs::file_record_size`] bytes of `data` are used, and shorter data fails with
+    /// [`NtfsError::BufferTooSmall`].
+    /// `position` is the absolute position of `data` within the filesystem, in bytes.
+    /// It is reported in errors and by [`NtfsFile::position`] and attribute positions, and may be 0 if unknown.
+    ///
+    /// The data is copied once, because the fixup modifies it.
+    /// Check [`NtfsFile::from_vec`] to reuse a buffer for that.
+    /// Resident attribute values of the returned file borrow from that copy.
+    pub fn from_bytes(
+        ntfs: &'n Ntfs,
+        data: &[u8],
+        position: u64,
+        file_record_number: u64,
+    ) -> Result<Self> {
+        let file_record_size = usize::min(data.len(), ntfs.file_record_size() as usize);
+        Self::from_vec(
+            ntfs,
+            data[..file_record_size].to_vec(),
+            position,
+            file_record_number,
+        )
+    }
+
+    /// Parses an [`NtfsFile`] from File Record data in memory like [`NtfsFile::from_bytes`], but fixes up the given
+    /// buffer in place instead of copying it.
+    ///
+    /// Get the buffer back via [`NtfsFile::into_record_data`] to reuse it for the next File Record.
+    pub fn from_vec(
+        ntfs: &'n Ntfs,
+        mut data: Vec<u8>,
+        position: u64,
+        file_record_number: u64,
+    ) -> Result<Self> {
+        let file_record_size = ntfs.file_record_size() as usize;
+        if data.len() < file_record_size {
+            return Err(NtfsError::BufferTooSmall {
+                expected: file_record_size,
+                actual: data.len(),
+            });
+        }
+
+        data.truncate(file_record_size);
+
+        let mut record = Record::new(data, NtfsPosition::new(position));
         Self::validate_signature(&record)?;
         record.fixup()?;
 
@@ -158,12 +217,32 @@ impl<'n> NtfsFile<'n> {
         Ok(file)
     }
 
+    /// Creates an [`NtfsFile`] from a File Record that has already been validated and fixed up.
+    pub(crate) fn from_record(ntfs: &'n Ntfs, record: Record, file_record_number: u64) -> Self {
+        Self {
+            ntfs,
+            record,
+            file_record_number,
+        }
+    }
+
     /// Returns the allocated size of this NTFS File Record, in bytes.
     pub fn allocated_size(&self) -> u32 {
         let start = offset_of!(FileRecordHeader, allocated_size);
         LittleEndian::read_u32(&self.record.data()[start..])
     }
 
+    /// Returns a reference to the base File Record if this is an extension File Record,
+    /// or a zero reference otherwise.
+    pub(crate) fn base_file_reference(&self) -> NtfsFileReference {
+        let start = offset_of!(FileRecordHeader, base_file_record);
+        NtfsFileReference::new(
+            self.record.data()[start..start + mem::size_of::<NtfsFileReference>()]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
     /// Returns an iterator over all attributes of this file.
     ///
     /// This provides a flattened "data-centric" view of the attributes and abstracts away the filesystem details
@@ -274,26 +353,7 @@ impl<'n> NtfsFile<'n> {
         }
 
         // A File Record may contain multiple indexes, so we have to match the name of the directory index.
-        let directory_index_name = "$I30";
-
-        // The IndexRoot attribute is always resident and has to exist for every directory.
-        let index_root_item =
-            self.find_attribute(fs, NtfsAttributeType::IndexRoot, Some(directory_index_name))?;
-        let index_root_attribute = index_root_item.to_attribute()?;
-        let index_root = index_root_attribute.resident_structured_value::<NtfsIndexRoot>()?;
-
-        // The IndexAllocation attribute is only required for "large" indexes.
-        // It is always non-resident and may even be in an Attribute List.
-        let mut index_allocation_item = None;
-        if index_root.is_large_index() {
-            index_allocation_item = Some(self.find_attribute(
-                fs,
-                NtfsAttributeType::IndexAllocation,
-                Some(directory_index_name),
-            )?);
-        }
-
-        NtfsIndex::<NtfsFileNameIndex>::new(index_root_item, index_allocation_item)
+        self.index(fs, "$I30")
     }
 
     /// Returns the NTFS File Record Number of this file.
@@ -308,7 +368,7 @@ impl<'n> NtfsFile<'n> {
     /// Returns [`NtfsError::AttributeNotFound`] if no such attribute could be found.
     ///
     /// This function also traverses Attribute Lists to find the attribute.
-    fn find_attribute<'f, T>(
+    pub(crate) fn find_attribute<'f, T>(
         &'f self,
         fs: &mut T,
         ty: NtfsAttributeType,
@@ -418,6 +478,28 @@ impl<'n> NtfsFile<'n> {
         LittleEndian::read_u16(&self.record.data()[start..])
     }
 
+    /// Returns an [`NtfsIndex`] for the index with the given name (e.g. `$I30` for directories).
+    pub(crate) fn index<'f, E, T>(&'f self, fs: &mut T, name: &str) -> Result<NtfsIndex<'n, 'f, E>>
+    where
+        E: NtfsIndexEntryType,
+        T: Read + Seek,
+    {
+        // The IndexRoot attribute is always resident and has to exist for every index.
+        let index_root_item = self.find_attribute(fs, NtfsAttributeType::IndexRoot, Some(name))?;
+        let index_root_attribute = index_root_item.to_attribute()?;
+        let index_root = index_root_attribute.resident_structured_value::<NtfsIndexRoot>()?;
+
+        // The IndexAllocation attribute is only required for "large" indexes.
+        // It is always non-resident and may even be in an Attribute List.
+        let mut index_allocation_item = None;
+        if index_root.is_large_index() {
+            index_allocation_item =
+                Some(self.find_attribute(fs, NtfsAttributeType::IndexAllocation, Some(name))?);
+        }
+
+        NtfsIndex::<E>::new(index_root_item, index_allocation_item)
+    }
+
     /// Convenience function to get the $STANDARD_INFORMATION attribute of this file
     /// (see [`NtfsStandardInformation`]).
     ///
@@ -482,6 +564,16 @@ impl<'n> NtfsFile<'n> {
         None
     }
 
+    pub(crate) fn into_record(self) -> Record {
+        self.record
+    }
+
+    /// Consumes this file and returns the fixed up File Record data, e.g. to reuse the buffer for
+    /// [`NtfsFile::from_vec`].
+    pub fn into_record_data(self) -> Vec<u8> {
+        self.record.into_data()
+    }
+
     /// Returns the [`Ntfs`] object reference associated to this file.
     pub fn ntfs(&self) -> &'n Ntfs {
         self.ntfs
@@ -496,6 +588,34 @@ impl<'n> NtfsFile<'n> {
         self.record.data()
     }
 
+    pub(crate) fn record_data_mut(&mut self) -> &mut [u8] {
+        self.record.data_mut()
+    }
+
+    /// Returns the security descriptor of this file, which contains its owner and access control lists.
+    ///
+    /// NTFS 3.x volumes store all security descriptors centrally in the $Secure file and only reference
+    /// them from the $STANDARD_INFORMATION attribute (see [`Ntfs::security_descriptor`]).
+    /// Older volumes store them in a $SECURITY_DESCRIPTOR attribute of each file.
+    /// This function handles both cases and returns `None` if the file has no security descriptor.
+    pub fn security_descriptor<T>(&self, fs: &mut T) -> Option<Result<NtfsSecurityDescriptor>>
+    where
+        T: Read + Seek,
+    {
+        match self.find_attribute(fs, NtfsAttributeType::SecurityDescriptor, None) {
+            Ok(item) => {
+                let attribute = iter_try!(item.to_attribute());
+                return Some(attribute.structured_value::<_, NtfsSecurityDescriptor>(fs));
+            }
+            Err(NtfsError::AttributeNotFound { .. }) => (),
+            Err(e) => return Some(Err(e)),
+        }
+
+        let std_info = iter_try!(self.info());
+        let security_id = std_info.security_id()?;
+        self.ntfs.security_descriptor(fs, security_id)
+    }
+
     /// Returns the sequence number of this file.
     ///
     /// NTFS reuses records of deleted files when new files are created.
@@ -506,6 +626,38 @@ impl<'n> NtfsFile<'n> {
         LittleEndian::read_u16(&self.record.data()[start..])
     }
 
+    /// Sets the modification time and the MFT record modification time in the $STANDARD_INFORMATION
+    /// attribute of this file to `time`, and writes the File Record back to the filesystem.
+    ///
+    /// Call this after modifying the file data (e.g. via [`NtfsNonResidentAttributeValue::write`])
+    /// to let the file times reflect that.
+    /// The copies of the file times in the $FILE_NAME attributes and directory indexes are not updated.
+    ///
+    /// [`NtfsNonResidentAttributeValue::write`]: crate::attribute_value::NtfsNonResidentAttributeValue::write
+    pub fn set_modification_time<T>(&mut self, fs: &mut T, time: NtfsTime) -> Result<()>
+    where
+        T: Read + Seek + Write,
+    {
+        let value_start = {
+            let attribute =
+                self.find_resident_attribute(NtfsAttributeType::StandardInformation, None, None)?;
+
+            // Validate the attribute before modifying it.
+            attribute.resident_structured_value::<NtfsStandardInformation>()?;
+            attribute.resident_value_range().start
+        };
+
+        let data = self.record_data_mut();
+        for offset in [
+            STANDARD_INFORMATION_MODIFICATION_TIME_OFFSET,
+            STANDARD_INFORMATION_MFT_RECORD_MODIFICATION_TIME_OFFSET,
+        ] {
+            LittleEndian::write_u64(&mut data[value_start + offset..], time.nt_timestamp());
+        }
+
+        self.ntfs.write_file_record(fs, self)
+    }
+
     fn validate_signature(record: &Record) -> Result<()> {
         let signature = &record.signature();
         let expected = b"FILE";
@@ -540,4 +692,125 @@ impl<'n> NtfsFile<'n> {
 
         Ok(())
     }
+
+    /// Returns an [`NtfsDirectoryWalker`] to recursively iterate over all files and directories below this directory.
+    ///
+    /// Apart from any propagated error, the walker returns [`NtfsError::NotADirectory`]
+    /// if this [`NtfsFile`] is not a directory.
+    pub fn walk(&self) -> NtfsDirectoryWalker<'n> {
+        NtfsDirectoryWalker::new(self.ntfs, self.file_record_number)
+    }
+
+    /// Returns an [`NtfsWofCompressedData`] reader for the file data if this file has been compressed by the
+    /// Windows Overlay Filter (WOF), e.g. via `compact /exe` or CompactOS.
+    ///
+    /// The unnamed $DATA attribute of such files is empty, and [`NtfsFile::data`] is of no use for reading
+    /// the file data.
+    /// This function returns `None` if the file has no WOF reparse point.
+    /// Only WOF-compressed files that store their data in the file itself are supported.
+    pub fn wof_compressed_data<'f, T>(
+        &'f self,
+        fs: &mut T,
+    ) -> Option<Result<NtfsWofCompressedData<'n, 'f>>>
+    where
+        T: Read + Seek,
+    {
+        NtfsWofCompressedData::new(fs, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribute_value::NtfsResidentAttributeValue;
+    use crate::structured_values::NtfsStructuredValueFromResidentAttributeValue;
+
+    #[test]
+    fn test_from_bytes() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let file = ntfs.file(&mut testfs1, 66).unwrap();
+        let next_file = ntfs.file(&mut testfs1, 67).unwrap();
+        let image = testfs1.into_inner();
+
+        // Only the first File Record of the given data is parsed.
+        let position = file.position().value().unwrap().get();
+        let parsed_file =
+            NtfsFile::from_bytes(&ntfs, &image[position as usize..], position, 66).unwrap();
+        assert_eq!(parsed_file.record_data(), file.record_data());
+        assert_eq!(parsed_file.position(), file.position());
+        assert_eq!(
+            parsed_file.info().unwrap().creation_time(),
+            file.info().unwrap().creation_time()
+        );
+
+        // The buffer can be reused for the next File Record.
+        let next_position = next_file.position().value().unwrap().get() as usize;
+        let mut data = parsed_file.into_record_data();
+        data.clear();
+        data.extend_from_slice(&image[next_position..next_position + 1024]);
+        let parsed_next_file = NtfsFile::from_vec(&ntfs, data, next_position as u64, 67).unwrap();
+        assert_eq!(parsed_next_file.record_data(), next_file.record_data());
+
+        // Structured values can be parsed from value data in memory.
+        let attribute = parsed_next_file.attributes_raw().next().unwrap().unwrap();
+        let value_data = attribute.resident_value().unwrap().data().to_vec();
+        let info = NtfsStandardInformation::from_resident_attribute_value(
+            NtfsResidentAttributeValue::from_bytes(&value_data, 0),
+        )
+        .unwrap();
+        assert_eq!(
+            info.creation_time(),
+            next_file.info().unwrap().creation_time()
+        );
+
+        // The boot sector is no File Record.
+        assert!(matches!(
+            NtfsFile::from_bytes(&ntfs, &image, 0, 0),
+            Err(NtfsError::InvalidFileSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_bytes_too_short() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let position = ntfs.file(&mut testfs1, 66).unwrap().position();
+        let position = position.value().unwrap().get() as usize;
+        let image = testfs1.into_inner();
+
+        assert!(matches!(
+            NtfsFile::from_bytes(&ntfs, b"FILE", 0, 0),
+            Err(NtfsError::BufferTooSmall {
+                expected: 1024,
+                actual: 4
+            })
+        ));
+        assert!(matches!(
+            NtfsFile::from_vec(&ntfs, image[position..position + 1023].to_vec(), 0, 66),
+            Err(NtfsError::BufferTooSmall {
+                expected: 1024,
+                actual: 1023
+            })
+        ));
+    }
+
+    #[test]
+    fn test_set_modification_time() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let mut file = ntfs.file(&mut testfs1, 66).unwrap();
+        let info = file.info().unwrap();
+
+        let time = NtfsTime::from(info.modification_time().nt_timestamp() + 12345);
+        file.set_modification_time(&mut testfs1, time).unwrap();
+
+        // The new times have been written to the filesystem, and all other file times are unchanged.
+        let new_info = ntfs.file(&mut testfs1, 66).unwrap().info().unwrap();
+        assert_eq!(new_info.modification_time(), time);
+        assert_eq!(new_info.mft_record_modification_time(), time);
+        assert_eq!(new_info.creation_time(), info.creation_time());
+        assert_eq!(new_info.access_time(), info.access_time());
+        assert_eq!(file.info().unwrap().modification_time(), time);
+    }
 }
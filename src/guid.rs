@@ -4,6 +4,7 @@
 use core::fmt;
 
 use binrw::BinRead;
+use byteorder::{ByteOrder, LittleEndian};
 
 /// Size of a single GUID on disk (= size of all GUID fields).
 pub(crate) const GUID_SIZE: usize = 16;
@@ -17,6 +18,22 @@ pub struct NtfsGuid {
     pub data4: [u8; 8],
 }
 
+impl NtfsGuid {
+    /// Creates an [`NtfsGuid`] from the first [`GUID_SIZE`] bytes of `bytes`, which must be in the usual
+    /// on-disk representation (little-endian fields).
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        let mut data4 = [0u8; 8];
+        data4.copy_from_slice(&bytes[8..GUID_SIZE]);
+
+        Self {
+            data1: LittleEndian::read_u32(&bytes[0..]),
+            data2: LittleEndian::read_u16(&bytes[4..]),
+            data3: LittleEndian::read_u16(&bytes[6..]),
+            data4,
+        }
+    }
+}
+
 impl fmt::Display for NtfsGuid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
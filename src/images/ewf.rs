@@ -0,0 +1,761 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::fmt::Write;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian};
+use md5::{Digest, Md5};
+use sha1::Sha1;
+
+use crate::attribute_value::seek_contiguous;
+use crate::error::{NtfsError, Result};
+use crate::types::NtfsPosition;
+
+/// Signature at the beginning of every EWF segment file.
+const EWF_SIGNATURE: &[u8] = b"EVF\x09\x0d\x0a\xff\x00";
+/// Size of the header of an EWF segment file, in bytes.
+const EWF_FILE_HEADER_SIZE: usize = 13;
+/// Size of an EWF section descriptor, in bytes.
+const EWF_SECTION_DESCRIPTOR_SIZE: usize = 76;
+/// Size of the header of an EWF table section, in bytes.
+const EWF_TABLE_HEADER_SIZE: usize = 24;
+/// Size of the volume section written by EnCase, in bytes.
+/// The volume section of the older SMART format is smaller and stores the sector count as a 32-bit value.
+const EWF_VOLUME_SIZE: usize = 1052;
+/// Minimum size of a volume section that is needed to read the geometry of the media.
+const EWF_VOLUME_MIN_SIZE: usize = 20;
+/// Flag in a table entry that marks a compressed chunk.
+const EWF_CHUNK_COMPRESSED: u32 = 0x8000_0000;
+/// Upper limit for the size of a single chunk, in bytes.
+/// EnCase always writes chunks of 32 KiB, so this only rejects garbage.
+const EWF_CHUNK_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The chunk offsets of a single EWF table section.
+#[derive(Clone, Debug)]
+struct EwfTable {
+    /// Number of the first chunk described by this table.
+    first_chunk: u64,
+    /// Index of the segment file containing the chunks.
+    segment: usize,
+    /// Offset that all entries are relative to.
+    base_offset: u64,
+    /// Chunk offsets, with [`EWF_CHUNK_COMPRESSED`] marking compressed chunks.
+    entries: Vec<u32>,
+    /// Offset of the end of the last chunk in the segment file.
+    end_offset: u64,
+}
+
+/// A reader for an image in the Expert Witness Compression Format (EWF) written by EnCase and FTK Imager,
+/// usually split into segment files `image.E01`, `image.E02`, and so on.
+///
+/// The media data is stored in chunks of usually 32 KiB, each of which may be zlib-compressed.
+/// All chunk offsets are read upfront (4 bytes per chunk), while the chunks themselves are only read and
+/// decompressed when needed.
+/// The most recently used chunk is kept in memory.
+///
+/// Call [`NtfsEwfReader::verify`] to check the media data against the MD5 and SHA1 hashes stored in the image.
+#[derive(Debug)]
+pub struct NtfsEwfReader<R> {
+    segments: Vec<R>,
+    tables: Vec<EwfTable>,
+    chunk_size: u64,
+    media_size: u64,
+    md5: Option<[u8; 16]>,
+    sha1: Option<[u8; 20]>,
+    /// Number and data of the most recently used chunk.
+    chunk: Option<(u64, Vec<u8>)>,
+    stream_position: u64,
+}
+
+impl<R> NtfsEwfReader<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new [`NtfsEwfReader`] from the given segment readers, which must be in the order of their
+    /// segment numbers.
+    ///
+    /// This reads the section descriptors, the volume geometry, all chunk tables, and the stored hashes.
+    pub fn new(mut segments: Vec<R>) -> Result<Self> {
+        let mut parser = EwfParser::default();
+
+        for (index, segment) in segments.iter_mut().enumerate() {
+            parser.parse_segment(segment, index)?;
+        }
+
+        let (chunk_size, media_size) = parser
+            .geometry
+            .ok_or(NtfsError::MissingEwfSection { ty: "volume" })?;
+        if parser.tables.is_empty() && media_size > 0 {
+            return Err(NtfsError::MissingEwfSection { ty: "table" });
+        }
+
+        Ok(Self {
+            segments,
+            tables: parser.tables,
+            chunk_size,
+            media_size,
+            md5: parser.md5,
+            sha1: parser.sha1,
+            chunk: None,
+            stream_position: 0,
+        })
+    }
+
+    /// Returns the data of the given chunk, reading and decompressing it if it is not the most recently used one.
+    fn chunk_data(&mut self, chunk: u64) -> Result<&[u8]> {
+        if !matches!(&self.chunk, Some((number, _)) if *number == chunk) {
+            let data = self.read_chunk(chunk)?;
+            self.chunk = Some((chunk, data));
+        }
+
+        // This unwrap is safe, because the chunk has just been stored.
+        Ok(&self.chunk.as_ref().unwrap().1)
+    }
+
+    /// Returns the size of a chunk of media data, in bytes.
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Consumes this reader and returns the segment readers.
+    pub fn into_inner(self) -> Vec<R> {
+        self.segments
+    }
+
+    /// Returns the size of the media data, in bytes.
+    pub fn media_size(&self) -> u64 {
+        self.media_size
+    }
+
+    fn read_chunk(&mut self, chunk: u64) -> Result<Vec<u8>> {
+        let table_index = self
+            .tables
+            .partition_point(|table| table.first_chunk <= chunk)
+            .checked_sub(1)
+            .ok_or(NtfsError::InvalidEwfChunk { chunk })?;
+        let table = &self.tables[table_index];
+
+        let index = (chunk - table.first_chunk) as usize;
+        let entry = *table
+            .entries
+            .get(index)
+            .ok_or(NtfsError::InvalidEwfChunk { chunk })?;
+        let chunk_offset = |entry: u32| {
+            table
+                .base_offset
+                .checked_add((entry & !EWF_CHUNK_COMPRESSED) as u64)
+                .ok_or(NtfsError::InvalidEwfChunk { chunk })
+        };
+        let offset = chunk_offset(entry)?;
+        let end_offset = match table.entries.get(index + 1) {
+            Some(next_entry) => chunk_offset(*next_entry)?,
+            None => table.end_offset,
+        };
+
+        // A chunk never grows by more than a few bytes, no matter whether it is compressed or stored with
+        // a trailing checksum.
+        let stored_size = end_offset
+            .checked_sub(offset)
+            .filter(|&size| size > 4 && size <= 2 * self.chunk_size + 1024)
+            .ok_or(NtfsError::InvalidEwfChunk { chunk })?;
+
+        let mut stored_data = vec![0u8; stored_size as usize];
+        let segment = &mut self.segments[table.segment];
+        segment.seek(SeekFrom::Start(offset))?;
+        segment.read_exact(&mut stored_data)?;
+
+        let data = if entry & EWF_CHUNK_COMPRESSED != 0 {
+            // A chunk never decompresses to more than the chunk size.
+            miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(
+                &stored_data,
+                self.chunk_size as usize,
+            )
+            .map_err(|_| NtfsError::InvalidEwfChunk { chunk })?
+        } else {
+            // An uncompressed chunk is followed by its Adler-32 checksum.
+            let (data, checksum) = stored_data.split_at(stored_data.len() - 4);
+            if adler32(data) != LittleEndian::read_u32(checksum) {
+                return Err(NtfsError::InvalidEwfChunk { chunk });
+            }
+
+            stored_data.truncate(stored_data.len() - 4);
+            stored_data
+        };
+
+        // Only the last chunk may be shorter.
+        let expected_size = u64::min(self.chunk_size, self.media_size - chunk * self.chunk_size);
+        if (data.len() as u64) < expected_size {
+            return Err(NtfsError::InvalidEwfChunk { chunk });
+        }
+
+        Ok(data)
+    }
+
+    /// Returns the MD5 hash of the media data stored in the image, if any.
+    pub fn stored_md5(&self) -> Option<&[u8; 16]> {
+        self.md5.as_ref()
+    }
+
+    /// Returns the SHA1 hash of the media data stored in the image, if any.
+    pub fn stored_sha1(&self) -> Option<&[u8; 20]> {
+        self.sha1.as_ref()
+    }
+
+    /// Reads the entire media data and compares its MD5 and SHA1 hashes with those stored in the image.
+    ///
+    /// Returns [`NtfsError::ImageHashMismatch`] if a hash does not match and [`NtfsError::MissingImageHash`]
+    /// if the image stores neither an MD5 nor a SHA1 hash.
+    /// This also detects all chunks that cannot be decompressed or fail their checksum.
+    pub fn verify(&mut self) -> Result<()> {
+        if self.md5.is_none() && self.sha1.is_none() {
+            return Err(NtfsError::MissingImageHash);
+        }
+
+        let mut md5 = Md5::new();
+        let mut sha1 = Sha1::new();
+        let chunk_count = (self.media_size + self.chunk_size - 1) / self.chunk_size;
+
+        for chunk in 0..chunk_count {
+            let size = u64::min(self.chunk_size, self.media_size - chunk * self.chunk_size);
+            let data = &self.chunk_data(chunk)?[..size as usize];
+            md5.update(data);
+            sha1.update(data);
+        }
+
+        if let Some(expected) = &self.md5 {
+            compare_hash("MD5", expected, &md5.finalize())?;
+        }
+
+        if let Some(expected) = &self.sha1 {
+            compare_hash("SHA1", expected, &sha1.finalize())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl NtfsEwfReader<File> {
+    /// Opens the given first segment file (like `image.E01`) along with all further segment files.
+    ///
+    /// Further segment files are found by the naming scheme of EnCase, which continues `.E01` to `.E99`
+    /// with `.EAA` to `.EZZ`, `.FAA`, and so on, until a file does not exist.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let segments = super::segment_paths(path.as_ref(), segment_extension)
+            .into_iter()
+            .map(File::open)
+            .collect::<io::Result<Vec<File>>>()?;
+
+        Self::new(segments)
+    }
+}
+
+impl<R> Read for NtfsEwfReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stream_position >= self.media_size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let chunk = self.stream_position / self.chunk_size;
+        let offset_in_chunk = (self.stream_position % self.chunk_size) as usize;
+        let remaining = self.media_size - self.stream_position;
+
+        let data = &self.chunk_data(chunk)?[offset_in_chunk..];
+        let bytes_to_read = usize::min(buf.len(), data.len());
+        let bytes_to_read = u64::min(bytes_to_read as u64, remaining) as usize;
+        buf[..bytes_to_read].copy_from_slice(&data[..bytes_to_read]);
+
+        self.stream_position += bytes_to_read as u64;
+        Ok(bytes_to_read)
+    }
+}
+
+impl<R> Seek for NtfsEwfReader<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_contiguous(&mut self.stream_position, self.media_size, pos).map_err(io::Error::from)
+    }
+}
+
+/// Collects the information of all segment files of an EWF image.
+#[derive(Default)]
+struct EwfParser {
+    /// Chunk size and media size, both in bytes.
+    geometry: Option<(u64, u64)>,
+    tables: Vec<EwfTable>,
+    chunk_count: u64,
+    md5: Option<[u8; 16]>,
+    sha1: Option<[u8; 20]>,
+}
+
+impl EwfParser {
+    fn parse_segment<R>(&mut self, segment: &mut R, index: usize) -> Result<()>
+    where
+        R: Read + Seek,
+    {
+        let segment_number = u16::try_from(index + 1).unwrap_or(u16::MAX);
+
+        let mut header = [0u8; EWF_FILE_HEADER_SIZE];
+        segment.seek(SeekFrom::Start(0))?;
+        segment.read_exact(&mut header)?;
+
+        if &header[..EWF_SIGNATURE.len()] != EWF_SIGNATURE {
+            return Err(NtfsError::InvalidEwfSignature {
+                segment: segment_number,
+                expected: EWF_SIGNATURE,
+                // This unwrap is safe, because the signature has exactly 8 bytes.
+                actual: header[..EWF_SIGNATURE.len()].try_into().unwrap(),
+            });
+        }
+
+        let actual_segment_number = LittleEndian::read_u16(&header[9..]);
+        if actual_segment_number != segment_number {
+            return Err(NtfsError::InvalidEwfSegmentNumber {
+                expected: segment_number,
+                actual: actual_segment_number,
+            });
+        }
+
+        // The sections form a chain, which ends with a "next" or "done" section pointing to itself.
+        let mut position = EWF_FILE_HEADER_SIZE as u64;
+        let mut sectors_end = None;
+
+        loop {
+            let invalid_section = || NtfsError::InvalidEwfSection {
+                segment: segment_number,
+                position: NtfsPosition::new(position),
+            };
+
+            let mut descriptor = [0u8; EWF_SECTION_DESCRIPTOR_SIZE];
+            segment.seek(SeekFrom::Start(position))?;
+            segment.read_exact(&mut descriptor)?;
+
+            let checksum = LittleEndian::read_u32(&descriptor[72..]);
+            if adler32(&descriptor[..72]) != checksum {
+                return Err(invalid_section());
+            }
+
+            let ty_length = descriptor[..16].iter().position(|&b| b == 0).unwrap_or(16);
+            let ty = &descriptor[..ty_length];
+            let next_position = LittleEndian::read_u64(&descriptor[16..]);
+            let size = LittleEndian::read_u64(&descriptor[24..]);
+            let data_position = position + EWF_SECTION_DESCRIPTOR_SIZE as u64;
+            let data_size = size.saturating_sub(EWF_SECTION_DESCRIPTOR_SIZE as u64);
+
+            match ty {
+                b"volume" | b"disk" | b"data" if self.geometry.is_none() => {
+                    let mut volume = [0u8; EWF_VOLUME_SIZE];
+                    let volume_size = u64::min(data_size, EWF_VOLUME_SIZE as u64) as usize;
+                    if volume_size < EWF_VOLUME_MIN_SIZE {
+                        return Err(invalid_section());
+                    }
+
+                    segment.read_exact(&mut volume[..volume_size])?;
+                    self.geometry =
+                        Some(parse_volume(&volume[..volume_size]).ok_or_else(invalid_section)?);
+                }
+                b"sectors" => sectors_end = Some(position + size),
+                b"table" => {
+                    if data_size < EWF_TABLE_HEADER_SIZE as u64 {
+                        return Err(invalid_section());
+                    }
+
+                    let mut table_header = [0u8; EWF_TABLE_HEADER_SIZE];
+                    segment.read_exact(&mut table_header)?;
+                    let entry_count = LittleEndian::read_u32(&table_header[0..]);
+                    let base_offset = LittleEndian::read_u64(&table_header[8..]);
+
+                    if entry_count as u64 * 4 > data_size - EWF_TABLE_HEADER_SIZE as u64 {
+                        return Err(invalid_section());
+                    }
+
+                    let mut entries_data = vec![0u8; entry_count as usize * 4];
+                    segment.read_exact(&mut entries_data)?;
+                    let entries = entries_data
+                        .chunks_exact(4)
+                        .map(LittleEndian::read_u32)
+                        .collect::<Vec<u32>>();
+
+                    // The chunks precede their table, either in a "sectors" section or directly.
+                    self.tables.push(EwfTable {
+                        first_chunk: self.chunk_count,
+                        segment: index,
+                        base_offset,
+                        entries,
+                        end_offset: sectors_end.unwrap_or(position),
+                    });
+                    self.chunk_count += entry_count as u64;
+                }
+                b"hash" if data_size >= 16 => {
+                    let mut md5 = [0u8; 16];
+                    segment.read_exact(&mut md5)?;
+                    self.md5 = Some(md5);
+                }
+                b"digest" if data_size >= 36 => {
+                    let mut md5 = [0u8; 16];
+                    let mut sha1 = [0u8; 20];
+                    segment.read_exact(&mut md5)?;
+                    segment.read_exact(&mut sha1)?;
+
+                    // Unused hashes are stored as zeros.
+                    if md5 != [0; 16] {
+                        self.md5 = Some(md5);
+                    }
+                    if sha1 != [0; 20] {
+                        self.sha1 = Some(sha1);
+                    }
+                }
+                b"next" | b"done" => break,
+                _ => (),
+            }
+
+            // Sections only ever follow each other, which also rules out any loops.
+            if next_position <= position || next_position < data_position {
+                return Err(invalid_section());
+            }
+
+            position = next_position;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the chunk size and media size (both in bytes) from the data of a volume section.
+fn parse_volume(volume: &[u8]) -> Option<(u64, u64)> {
+    let sectors_per_chunk = LittleEndian::read_u32(&volume[8..]) as u64;
+    let bytes_per_sector = LittleEndian::read_u32(&volume[12..]) as u64;
+    let sector_count = if volume.len() >= EWF_VOLUME_SIZE {
+        LittleEndian::read_u64(&volume[16..])
+    } else {
+        LittleEndian::read_u32(&volume[16..]) as u64
+    };
+
+    let chunk_size = sectors_per_chunk * bytes_per_sector;
+    if chunk_size == 0 || chunk_size > EWF_CHUNK_MAX_SIZE {
+        return None;
+    }
+
+    let media_size = sector_count.checked_mul(bytes_per_sector)?;
+    Some((chunk_size, media_size))
+}
+
+/// Calculates the Adler-32 checksum used throughout EWF.
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    // Up to 5552 bytes can be summed up before the sums need to be reduced to not overflow.
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+
+    (b << 16) | a
+}
+
+fn compare_hash(algorithm: &'static str, expected: &[u8], actual: &[u8]) -> Result<()> {
+    if expected == actual {
+        return Ok(());
+    }
+
+    Err(NtfsError::ImageHashMismatch {
+        algorithm,
+        expected: to_hex(expected),
+        actual: to_hex(actual),
+    })
+}
+
+/// Returns the file extension of segment file `number` for an EWF image whose first segment file has
+/// the extension `first_extension` (like `E01`).
+fn segment_extension(first_extension: &str, number: u32) -> Option<String> {
+    let bytes = first_extension.as_bytes();
+    if bytes.len() != 3 || !bytes[0].is_ascii_alphabetic() || &bytes[1..] != b"01" {
+        return None;
+    }
+
+    if number <= 99 {
+        return Some(format!("{}{:02}", bytes[0] as char, number));
+    }
+
+    // After 99, two letters take over the digits, and the first letter is incremented after `ZZ`.
+    let number = number - 100;
+    let base = if bytes[0].is_ascii_uppercase() {
+        b'A'
+    } else {
+        b'a'
+    };
+    let first = bytes[0] as u32 + number / (26 * 26);
+    if first > base as u32 + 25 {
+        return None;
+    }
+
+    let extension = [
+        first as u8,
+        base + (number / 26 % 26) as u8,
+        base + (number % 26) as u8,
+    ];
+    Some(extension.iter().map(|&b| b as char).collect())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+
+    for byte in bytes {
+        // This unwrap is safe, because writing to a String cannot fail.
+        write!(hex, "{byte:02x}").unwrap();
+    }
+
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::Ntfs;
+    use std::io::Cursor;
+
+    /// Number of chunks that are stored in the first segment file.
+    const CHUNKS_IN_FIRST_SEGMENT: usize = 40;
+
+    fn write_section(segment: &mut Vec<u8>, ty: &str, data: &[u8]) {
+        let position = segment.len() as u64;
+        let size = (EWF_SECTION_DESCRIPTOR_SIZE + data.len()) as u64;
+        // "next" and "done" sections point to themselves.
+        let next_position = if ty == "next" || ty == "done" {
+            position
+        } else {
+            position + size
+        };
+
+        let mut descriptor = [0u8; EWF_SECTION_DESCRIPTOR_SIZE];
+        descriptor[..ty.len()].copy_from_slice(ty.as_bytes());
+        LittleEndian::write_u64(&mut descriptor[16..], next_position);
+        LittleEndian::write_u64(&mut descriptor[24..], size);
+        let checksum = adler32(&descriptor[..72]);
+        LittleEndian::write_u32(&mut descriptor[72..], checksum);
+
+        segment.extend_from_slice(&descriptor);
+        segment.extend_from_slice(data);
+    }
+
+    fn write_chunks(segment: &mut Vec<u8>, chunks: &[&[u8]], first_compressed: bool) {
+        // The chunks of a "sectors" section are referenced by the following "table" section.
+        let sectors_position = segment.len() as u64;
+        let mut sectors = Vec::new();
+        let mut entries = Vec::new();
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let offset =
+                sectors_position + EWF_SECTION_DESCRIPTOR_SIZE as u64 + sectors.len() as u64;
+
+            if (i % 2 == 0) == first_compressed {
+                entries.push(offset as u32 | EWF_CHUNK_COMPRESSED);
+                sectors.extend(miniz_oxide::deflate::compress_to_vec_zlib(chunk, 6));
+            } else {
+                entries.push(offset as u32);
+                sectors.extend_from_slice(chunk);
+                sectors.extend(adler32(chunk).to_le_bytes());
+            }
+        }
+
+        write_section(segment, "sectors", &sectors);
+
+        let mut table = vec![0u8; EWF_TABLE_HEADER_SIZE];
+        LittleEndian::write_u32(&mut table[0..], entries.len() as u32);
+        for entry in entries {
+            table.extend(entry.to_le_bytes());
+        }
+        write_section(segment, "table", &table);
+        write_section(segment, "table2", &table);
+    }
+
+    /// Builds an EWF image of testfs1 in two segment files with alternating compressed and uncompressed chunks.
+    fn ewf_image(media: &[u8]) -> Vec<Vec<u8>> {
+        let chunk_size = 32768;
+        let chunks = media.chunks(chunk_size).collect::<Vec<_>>();
+
+        let mut volume = vec![0u8; EWF_VOLUME_SIZE];
+        LittleEndian::write_u32(&mut volume[4..], chunks.len() as u32);
+        LittleEndian::write_u32(&mut volume[8..], 64);
+        LittleEndian::write_u32(&mut volume[12..], 512);
+        LittleEndian::write_u64(&mut volume[16..], media.len() as u64 / 512);
+
+        let mut digest = vec![0u8; 80];
+        digest[..16].copy_from_slice(&Md5::digest(media));
+        digest[16..36].copy_from_slice(&Sha1::digest(media));
+
+        let mut segments = Vec::new();
+        for number in 1..=2u16 {
+            let mut segment = EWF_SIGNATURE.to_vec();
+            segment.push(1);
+            segment.extend(number.to_le_bytes());
+            segment.extend([0, 0]);
+
+            if number == 1 {
+                write_section(&mut segment, "volume", &volume);
+                write_chunks(&mut segment, &chunks[..CHUNKS_IN_FIRST_SEGMENT], true);
+                write_section(&mut segment, "next", &[]);
+            } else {
+                write_section(&mut segment, "data", &volume);
+                write_chunks(&mut segment, &chunks[CHUNKS_IN_FIRST_SEGMENT..], false);
+                write_section(&mut segment, "digest", &digest);
+                write_section(&mut segment, "done", &[]);
+            }
+
+            segments.push(segment);
+        }
+
+        segments
+    }
+
+    #[test]
+    fn test_ewf() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let segments = ewf_image(&testfs1);
+
+        let mut reader =
+            NtfsEwfReader::new(segments.iter().cloned().map(Cursor::new).collect()).unwrap();
+        assert_eq!(reader.chunk_size(), 32768);
+        assert_eq!(reader.media_size(), testfs1.len() as u64);
+        assert!(reader.stored_md5().is_some());
+        assert!(reader.stored_sha1().is_some());
+        reader.verify().unwrap();
+
+        // A read across the boundary of two chunks in different segment files returns the original data.
+        let boundary = CHUNKS_IN_FIRST_SEGMENT * 32768;
+        let mut buf = vec![0u8; 1000];
+        reader.seek(SeekFrom::Start(boundary as u64 - 500)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, testfs1[boundary - 500..boundary + 500]);
+
+        let ntfs = Ntfs::new(&mut reader).unwrap();
+        let file = ntfs.file(&mut reader, 66).unwrap();
+        assert!(file.data(&mut reader, "").is_some());
+
+        // Change a byte of an uncompressed chunk in the second segment file, which fails its checksum.
+        let mut damaged_segments = segments.clone();
+        let position = damaged_segments[1].len() - 4096;
+        damaged_segments[1][position] ^= 0xff;
+        let mut reader =
+            NtfsEwfReader::new(damaged_segments.into_iter().map(Cursor::new).collect()).unwrap();
+        assert!(matches!(
+            reader.verify(),
+            Err(NtfsError::InvalidEwfChunk { .. })
+        ));
+
+        // Swapped segment files are detected.
+        let swapped_segments = vec![
+            Cursor::new(segments[1].clone()),
+            Cursor::new(segments[0].clone()),
+        ];
+        assert!(matches!(
+            NtfsEwfReader::new(swapped_segments),
+            Err(NtfsError::InvalidEwfSegmentNumber {
+                expected: 1,
+                actual: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_ewf_invalid_chunks() {
+        // A single segment file whose only chunk decompresses to twice the chunk size.
+        let mut volume = vec![0u8; EWF_VOLUME_SIZE];
+        LittleEndian::write_u32(&mut volume[4..], 1);
+        LittleEndian::write_u32(&mut volume[8..], 64);
+        LittleEndian::write_u32(&mut volume[12..], 512);
+        LittleEndian::write_u64(&mut volume[16..], 64);
+
+        let mut segment = EWF_SIGNATURE.to_vec();
+        segment.extend([1, 1, 0, 0, 0]);
+        write_section(&mut segment, "volume", &volume);
+        write_chunks(&mut segment, &[&[0u8; 65536]], true);
+        write_section(&mut segment, "done", &[]);
+
+        let mut reader = NtfsEwfReader::new(vec![Cursor::new(segment.clone())]).unwrap();
+        assert!(matches!(
+            reader.read_chunk(0),
+            Err(NtfsError::InvalidEwfChunk { chunk: 0 })
+        ));
+
+        // A table whose base offset makes the chunk offsets overflow.
+        let table_position = segment
+            .windows(6)
+            .position(|window| window == b"table\0")
+            .unwrap();
+        let base_offset_position = table_position + EWF_SECTION_DESCRIPTOR_SIZE + 8;
+        LittleEndian::write_u64(&mut segment[base_offset_position..], u64::MAX);
+
+        let mut reader = NtfsEwfReader::new(vec![Cursor::new(segment)]).unwrap();
+        assert!(matches!(
+            reader.read_chunk(0),
+            Err(NtfsError::InvalidEwfChunk { chunk: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_ewf_hash_mismatch() {
+        let mut testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let segments = ewf_image(&testfs1);
+
+        // Build another image with modified media data, but the hashes of the original one.
+        testfs1[0] ^= 0xff;
+        let mut modified_segments = ewf_image(&testfs1);
+        // The data of the "digest" section is only followed by the descriptor of the "done" section.
+        let digest_position = modified_segments[1].len() - EWF_SECTION_DESCRIPTOR_SIZE - 80;
+        modified_segments[1][digest_position..digest_position + 80]
+            .copy_from_slice(&segments[1][digest_position..digest_position + 80]);
+
+        let mut reader =
+            NtfsEwfReader::new(modified_segments.into_iter().map(Cursor::new).collect()).unwrap();
+        assert_eq!(
+            reader.stored_md5().unwrap()[..],
+            segments[1][digest_position..digest_position + 16]
+        );
+        assert!(matches!(
+            reader.verify(),
+            Err(NtfsError::ImageHashMismatch {
+                algorithm: "MD5",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_segment_extension() {
+        assert_eq!(segment_extension("E01", 2).as_deref(), Some("E02"));
+        assert_eq!(segment_extension("E01", 99).as_deref(), Some("E99"));
+        assert_eq!(segment_extension("E01", 100).as_deref(), Some("EAA"));
+        assert_eq!(segment_extension("e01", 101).as_deref(), Some("eab"));
+        assert_eq!(
+            segment_extension("E01", 100 + 26 * 26).as_deref(),
+            Some("FAA")
+        );
+        assert_eq!(segment_extension("Z01", 100 + 26 * 26), None);
+        assert_eq!(segment_extension("001", 2), None);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Readers for disk image formats, which present the contained raw data via [`Read`] and [`Seek`].
+//!
+//! Every reader of this module can be passed to [`Ntfs::new`] for an image of a single NTFS partition,
+//! or to [`NtfsPartitionTable::new`] for an image of an entire disk.
+//! The latter is the usual case for the virtual disks of [`NtfsVhdReader`] and [`NtfsVhdxReader`].
+//!
+//! [`Ntfs::new`]: crate::Ntfs::new
+//! [`NtfsPartitionTable::new`]: crate::NtfsPartitionTable::new
+//! [`Read`]: std::io::Read
+//! [`Seek`]: std::io::Seek
+
+mod ewf;
+mod split_raw;
+mod vhd;
+mod vhdx;
+
+pub use ewf::*;
+pub use split_raw::*;
+pub use vhd::*;
+pub use vhdx::*;
+
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::error::{NtfsError, Result};
+
+/// Returns the path of the parent of a differencing virtual disk at `child_path`, taken from the first of the
+/// given parent locations that refers to an existing file.
+///
+/// Relative locations are resolved against the directory of the child.
+/// As virtual disks are often copied together, a location that does not exist is also tried as a file name
+/// in the directory of the child.
+fn find_parent_path(child_path: &Path, locations: &[String]) -> Result<PathBuf> {
+    let child_directory = child_path.parent().unwrap_or_else(|| Path::new(""));
+
+    for location in locations {
+        // Parent locations are usually Windows paths.
+        let location = location.replace('\\', &std::path::MAIN_SEPARATOR.to_string());
+        let location = Path::new(&location);
+
+        let mut candidates = vec![child_directory.join(location)];
+        if let Some(file_name) = location.file_name() {
+            candidates.push(child_directory.join(file_name));
+        }
+
+        if let Some(path) = candidates.into_iter().find(|path| path.is_file()) {
+            return Ok(path);
+        }
+    }
+
+    Err(NtfsError::VirtualDiskParentNotFound {
+        locations: locations.to_vec(),
+    })
+}
+
+/// Reads data of a differencing virtual disk from its parent, because the differencing disk does not store it.
+///
+/// Any data beyond the end of the parent is read as zeros.
+fn read_from_parent<P>(parent: Option<&mut P>, position: u64, buf: &mut [u8]) -> Result<usize>
+where
+    P: Read + Seek,
+{
+    let parent = parent.ok_or(NtfsError::MissingVirtualDiskParent)?;
+    parent.seek(SeekFrom::Start(position))?;
+
+    match parent.read(buf)? {
+        0 => Ok(read_zeros(buf)),
+        bytes_read => Ok(bytes_read),
+    }
+}
+
+/// Fills `buf` with zeros for data that is not allocated in a virtual disk.
+fn read_zeros(buf: &mut [u8]) -> usize {
+    buf.fill(0);
+    buf.len()
+}
+
+/// Returns whether `sector` is set in the sector bitmap of a virtual disk block, along with the number of
+/// consecutive sectors (starting at `sector`, and at most `max_count`) that have the same state.
+///
+/// VHD stores the bits of each byte starting with the most significant one, VHDX with the least significant one.
+fn sector_run(bitmap: &[u8], sector: u64, max_count: u64, msb_first: bool) -> (bool, u64) {
+    let is_set = |sector: u64| {
+        let bit = (sector % 8) as u32;
+        let mask = if msb_first { 0x80 >> bit } else { 1 << bit };
+        bitmap
+            .get((sector / 8) as usize)
+            .map_or(false, |byte| byte & mask != 0)
+    };
+
+    let state = is_set(sector);
+    let count = (1..max_count)
+        .find(|&count| is_set(sector + count) != state)
+        .unwrap_or(max_count);
+
+    (state, count)
+}
+
+/// Checks that the file at `path` has not been opened before as part of the same chain of differencing
+/// virtual disks, and records it.
+fn visit_parent_chain(path: &Path, visited: &mut Vec<PathBuf>) -> Result<()> {
+    let path = path.canonicalize()?;
+
+    if visited.contains(&path) {
+        return Err(NtfsError::VirtualDiskParentLoop {
+            path: path.to_string_lossy().into_owned(),
+        });
+    }
+
+    visited.push(path);
+    Ok(())
+}
+
+/// Returns the path of the given first segment file followed by the paths of all further segment files
+/// that exist.
+///
+/// `segment_extension` returns the file extension of segment file `number` (starting at 1) from the extension
+/// of the first segment file, or `None` if there can be no such segment file.
+fn segment_paths<F>(first_path: &Path, segment_extension: F) -> Vec<PathBuf>
+where
+    F: Fn(&str, u32) -> Option<String>,
+{
+    let mut paths = vec![first_path.to_path_buf()];
+
+    let first_extension = match first_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some(extension) => extension,
+        None => return paths,
+    };
+
+    for number in 2.. {
+        let path = match segment_extension(first_extension, number) {
+            Some(extension) => first_path.with_extension(extension),
+            None => break,
+        };
+
+        if !path.is_file() {
+            break;
+        }
+
+        paths.push(path);
+    }
+
+    paths
+}
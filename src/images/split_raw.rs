@@ -0,0 +1,185 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::attribute_value::seek_contiguous;
+use crate::error::Result;
+
+/// A reader for a raw image split into multiple segment files, like `image.001`, `image.002`, and so on.
+///
+/// The segments are joined in the given order and presented as a single contiguous image.
+/// Every segment may have a different size.
+#[derive(Debug)]
+pub struct NtfsSplitRawReader<R> {
+    segments: Vec<R>,
+    /// Position of the first byte of each segment within the joined image, followed by the total size.
+    segment_offsets: Vec<u64>,
+    stream_position: u64,
+}
+
+impl<R> NtfsSplitRawReader<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new [`NtfsSplitRawReader`] joining the given segment readers in order.
+    ///
+    /// The size of each segment is determined by seeking to its end.
+    pub fn new(mut segments: Vec<R>) -> Result<Self> {
+        let mut segment_offsets = Vec::with_capacity(segments.len() + 1);
+        let mut offset = 0u64;
+
+        for segment in &mut segments {
+            segment_offsets.push(offset);
+            offset += segment.seek(SeekFrom::End(0))?;
+        }
+
+        segment_offsets.push(offset);
+
+        Ok(Self {
+            segments,
+            segment_offsets,
+            stream_position: 0,
+        })
+    }
+
+    /// Consumes this reader and returns the segment readers.
+    pub fn into_inner(self) -> Vec<R> {
+        self.segments
+    }
+
+    /// Returns the total size of all segments, in bytes.
+    pub fn len(&self) -> u64 {
+        // This unwrap is safe, because `segment_offsets` always contains the total size.
+        *self.segment_offsets.last().unwrap()
+    }
+
+    /// Returns `true` if all segments are empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl NtfsSplitRawReader<File> {
+    /// Opens the given first segment file along with all further segment files.
+    ///
+    /// If the file extension of `path` is a number (like `.001` or `.000`), further segment files are found by
+    /// incrementing that number while keeping its width, until a file does not exist.
+    /// Otherwise, `path` is opened as the only segment.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let segments = super::segment_paths(path.as_ref(), segment_extension)
+            .into_iter()
+            .map(File::open)
+            .collect::<io::Result<Vec<File>>>()?;
+
+        Self::new(segments)
+    }
+}
+
+impl<R> Read for NtfsSplitRawReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stream_position >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Find the last segment starting at or before the stream position, skipping empty segments.
+        let index = self
+            .segment_offsets
+            .partition_point(|&offset| offset <= self.stream_position)
+            - 1;
+        let segment_position = self.stream_position - self.segment_offsets[index];
+        let remaining = self.segment_offsets[index + 1] - self.stream_position;
+        let bytes_to_read = u64::min(buf.len() as u64, remaining) as usize;
+
+        let segment = &mut self.segments[index];
+        segment.seek(SeekFrom::Start(segment_position))?;
+        let bytes_read = segment.read(&mut buf[..bytes_to_read])?;
+
+        self.stream_position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R> Seek for NtfsSplitRawReader<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let length = self.len();
+        seek_contiguous(&mut self.stream_position, length, pos).map_err(io::Error::from)
+    }
+}
+
+/// Returns the file extension of segment file `number` for a split raw image whose first segment file has
+/// the extension `first_extension`.
+fn segment_extension(first_extension: &str, number: u32) -> Option<String> {
+    if first_extension.is_empty() || !first_extension.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let first_number = first_extension.parse::<u32>().ok()?;
+    let extension = format!(
+        "{:0width$}",
+        first_number.checked_add(number - 1)?,
+        width = first_extension.len()
+    );
+
+    if extension.len() > first_extension.len() {
+        return None;
+    }
+
+    Some(extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::Ntfs;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_split_raw() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+
+        // Split the image into segments of different sizes, including an empty one.
+        let segments = vec![
+            Cursor::new(testfs1[..1000].to_vec()),
+            Cursor::new(Vec::new()),
+            Cursor::new(testfs1[1000..1_000_000].to_vec()),
+            Cursor::new(testfs1[1_000_000..].to_vec()),
+        ];
+        let mut reader = NtfsSplitRawReader::new(segments).unwrap();
+        assert_eq!(reader.len(), testfs1.len() as u64);
+
+        // A read across segment boundaries returns the joined data.
+        let mut buf = vec![0u8; 4000];
+        reader.seek(SeekFrom::Start(999_000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, testfs1[999_000..1_003_000]);
+
+        reader.seek(SeekFrom::End(-1)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        let ntfs = Ntfs::new(&mut reader).unwrap();
+        let file = ntfs.file(&mut reader, 66).unwrap();
+        assert!(file.data(&mut reader, "").is_some());
+    }
+
+    #[test]
+    fn test_segment_extension() {
+        assert_eq!(segment_extension("001", 2).as_deref(), Some("002"));
+        assert_eq!(segment_extension("000", 11).as_deref(), Some("010"));
+        assert_eq!(segment_extension("998", 3), None);
+        assert_eq!(segment_extension("raw", 2), None);
+    }
+}
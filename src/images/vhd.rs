@@ -0,0 +1,804 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::attribute_value::seek_contiguous;
+use crate::error::{NtfsError, Result};
+use crate::guid::{NtfsGuid, GUID_SIZE};
+use crate::types::NtfsPosition;
+
+/// Size of the footer at the end of every VHD image (and its copy at the beginning of a dynamic VHD), in bytes.
+const VHD_FOOTER_SIZE: usize = 512;
+/// Cookie at the beginning of a VHD footer.
+const VHD_FOOTER_COOKIE: &[u8] = b"conectix";
+/// Size of the header of a dynamic or differencing VHD, in bytes.
+const VHD_DYNAMIC_HEADER_SIZE: usize = 1024;
+/// Cookie at the beginning of the header of a dynamic or differencing VHD.
+const VHD_DYNAMIC_HEADER_COOKIE: &[u8] = b"cxsparse";
+/// Size of a VHD sector, which is the unit of the BAT entries and sector bitmaps, in bytes.
+const VHD_SECTOR_SIZE: u64 = 512;
+/// Disk type of a VHD that stores all data in a single block after the footer.
+const VHD_DISK_TYPE_FIXED: u32 = 2;
+/// Disk type of a VHD that only stores the allocated blocks.
+const VHD_DISK_TYPE_DYNAMIC: u32 = 3;
+/// Disk type of a VHD that only stores the blocks that differ from its parent.
+const VHD_DISK_TYPE_DIFFERENCING: u32 = 4;
+/// BAT entry of a block that is not allocated.
+const VHD_BAT_ENTRY_UNUSED: u32 = u32::MAX;
+/// Upper limit for the block size of a dynamic VHD, in bytes.
+/// Windows always uses 2 MiB blocks, so this only rejects garbage.
+const VHD_BLOCK_MAX_SIZE: u32 = 256 * 1024 * 1024;
+/// Number of parent locator entries in the header of a differencing VHD.
+const VHD_PARENT_LOCATOR_COUNT: usize = 8;
+/// Size of a parent locator entry, in bytes.
+const VHD_PARENT_LOCATOR_SIZE: usize = 24;
+/// Upper limit for the size of the data of a single parent locator, in bytes.
+const VHD_PARENT_LOCATOR_MAX_SIZE: u32 = 64 * 1024;
+/// Platform codes of the parent locators that store a relative ("W2ru") or absolute ("W2ku") Windows path
+/// in UTF-16, in the order they are tried.
+const VHD_PARENT_LOCATOR_PLATFORM_CODES: [u32; 2] = [0x5732_7275, 0x5732_6b75];
+
+/// The block allocation of a dynamic or differencing VHD.
+#[derive(Clone, Debug)]
+struct VhdDynamic {
+    /// Size of a block of data, in bytes.
+    block_size: u32,
+    /// Size of the sector bitmap preceding the data of each allocated block, in bytes.
+    bitmap_size: u64,
+    /// Sector number of each allocated block, or [`VHD_BAT_ENTRY_UNUSED`].
+    bat: Vec<u32>,
+    /// Unique ID of the parent of a differencing VHD.
+    parent_id: Option<NtfsGuid>,
+    /// Paths to the parent of a differencing VHD, in the order they should be tried.
+    parent_locations: Vec<String>,
+}
+
+/// A reader for a virtual disk in the VHD format of Virtual PC and Hyper-V.
+///
+/// Fixed VHDs store the disk data as is, followed by a footer.
+/// Dynamic VHDs only store the blocks that have been allocated, each one preceded by a bitmap of the sectors
+/// that have been written.
+/// Differencing VHDs are dynamic VHDs that only store the sectors that differ from a parent VHD.
+/// The parent is set via [`NtfsVhdReader::set_parent`], or found automatically by [`NtfsVhdReader::open`].
+///
+/// A VHD usually contains an entire disk, so pass this reader to [`NtfsPartitionTable::new`] to find the
+/// NTFS partitions.
+///
+/// [`NtfsPartitionTable::new`]: crate::NtfsPartitionTable::new
+#[derive(Debug)]
+pub struct NtfsVhdReader<R> {
+    inner: R,
+    disk_size: u64,
+    id: NtfsGuid,
+    dynamic: Option<VhdDynamic>,
+    parent: Option<Box<NtfsVhdReader<R>>>,
+    /// Number and sector bitmap of the most recently used block.
+    bitmap: Option<(u64, Vec<u8>)>,
+    stream_position: u64,
+}
+
+impl<R> NtfsVhdReader<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new [`NtfsVhdReader`] for the given VHD image.
+    ///
+    /// This reads the footer and, for dynamic and differencing VHDs, the header and the Block Allocation Table (BAT).
+    /// The parent of a differencing VHD needs to be set separately via [`NtfsVhdReader::set_parent`].
+    pub fn new(mut inner: R) -> Result<Self> {
+        let file_size = inner.seek(SeekFrom::End(0))?;
+        let footer_position = file_size.checked_sub(VHD_FOOTER_SIZE as u64).ok_or(
+            NtfsError::InvalidVirtualDiskStructure {
+                structure: "footer",
+                position: NtfsPosition::none(),
+            },
+        )?;
+
+        // Dynamic and differencing VHDs have a copy of the footer at the beginning, which is used if the footer
+        // at the end is damaged.
+        let footer = match read_footer(&mut inner, footer_position) {
+            Ok(footer) => footer,
+            Err(e) => match read_footer(&mut inner, 0) {
+                Ok(footer) if footer.disk_type != VHD_DISK_TYPE_FIXED => footer,
+                _ => return Err(e),
+            },
+        };
+
+        let dynamic = match footer.disk_type {
+            VHD_DISK_TYPE_FIXED => {
+                if footer.disk_size > footer_position {
+                    return Err(NtfsError::InvalidVirtualDiskStructure {
+                        structure: "footer",
+                        position: NtfsPosition::new(footer_position),
+                    });
+                }
+
+                None
+            }
+            VHD_DISK_TYPE_DYNAMIC | VHD_DISK_TYPE_DIFFERENCING => Some(read_dynamic_header(
+                &mut inner,
+                &footer,
+                footer.disk_type == VHD_DISK_TYPE_DIFFERENCING,
+            )?),
+            actual => return Err(NtfsError::UnsupportedVirtualDiskType { actual }),
+        };
+
+        Ok(Self {
+            inner,
+            disk_size: footer.disk_size,
+            id: footer.id,
+            dynamic,
+            parent: None,
+            bitmap: None,
+            stream_position: 0,
+        })
+    }
+
+    /// Returns the sector bitmap of the given allocated block, reading it if it is not the most recently used one.
+    fn block_bitmap(&mut self, block: u64, sector: u32) -> Result<&[u8]> {
+        if !matches!(&self.bitmap, Some((number, _)) if *number == block) {
+            // This unwrap is safe, because only dynamic and differencing VHDs have sector bitmaps.
+            let dynamic = self.dynamic.as_ref().unwrap();
+            let mut bitmap = vec![0u8; dynamic.bitmap_size as usize];
+            self.inner
+                .seek(SeekFrom::Start(sector as u64 * VHD_SECTOR_SIZE))?;
+            self.inner.read_exact(&mut bitmap)?;
+            self.bitmap = Some((block, bitmap));
+        }
+
+        // This unwrap is safe, because the bitmap has just been stored.
+        Ok(&self.bitmap.as_ref().unwrap().1)
+    }
+
+    /// Returns the size of the virtual disk, in bytes.
+    pub fn disk_size(&self) -> u64 {
+        self.disk_size
+    }
+
+    /// Returns the unique ID of this VHD, which differencing VHDs use to refer to their parent.
+    pub fn id(&self) -> &NtfsGuid {
+        &self.id
+    }
+
+    /// Consumes this reader and returns the reader of this VHD image (without any parent).
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns `true` if this is a differencing VHD, which needs a parent.
+    pub fn is_differencing(&self) -> bool {
+        matches!(&self.dynamic, Some(dynamic) if dynamic.parent_id.is_some())
+    }
+
+    /// Returns the parent of this differencing VHD, if it has been set.
+    pub fn parent(&self) -> Option<&Self> {
+        self.parent.as_deref()
+    }
+
+    /// Returns the paths to the parent of this differencing VHD stored in the image, in the order they should
+    /// be tried.
+    ///
+    /// These are usually Windows paths, either relative to the directory of this VHD or absolute.
+    pub fn parent_locations(&self) -> &[String] {
+        match &self.dynamic {
+            Some(dynamic) => &dynamic.parent_locations,
+            None => &[],
+        }
+    }
+
+    fn read_dynamic(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // This unwrap is safe, because `read` only calls this function for dynamic and differencing VHDs.
+        let dynamic = self.dynamic.as_ref().unwrap();
+        let block_size = dynamic.block_size as u64;
+        let bitmap_size = dynamic.bitmap_size;
+        let is_differencing = dynamic.parent_id.is_some();
+
+        let block = self.stream_position / block_size;
+        let offset_in_block = self.stream_position % block_size;
+        let bytes_to_read = u64::min(buf.len() as u64, block_size - offset_in_block);
+        let sector = dynamic.bat[block as usize];
+
+        let (is_present, bytes_to_read) = if sector == VHD_BAT_ENTRY_UNUSED {
+            (false, bytes_to_read)
+        } else {
+            // Only read as many bytes as there are consecutive sectors that are either present or absent.
+            let first_sector = offset_in_block / VHD_SECTOR_SIZE;
+            let last_sector = (offset_in_block + bytes_to_read - 1) / VHD_SECTOR_SIZE;
+            let bitmap = self.block_bitmap(block, sector)?;
+            let (is_present, count) =
+                super::sector_run(bitmap, first_sector, last_sector - first_sector + 1, true);
+            let run_end = (first_sector + count) * VHD_SECTOR_SIZE;
+
+            (
+                is_present,
+                u64::min(bytes_to_read, run_end - offset_in_block),
+            )
+        };
+
+        let buf = &mut buf[..bytes_to_read as usize];
+
+        if is_present {
+            let position = sector as u64 * VHD_SECTOR_SIZE + bitmap_size + offset_in_block;
+            self.inner.seek(SeekFrom::Start(position))?;
+            Ok(self.inner.read(buf)?)
+        } else if is_differencing {
+            super::read_from_parent(self.parent.as_deref_mut(), self.stream_position, buf)
+        } else {
+            Ok(super::read_zeros(buf))
+        }
+    }
+
+    /// Sets the parent of this differencing VHD, which must have the unique ID this VHD refers to.
+    pub fn set_parent(&mut self, parent: Self) -> Result<()> {
+        let expected = match &self.dynamic {
+            Some(VhdDynamic {
+                parent_id: Some(parent_id),
+                ..
+            }) => parent_id,
+            _ => return Err(NtfsError::UnexpectedVirtualDiskParent),
+        };
+
+        if *expected != parent.id {
+            return Err(NtfsError::VirtualDiskParentMismatch {
+                expected: expected.clone(),
+                actual: parent.id,
+            });
+        }
+
+        self.parent = Some(Box::new(parent));
+        Ok(())
+    }
+}
+
+impl NtfsVhdReader<File> {
+    /// Opens the given VHD image.
+    ///
+    /// For a differencing VHD, this also opens its parent (and so on up the chain), using the parent locations
+    /// stored in the image.
+    /// See [`NtfsVhdReader::parent_locations`].
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_chain(path.as_ref(), &mut Vec::new())
+    }
+
+    fn open_chain(path: &Path, visited: &mut Vec<PathBuf>) -> Result<Self> {
+        super::visit_parent_chain(path, visited)?;
+        let mut reader = Self::new(File::open(path)?)?;
+
+        if reader.is_differencing() {
+            let parent_path = super::find_parent_path(path, reader.parent_locations())?;
+            let parent = Self::open_chain(&parent_path, visited)?;
+            reader.set_parent(parent)?;
+        }
+
+        Ok(reader)
+    }
+}
+
+impl<R> Read for NtfsVhdReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stream_position >= self.disk_size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let remaining = self.disk_size - self.stream_position;
+        let bytes_to_read = u64::min(buf.len() as u64, remaining) as usize;
+        let buf = &mut buf[..bytes_to_read];
+
+        let bytes_read = if self.dynamic.is_some() {
+            self.read_dynamic(buf)?
+        } else {
+            // The data of a fixed VHD starts at the beginning of the image.
+            self.inner.seek(SeekFrom::Start(self.stream_position))?;
+            self.inner.read(buf)?
+        };
+
+        self.stream_position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R> Seek for NtfsVhdReader<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_contiguous(&mut self.stream_position, self.disk_size, pos).map_err(io::Error::from)
+    }
+}
+
+/// The fields of a VHD footer that are needed to read the disk.
+struct VhdFooter {
+    data_offset: u64,
+    disk_size: u64,
+    disk_type: u32,
+    id: NtfsGuid,
+}
+
+/// Returns the checksum of a VHD structure, which is the one's complement of the sum of all bytes except
+/// the 4 bytes of the checksum at `checksum_offset`.
+fn checksum(data: &[u8], checksum_offset: usize) -> u32 {
+    let sum = data
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !(checksum_offset..checksum_offset + 4).contains(i))
+        .fold(0u32, |sum, (_, &byte)| sum.wrapping_add(byte as u32));
+
+    !sum
+}
+
+fn read_dynamic_header<R>(
+    inner: &mut R,
+    footer: &VhdFooter,
+    is_differencing: bool,
+) -> Result<VhdDynamic>
+where
+    R: Read + Seek,
+{
+    let position = footer.data_offset;
+    let invalid_header = || NtfsError::InvalidVirtualDiskStructure {
+        structure: "dynamic disk header",
+        position: NtfsPosition::new(position),
+    };
+
+    let mut header = [0u8; VHD_DYNAMIC_HEADER_SIZE];
+    inner.seek(SeekFrom::Start(position))?;
+    inner.read_exact(&mut header)?;
+    validate_cookie(&header, VHD_DYNAMIC_HEADER_COOKIE, position)?;
+
+    if BigEndian::read_u32(&header[36..]) != checksum(&header, 36) {
+        return Err(invalid_header());
+    }
+
+    let table_offset = BigEndian::read_u64(&header[16..]);
+    let max_table_entries = BigEndian::read_u32(&header[28..]);
+    let block_size = BigEndian::read_u32(&header[32..]);
+
+    if !block_size.is_power_of_two()
+        || (block_size as u64) < VHD_SECTOR_SIZE
+        || block_size > VHD_BLOCK_MAX_SIZE
+    {
+        return Err(invalid_header());
+    }
+
+    let block_count = footer
+        .disk_size
+        .checked_add(block_size as u64 - 1)
+        .ok_or_else(invalid_header)?
+        / block_size as u64;
+    if block_count > max_table_entries as u64 {
+        return Err(invalid_header());
+    }
+
+    // Never allocate more for the BAT than the image file can hold.
+    let bat_size = block_count * 4;
+    let file_size = inner.seek(SeekFrom::End(0))?;
+    if table_offset
+        .checked_add(bat_size)
+        .map_or(true, |bat_end| bat_end > file_size)
+    {
+        return Err(NtfsError::InvalidVirtualDiskStructure {
+            structure: "BAT",
+            position: NtfsPosition::new(table_offset),
+        });
+    }
+
+    let mut bat_data = vec![0u8; bat_size as usize];
+    inner.seek(SeekFrom::Start(table_offset))?;
+    inner.read_exact(&mut bat_data)?;
+    let bat = bat_data
+        .chunks_exact(4)
+        .map(BigEndian::read_u32)
+        .collect::<Vec<u32>>();
+
+    // The sector bitmap has one bit per sector and is padded to a full sector.
+    let sectors_per_block = block_size as u64 / VHD_SECTOR_SIZE;
+    let bitmap_bytes = (sectors_per_block + 7) / 8;
+    let bitmap_size = (bitmap_bytes + VHD_SECTOR_SIZE - 1) / VHD_SECTOR_SIZE * VHD_SECTOR_SIZE;
+
+    let (parent_id, parent_locations) = if is_differencing {
+        (
+            Some(NtfsGuid::from_bytes(&header[40..40 + GUID_SIZE])),
+            read_parent_locations(inner, &header)?,
+        )
+    } else {
+        (None, Vec::new())
+    };
+
+    Ok(VhdDynamic {
+        block_size,
+        bitmap_size,
+        bat,
+        parent_id,
+        parent_locations,
+    })
+}
+
+fn read_footer<R>(inner: &mut R, position: u64) -> Result<VhdFooter>
+where
+    R: Read + Seek,
+{
+    let mut footer = [0u8; VHD_FOOTER_SIZE];
+    inner.seek(SeekFrom::Start(position))?;
+    inner.read_exact(&mut footer)?;
+    validate_cookie(&footer, VHD_FOOTER_COOKIE, position)?;
+
+    if BigEndian::read_u32(&footer[64..]) != checksum(&footer, 64) {
+        return Err(NtfsError::InvalidVirtualDiskStructure {
+            structure: "footer",
+            position: NtfsPosition::new(position),
+        });
+    }
+
+    Ok(VhdFooter {
+        data_offset: BigEndian::read_u64(&footer[16..]),
+        disk_size: BigEndian::read_u64(&footer[48..]),
+        disk_type: BigEndian::read_u32(&footer[60..]),
+        id: NtfsGuid::from_bytes(&footer[68..68 + GUID_SIZE]),
+    })
+}
+
+/// Returns the parent locations stored in the header of a differencing VHD.
+///
+/// The relative and absolute Windows paths of the parent locator entries come first, followed by the name of
+/// the parent stored in the header itself.
+fn read_parent_locations<R>(inner: &mut R, header: &[u8]) -> Result<Vec<String>>
+where
+    R: Read + Seek,
+{
+    let mut locations = Vec::new();
+
+    for platform_code in VHD_PARENT_LOCATOR_PLATFORM_CODES {
+        for i in 0..VHD_PARENT_LOCATOR_COUNT {
+            let entry = &header[576 + i * VHD_PARENT_LOCATOR_SIZE..];
+            let data_length = BigEndian::read_u32(&entry[8..]);
+            let data_offset = BigEndian::read_u64(&entry[16..]);
+
+            if BigEndian::read_u32(&entry[0..]) != platform_code
+                || data_length == 0
+                || data_length > VHD_PARENT_LOCATOR_MAX_SIZE
+            {
+                continue;
+            }
+
+            let mut data = vec![0u8; data_length as usize];
+            inner.seek(SeekFrom::Start(data_offset))?;
+            inner.read_exact(&mut data)?;
+
+            let location = utf16_to_string(
+                data.chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]])),
+            );
+            if !location.is_empty() {
+                locations.push(location);
+            }
+        }
+    }
+
+    let parent_name = utf16_to_string(header[64..576].chunks_exact(2).map(BigEndian::read_u16));
+    if !parent_name.is_empty() {
+        locations.push(parent_name);
+    }
+
+    Ok(locations)
+}
+
+/// Decodes a UTF-16 string that may be terminated by a NUL character.
+fn utf16_to_string<I>(code_units: I) -> String
+where
+    I: Iterator<Item = u16>,
+{
+    char::decode_utf16(code_units.take_while(|&code_unit| code_unit != 0))
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn validate_cookie(data: &[u8], expected: &'static [u8], position: u64) -> Result<()> {
+    if &data[..expected.len()] != expected {
+        return Err(NtfsError::InvalidVirtualDiskSignature {
+            position: NtfsPosition::new(position),
+            expected,
+            // This unwrap is safe, because all VHD cookies have exactly 8 bytes.
+            actual: data[..8].try_into().unwrap(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::Ntfs;
+    use std::io::Cursor;
+
+    /// Block size of most dynamic VHDs built by the tests, which is smaller than usual to get more blocks.
+    const BLOCK_SIZE: usize = 64 * 1024;
+
+    fn build_footer(disk_type: u32, disk_size: u64, data_offset: u64, id: u8) -> Vec<u8> {
+        let mut footer = vec![0u8; VHD_FOOTER_SIZE];
+        footer[..8].copy_from_slice(VHD_FOOTER_COOKIE);
+        BigEndian::write_u64(&mut footer[16..], data_offset);
+        BigEndian::write_u64(&mut footer[40..], disk_size);
+        BigEndian::write_u64(&mut footer[48..], disk_size);
+        BigEndian::write_u32(&mut footer[60..], disk_type);
+        footer[68..84].fill(id);
+
+        let checksum = checksum(&footer, 64);
+        BigEndian::write_u32(&mut footer[64..], checksum);
+        footer
+    }
+
+    /// Builds a dynamic VHD, or a differencing VHD if `parent` (ID and relative path) is given,
+    /// which stores all sectors of `data` for which `is_present` returns `true`.
+    fn build_dynamic_vhd<F>(
+        data: &[u8],
+        id: u8,
+        parent: Option<(u8, &str)>,
+        is_present: F,
+    ) -> Vec<u8>
+    where
+        F: Fn(usize) -> bool,
+    {
+        build_dynamic_vhd_with_block_size(data, BLOCK_SIZE, id, parent, is_present)
+    }
+
+    fn build_dynamic_vhd_with_block_size<F>(
+        data: &[u8],
+        block_size: usize,
+        id: u8,
+        parent: Option<(u8, &str)>,
+        is_present: F,
+    ) -> Vec<u8>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let block_count = (data.len() + block_size - 1) / block_size;
+        let sectors_per_block = block_size / 512;
+        let disk_type = if parent.is_some() {
+            VHD_DISK_TYPE_DIFFERENCING
+        } else {
+            VHD_DISK_TYPE_DYNAMIC
+        };
+        let footer = build_footer(disk_type, data.len() as u64, 512, id);
+
+        // Footer copy, dynamic disk header, BAT, and parent locator data.
+        let bat_offset = 1536;
+        let locator_offset = bat_offset + (block_count * 4 + 511) / 512 * 512;
+        let mut image = vec![0u8; locator_offset + 512];
+        image[..512].copy_from_slice(&footer);
+
+        let header = &mut image[512..1536];
+        header[..8].copy_from_slice(VHD_DYNAMIC_HEADER_COOKIE);
+        BigEndian::write_u64(&mut header[8..], u64::MAX);
+        BigEndian::write_u64(&mut header[16..], bat_offset as u64);
+        BigEndian::write_u32(&mut header[24..], 0x0001_0000);
+        BigEndian::write_u32(&mut header[28..], block_count as u32);
+        BigEndian::write_u32(&mut header[32..], block_size as u32);
+
+        if let Some((parent_id, parent_path)) = parent {
+            header[40..56].fill(parent_id);
+
+            let file_name = parent_path.rsplit('\\').next().unwrap();
+            for (i, code_unit) in file_name.encode_utf16().enumerate() {
+                BigEndian::write_u16(&mut header[64 + i * 2..], code_unit);
+            }
+
+            let locator = &mut header[576..];
+            BigEndian::write_u32(&mut locator[0..], VHD_PARENT_LOCATOR_PLATFORM_CODES[0]);
+            BigEndian::write_u32(&mut locator[4..], 512);
+            BigEndian::write_u32(&mut locator[8..], parent_path.len() as u32 * 2);
+            BigEndian::write_u64(&mut locator[16..], locator_offset as u64);
+
+            for (i, code_unit) in parent_path.encode_utf16().enumerate() {
+                let position = locator_offset + i * 2;
+                image[position..position + 2].copy_from_slice(&code_unit.to_le_bytes());
+            }
+        }
+
+        let header = &mut image[512..1536];
+        let checksum = checksum(header, 36);
+        BigEndian::write_u32(&mut header[36..], checksum);
+
+        for block in 0..block_count {
+            let first_sector = block * sectors_per_block;
+            let present = (first_sector..first_sector + sectors_per_block)
+                .map(|sector| sector * 512 < data.len() && is_present(sector))
+                .collect::<Vec<bool>>();
+
+            let bat_entry = if present.contains(&true) {
+                let sector = (image.len() / 512) as u32;
+                let mut bitmap = [0u8; 512];
+                let mut block_data = vec![0xcc; block_size];
+
+                for (i, _) in present.iter().enumerate().filter(|(_, &p)| p) {
+                    bitmap[i / 8] |= 0x80 >> (i % 8);
+                    let position = (first_sector + i) * 512;
+                    block_data[i * 512..(i + 1) * 512]
+                        .copy_from_slice(&data[position..position + 512]);
+                }
+
+                image.extend_from_slice(&bitmap);
+                image.extend_from_slice(&block_data);
+                sector
+            } else {
+                VHD_BAT_ENTRY_UNUSED
+            };
+
+            BigEndian::write_u32(&mut image[bat_offset + block * 4..], bat_entry);
+        }
+
+        image.extend_from_slice(&footer);
+        image
+    }
+
+    fn read_all<R>(reader: &mut R) -> Vec<u8>
+    where
+        R: Read + Seek,
+    {
+        let mut data = Vec::new();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    fn assert_testfs1<R>(reader: &mut R)
+    where
+        R: Read + Seek,
+    {
+        let ntfs = Ntfs::new(reader).unwrap();
+        let file = ntfs.file(reader, 66).unwrap();
+        assert!(file.data(reader, "").is_some());
+    }
+
+    #[test]
+    fn test_vhd_fixed() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let mut image = testfs1.clone();
+        image.extend(build_footer(
+            VHD_DISK_TYPE_FIXED,
+            testfs1.len() as u64,
+            u64::MAX,
+            1,
+        ));
+
+        let mut reader = NtfsVhdReader::new(Cursor::new(image)).unwrap();
+        assert_eq!(reader.disk_size(), testfs1.len() as u64);
+        assert!(!reader.is_differencing());
+        assert_eq!(read_all(&mut reader), testfs1);
+        assert_testfs1(&mut reader);
+    }
+
+    #[test]
+    fn test_vhd_dynamic() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let image = build_dynamic_vhd(&testfs1, 1, None, |sector| {
+            testfs1[sector * 512..(sector + 1) * 512] != [0; 512]
+        });
+
+        let mut reader = NtfsVhdReader::new(Cursor::new(image.clone())).unwrap();
+        assert!(reader
+            .dynamic
+            .as_ref()
+            .unwrap()
+            .bat
+            .contains(&VHD_BAT_ENTRY_UNUSED));
+        assert_eq!(read_all(&mut reader), testfs1);
+        assert_testfs1(&mut reader);
+
+        // The copy of the footer at the beginning is used if the footer at the end is damaged.
+        let mut image = image;
+        let length = image.len();
+        image[length - 1] ^= 0xff;
+        let mut reader = NtfsVhdReader::new(Cursor::new(image)).unwrap();
+        assert_eq!(read_all(&mut reader), testfs1);
+    }
+
+    #[test]
+    fn test_vhd_differencing() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let parent_image = build_dynamic_vhd(&testfs1, 1, None, |_| true);
+
+        // The differencing VHD only stores the modified first sector of "1000-bytes-file".
+        let modified_sector = 2567;
+        let mut modified = testfs1.clone();
+        modified[modified_sector * 512..(modified_sector + 1) * 512].fill(b'x');
+        let child_image = build_dynamic_vhd(&modified, 2, Some((1, ".\\parent.vhd")), |sector| {
+            sector == modified_sector
+        });
+
+        let mut reader = NtfsVhdReader::new(Cursor::new(child_image)).unwrap();
+        assert!(reader.is_differencing());
+        assert_eq!(reader.parent_locations(), [".\\parent.vhd", "parent.vhd"]);
+
+        // The stored sector can be read without the parent, but nothing else.
+        let mut buf = [0u8; 512];
+        reader
+            .seek(SeekFrom::Start(modified_sector as u64 * 512))
+            .unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [b'x'; 512]);
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        assert!(reader.read_exact(&mut buf).is_err());
+
+        // The parent needs to have the ID the differencing VHD refers to.
+        let mut wrong_parent = testfs1.clone();
+        wrong_parent.extend(build_footer(
+            VHD_DISK_TYPE_FIXED,
+            testfs1.len() as u64,
+            u64::MAX,
+            3,
+        ));
+        let wrong_parent = NtfsVhdReader::new(Cursor::new(wrong_parent)).unwrap();
+        assert!(matches!(
+            reader.set_parent(wrong_parent),
+            Err(NtfsError::VirtualDiskParentMismatch { .. })
+        ));
+
+        let parent = NtfsVhdReader::new(Cursor::new(parent_image)).unwrap();
+        reader.set_parent(parent).unwrap();
+        assert_eq!(read_all(&mut reader), modified);
+        assert_testfs1(&mut reader);
+    }
+
+    #[test]
+    fn test_vhd_small_blocks() {
+        // With 2048-byte blocks, the sector bitmap of each block still takes an entire sector.
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let image = build_dynamic_vhd_with_block_size(&testfs1, 2048, 1, None, |_| true);
+
+        let mut reader = NtfsVhdReader::new(Cursor::new(image)).unwrap();
+        assert_eq!(reader.dynamic.as_ref().unwrap().bitmap_size, 512);
+        assert_eq!(read_all(&mut reader), testfs1);
+    }
+
+    #[test]
+    fn test_vhd_invalid_bat() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let image = build_dynamic_vhd(&testfs1, 1, None, |_| true);
+
+        // Replaces both footers with one announcing `disk_size` and allows any number of BAT entries.
+        let with_disk_size = |disk_size: u64| {
+            let mut image = image.clone();
+            let footer = build_footer(VHD_DISK_TYPE_DYNAMIC, disk_size, 512, 1);
+            let length = image.len();
+            image[..512].copy_from_slice(&footer);
+            image[length - 512..].copy_from_slice(&footer);
+
+            let header = &mut image[512..1536];
+            BigEndian::write_u32(&mut header[28..], u32::MAX);
+            let checksum = checksum(header, 36);
+            BigEndian::write_u32(&mut header[36..], checksum);
+            image
+        };
+
+        // The block count of a disk size close to the maximum overflows.
+        assert!(matches!(
+            NtfsVhdReader::new(Cursor::new(with_disk_size(u64::MAX - 1))),
+            Err(NtfsError::InvalidVirtualDiskStructure {
+                structure: "dynamic disk header",
+                ..
+            })
+        ));
+
+        // The BAT of a 1 TiB disk doesn't fit into the image file.
+        assert!(matches!(
+            NtfsVhdReader::new(Cursor::new(with_disk_size(1 << 40))),
+            Err(NtfsError::InvalidVirtualDiskStructure {
+                structure: "BAT",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,1208 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::attribute_value::seek_contiguous;
+use crate::error::{NtfsError, Result};
+use crate::guid::{NtfsGuid, GUID_SIZE};
+use crate::types::NtfsPosition;
+
+/// Signature at the beginning of every VHDX image.
+const VHDX_FILE_SIGNATURE: &[u8] = b"vhdxfile";
+/// Positions of the two copies of the VHDX header.
+const VHDX_HEADER_POSITIONS: [u64; 2] = [64 * 1024, 128 * 1024];
+/// Size of a VHDX header that is covered by its checksum, in bytes.
+const VHDX_HEADER_SIZE: usize = 4096;
+/// Signature of a VHDX header.
+const VHDX_HEADER_SIGNATURE: &[u8] = b"head";
+/// Positions of the two copies of the VHDX region table.
+const VHDX_REGION_TABLE_POSITIONS: [u64; 2] = [192 * 1024, 256 * 1024];
+/// Size of the VHDX region table and the metadata table, in bytes.
+const VHDX_TABLE_SIZE: usize = 64 * 1024;
+/// Signature of a VHDX region table.
+const VHDX_REGION_TABLE_SIGNATURE: &[u8] = b"regi";
+/// Signature of the VHDX metadata table.
+const VHDX_METADATA_TABLE_SIGNATURE: &[u8] = b"metadata";
+/// Size of the header of the region table and the metadata table, as well as of each of their entries, in bytes.
+const VHDX_TABLE_ENTRY_SIZE: usize = 32;
+/// Maximum number of entries in the region table and the metadata table.
+const VHDX_TABLE_MAX_ENTRIES: u32 = 2047;
+/// Upper limit for the size of a single metadata item, in bytes.
+const VHDX_METADATA_ITEM_MAX_SIZE: u32 = 1024 * 1024;
+/// Flag of a metadata table entry that marks an item which must be understood to read the disk.
+const VHDX_METADATA_IS_REQUIRED: u32 = 1 << 2;
+/// Flag of the file parameters that marks a differencing disk.
+const VHDX_FILE_PARAMETERS_HAS_PARENT: u32 = 1 << 1;
+/// Minimum and maximum size of a payload block, in bytes.
+const VHDX_BLOCK_SIZE_RANGE: [u32; 2] = [1024 * 1024, 256 * 1024 * 1024];
+/// Number of sectors described by a sector bitmap block.
+const VHDX_SECTOR_BITMAP_SECTORS: u64 = 1 << 23;
+/// Unit of the file offsets stored in BAT entries, in bytes.
+const VHDX_BAT_FILE_OFFSET_UNIT: u64 = 1024 * 1024;
+/// Mask for the state stored in a BAT entry.
+const VHDX_BAT_STATE_MASK: u64 = 0x7;
+/// BAT entry state of a payload block that is not stored in this disk.
+const VHDX_PAYLOAD_BLOCK_NOT_PRESENT: u64 = 0;
+/// BAT entry state of a payload block whose data is undefined.
+const VHDX_PAYLOAD_BLOCK_UNDEFINED: u64 = 1;
+/// BAT entry state of a payload block that only contains zeros.
+const VHDX_PAYLOAD_BLOCK_ZERO: u64 = 2;
+/// BAT entry state of a payload block that has been unmapped (trimmed).
+const VHDX_PAYLOAD_BLOCK_UNMAPPED: u64 = 3;
+/// BAT entry state of a payload block that is entirely stored in this disk.
+const VHDX_PAYLOAD_BLOCK_FULLY_PRESENT: u64 = 6;
+/// BAT entry state of a payload block of a differencing disk whose sector bitmap tells which sectors are stored
+/// in this disk.
+const VHDX_PAYLOAD_BLOCK_PARTIALLY_PRESENT: u64 = 7;
+/// BAT entry state of a sector bitmap block that is stored in this disk.
+const VHDX_SB_BLOCK_PRESENT: u64 = 6;
+
+/// GUID of the region containing the Block Allocation Table (BAT).
+const VHDX_REGION_BAT: NtfsGuid = NtfsGuid {
+    data1: 0x2dc27766,
+    data2: 0xf623,
+    data3: 0x4200,
+    data4: [0x9d, 0x64, 0x11, 0x5e, 0x9b, 0xfd, 0x4a, 0x08],
+};
+/// GUID of the region containing the metadata.
+const VHDX_REGION_METADATA: NtfsGuid = NtfsGuid {
+    data1: 0x8b7ca206,
+    data2: 0x4790,
+    data3: 0x4b9a,
+    data4: [0xb8, 0xfe, 0x57, 0x5f, 0x05, 0x0f, 0x88, 0x6e],
+};
+/// GUID of the metadata item containing the block size and the flags of the disk.
+const VHDX_METADATA_FILE_PARAMETERS: NtfsGuid = NtfsGuid {
+    data1: 0xcaa16737,
+    data2: 0xfa36,
+    data3: 0x4d43,
+    data4: [0xb3, 0xb6, 0x33, 0xf0, 0xaa, 0x44, 0xe7, 0x6b],
+};
+/// GUID of the metadata item containing the size of the virtual disk.
+const VHDX_METADATA_VIRTUAL_DISK_SIZE: NtfsGuid = NtfsGuid {
+    data1: 0x2fa54224,
+    data2: 0xcd1b,
+    data3: 0x4876,
+    data4: [0xb2, 0x11, 0x5d, 0xbe, 0xd8, 0x3b, 0xf4, 0xb8],
+};
+/// GUID of the metadata item containing the ID of the virtual disk.
+const VHDX_METADATA_VIRTUAL_DISK_ID: NtfsGuid = NtfsGuid {
+    data1: 0xbeca12ab,
+    data2: 0xb2e6,
+    data3: 0x4523,
+    data4: [0x93, 0xef, 0xc3, 0x09, 0xe0, 0x00, 0xc7, 0x46],
+};
+/// GUID of the metadata item containing the logical sector size of the virtual disk.
+const VHDX_METADATA_LOGICAL_SECTOR_SIZE: NtfsGuid = NtfsGuid {
+    data1: 0x8141bf1d,
+    data2: 0xa96f,
+    data3: 0x4709,
+    data4: [0xba, 0x47, 0xf2, 0x33, 0xa8, 0xfa, 0xab, 0x5f],
+};
+/// GUID of the metadata item containing the physical sector size of the virtual disk.
+const VHDX_METADATA_PHYSICAL_SECTOR_SIZE: NtfsGuid = NtfsGuid {
+    data1: 0xcda348c7,
+    data2: 0x445d,
+    data3: 0x4471,
+    data4: [0x9c, 0xc9, 0xe9, 0x88, 0x52, 0x51, 0xc5, 0x56],
+};
+/// GUID of the metadata item locating the parent of a differencing disk.
+const VHDX_METADATA_PARENT_LOCATOR: NtfsGuid = NtfsGuid {
+    data1: 0xa8d35f2d,
+    data2: 0xb30b,
+    data3: 0x454d,
+    data4: [0xab, 0xf7, 0xd3, 0xd8, 0x48, 0x34, 0xab, 0x0c],
+};
+
+/// Keys of the parent locator entries that store a path to the parent, in the order they are tried.
+const VHDX_PARENT_LOCATOR_PATH_KEYS: [&str; 3] =
+    ["relative_path", "volume_path", "absolute_win32_path"];
+/// Keys of the parent locator entries that store the Data Write GUID of the parent.
+const VHDX_PARENT_LOCATOR_LINKAGE_KEYS: [&str; 2] = ["parent_linkage", "parent_linkage2"];
+
+/// A reader for a virtual disk in the VHDX format of Hyper-V.
+///
+/// The data of the virtual disk is stored in payload blocks of 1 MiB to 256 MiB, which are only present in the
+/// image if they have been allocated.
+/// Differencing VHDX images additionally store a sector bitmap for each partially present block, telling which
+/// sectors differ from the parent VHDX.
+/// The parent is set via [`NtfsVhdxReader::set_parent`], or found automatically by [`NtfsVhdxReader::open`].
+///
+/// Images with a non-empty log (which Hyper-V replays after a crash) are not supported.
+///
+/// A VHDX usually contains an entire disk, so pass this reader to [`NtfsPartitionTable::new`] to find the
+/// NTFS partitions.
+///
+/// [`NtfsPartitionTable::new`]: crate::NtfsPartitionTable::new
+#[derive(Debug)]
+pub struct NtfsVhdxReader<R> {
+    inner: R,
+    disk_size: u64,
+    id: NtfsGuid,
+    data_write_guid: NtfsGuid,
+    block_size: u32,
+    logical_sector_size: u32,
+    /// Number of payload blocks described by a single sector bitmap block.
+    chunk_ratio: u64,
+    bat: Vec<u64>,
+    bat_position: u64,
+    /// Data Write GUIDs the parent of a differencing VHDX may have.
+    parent_linkage: Vec<NtfsGuid>,
+    parent_locations: Vec<String>,
+    parent: Option<Box<NtfsVhdxReader<R>>>,
+    /// Number and sector bitmap of the most recently used payload block.
+    bitmap: Option<(u64, Vec<u8>)>,
+    stream_position: u64,
+}
+
+impl<R> NtfsVhdxReader<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new [`NtfsVhdxReader`] for the given VHDX image.
+    ///
+    /// This reads the current header, the region table, the metadata, and the Block Allocation Table (BAT).
+    /// The parent of a differencing VHDX needs to be set separately via [`NtfsVhdxReader::set_parent`].
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut signature = [0u8; 8];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut signature)?;
+        if signature != VHDX_FILE_SIGNATURE {
+            return Err(NtfsError::InvalidVirtualDiskSignature {
+                position: NtfsPosition::none(),
+                expected: VHDX_FILE_SIGNATURE,
+                actual: signature,
+            });
+        }
+
+        // The valid header with the higher sequence number is the current one.
+        let mut header = None::<VhdxHeader>;
+        for position in VHDX_HEADER_POSITIONS {
+            if let Some(candidate) = read_header(&mut inner, position)? {
+                if header.as_ref().map_or(true, |header| {
+                    candidate.sequence_number > header.sequence_number
+                }) {
+                    header = Some(candidate);
+                }
+            }
+        }
+
+        let header = header.ok_or(NtfsError::InvalidVirtualDiskStructure {
+            structure: "header",
+            position: NtfsPosition::new(VHDX_HEADER_POSITIONS[0]),
+        })?;
+        if header.has_log {
+            return Err(NtfsError::UnsupportedVhdxLog);
+        }
+
+        let regions = read_region_table(&mut inner)?;
+        let metadata = read_metadata(&mut inner, &regions)?;
+        let invalid_metadata = || NtfsError::InvalidVirtualDiskStructure {
+            structure: "metadata",
+            position: NtfsPosition::new(regions.metadata.0),
+        };
+
+        let block_size = metadata.block_size.ok_or_else(invalid_metadata)?;
+        let disk_size = metadata.disk_size.ok_or_else(invalid_metadata)?;
+        let id = metadata.id.clone().ok_or_else(invalid_metadata)?;
+        let logical_sector_size = metadata.logical_sector_size.ok_or_else(invalid_metadata)?;
+        if !block_size.is_power_of_two()
+            || !(VHDX_BLOCK_SIZE_RANGE[0]..=VHDX_BLOCK_SIZE_RANGE[1]).contains(&block_size)
+            || !matches!(logical_sector_size, 512 | 4096)
+        {
+            return Err(invalid_metadata());
+        }
+
+        let (parent_linkage, parent_locations) = match &metadata.parent_locator {
+            Some(parent_locator) if metadata.has_parent => {
+                parse_parent_locator(parent_locator).ok_or_else(invalid_metadata)?
+            }
+            None if metadata.has_parent => return Err(invalid_metadata()),
+            _ => (Vec::new(), Vec::new()),
+        };
+
+        // Each group of `chunk_ratio` payload block entries is followed by a sector bitmap block entry.
+        // Only differencing disks need the sector bitmap block entry of the last group.
+        let chunk_ratio =
+            VHDX_SECTOR_BITMAP_SECTORS * logical_sector_size as u64 / block_size as u64;
+        let block_count = disk_size
+            .checked_add(block_size as u64 - 1)
+            .ok_or_else(invalid_metadata)?
+            / block_size as u64;
+        let bat_entries = if metadata.has_parent {
+            ((block_count + chunk_ratio - 1) / chunk_ratio).checked_mul(chunk_ratio + 1)
+        } else {
+            block_count.checked_add(block_count.saturating_sub(1) / chunk_ratio)
+        };
+
+        // Never allocate more for the BAT than its region and the image file can hold.
+        let (bat_position, bat_length) = regions.bat;
+        let file_size = inner.seek(SeekFrom::End(0))?;
+        let bat_size = bat_entries.and_then(|bat_entries| bat_entries.checked_mul(8));
+        if bat_size.map_or(true, |bat_size| {
+            bat_size > bat_length as u64
+                || bat_position
+                    .checked_add(bat_size)
+                    .map_or(true, |bat_end| bat_end > file_size)
+        }) {
+            return Err(NtfsError::InvalidVirtualDiskStructure {
+                structure: "BAT",
+                position: NtfsPosition::new(bat_position),
+            });
+        }
+
+        // This unwrap is safe, because `bat_size` has just been checked.
+        let mut bat_data = vec![0u8; bat_size.unwrap() as usize];
+        inner.seek(SeekFrom::Start(bat_position))?;
+        inner.read_exact(&mut bat_data)?;
+        let bat = bat_data
+            .chunks_exact(8)
+            .map(LittleEndian::read_u64)
+            .collect::<Vec<u64>>();
+
+        Ok(Self {
+            inner,
+            disk_size,
+            id,
+            data_write_guid: header.data_write_guid,
+            block_size,
+            logical_sector_size,
+            chunk_ratio,
+            bat,
+            bat_position,
+            parent_linkage,
+            parent_locations,
+            parent: None,
+            bitmap: None,
+            stream_position: 0,
+        })
+    }
+
+    /// Returns the BAT entry at the given index, or an error if it has an invalid state.
+    fn bat_entry(&self, index: u64, valid_states: &[u64]) -> Result<(u64, u64)> {
+        let entry = self.bat[index as usize];
+        let state = entry & VHDX_BAT_STATE_MASK;
+        let file_offset = (entry >> 20) * VHDX_BAT_FILE_OFFSET_UNIT;
+
+        if !valid_states.contains(&state) {
+            return Err(NtfsError::InvalidVirtualDiskStructure {
+                structure: "BAT entry",
+                position: NtfsPosition::new(self.bat_position + index * 8),
+            });
+        }
+
+        Ok((state, file_offset))
+    }
+
+    /// Returns the part of the sector bitmap that describes the sectors of the given payload block,
+    /// reading it if it is not the most recently used one.
+    fn block_bitmap(&mut self, block: u64) -> Result<&[u8]> {
+        if !matches!(&self.bitmap, Some((number, _)) if *number == block) {
+            let chunk = block / self.chunk_ratio;
+            let bat_index = chunk * (self.chunk_ratio + 1) + self.chunk_ratio;
+            let (_, file_offset) = self.bat_entry(bat_index, &[VHDX_SB_BLOCK_PRESENT])?;
+
+            let sectors_per_block = self.block_size as u64 / self.logical_sector_size as u64;
+            let offset_in_bitmap = (block % self.chunk_ratio) * sectors_per_block / 8;
+            let mut bitmap = vec![0u8; (sectors_per_block / 8) as usize];
+            self.inner
+                .seek(SeekFrom::Start(file_offset + offset_in_bitmap))?;
+            self.inner.read_exact(&mut bitmap)?;
+            self.bitmap = Some((block, bitmap));
+        }
+
+        // This unwrap is safe, because the bitmap has just been stored.
+        Ok(&self.bitmap.as_ref().unwrap().1)
+    }
+
+    /// Returns the Data Write GUID of this VHDX, which differencing VHDX images use to refer to their parent.
+    ///
+    /// It changes whenever the data of the virtual disk is modified.
+    pub fn data_write_guid(&self) -> &NtfsGuid {
+        &self.data_write_guid
+    }
+
+    /// Returns the size of the virtual disk, in bytes.
+    pub fn disk_size(&self) -> u64 {
+        self.disk_size
+    }
+
+    /// Returns the ID of the virtual disk, which stays the same for its entire lifetime.
+    pub fn id(&self) -> &NtfsGuid {
+        &self.id
+    }
+
+    /// Consumes this reader and returns the reader of this VHDX image (without any parent).
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns `true` if this is a differencing VHDX, which needs a parent.
+    pub fn is_differencing(&self) -> bool {
+        !self.parent_linkage.is_empty()
+    }
+
+    /// Returns the logical sector size of the virtual disk, in bytes.
+    pub fn logical_sector_size(&self) -> u32 {
+        self.logical_sector_size
+    }
+
+    /// Returns the parent of this differencing VHDX, if it has been set.
+    pub fn parent(&self) -> Option<&Self> {
+        self.parent.as_deref()
+    }
+
+    /// Returns the paths to the parent of this differencing VHDX stored in the image, in the order they should
+    /// be tried.
+    ///
+    /// These are usually Windows paths, either relative to the directory of this VHDX or absolute.
+    pub fn parent_locations(&self) -> &[String] {
+        &self.parent_locations
+    }
+
+    fn read_block(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let block_size = self.block_size as u64;
+        let block = self.stream_position / block_size;
+        let offset_in_block = self.stream_position % block_size;
+        let bytes_to_read = u64::min(buf.len() as u64, block_size - offset_in_block);
+
+        let bat_index = block + block / self.chunk_ratio;
+        let (state, file_offset) = if self.is_differencing() {
+            self.bat_entry(
+                bat_index,
+                &[
+                    VHDX_PAYLOAD_BLOCK_NOT_PRESENT,
+                    VHDX_PAYLOAD_BLOCK_UNDEFINED,
+                    VHDX_PAYLOAD_BLOCK_ZERO,
+                    VHDX_PAYLOAD_BLOCK_UNMAPPED,
+                    VHDX_PAYLOAD_BLOCK_FULLY_PRESENT,
+                    VHDX_PAYLOAD_BLOCK_PARTIALLY_PRESENT,
+                ],
+            )?
+        } else {
+            self.bat_entry(
+                bat_index,
+                &[
+                    VHDX_PAYLOAD_BLOCK_NOT_PRESENT,
+                    VHDX_PAYLOAD_BLOCK_UNDEFINED,
+                    VHDX_PAYLOAD_BLOCK_ZERO,
+                    VHDX_PAYLOAD_BLOCK_UNMAPPED,
+                    VHDX_PAYLOAD_BLOCK_FULLY_PRESENT,
+                ],
+            )?
+        };
+
+        let (source, bytes_to_read) = match state {
+            VHDX_PAYLOAD_BLOCK_FULLY_PRESENT => (BlockSource::Image, bytes_to_read),
+            VHDX_PAYLOAD_BLOCK_PARTIALLY_PRESENT => {
+                // Only read as many bytes as there are consecutive sectors that are either present or absent.
+                let sector_size = self.logical_sector_size as u64;
+                let first_sector = offset_in_block / sector_size;
+                let last_sector = (offset_in_block + bytes_to_read - 1) / sector_size;
+                let bitmap = self.block_bitmap(block)?;
+                let (is_present, count) =
+                    super::sector_run(bitmap, first_sector, last_sector - first_sector + 1, false);
+                let run_end = (first_sector + count) * sector_size;
+                let source = if is_present {
+                    BlockSource::Image
+                } else {
+                    BlockSource::Parent
+                };
+
+                (source, u64::min(bytes_to_read, run_end - offset_in_block))
+            }
+            VHDX_PAYLOAD_BLOCK_NOT_PRESENT if self.is_differencing() => {
+                (BlockSource::Parent, bytes_to_read)
+            }
+            _ => (BlockSource::Zeros, bytes_to_read),
+        };
+
+        let buf = &mut buf[..bytes_to_read as usize];
+
+        match source {
+            BlockSource::Image => {
+                self.inner
+                    .seek(SeekFrom::Start(file_offset + offset_in_block))?;
+                Ok(self.inner.read(buf)?)
+            }
+            BlockSource::Parent => {
+                super::read_from_parent(self.parent.as_deref_mut(), self.stream_position, buf)
+            }
+            BlockSource::Zeros => Ok(super::read_zeros(buf)),
+        }
+    }
+
+    /// Sets the parent of this differencing VHDX, which must have the Data Write GUID this VHDX refers to.
+    pub fn set_parent(&mut self, parent: Self) -> Result<()> {
+        let expected = match self.parent_linkage.first() {
+            Some(expected) => expected,
+            None => return Err(NtfsError::UnexpectedVirtualDiskParent),
+        };
+
+        if !self.parent_linkage.contains(&parent.data_write_guid) {
+            return Err(NtfsError::VirtualDiskParentMismatch {
+                expected: expected.clone(),
+                actual: parent.data_write_guid,
+            });
+        }
+
+        self.parent = Some(Box::new(parent));
+        Ok(())
+    }
+}
+
+impl NtfsVhdxReader<File> {
+    /// Opens the given VHDX image.
+    ///
+    /// For a differencing VHDX, this also opens its parent (and so on up the chain), using the parent locations
+    /// stored in the image.
+    /// See [`NtfsVhdxReader::parent_locations`].
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_chain(path.as_ref(), &mut Vec::new())
+    }
+
+    fn open_chain(path: &Path, visited: &mut Vec<PathBuf>) -> Result<Self> {
+        super::visit_parent_chain(path, visited)?;
+        let mut reader = Self::new(File::open(path)?)?;
+
+        if reader.is_differencing() {
+            let parent_path = super::find_parent_path(path, reader.parent_locations())?;
+            let parent = Self::open_chain(&parent_path, visited)?;
+            reader.set_parent(parent)?;
+        }
+
+        Ok(reader)
+    }
+}
+
+impl<R> Read for NtfsVhdxReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stream_position >= self.disk_size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let remaining = self.disk_size - self.stream_position;
+        let bytes_to_read = u64::min(buf.len() as u64, remaining) as usize;
+        let bytes_read = self.read_block(&mut buf[..bytes_to_read])?;
+
+        self.stream_position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R> Seek for NtfsVhdxReader<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_contiguous(&mut self.stream_position, self.disk_size, pos).map_err(io::Error::from)
+    }
+}
+
+/// Where the data of a part of a payload block comes from.
+enum BlockSource {
+    Image,
+    Parent,
+    Zeros,
+}
+
+/// The fields of a VHDX header that are needed to read the disk.
+struct VhdxHeader {
+    sequence_number: u64,
+    data_write_guid: NtfsGuid,
+    has_log: bool,
+}
+
+/// The known items of the VHDX metadata.
+#[derive(Default)]
+struct VhdxMetadata {
+    block_size: Option<u32>,
+    has_parent: bool,
+    disk_size: Option<u64>,
+    id: Option<NtfsGuid>,
+    logical_sector_size: Option<u32>,
+    parent_locator: Option<Vec<u8>>,
+}
+
+/// Positions and lengths of the known VHDX regions.
+struct VhdxRegions {
+    bat: (u64, u32),
+    metadata: (u64, u32),
+}
+
+/// Returns the CRC-32C (Castagnoli) checksum of `data`, as used by VHDX.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+/// Returns whether `data` has the given signature and a valid CRC-32C checksum at byte 4.
+fn has_valid_checksum(data: &mut [u8], signature: &[u8]) -> bool {
+    if &data[..signature.len()] != signature {
+        return false;
+    }
+
+    // The checksum is calculated with the checksum field set to zero.
+    let checksum = LittleEndian::read_u32(&data[4..]);
+    data[4..8].fill(0);
+    crc32c(data) == checksum
+}
+
+/// Parses a GUID in its registry format, like "{2DC27766-F623-4200-9D64-115E9BFD4A08}".
+fn parse_guid(string: &str) -> Option<NtfsGuid> {
+    let string = string.trim_start_matches('{').trim_end_matches('}');
+    let mut parts = string.split('-');
+    let mut next_part = |length: usize| {
+        parts
+            .next()
+            .filter(|part| part.len() == length && part.bytes().all(|b| b.is_ascii_hexdigit()))
+    };
+
+    let data1 = u32::from_str_radix(next_part(8)?, 16).ok()?;
+    let data2 = u16::from_str_radix(next_part(4)?, 16).ok()?;
+    let data3 = u16::from_str_radix(next_part(4)?, 16).ok()?;
+    let data4_hex = [next_part(4)?, next_part(12)?].concat();
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let mut data4 = [0u8; 8];
+    for (i, byte) in data4.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&data4_hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(NtfsGuid {
+        data1,
+        data2,
+        data3,
+        data4,
+    })
+}
+
+/// Parses the parent locator metadata item of a differencing VHDX, which consists of UTF-16 key/value pairs.
+///
+/// Returns the possible Data Write GUIDs of the parent and the paths to it, or `None` if the parent locator
+/// is invalid.
+fn parse_parent_locator(data: &[u8]) -> Option<(Vec<NtfsGuid>, Vec<String>)> {
+    let utf16_at = |offset: u32, length: u16| {
+        let bytes = data.get(offset as usize..offset as usize + length as usize)?;
+        char::decode_utf16(bytes.chunks_exact(2).map(LittleEndian::read_u16))
+            .collect::<core::result::Result<String, _>>()
+            .ok()
+    };
+
+    let key_value_count = LittleEndian::read_u16(data.get(18..20)?);
+    let mut entries = Vec::with_capacity(key_value_count as usize);
+
+    for i in 0..key_value_count as usize {
+        let entry = data.get(20 + i * 12..32 + i * 12)?;
+        let key = utf16_at(
+            LittleEndian::read_u32(&entry[0..]),
+            LittleEndian::read_u16(&entry[8..]),
+        )?;
+        let value = utf16_at(
+            LittleEndian::read_u32(&entry[4..]),
+            LittleEndian::read_u16(&entry[10..]),
+        )?;
+        entries.push((key, value));
+    }
+
+    let value_of = |wanted_key: &str| {
+        entries
+            .iter()
+            .find(|(key, _)| key == wanted_key)
+            .map(|(_, value)| value)
+    };
+
+    let parent_linkage = VHDX_PARENT_LOCATOR_LINKAGE_KEYS
+        .iter()
+        .filter_map(|key| value_of(key))
+        .map(|value| parse_guid(value))
+        .collect::<Option<Vec<NtfsGuid>>>()?;
+    if parent_linkage.is_empty() {
+        return None;
+    }
+
+    let parent_locations = VHDX_PARENT_LOCATOR_PATH_KEYS
+        .iter()
+        .filter_map(|key| value_of(key).cloned())
+        .collect();
+
+    Some((parent_linkage, parent_locations))
+}
+
+fn read_header<R>(inner: &mut R, position: u64) -> Result<Option<VhdxHeader>>
+where
+    R: Read + Seek,
+{
+    let mut header = vec![0u8; VHDX_HEADER_SIZE];
+    inner.seek(SeekFrom::Start(position))?;
+    inner.read_exact(&mut header)?;
+
+    let version = LittleEndian::read_u16(&header[66..]);
+    if !has_valid_checksum(&mut header, VHDX_HEADER_SIGNATURE) || version != 1 {
+        return Ok(None);
+    }
+
+    Ok(Some(VhdxHeader {
+        sequence_number: LittleEndian::read_u64(&header[8..]),
+        data_write_guid: NtfsGuid::from_bytes(&header[32..32 + GUID_SIZE]),
+        has_log: header[48..48 + GUID_SIZE] != [0; GUID_SIZE],
+    }))
+}
+
+fn read_metadata<R>(inner: &mut R, regions: &VhdxRegions) -> Result<VhdxMetadata>
+where
+    R: Read + Seek,
+{
+    let (position, length) = regions.metadata;
+    let invalid_metadata = || NtfsError::InvalidVirtualDiskStructure {
+        structure: "metadata",
+        position: NtfsPosition::new(position),
+    };
+
+    if (length as usize) < VHDX_TABLE_SIZE {
+        return Err(invalid_metadata());
+    }
+
+    let mut table = vec![0u8; VHDX_TABLE_SIZE];
+    inner.seek(SeekFrom::Start(position))?;
+    inner.read_exact(&mut table)?;
+
+    if &table[..8] != VHDX_METADATA_TABLE_SIGNATURE {
+        return Err(NtfsError::InvalidVirtualDiskSignature {
+            position: NtfsPosition::new(position),
+            expected: VHDX_METADATA_TABLE_SIGNATURE,
+            // This unwrap is safe, because we have just checked the slice length.
+            actual: table[..8].try_into().unwrap(),
+        });
+    }
+
+    let entry_count = LittleEndian::read_u16(&table[10..]) as u32;
+    if entry_count > VHDX_TABLE_MAX_ENTRIES {
+        return Err(invalid_metadata());
+    }
+
+    let mut metadata = VhdxMetadata::default();
+
+    for i in 0..entry_count as usize {
+        let entry = &table[(i + 1) * VHDX_TABLE_ENTRY_SIZE..(i + 2) * VHDX_TABLE_ENTRY_SIZE];
+        let item_id = NtfsGuid::from_bytes(&entry[..GUID_SIZE]);
+        let item_offset = LittleEndian::read_u32(&entry[16..]);
+        let item_length = LittleEndian::read_u32(&entry[20..]);
+        let flags = LittleEndian::read_u32(&entry[24..]);
+
+        let is_known = [
+            VHDX_METADATA_FILE_PARAMETERS,
+            VHDX_METADATA_VIRTUAL_DISK_SIZE,
+            VHDX_METADATA_VIRTUAL_DISK_ID,
+            VHDX_METADATA_LOGICAL_SECTOR_SIZE,
+            VHDX_METADATA_PHYSICAL_SECTOR_SIZE,
+            VHDX_METADATA_PARENT_LOCATOR,
+        ]
+        .contains(&item_id);
+        if !is_known {
+            if flags & VHDX_METADATA_IS_REQUIRED != 0 {
+                return Err(NtfsError::UnsupportedVhdxItem { guid: item_id });
+            }
+
+            continue;
+        }
+
+        if item_length > VHDX_METADATA_ITEM_MAX_SIZE
+            || item_offset as u64 + item_length as u64 > length as u64
+        {
+            return Err(invalid_metadata());
+        }
+
+        let mut item = vec![0u8; item_length as usize];
+        inner.seek(SeekFrom::Start(position + item_offset as u64))?;
+        inner.read_exact(&mut item)?;
+
+        if item_id == VHDX_METADATA_PARENT_LOCATOR {
+            metadata.parent_locator = Some(item);
+            continue;
+        }
+
+        // All other known items have a fixed size.
+        let expected_length = match item_id {
+            VHDX_METADATA_FILE_PARAMETERS | VHDX_METADATA_VIRTUAL_DISK_SIZE => 8,
+            VHDX_METADATA_VIRTUAL_DISK_ID => GUID_SIZE,
+            _ => 4,
+        };
+        if item.len() < expected_length {
+            return Err(invalid_metadata());
+        }
+
+        match item_id {
+            VHDX_METADATA_FILE_PARAMETERS => {
+                metadata.block_size = Some(LittleEndian::read_u32(&item[0..]));
+                metadata.has_parent =
+                    LittleEndian::read_u32(&item[4..]) & VHDX_FILE_PARAMETERS_HAS_PARENT != 0;
+            }
+            VHDX_METADATA_VIRTUAL_DISK_SIZE => {
+                metadata.disk_size = Some(LittleEndian::read_u64(&item));
+            }
+            VHDX_METADATA_VIRTUAL_DISK_ID => metadata.id = Some(NtfsGuid::from_bytes(&item)),
+            VHDX_METADATA_LOGICAL_SECTOR_SIZE => {
+                metadata.logical_sector_size = Some(LittleEndian::read_u32(&item));
+            }
+            _ => (),
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn read_region_table<R>(inner: &mut R) -> Result<VhdxRegions>
+where
+    R: Read + Seek,
+{
+    let mut table = vec![0u8; VHDX_TABLE_SIZE];
+    let mut found_position = None;
+
+    // Both copies of the region table are identical, so the first valid one is used.
+    for position in VHDX_REGION_TABLE_POSITIONS {
+        inner.seek(SeekFrom::Start(position))?;
+        inner.read_exact(&mut table)?;
+
+        if has_valid_checksum(&mut table, VHDX_REGION_TABLE_SIGNATURE)
+            && LittleEndian::read_u32(&table[8..]) <= VHDX_TABLE_MAX_ENTRIES
+        {
+            found_position = Some(position);
+            break;
+        }
+    }
+
+    let position = found_position.ok_or(NtfsError::InvalidVirtualDiskStructure {
+        structure: "region table",
+        position: NtfsPosition::new(VHDX_REGION_TABLE_POSITIONS[0]),
+    })?;
+
+    let mut bat = None;
+    let mut metadata = None;
+    let entry_count = LittleEndian::read_u32(&table[8..]) as usize;
+
+    for i in 0..entry_count {
+        // The region table header has a size of 16 bytes.
+        let entry = &table[16 + i * VHDX_TABLE_ENTRY_SIZE..16 + (i + 1) * VHDX_TABLE_ENTRY_SIZE];
+        let guid = NtfsGuid::from_bytes(&entry[..GUID_SIZE]);
+        let region = (
+            LittleEndian::read_u64(&entry[16..]),
+            LittleEndian::read_u32(&entry[24..]),
+        );
+        let is_required = LittleEndian::read_u32(&entry[28..]) & 1 != 0;
+
+        if guid == VHDX_REGION_BAT {
+            bat = Some(region);
+        } else if guid == VHDX_REGION_METADATA {
+            metadata = Some(region);
+        } else if is_required {
+            return Err(NtfsError::UnsupportedVhdxItem { guid });
+        }
+    }
+
+    match (bat, metadata) {
+        (Some(bat), Some(metadata)) => Ok(VhdxRegions { bat, metadata }),
+        _ => Err(NtfsError::InvalidVirtualDiskStructure {
+            structure: "region table",
+            position: NtfsPosition::new(position),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::Ntfs;
+    use crate::partition::NtfsPartitionTable;
+    use std::io::Cursor;
+
+    const MIB: usize = 1024 * 1024;
+    /// Block size of the VHDX images built by the tests.
+    const BLOCK_SIZE: usize = MIB;
+    /// BAT index of the sector bitmap block entry of the first chunk (for 512-byte sectors and 1 MiB blocks).
+    const SECTOR_BITMAP_BAT_INDEX: usize = 4096;
+
+    fn guid_bytes(guid: &NtfsGuid) -> [u8; GUID_SIZE] {
+        let mut bytes = [0u8; GUID_SIZE];
+        LittleEndian::write_u32(&mut bytes[0..], guid.data1);
+        LittleEndian::write_u16(&mut bytes[4..], guid.data2);
+        LittleEndian::write_u16(&mut bytes[6..], guid.data3);
+        bytes[8..].copy_from_slice(&guid.data4);
+        bytes
+    }
+
+    /// Returns the registry format of a GUID whose bytes all have the value `byte`.
+    fn guid_string(byte: u8) -> String {
+        let hex = format!("{byte:02X}");
+        format!(
+            "{{{}-{}-{}-{}-{}}}",
+            hex.repeat(4),
+            hex.repeat(2),
+            hex.repeat(2),
+            hex.repeat(2),
+            hex.repeat(6)
+        )
+    }
+
+    fn write_header(image: &mut [u8], position: u64, sequence_number: u64, data_write_guid: u8) {
+        let header = &mut image[position as usize..position as usize + VHDX_HEADER_SIZE];
+        header[..4].copy_from_slice(VHDX_HEADER_SIGNATURE);
+        LittleEndian::write_u64(&mut header[8..], sequence_number);
+        header[32..48].fill(data_write_guid);
+        LittleEndian::write_u16(&mut header[66..], 1);
+
+        let checksum = crc32c(header);
+        LittleEndian::write_u32(&mut header[4..], checksum);
+    }
+
+    fn write_region_table(image: &mut [u8], position: u64) {
+        let table = &mut image[position as usize..position as usize + VHDX_TABLE_SIZE];
+        table[..4].copy_from_slice(VHDX_REGION_TABLE_SIGNATURE);
+        LittleEndian::write_u32(&mut table[8..], 2);
+
+        for (i, (guid, offset)) in [(VHDX_REGION_BAT, 2 * MIB), (VHDX_REGION_METADATA, MIB)]
+            .iter()
+            .enumerate()
+        {
+            let entry = &mut table[16 + i * VHDX_TABLE_ENTRY_SIZE..];
+            entry[..GUID_SIZE].copy_from_slice(&guid_bytes(guid));
+            LittleEndian::write_u64(&mut entry[16..], *offset as u64);
+            LittleEndian::write_u32(&mut entry[24..], MIB as u32);
+            LittleEndian::write_u32(&mut entry[28..], 1);
+        }
+
+        let checksum = crc32c(table);
+        LittleEndian::write_u32(&mut table[4..], checksum);
+    }
+
+    fn build_parent_locator(parent_linkage: &str, relative_path: &str) -> Vec<u8> {
+        let mut locator = vec![0u8; 20 + 2 * 12];
+        LittleEndian::write_u16(&mut locator[18..], 2);
+
+        let pairs = [
+            ("parent_linkage", parent_linkage),
+            ("relative_path", relative_path),
+        ];
+        for (i, (key, value)) in pairs.iter().enumerate() {
+            let mut add_string = |string: &str| {
+                let offset = locator.len() as u32;
+                let length = string.len() as u16 * 2;
+                locator.extend(string.encode_utf16().flat_map(|c| c.to_le_bytes()));
+                (offset, length)
+            };
+            let (key_offset, key_length) = add_string(key);
+            let (value_offset, value_length) = add_string(value);
+
+            let entry = &mut locator[20 + i * 12..];
+            LittleEndian::write_u32(&mut entry[0..], key_offset);
+            LittleEndian::write_u32(&mut entry[4..], value_offset);
+            LittleEndian::write_u16(&mut entry[8..], key_length);
+            LittleEndian::write_u16(&mut entry[10..], value_length);
+        }
+
+        locator
+    }
+
+    fn write_metadata(image: &mut [u8], disk_size: u64, parent: Option<(u8, &str)>) {
+        let flags = if parent.is_some() {
+            VHDX_FILE_PARAMETERS_HAS_PARENT
+        } else {
+            0
+        };
+
+        let mut file_parameters = vec![0u8; 8];
+        LittleEndian::write_u32(&mut file_parameters[0..], BLOCK_SIZE as u32);
+        LittleEndian::write_u32(&mut file_parameters[4..], flags);
+
+        let mut items = vec![
+            (VHDX_METADATA_FILE_PARAMETERS, file_parameters),
+            (
+                VHDX_METADATA_VIRTUAL_DISK_SIZE,
+                disk_size.to_le_bytes().to_vec(),
+            ),
+            (VHDX_METADATA_VIRTUAL_DISK_ID, vec![0x42; GUID_SIZE]),
+            (
+                VHDX_METADATA_LOGICAL_SECTOR_SIZE,
+                512u32.to_le_bytes().to_vec(),
+            ),
+            (
+                VHDX_METADATA_PHYSICAL_SECTOR_SIZE,
+                4096u32.to_le_bytes().to_vec(),
+            ),
+        ];
+        if let Some((parent_data_write_guid, relative_path)) = parent {
+            let locator = build_parent_locator(&guid_string(parent_data_write_guid), relative_path);
+            items.push((VHDX_METADATA_PARENT_LOCATOR, locator));
+        }
+
+        let metadata = &mut image[MIB..2 * MIB];
+        metadata[..8].copy_from_slice(VHDX_METADATA_TABLE_SIGNATURE);
+        LittleEndian::write_u16(&mut metadata[10..], items.len() as u16);
+
+        let mut item_offset = VHDX_TABLE_SIZE;
+        for (i, (guid, data)) in items.iter().enumerate() {
+            let entry = &mut metadata[(i + 1) * VHDX_TABLE_ENTRY_SIZE..];
+            entry[..GUID_SIZE].copy_from_slice(&guid_bytes(guid));
+            LittleEndian::write_u32(&mut entry[16..], item_offset as u32);
+            LittleEndian::write_u32(&mut entry[20..], data.len() as u32);
+            LittleEndian::write_u32(&mut entry[24..], VHDX_METADATA_IS_REQUIRED);
+
+            metadata[item_offset..item_offset + data.len()].copy_from_slice(data);
+            item_offset += data.len();
+        }
+    }
+
+    /// Builds a VHDX, or a differencing VHDX if `parent` (Data Write GUID and relative path) is given,
+    /// which stores all sectors of `data` for which `is_present` returns `true`.
+    fn build_vhdx<F>(
+        data: &[u8],
+        data_write_guid: u8,
+        parent: Option<(u8, &str)>,
+        is_present: F,
+    ) -> Vec<u8>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut image = vec![0u8; 3 * MIB];
+        image[..8].copy_from_slice(VHDX_FILE_SIGNATURE);
+
+        // The header with the higher sequence number is the current one.
+        write_header(&mut image, VHDX_HEADER_POSITIONS[0], 2, data_write_guid);
+        write_header(&mut image, VHDX_HEADER_POSITIONS[1], 1, 0xff);
+        write_region_table(&mut image, VHDX_REGION_TABLE_POSITIONS[0]);
+        write_region_table(&mut image, VHDX_REGION_TABLE_POSITIONS[1]);
+        write_metadata(&mut image, data.len() as u64, parent);
+
+        let block_count = (data.len() + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        let sectors_per_block = BLOCK_SIZE / 512;
+        let mut bat = vec![0u64; SECTOR_BITMAP_BAT_INDEX + 1];
+        let mut sector_bitmap = vec![0u8; MIB];
+
+        for (block, bat_entry) in bat.iter_mut().enumerate().take(block_count) {
+            let first_sector = block * sectors_per_block;
+            let present = (first_sector..first_sector + sectors_per_block)
+                .map(|sector| sector * 512 < data.len() && is_present(sector))
+                .collect::<Vec<bool>>();
+
+            let state = if !present.contains(&true) {
+                VHDX_PAYLOAD_BLOCK_NOT_PRESENT
+            } else if parent.is_some() && present.contains(&false) {
+                VHDX_PAYLOAD_BLOCK_PARTIALLY_PRESENT
+            } else {
+                VHDX_PAYLOAD_BLOCK_FULLY_PRESENT
+            };
+
+            if state != VHDX_PAYLOAD_BLOCK_NOT_PRESENT {
+                // Sectors that are not present must not be read from a partially present block.
+                let mut block_data = vec![0xcc; BLOCK_SIZE];
+
+                for i in 0..sectors_per_block {
+                    let sector = first_sector + i;
+                    if sector * 512 >= data.len() {
+                        block_data[i * 512..].fill(0);
+                        break;
+                    }
+
+                    if present[i] {
+                        sector_bitmap[sector / 8] |= 1 << (sector % 8);
+                    }
+
+                    if present[i] || state == VHDX_PAYLOAD_BLOCK_FULLY_PRESENT {
+                        block_data[i * 512..(i + 1) * 512]
+                            .copy_from_slice(&data[sector * 512..(sector + 1) * 512]);
+                    }
+                }
+
+                *bat_entry = ((image.len() / MIB) as u64) << 20 | state;
+                image.extend_from_slice(&block_data);
+            }
+        }
+
+        if parent.is_some() {
+            bat[SECTOR_BITMAP_BAT_INDEX] =
+                ((image.len() / MIB) as u64) << 20 | VHDX_SB_BLOCK_PRESENT;
+            image.extend_from_slice(&sector_bitmap);
+        }
+
+        for (i, entry) in bat.iter().enumerate() {
+            LittleEndian::write_u64(&mut image[2 * MIB + i * 8..], *entry);
+        }
+
+        image
+    }
+
+    /// Returns a disk with an MBR and a single partition at 1 MiB containing testfs1, followed by unused space.
+    fn build_disk() -> Vec<u8> {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let mut disk = vec![0u8; MIB];
+
+        let entry = &mut disk[446..];
+        entry[4] = 0x07;
+        LittleEndian::write_u32(&mut entry[8..], (MIB / 512) as u32);
+        LittleEndian::write_u32(&mut entry[12..], (testfs1.len() / 512) as u32);
+        disk[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+        disk.extend_from_slice(&testfs1);
+        disk.resize(disk.len() + MIB + MIB / 2, 0);
+        disk
+    }
+
+    fn read_all<R>(reader: &mut R) -> Vec<u8>
+    where
+        R: Read + Seek,
+    {
+        let mut data = Vec::new();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    fn assert_testfs1<R>(reader: &mut R)
+    where
+        R: Read + Seek,
+    {
+        let table = NtfsPartitionTable::new(reader).unwrap();
+        let partition = table.ntfs_partitions().next().unwrap();
+        let mut partition_reader = partition.reader(reader);
+
+        let ntfs = Ntfs::new(&mut partition_reader).unwrap();
+        let file = ntfs.file(&mut partition_reader, 66).unwrap();
+        assert!(file.data(&mut partition_reader, "").is_some());
+    }
+
+    #[test]
+    fn test_vhdx() {
+        let disk = build_disk();
+        let image = build_vhdx(&disk, 0x11, None, |sector| {
+            disk[sector * 512..(sector + 1) * 512] != [0; 512]
+        });
+
+        let mut reader = NtfsVhdxReader::new(Cursor::new(image)).unwrap();
+        assert_eq!(reader.disk_size(), disk.len() as u64);
+        assert_eq!(reader.logical_sector_size(), 512);
+        assert_eq!(*reader.id(), NtfsGuid::from_bytes(&[0x42; GUID_SIZE]));
+        assert_eq!(
+            *reader.data_write_guid(),
+            NtfsGuid::from_bytes(&[0x11; GUID_SIZE])
+        );
+        assert!(!reader.is_differencing());
+        assert_eq!(read_all(&mut reader), disk);
+        assert_testfs1(&mut reader);
+    }
+
+    #[test]
+    fn test_vhdx_differencing() {
+        let disk = build_disk();
+        let parent_image = build_vhdx(&disk, 0x11, None, |_| true);
+
+        // The differencing VHDX only stores the modified first sector of "1000-bytes-file".
+        let modified_sector = MIB / 512 + 2567;
+        let mut modified = disk.clone();
+        modified[modified_sector * 512..(modified_sector + 1) * 512].fill(b'x');
+        let child_image = build_vhdx(&modified, 0x22, Some((0x11, "..\\base.vhdx")), |sector| {
+            sector == modified_sector
+        });
+
+        let mut reader = NtfsVhdxReader::new(Cursor::new(child_image)).unwrap();
+        assert!(reader.is_differencing());
+        assert_eq!(reader.parent_locations(), ["..\\base.vhdx"]);
+
+        // The stored sector can be read without the parent, but nothing else.
+        let mut buf = [0u8; 512];
+        reader
+            .seek(SeekFrom::Start(modified_sector as u64 * 512))
+            .unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [b'x'; 512]);
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        assert!(reader.read_exact(&mut buf).is_err());
+
+        // The parent needs to have the Data Write GUID the differencing VHDX refers to.
+        let wrong_parent = build_vhdx(&disk, 0x33, None, |_| true);
+        let wrong_parent = NtfsVhdxReader::new(Cursor::new(wrong_parent)).unwrap();
+        assert!(matches!(
+            reader.set_parent(wrong_parent),
+            Err(NtfsError::VirtualDiskParentMismatch { .. })
+        ));
+
+        let parent = NtfsVhdxReader::new(Cursor::new(parent_image)).unwrap();
+        reader.set_parent(parent).unwrap();
+        assert_eq!(read_all(&mut reader), modified);
+        assert_testfs1(&mut reader);
+    }
+
+    #[test]
+    fn test_crc32c() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    }
+
+    #[test]
+    fn test_parse_guid() {
+        assert_eq!(
+            parse_guid("{2DC27766-F623-4200-9D64-115E9BFD4A08}"),
+            Some(VHDX_REGION_BAT)
+        );
+        assert_eq!(
+            parse_guid("2dc27766-f623-4200-9d64-115e9bfd4a08"),
+            Some(VHDX_REGION_BAT)
+        );
+        assert_eq!(parse_guid("{2DC27766-F623-4200-9D64}"), None);
+        assert_eq!(parse_guid("{2DC27766-F623-4200-9D64-115E9BFD4A0G}"), None);
+    }
+
+    #[test]
+    fn test_vhdx_invalid_bat() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+        let image = build_vhdx(&testfs1, 1, None, |_| true);
+
+        // The block count of a disk size close to the maximum overflows.
+        let mut overflowing_image = image.clone();
+        write_metadata(&mut overflowing_image, u64::MAX - 1, None);
+        assert!(matches!(
+            NtfsVhdxReader::new(Cursor::new(overflowing_image)),
+            Err(NtfsError::InvalidVirtualDiskStructure {
+                structure: "metadata",
+                ..
+            })
+        ));
+
+        // The BAT of a 64 GiB disk fits into its region, but not into the truncated image file.
+        let mut truncated_image = image;
+        write_metadata(&mut truncated_image, 64 << 30, None);
+        truncated_image.truncate(2 * MIB + 4096);
+        assert!(matches!(
+            NtfsVhdxReader::new(Cursor::new(truncated_image)),
+            Err(NtfsError::InvalidVirtualDiskStructure {
+                structure: "BAT",
+                ..
+            })
+        ));
+    }
+}
@@ -4,6 +4,7 @@
 use core::ops::Range;
 
 use alloc::vec;
+use alloc::vec::Vec;
 use binrw::io::{Read, Seek};
 use byteorder::{ByteOrder, LittleEndian};
 use memoffset::offset_of;
@@ -12,6 +13,7 @@ use crate::attribute_value::NtfsAttributeValue;
 use crate::error::{NtfsError, Result};
 use crate::index_entry::{IndexNodeEntryRanges, NtfsIndexNodeEntries};
 use crate::indexes::NtfsIndexEntryType;
+use crate::ntfs::Ntfs;
 use crate::record::Record;
 use crate::record::RecordHeader;
 use crate::traits::NtfsReadSeek;
@@ -57,6 +59,7 @@ const HAS_SUBNODES_FLAG: u8 = 0x01;
 
 impl NtfsIndexRecord {
     pub(crate) fn new<T>(
+        ntfs: &Ntfs,
         fs: &mut T,
         mut value: NtfsAttributeValue,
         index_record_size: u32,
@@ -66,10 +69,51 @@ impl NtfsIndexRecord {
     {
         let data_position = value.data_position();
 
-        let mut data = vec![0; index_record_size as usize];
-        value.read_exact(fs, &mut data)?;
+        let record = ntfs.read_record(data_position, || {
+            let mut data = vec![0; index_record_size as usize];
+            value.read_exact(fs, &mut data)?;
 
-        let mut record = Record::new(data, data_position);
+            Self::from_record(Record::new(data, data_position))
+                .map(|index_record| index_record.record)
+        })?;
+
+        Ok(Self { record })
+    }
+
+    /// Parses an [`NtfsIndexRecord`] from Index Record data in memory, e.g. from a memory-mapped filesystem image.
+    ///
+    /// `data` must contain exactly one Index Record, whose size is given by
+    /// [`NtfsIndexRoot::index_record_size`].
+    /// `position` is the absolute position of `data` within the filesystem, in bytes.
+    /// It is reported in errors and by the positions of index entries, and may be 0 if unknown.
+    /// Data too short for the Index Record headers fails with [`NtfsError::BufferTooSmall`].
+    ///
+    /// The data is copied once, because the fixup modifies it.
+    /// Check [`NtfsIndexRecord::from_vec`] to reuse a buffer for that.
+    /// The keys and data of all index entries borrow from that copy.
+    ///
+    /// [`NtfsIndexRoot::index_record_size`]: crate::structured_values::NtfsIndexRoot::index_record_size
+    pub fn from_bytes(data: &[u8], position: u64) -> Result<Self> {
+        Self::from_vec(data.to_vec(), position)
+    }
+
+    /// Parses an [`NtfsIndexRecord`] from Index Record data in memory like [`NtfsIndexRecord::from_bytes`],
+    /// but fixes up the given buffer in place instead of copying it.
+    ///
+    /// Get the buffer back via [`NtfsIndexRecord::into_record_data`] to reuse it for the next Index Record.
+    pub fn from_vec(data: Vec<u8>, position: u64) -> Result<Self> {
+        let minimum_size = INDEX_RECORD_HEADER_SIZE as usize + INDEX_NODE_HEADER_SIZE;
+        if data.len() < minimum_size {
+            return Err(NtfsError::BufferTooSmall {
+                expected: minimum_size,
+                actual: data.len(),
+            });
+        }
+
+        Self::from_record(Record::new(data, NtfsPosition::new(position)))
+    }
+
+    fn from_record(mut record: Record) -> Result<Self> {
         Self::validate_signature(&record)?;
         record.fixup()?;
 
@@ -82,7 +126,7 @@ impl NtfsIndexRecord {
     /// Returns an iterator over all entries of this Index Record (cf. [`NtfsIndexEntry`]).
     ///
     /// [`NtfsIndexEntry`]: crate::NtfsIndexEntry
-    pub fn entries<E>(&self) -> Result<NtfsIndexNodeEntries<E>>
+    pub fn entries<E>(&self) -> Result<NtfsIndexNodeEntries<'_, E>>
     where
         E: NtfsIndexEntryType,
     {
@@ -133,6 +177,12 @@ impl NtfsIndexRecord {
         IndexNodeEntryRanges::new(self.record.into_data(), entries_range, position)
     }
 
+    /// Consumes this Index Record and returns the fixed up Index Record data, e.g. to reuse the buffer for
+    /// [`NtfsIndexRecord::from_vec`].
+    pub fn into_record_data(self) -> Vec<u8> {
+        self.record.into_data()
+    }
+
     fn validate_signature(record: &Record) -> Result<()> {
         let signature = &record.signature();
         let expected = b"INDX";
@@ -187,3 +237,98 @@ impl NtfsIndexRecord {
         Vcn::from(LittleEndian::read_i64(&self.record.data()[start..]))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribute::NtfsAttributeType;
+    use crate::indexes::NtfsFileNameIndex;
+    use crate::structured_values::{NtfsIndexAllocation, NtfsIndexRoot};
+
+    #[test]
+    fn test_from_bytes() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let mut ntfs = Ntfs::new(&mut testfs1).unwrap();
+        ntfs.read_upcase_table(&mut testfs1).unwrap();
+        let directory = ntfs.file_by_path(&mut testfs1, "many_subdirs").unwrap();
+
+        // Get the first Index Record of the directory along with its position.
+        let mut index_record_size = 0;
+        let mut index_allocation_position = 0;
+        let mut index_record = None;
+
+        for attribute in directory.attributes_raw() {
+            let attribute = attribute.unwrap();
+
+            match attribute.ty().unwrap() {
+                NtfsAttributeType::IndexRoot => {
+                    let index_root = attribute
+                        .resident_structured_value::<NtfsIndexRoot>()
+                        .unwrap();
+                    index_record_size = index_root.index_record_size();
+                }
+                NtfsAttributeType::IndexAllocation => {
+                    let value = attribute.value(&mut testfs1).unwrap();
+                    index_allocation_position = value.data_position().value().unwrap().get();
+
+                    let index_allocation = attribute
+                        .structured_value::<_, NtfsIndexAllocation>(&mut testfs1)
+                        .unwrap();
+                    let mut records = index_allocation.records(index_record_size);
+                    index_record = Some(records.next(&mut testfs1).unwrap().unwrap());
+                }
+                _ => (),
+            }
+        }
+
+        let index_record = index_record.unwrap();
+        let image = testfs1.into_inner();
+        let start = index_allocation_position as usize;
+        let end = start + index_record_size as usize;
+
+        let parsed_index_record =
+            NtfsIndexRecord::from_bytes(&image[start..end], index_allocation_position).unwrap();
+        assert_eq!(parsed_index_record.vcn(), index_record.vcn());
+        assert_eq!(
+            parsed_index_record.index_data_size(),
+            index_record.index_data_size()
+        );
+
+        // Index keys borrow from the parsed Index Record.
+        let names = |index_record: &NtfsIndexRecord| {
+            index_record
+                .entries::<NtfsFileNameIndex>()
+                .unwrap()
+                .filter_map(|entry| Some(entry.unwrap().key()?.unwrap().name().to_string_lossy()))
+                .collect::<Vec<_>>()
+        };
+        assert!(!names(&parsed_index_record).is_empty());
+        assert_eq!(names(&parsed_index_record), names(&index_record));
+
+        // The buffer can be reused.
+        let mut data = parsed_index_record.into_record_data();
+        data.copy_from_slice(&image[start..end]);
+        let reparsed_index_record = NtfsIndexRecord::from_vec(data, 0).unwrap();
+        assert_eq!(names(&reparsed_index_record), names(&index_record));
+
+        // Data too short for the headers or the sectors protected by the Update Sequence Array is rejected.
+        assert!(matches!(
+            NtfsIndexRecord::from_bytes(&[], 0),
+            Err(NtfsError::BufferTooSmall {
+                expected: 40,
+                actual: 0
+            })
+        ));
+        assert!(matches!(
+            NtfsIndexRecord::from_bytes(&image[start..start + 39], 0),
+            Err(NtfsError::BufferTooSmall {
+                expected: 40,
+                actual: 39
+            })
+        ));
+        assert!(matches!(
+            NtfsIndexRecord::from_bytes(&image[start..start + 600], 0),
+            Err(NtfsError::UpdateSequenceArrayExceedsRecordSize { .. })
+        ));
+    }
+}
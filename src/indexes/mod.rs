@@ -15,8 +15,10 @@
 //! [`NtfsIndexRoot`]: crate::structured_values::NtfsIndexRoot
 
 mod file_name;
+mod security_id;
 
 pub use file_name::*;
+pub use security_id::*;
 
 use core::fmt;
 
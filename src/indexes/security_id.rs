@@ -0,0 +1,141 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::mem;
+
+use binrw::io::{Cursor, Read, Seek};
+use binrw::{BinRead, BinReaderExt};
+
+use crate::attribute::NtfsAttributeType;
+use crate::error::{NtfsError, Result};
+use crate::index::NtfsIndexFinder;
+use crate::index_entry::NtfsIndexEntry;
+use crate::indexes::{
+    NtfsIndexEntryData, NtfsIndexEntryHasData, NtfsIndexEntryKey, NtfsIndexEntryType,
+};
+use crate::types::NtfsPosition;
+
+/// Size of all [`NtfsSecurityDescriptorHeader`] fields.
+pub(crate) const SECURITY_DESCRIPTOR_HEADER_SIZE: usize = 20;
+
+/// Defines the [`NtfsIndexEntryType`] for the $SII index of the $Secure file, which maps
+/// Security IDs to the location of their security descriptors in the $SDS stream.
+///
+/// Most users want to call [`Ntfs::security_descriptor`] instead of looking up entries of this index themselves.
+///
+/// [`Ntfs::security_descriptor`]: crate::Ntfs::security_descriptor
+#[derive(Clone, Copy, Debug)]
+pub struct NtfsSecurityIdIndex;
+
+impl NtfsSecurityIdIndex {
+    /// Finds a Security ID in a $SII index and returns the [`NtfsIndexEntry`] (if any).
+    pub fn find<'a, T>(
+        index_finder: &'a mut NtfsIndexFinder<Self>,
+        fs: &mut T,
+        security_id: u32,
+    ) -> Option<Result<NtfsIndexEntry<'a, Self>>>
+    where
+        T: Read + Seek,
+    {
+        index_finder.find(fs, |key| security_id.cmp(&key.security_id()))
+    }
+}
+
+impl NtfsIndexEntryType for NtfsSecurityIdIndex {
+    type KeyType = NtfsSecurityIdIndexKey;
+}
+
+impl NtfsIndexEntryHasData for NtfsSecurityIdIndex {
+    type DataType = NtfsSecurityDescriptorHeader;
+}
+
+/// Key of an [`NtfsSecurityIdIndex`] entry.
+#[derive(Clone, Debug)]
+pub struct NtfsSecurityIdIndexKey {
+    security_id: u32,
+}
+
+impl NtfsSecurityIdIndexKey {
+    /// Returns the Security ID of this entry.
+    pub fn security_id(&self) -> u32 {
+        self.security_id
+    }
+}
+
+impl NtfsIndexEntryKey for NtfsSecurityIdIndexKey {
+    fn key_from_slice(slice: &[u8], position: NtfsPosition) -> Result<Self> {
+        if slice.len() < mem::size_of::<u32>() {
+            return Err(NtfsError::InvalidStructuredValueSize {
+                position,
+                ty: NtfsAttributeType::SecurityDescriptor,
+                expected: mem::size_of::<u32>() as u64,
+                actual: slice.len() as u64,
+            });
+        }
+
+        let security_id = Cursor::new(slice).read_le::<u32>()?;
+        Ok(Self { security_id })
+    }
+}
+
+/// Header of a security descriptor entry in the $SDS stream of the $Secure file.
+///
+/// The same structure is also the data of an [`NtfsSecurityIdIndex`] entry, pointing to the
+/// corresponding $SDS entry.
+///
+/// Reference: <https://flatcap.github.io/linux-ntfs/ntfs/files/secure.html>
+#[derive(BinRead, Clone, Debug)]
+pub struct NtfsSecurityDescriptorHeader {
+    hash: u32,
+    security_id: u32,
+    offset: u64,
+    length: u32,
+}
+
+impl NtfsSecurityDescriptorHeader {
+    pub(crate) fn new<T>(r: &mut T, position: NtfsPosition, value_length: u64) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        if value_length < SECURITY_DESCRIPTOR_HEADER_SIZE as u64 {
+            return Err(NtfsError::InvalidStructuredValueSize {
+                position,
+                ty: NtfsAttributeType::SecurityDescriptor,
+                expected: SECURITY_DESCRIPTOR_HEADER_SIZE as u64,
+                actual: value_length,
+            });
+        }
+
+        let header = r.read_le::<Self>()?;
+        Ok(header)
+    }
+
+    /// Returns the hash of the security descriptor, which is the key of the $SDH index.
+    pub fn hash(&self) -> u32 {
+        self.hash
+    }
+
+    /// Returns the length of the $SDS entry, including this header, in bytes.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Returns the offset of the $SDS entry within the $SDS stream, in bytes.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the Security ID of the security descriptor.
+    pub fn security_id(&self) -> u32 {
+        self.security_id
+    }
+}
+
+impl NtfsIndexEntryData for NtfsSecurityDescriptorHeader {
+    fn data_from_slice(slice: &[u8], position: NtfsPosition) -> Result<Self> {
+        let value_length = slice.len() as u64;
+
+        let mut cursor = Cursor::new(slice);
+        Self::new(&mut cursor, position, value_length)
+    }
+}
@@ -11,6 +11,7 @@
 //!
 //! # Getting started
 //! 1. Create an [`Ntfs`] structure from a reader by calling [`Ntfs::new`].
+//!    For a whole-disk image, first pick the partition via [`NtfsPartitionTable`] and pass its [`NtfsPartition::reader`].
 //! 2. Retrieve the [`NtfsFile`] of the root directory via [`Ntfs::root_directory`].
 //! 3. Dig into its attributes via [`NtfsFile::attributes`], go even deeper via [`NtfsFile::attributes_raw`] or use one of the convenience functions, like [`NtfsFile::directory_index`], [`NtfsFile::info`] or [`NtfsFile::name`].
 //!
@@ -42,26 +43,57 @@ extern crate alloc;
 #[macro_use]
 mod helpers;
 
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+mod async_reader;
 mod attribute;
 pub mod attribute_value;
 mod boot_sector;
+pub mod check;
+mod cluster_bitmap;
+mod cluster_owners;
+mod compression;
 mod error;
+mod extent_map;
 mod file;
 mod file_reference;
 mod guid;
+#[cfg(feature = "images")]
+#[cfg_attr(docsrs, doc(cfg(feature = "images")))]
+pub mod images;
 mod index;
 mod index_entry;
 mod index_record;
 pub mod indexes;
+mod log_replay;
+mod logfile;
+mod mft;
 mod ntfs;
+mod overlay;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+mod owned;
+mod partition;
+mod read_at;
 mod record;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+mod record_cache;
+mod recovery;
 pub mod structured_values;
 mod time;
 mod traits;
 pub mod types;
 mod upcase_table;
+mod usn_journal;
+mod walker;
+mod wof;
 
+#[cfg(feature = "async")]
+pub use crate::async_reader::*;
 pub use crate::attribute::*;
+pub use crate::cluster_bitmap::*;
+pub use crate::cluster_owners::*;
 pub use crate::error::*;
 pub use crate::file::*;
 pub use crate::file_reference::*;
@@ -69,7 +101,21 @@ pub use crate::guid::*;
 pub use crate::index::*;
 pub use crate::index_entry::*;
 pub use crate::index_record::*;
+pub use crate::log_replay::*;
+pub use crate::logfile::*;
+pub use crate::mft::*;
 pub use crate::ntfs::*;
+pub use crate::overlay::*;
+#[cfg(feature = "std")]
+pub use crate::owned::*;
+pub use crate::partition::*;
+pub use crate::read_at::*;
+#[cfg(feature = "std")]
+pub use crate::record_cache::*;
+pub use crate::recovery::*;
 pub use crate::time::*;
 pub use crate::traits::*;
 pub use crate::upcase_table::*;
+pub use crate::usn_journal::*;
+pub use crate::walker::*;
+pub use crate::wof::*;
@@ -0,0 +1,1083 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Redo of committed $LogFile transactions onto an [`NtfsOverlay`].
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek, SeekFrom};
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::error::Result;
+use crate::file::NtfsFileFlags;
+use crate::logfile::{
+    NtfsLogFile, NtfsLogOperation, NtfsLogOperationRecord, NtfsLogRecordType, NtfsLogRestartFlags,
+};
+use crate::ntfs::Ntfs;
+use crate::overlay::NtfsOverlay;
+use crate::record::Record;
+use crate::types::{Lcn, NtfsPosition};
+
+/// Log operations address their target record in units of this size.
+const CLUSTER_BLOCK_SIZE: u64 = 512;
+
+// Offsets within a File Record.
+const FILE_RECORD_FLAGS_OFFSET: usize = 0x16;
+const FILE_RECORD_BYTES_IN_USE_OFFSET: usize = 0x18;
+
+// Offsets within an attribute of a File Record.
+const ATTRIBUTE_LENGTH_OFFSET: usize = 0x04;
+const RESIDENT_VALUE_LENGTH_OFFSET: usize = 0x10;
+const RESIDENT_VALUE_OFFSET_OFFSET: usize = 0x14;
+const NON_RESIDENT_DATA_RUNS_OFFSET_OFFSET: usize = 0x20;
+const NON_RESIDENT_SIZES_OFFSET: usize = 0x28;
+
+// Offsets within an Index Root value, an Index Record, and an Index Node Header.
+const INDEX_ROOT_NODE_HEADER_OFFSET: usize = 0x10;
+const INDEX_RECORD_NODE_HEADER_OFFSET: usize = 0x18;
+const INDEX_NODE_INDEX_SIZE_OFFSET: usize = 0x04;
+const INDEX_NODE_ALLOCATED_SIZE_OFFSET: usize = 0x08;
+
+// Offsets within an Index Entry.
+const INDEX_ENTRY_DATA_OFFSET_OFFSET: usize = 0x00;
+const INDEX_ENTRY_LENGTH_OFFSET: usize = 0x08;
+const INDEX_ENTRY_DUPLICATED_INFORMATION_OFFSET: usize = 0x18;
+
+/// Offset of the Log File Sequence Number within a File Record or Index Record.
+const RECORD_LSN_OFFSET: usize = 0x08;
+
+/// Statistics about a redo of the $LogFile, returned by [`NtfsLogFile::redo_committed`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NtfsLogRedoSummary {
+    applied: u64,
+    skipped: u64,
+    uncommitted: u64,
+}
+
+impl NtfsLogRedoSummary {
+    /// Returns the number of redo operations that have been written to the overlay.
+    pub fn applied_operations(&self) -> u64 {
+        self.applied
+    }
+
+    /// Returns the number of committed redo operations that have not been written to the overlay,
+    /// either because the target record already contains the change or because the target is invalid.
+    pub fn skipped_operations(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Returns the number of redo operations that belong to a transaction without a commit record
+    /// (or that has been forgotten before its commit) and have therefore been ignored.
+    pub fn uncommitted_operations(&self) -> u64 {
+        self.uncommitted
+    }
+}
+
+/// What a log operation modifies.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TargetKind {
+    FileRecord,
+    IndexRecord,
+    Raw,
+}
+
+impl TargetKind {
+    fn of(operation: NtfsLogOperation) -> Option<Self> {
+        match operation {
+            NtfsLogOperation::InitializeFileRecordSegment
+            | NtfsLogOperation::DeallocateFileRecordSegment
+            | NtfsLogOperation::WriteEndOfFileRecordSegment
+            | NtfsLogOperation::CreateAttribute
+            | NtfsLogOperation::DeleteAttribute
+            | NtfsLogOperation::UpdateResidentValue
+            | NtfsLogOperation::UpdateMappingPairs
+            | NtfsLogOperation::SetNewAttributeSizes
+            | NtfsLogOperation::AddIndexEntryRoot
+            | NtfsLogOperation::DeleteIndexEntryRoot
+            | NtfsLogOperation::SetIndexEntryVcnRoot
+            | NtfsLogOperation::UpdateFileNameRoot
+            | NtfsLogOperation::UpdateRecordDataRoot => Some(Self::FileRecord),
+            NtfsLogOperation::AddIndexEntryAllocation
+            | NtfsLogOperation::DeleteIndexEntryAllocation
+            | NtfsLogOperation::WriteEndOfIndexBuffer
+            | NtfsLogOperation::SetIndexEntryVcnAllocation
+            | NtfsLogOperation::UpdateFileNameAllocation
+            | NtfsLogOperation::UpdateRecordDataAllocation => Some(Self::IndexRecord),
+            NtfsLogOperation::UpdateNonresidentValue
+            | NtfsLogOperation::SetBitsInNonresidentBitMap
+            | NtfsLogOperation::ClearBitsInNonresidentBitMap => Some(Self::Raw),
+            _ => None,
+        }
+    }
+
+    fn signature(&self) -> &'static [u8; 4] {
+        match self {
+            Self::FileRecord => b"FILE",
+            Self::IndexRecord => b"INDX",
+            Self::Raw => unreachable!(),
+        }
+    }
+}
+
+/// The clusters modified by a log operation, as given by its target LCNs and cluster block offset.
+struct Target {
+    lcns: Vec<Lcn>,
+    base: u64,
+    cluster_size: u64,
+}
+
+impl Target {
+    fn new(ntfs: &Ntfs, operation_record: &NtfsLogOperationRecord) -> Self {
+        Self {
+            lcns: operation_record.target_lcns().collect(),
+            base: operation_record.cluster_block_offset() as u64 * CLUSTER_BLOCK_SIZE,
+            cluster_size: ntfs.cluster_size() as u64,
+        }
+    }
+
+    /// Returns the number of bytes covered by the target LCNs from the cluster block offset on.
+    fn len(&self) -> u64 {
+        (self.lcns.len() as u64 * self.cluster_size).saturating_sub(self.base)
+    }
+
+    /// Returns the absolute positions and lengths of the pieces covering `length` bytes at `offset`
+    /// within the target, or `None` if the target LCNs don't cover that range.
+    fn pieces(&self, offset: u64, length: usize) -> Option<Vec<(u64, usize)>> {
+        let mut pieces = Vec::new();
+        let mut offset = self.base.checked_add(offset)?;
+        let mut remaining = length as u64;
+
+        while remaining > 0 {
+            let lcn = self.lcns.get((offset / self.cluster_size) as usize)?;
+            let offset_in_cluster = offset % self.cluster_size;
+            let piece_length = u64::min(remaining, self.cluster_size - offset_in_cluster);
+            let position = lcn
+                .value()
+                .checked_mul(self.cluster_size)?
+                .checked_add(offset_in_cluster)?;
+
+            pieces.push((position, piece_length as usize));
+            offset += piece_length;
+            remaining -= piece_length;
+        }
+
+        Some(pieces)
+    }
+
+    fn read<T>(
+        &self,
+        overlay: &mut NtfsOverlay<T>,
+        offset: u64,
+        length: usize,
+    ) -> Result<Option<Vec<u8>>>
+    where
+        T: Read + Seek,
+    {
+        let pieces = match self.pieces(offset, length) {
+            Some(pieces) => pieces,
+            None => return Ok(None),
+        };
+
+        let mut data = vec![0u8; length];
+        let mut start = 0;
+        for (position, piece_length) in pieces {
+            overlay.seek(SeekFrom::Start(position))?;
+            overlay.read_exact(&mut data[start..start + piece_length])?;
+            start += piece_length;
+        }
+
+        Ok(Some(data))
+    }
+
+    fn write<T>(&self, overlay: &mut NtfsOverlay<T>, offset: u64, data: &[u8]) -> Result<bool>
+    where
+        T: Read + Seek,
+    {
+        let pieces = match self.pieces(offset, data.len()) {
+            Some(pieces) => pieces,
+            None => return Ok(false),
+        };
+
+        let mut start = 0;
+        for (position, piece_length) in pieces {
+            overlay.write_at(position, &data[start..start + piece_length])?;
+            start += piece_length;
+        }
+
+        Ok(true)
+    }
+}
+
+impl NtfsLogFile {
+    /// Redoes the operations of all committed transactions in the $LogFile onto the given [`NtfsOverlay`].
+    ///
+    /// This brings File Records, Index Records, and non-resident attribute values up to date with the
+    /// transactions Windows has committed before the volume was last unmounted, without writing to the
+    /// underlying filesystem.
+    /// Afterwards, a new [`Ntfs`] object created from the overlay provides a view of a volume that has not
+    /// been unmounted cleanly (see [`NtfsVolumeFlags::IS_DIRTY`]).
+    ///
+    /// This is only the redo pass of a log recovery:
+    /// Operations of transactions without a [`NtfsLogOperation::CommitTransaction`] record are ignored,
+    /// and their undo operations are not performed.
+    /// If the volume contains changes of such loser transactions that have already been written to disk,
+    /// these changes are left as they are.
+    ///
+    /// Log records are considered from the last checkpoint of the first log client on,
+    /// or from the current LSN of the restart area if that client has not written a checkpoint yet.
+    /// Nothing is done if the restart area marks the volume as clean.
+    ///
+    /// The log records are read through the overlay, so `overlay` must wrap the same filesystem reader
+    /// that has been used to create this [`NtfsLogFile`].
+    ///
+    /// [`NtfsVolumeFlags::IS_DIRTY`]: crate::structured_values::NtfsVolumeFlags::IS_DIRTY
+    pub fn redo_committed<T>(
+        &self,
+        ntfs: &Ntfs,
+        overlay: &mut NtfsOverlay<T>,
+    ) -> Result<NtfsLogRedoSummary>
+    where
+        T: Read + Seek,
+    {
+        let mut summary = NtfsLogRedoSummary::default();
+        let restart_page = self.restart_page();
+
+        if restart_page
+            .flags()
+            .contains(NtfsLogRestartFlags::VOLUME_IS_CLEAN)
+        {
+            return Ok(summary);
+        }
+
+        // Everything logged before the checkpoint has already been written to disk.
+        let start_lsn = restart_page
+            .clients()
+            .next()
+            .map(|client| client.client_restart_lsn())
+            .filter(|lsn| *lsn != 0)
+            .unwrap_or_else(|| restart_page.current_lsn());
+
+        // Pass 1: Collect the LSNs of all redo operations that belong to committed transactions.
+        let mut pending = BTreeMap::<u32, Vec<u64>>::new();
+        let mut committed = BTreeSet::<u64>::new();
+        let mut records = self.records_from(start_lsn);
+
+        while let Some(record) = records.next(overlay) {
+            let record = record?;
+            if record.record_type() != NtfsLogRecordType::ClientRecord {
+                continue;
+            }
+
+            let operation_record = match record.operation_record() {
+                Some(operation_record) => operation_record,
+                None => continue,
+            };
+            let redo_operation = operation_record.redo_operation();
+
+            match redo_operation {
+                NtfsLogOperation::CommitTransaction => {
+                    if let Some(lsns) = pending.remove(&record.transaction_id()) {
+                        committed.extend(lsns);
+                    }
+                }
+                NtfsLogOperation::ForgetTransaction => {
+                    // The transaction ID may be reused afterwards, so a later commit must not pick up
+                    // the operations of this transaction.
+                    if let Some(lsns) = pending.remove(&record.transaction_id()) {
+                        summary.uncommitted += lsns.len() as u64;
+                    }
+                }
+                operation if TargetKind::of(operation).is_some() => {
+                    pending
+                        .entry(record.transaction_id())
+                        .or_default()
+                        .push(record.lsn());
+                }
+                _ => (),
+            }
+        }
+
+        summary.uncommitted += pending.values().map(|lsns| lsns.len() as u64).sum::<u64>();
+
+        // Pass 2: Redo the committed operations in LSN order.
+        for lsn in committed {
+            let record = self.record(overlay, lsn)?;
+
+            // This unwrap is safe, because the record has yielded an operation record in pass 1.
+            let operation_record = record.operation_record().unwrap();
+
+            if redo(ntfs, overlay, &operation_record, lsn)? {
+                summary.applied += 1;
+            } else {
+                summary.skipped += 1;
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Performs a single redo operation and returns whether it has been applied.
+fn redo<T>(
+    ntfs: &Ntfs,
+    overlay: &mut NtfsOverlay<T>,
+    operation_record: &NtfsLogOperationRecord,
+    lsn: u64,
+) -> Result<bool>
+where
+    T: Read + Seek,
+{
+    let operation = operation_record.redo_operation();
+    let target = Target::new(ntfs, operation_record);
+
+    // This unwrap is safe, because only operations with a target kind are collected.
+    let kind = TargetKind::of(operation).unwrap();
+    if kind == TargetKind::Raw {
+        return redo_raw(overlay, &target, operation_record);
+    }
+
+    let record_size = match kind {
+        TargetKind::FileRecord => ntfs.file_record_size() as usize,
+        _ => match index_record_size(overlay, &target)? {
+            Some(record_size) => record_size,
+            None => return Ok(false),
+        },
+    };
+    let data = match target.read(overlay, 0, record_size)? {
+        Some(data) => data,
+        None => return Ok(false),
+    };
+
+    let mut record = Record::new(data, NtfsPosition::none());
+    let valid = &record.signature() == kind.signature() && record.fixup().is_ok();
+    let mut data = record.into_data();
+
+    if valid {
+        // Skip the operation if the record already contains this or a later change.
+        if LittleEndian::read_u64(&data[RECORD_LSN_OFFSET..]) >= lsn {
+            return Ok(false);
+        }
+    } else if operation == NtfsLogOperation::InitializeFileRecordSegment {
+        data.fill(0);
+    } else {
+        return Ok(false);
+    }
+
+    let applied = match kind {
+        TargetKind::FileRecord => redo_file_record(&mut data, operation_record),
+        _ => redo_index_record(&mut data, operation_record),
+    };
+    if applied.is_none() {
+        return Ok(false);
+    }
+
+    LittleEndian::write_u64(&mut data[RECORD_LSN_OFFSET..], lsn);
+
+    let mut record = Record::new(data, NtfsPosition::none());
+    if record.protect().is_err() {
+        return Ok(false);
+    }
+
+    target.write(overlay, 0, record.data())
+}
+
+/// Determines the size of the Index Record addressed by `target` from its Update Sequence Array.
+fn index_record_size<T>(overlay: &mut NtfsOverlay<T>, target: &Target) -> Result<Option<usize>>
+where
+    T: Read + Seek,
+{
+    let header = match target.read(overlay, 0, 8)? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    // The Update Sequence Array has one entry for the Update Sequence Number and one for each sector.
+    let update_sequence_count = LittleEndian::read_u16(&header[6..]) as usize;
+    let record_size = update_sequence_count.saturating_sub(1) * CLUSTER_BLOCK_SIZE as usize;
+
+    if record_size == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(record_size))
+    }
+}
+
+fn redo_raw<T>(
+    overlay: &mut NtfsOverlay<T>,
+    target: &Target,
+    operation_record: &NtfsLogOperationRecord,
+) -> Result<bool>
+where
+    T: Read + Seek,
+{
+    let offset =
+        operation_record.record_offset() as u64 + operation_record.attribute_offset() as u64;
+    let data = operation_record.redo_data();
+
+    match operation_record.redo_operation() {
+        NtfsLogOperation::UpdateNonresidentValue => {
+            // A new Index Record is logged without its Update Sequence Array applied.
+            if offset == 0
+                && data.starts_with(b"INDX")
+                && data.len() % CLUSTER_BLOCK_SIZE as usize == 0
+            {
+                let mut record = Record::new(data.to_vec(), NtfsPosition::none());
+                if record.protect().is_ok() {
+                    return target.write(overlay, offset, record.data());
+                }
+            }
+
+            target.write(overlay, offset, data)
+        }
+        operation => {
+            if data.len() < 8 {
+                return Ok(false);
+            }
+
+            let bit_offset = LittleEndian::read_u32(data) as u64;
+            let bit_count = LittleEndian::read_u32(&data[4..]) as u64;
+            if bit_count == 0 {
+                return Ok(true);
+            }
+
+            // Both values come from the log record, so make sure they address bits within the target
+            // before allocating anything.
+            let first_byte = bit_offset / 8;
+            let last_byte = (bit_offset + bit_count - 1) / 8;
+            if offset + last_byte >= target.len() {
+                return Ok(false);
+            }
+
+            let mut bytes = match target.read(
+                overlay,
+                offset + first_byte,
+                (last_byte - first_byte + 1) as usize,
+            )? {
+                Some(bytes) => bytes,
+                None => return Ok(false),
+            };
+
+            let set = operation == NtfsLogOperation::SetBitsInNonresidentBitMap;
+            for bit in bit_offset..bit_offset + bit_count {
+                let byte = &mut bytes[(bit / 8 - first_byte) as usize];
+                let mask = 1u8 << (bit % 8);
+
+                if set {
+                    *byte |= mask;
+                } else {
+                    *byte &= !mask;
+                }
+            }
+
+            target.write(overlay, offset + first_byte, &bytes)
+        }
+    }
+}
+
+fn redo_file_record(record: &mut [u8], operation_record: &NtfsLogOperationRecord) -> Option<()> {
+    let record_offset = operation_record.record_offset() as usize;
+    let target_offset = record_offset + operation_record.attribute_offset() as usize;
+    let data = operation_record.redo_data();
+
+    match operation_record.redo_operation() {
+        NtfsLogOperation::InitializeFileRecordSegment => copy(record, record_offset, data),
+        NtfsLogOperation::DeallocateFileRecordSegment => {
+            let flags = read_u16(record, FILE_RECORD_FLAGS_OFFSET)?;
+            let flags = flags & !NtfsFileFlags::IN_USE.bits();
+            write_u16(record, FILE_RECORD_FLAGS_OFFSET, flags)
+        }
+        NtfsLogOperation::WriteEndOfFileRecordSegment => {
+            copy(record, target_offset, data)?;
+            set_bytes_in_use(record, target_offset + data.len())
+        }
+        NtfsLogOperation::CreateAttribute => {
+            let bytes_in_use = bytes_in_use(record)?;
+            insert(record, record_offset, data, bytes_in_use)?;
+            set_bytes_in_use(record, bytes_in_use + data.len())
+        }
+        NtfsLogOperation::DeleteAttribute => {
+            let bytes_in_use = bytes_in_use(record)?;
+            let length = read_u32(record, record_offset + ATTRIBUTE_LENGTH_OFFSET)? as usize;
+            remove(record, record_offset, length, bytes_in_use)?;
+            set_bytes_in_use(record, bytes_in_use - length)
+        }
+        NtfsLogOperation::UpdateResidentValue => {
+            let value_offset =
+                read_u16(record, record_offset + RESIDENT_VALUE_OFFSET_OFFSET)? as usize;
+            let value_length =
+                read_u32(record, record_offset + RESIDENT_VALUE_LENGTH_OFFSET)? as usize;
+            let value_end = record_offset + value_offset + value_length;
+            let new_value_end = target_offset + data.len();
+
+            if new_value_end > value_end {
+                let new_value_length = new_value_end - record_offset - value_offset;
+                resize_attribute(record, record_offset, value_offset + new_value_length)?;
+                write_u32(
+                    record,
+                    record_offset + RESIDENT_VALUE_LENGTH_OFFSET,
+                    new_value_length as u32,
+                )?;
+            }
+
+            copy(record, target_offset, data)
+        }
+        NtfsLogOperation::UpdateMappingPairs => {
+            let attribute_offset = operation_record.attribute_offset() as usize;
+            resize_attribute(record, record_offset, attribute_offset + data.len())?;
+            copy(record, target_offset, data)
+        }
+        NtfsLogOperation::SetNewAttributeSizes => {
+            let data_runs_offset =
+                read_u16(record, record_offset + NON_RESIDENT_DATA_RUNS_OFFSET_OFFSET)? as usize;
+            let sizes_length = data_runs_offset.checked_sub(NON_RESIDENT_SIZES_OFFSET)?;
+            let length = usize::min(data.len(), sizes_length);
+            copy(
+                record,
+                record_offset + NON_RESIDENT_SIZES_OFFSET,
+                &data[..length],
+            )
+        }
+        NtfsLogOperation::AddIndexEntryRoot => {
+            let bytes_in_use = bytes_in_use(record)?;
+            insert(record, target_offset, data, bytes_in_use)?;
+            set_bytes_in_use(record, bytes_in_use + data.len())?;
+            adjust_index_root(record, record_offset, data.len() as isize)
+        }
+        NtfsLogOperation::DeleteIndexEntryRoot => {
+            let bytes_in_use = bytes_in_use(record)?;
+            let length = read_u16(record, target_offset + INDEX_ENTRY_LENGTH_OFFSET)? as usize;
+            remove(record, target_offset, length, bytes_in_use)?;
+            set_bytes_in_use(record, bytes_in_use - length)?;
+            adjust_index_root(record, record_offset, -(length as isize))
+        }
+        operation => redo_index_entry(record, target_offset, operation, data),
+    }
+}
+
+fn redo_index_record(record: &mut [u8], operation_record: &NtfsLogOperationRecord) -> Option<()> {
+    let target_offset =
+        operation_record.record_offset() as usize + operation_record.attribute_offset() as usize;
+    let data = operation_record.redo_data();
+
+    let index_size_offset = INDEX_RECORD_NODE_HEADER_OFFSET + INDEX_NODE_INDEX_SIZE_OFFSET;
+    let index_size = read_u32(record, index_size_offset)? as usize;
+    let index_end = INDEX_RECORD_NODE_HEADER_OFFSET + index_size;
+
+    match operation_record.redo_operation() {
+        NtfsLogOperation::AddIndexEntryAllocation => {
+            let allocated_size = read_u32(
+                record,
+                INDEX_RECORD_NODE_HEADER_OFFSET + INDEX_NODE_ALLOCATED_SIZE_OFFSET,
+            )? as usize;
+            if index_size + data.len() > allocated_size {
+                return None;
+            }
+
+            insert(record, target_offset, data, index_end)?;
+            write_u32(record, index_size_offset, (index_size + data.len()) as u32)
+        }
+        NtfsLogOperation::DeleteIndexEntryAllocation => {
+            let length = read_u16(record, target_offset + INDEX_ENTRY_LENGTH_OFFSET)? as usize;
+            remove(record, target_offset, length, index_end)?;
+            write_u32(record, index_size_offset, (index_size - length) as u32)
+        }
+        NtfsLogOperation::WriteEndOfIndexBuffer => {
+            copy(record, target_offset, data)?;
+            let new_index_size =
+                (target_offset + data.len()).checked_sub(INDEX_RECORD_NODE_HEADER_OFFSET)?;
+            write_u32(record, index_size_offset, new_index_size as u32)
+        }
+        operation => redo_index_entry(record, target_offset, operation, data),
+    }
+}
+
+/// Performs the operations that modify a single Index Entry in place,
+/// which work the same in an Index Root and an Index Record.
+fn redo_index_entry(
+    record: &mut [u8],
+    entry_offset: usize,
+    operation: NtfsLogOperation,
+    data: &[u8],
+) -> Option<()> {
+    match operation {
+        NtfsLogOperation::SetIndexEntryVcnRoot | NtfsLogOperation::SetIndexEntryVcnAllocation => {
+            // The subnode VCN is stored in the last 8 bytes of an Index Entry.
+            let length = read_u16(record, entry_offset + INDEX_ENTRY_LENGTH_OFFSET)? as usize;
+            let vcn_offset = (entry_offset + length).checked_sub(8)?;
+            copy(record, vcn_offset, data.get(..8)?)
+        }
+        NtfsLogOperation::UpdateFileNameRoot | NtfsLogOperation::UpdateFileNameAllocation => copy(
+            record,
+            entry_offset + INDEX_ENTRY_DUPLICATED_INFORMATION_OFFSET,
+            data,
+        ),
+        NtfsLogOperation::UpdateRecordDataRoot | NtfsLogOperation::UpdateRecordDataAllocation => {
+            let data_offset =
+                read_u16(record, entry_offset + INDEX_ENTRY_DATA_OFFSET_OFFSET)? as usize;
+            copy(record, entry_offset + data_offset, data)
+        }
+        _ => None,
+    }
+}
+
+/// Adjusts the sizes of the Index Root attribute at `attribute_offset` after an Index Entry
+/// of `delta` bytes has been inserted or removed.
+fn adjust_index_root(record: &mut [u8], attribute_offset: usize, delta: isize) -> Option<()> {
+    let value_offset = read_u16(record, attribute_offset + RESIDENT_VALUE_OFFSET_OFFSET)? as usize;
+    let node_header_offset = attribute_offset + value_offset + INDEX_ROOT_NODE_HEADER_OFFSET;
+
+    for offset in [
+        attribute_offset + ATTRIBUTE_LENGTH_OFFSET,
+        attribute_offset + RESIDENT_VALUE_LENGTH_OFFSET,
+        node_header_offset + INDEX_NODE_INDEX_SIZE_OFFSET,
+        node_header_offset + INDEX_NODE_ALLOCATED_SIZE_OFFSET,
+    ] {
+        let value = read_u32(record, offset)? as isize;
+        let value = value.checked_add(delta).filter(|value| *value >= 0)?;
+        write_u32(record, offset, value as u32)?;
+    }
+
+    Some(())
+}
+
+/// Grows the attribute at `attribute_offset` to hold at least `length` bytes,
+/// moving all following attributes.
+fn resize_attribute(record: &mut [u8], attribute_offset: usize, length: usize) -> Option<()> {
+    let attribute_length_offset = attribute_offset + ATTRIBUTE_LENGTH_OFFSET;
+    let attribute_length = read_u32(record, attribute_length_offset)? as usize;
+    let new_attribute_length = (length + 7) & !7;
+
+    if new_attribute_length > attribute_length {
+        let bytes_in_use = bytes_in_use(record)?;
+        let growth = new_attribute_length - attribute_length;
+        let zeros = vec![0u8; growth];
+
+        insert(
+            record,
+            attribute_offset + attribute_length,
+            &zeros,
+            bytes_in_use,
+        )?;
+        set_bytes_in_use(record, bytes_in_use + growth)?;
+        write_u32(record, attribute_length_offset, new_attribute_length as u32)?;
+    }
+
+    Some(())
+}
+
+fn bytes_in_use(record: &[u8]) -> Option<usize> {
+    let bytes_in_use = read_u32(record, FILE_RECORD_BYTES_IN_USE_OFFSET)? as usize;
+    if bytes_in_use <= record.len() {
+        Some(bytes_in_use)
+    } else {
+        None
+    }
+}
+
+fn set_bytes_in_use(record: &mut [u8], bytes_in_use: usize) -> Option<()> {
+    if bytes_in_use > record.len() {
+        return None;
+    }
+
+    write_u32(record, FILE_RECORD_BYTES_IN_USE_OFFSET, bytes_in_use as u32)
+}
+
+fn copy(record: &mut [u8], offset: usize, data: &[u8]) -> Option<()> {
+    let end = offset.checked_add(data.len())?;
+    record.get_mut(offset..end)?.copy_from_slice(data);
+    Some(())
+}
+
+/// Inserts `data` at `offset`, moving the bytes up to `end` behind it.
+fn insert(record: &mut [u8], offset: usize, data: &[u8], end: usize) -> Option<()> {
+    let new_end = end.checked_add(data.len())?;
+    if offset > end || new_end > record.len() {
+        return None;
+    }
+
+    record.copy_within(offset..end, offset + data.len());
+    copy(record, offset, data)
+}
+
+/// Removes `length` bytes at `offset`, moving the bytes up to `end` in front and zeroing the freed space.
+fn remove(record: &mut [u8], offset: usize, length: usize, end: usize) -> Option<()> {
+    let removed_end = offset.checked_add(length)?;
+    if removed_end > end || end > record.len() {
+        return None;
+    }
+
+    record.copy_within(removed_end..end, offset);
+    record[end - length..end].fill(0);
+    Some(())
+}
+
+fn read_u16(record: &[u8], offset: usize) -> Option<u16> {
+    record.get(offset..offset + 2).map(LittleEndian::read_u16)
+}
+
+fn read_u32(record: &[u8], offset: usize) -> Option<u32> {
+    record.get(offset..offset + 4).map(LittleEndian::read_u32)
+}
+
+fn write_u16(record: &mut [u8], offset: usize, value: u16) -> Option<()> {
+    LittleEndian::write_u16(record.get_mut(offset..offset + 2)?, value);
+    Some(())
+}
+
+fn write_u32(record: &mut [u8], offset: usize, value: u32) -> Option<()> {
+    LittleEndian::write_u32(record.get_mut(offset..offset + 4)?, value);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logfile::tests::{operation, write_log_file};
+    use crate::traits::NtfsReadSeek;
+
+    /// Points the operation at the given offsets within its target, which starts at the first target LCN.
+    fn set_offsets(operation: &mut [u8], record_offset: u64, attribute_offset: u64) {
+        LittleEndian::write_u16(&mut operation[16..], record_offset as u16);
+        LittleEndian::write_u16(&mut operation[18..], attribute_offset as u16);
+        LittleEndian::write_u16(&mut operation[20..], 0);
+        LittleEndian::write_i64(&mut operation[24..], 0);
+    }
+
+    /// Returns a checkpoint followed by a committed transaction that overwrites "1000-bytes-file"
+    /// (File Record 66, LCN 2567) at the given offset.
+    fn nonresident_records(
+        transaction_id: u32,
+        offset: u64,
+        redo_data: &[u8],
+        undo_data: &[u8],
+    ) -> Vec<(u32, NtfsLogRecordType, Vec<u8>)> {
+        let mut update = operation(
+            NtfsLogOperation::UpdateNonresidentValue,
+            NtfsLogOperation::UpdateNonresidentValue,
+            &[2567],
+            redo_data,
+            undo_data,
+        );
+        set_offsets(&mut update, 0, offset);
+        let commit = operation(
+            NtfsLogOperation::CommitTransaction,
+            NtfsLogOperation::Noop,
+            &[],
+            b"",
+            b"",
+        );
+
+        vec![
+            (0, NtfsLogRecordType::ClientRestart, vec![0u8; 8]),
+            (transaction_id, NtfsLogRecordType::ClientRecord, update),
+            (transaction_id, NtfsLogRecordType::ClientRecord, commit),
+        ]
+    }
+
+    fn read_data<T>(ntfs: &Ntfs, fs: &mut T, file_record_number: u64, length: usize) -> Vec<u8>
+    where
+        T: Read + Seek,
+    {
+        let file = ntfs.file(fs, file_record_number).unwrap();
+        let data_item = file.data(fs, "").unwrap().unwrap();
+        let data_attribute = data_item.to_attribute().unwrap();
+        let mut data_value = data_attribute.value(fs).unwrap();
+        assert_eq!(data_value.len(), length as u64);
+
+        let mut data = vec![0u8; length];
+        data_value.read_exact(fs, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_redo_committed() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let mut ntfs = Ntfs::new(&mut testfs1).unwrap();
+        ntfs.read_upcase_table(&mut testfs1).unwrap();
+
+        // Locate the resident $DATA value of "file-with-12345" within its File Record,
+        // which spans 2 clusters.
+        let file = ntfs.file_by_path(&mut testfs1, "file-with-12345").unwrap();
+        let resident_file_record_number = file.file_record_number();
+        let attribute_count = file.attributes_raw().count();
+        let file_position = file.position().value().unwrap().get();
+        let data_item = file.data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_item.to_attribute().unwrap();
+        let attribute_position = data_attribute.position().value().unwrap().get();
+        let mut nonresident_data = read_data(&ntfs, &mut testfs1, 66, 1000);
+        nonresident_data[..3].copy_from_slice(b"abc");
+
+        let record_lcns = [file_position / 512, file_position / 512 + 1];
+        let record_offset = attribute_position - file_position;
+        let value_offset_position = (attribute_position as usize) + RESIDENT_VALUE_OFFSET_OFFSET;
+        let attribute_offset =
+            LittleEndian::read_u16(&testfs1.get_ref()[value_offset_position..]) as u64;
+
+        let mut update_value = operation(
+            NtfsLogOperation::UpdateResidentValue,
+            NtfsLogOperation::UpdateResidentValue,
+            &record_lcns,
+            b"54321",
+            b"12345",
+        );
+        set_offsets(&mut update_value, record_offset, attribute_offset);
+
+        // Append to the value, which requires growing the attribute.
+        let mut extend_value = operation(
+            NtfsLogOperation::UpdateResidentValue,
+            NtfsLogOperation::UpdateResidentValue,
+            &record_lcns,
+            b"6789",
+            b"",
+        );
+        set_offsets(&mut extend_value, record_offset, attribute_offset + 5);
+
+        // Overwrite the start of the non-resident "1000-bytes-file" (File Record 66).
+        let mut update_nonresident = operation(
+            NtfsLogOperation::UpdateNonresidentValue,
+            NtfsLogOperation::UpdateNonresidentValue,
+            &[2567],
+            b"abc",
+            b"\0\0\0",
+        );
+        set_offsets(&mut update_nonresident, 0, 0);
+
+        let mut uncommitted_value = operation(
+            NtfsLogOperation::UpdateResidentValue,
+            NtfsLogOperation::UpdateResidentValue,
+            &record_lcns,
+            b"XXXXX",
+            b"12345",
+        );
+        set_offsets(&mut uncommitted_value, record_offset, attribute_offset);
+
+        // Overwrite the end of "1000-bytes-file" before the checkpoint, which must be ignored.
+        let mut before_checkpoint = operation(
+            NtfsLogOperation::UpdateNonresidentValue,
+            NtfsLogOperation::UpdateNonresidentValue,
+            &[2567],
+            b"zzz",
+            b"\0\0\0",
+        );
+        set_offsets(&mut before_checkpoint, 0, 997);
+
+        // A forgotten transaction is not committed by a later commit record reusing its ID.
+        let mut forgotten_value = operation(
+            NtfsLogOperation::UpdateResidentValue,
+            NtfsLogOperation::UpdateResidentValue,
+            &record_lcns,
+            b"YYYYY",
+            b"12345",
+        );
+        set_offsets(&mut forgotten_value, record_offset, attribute_offset);
+
+        let forget = operation(
+            NtfsLogOperation::ForgetTransaction,
+            NtfsLogOperation::CompensationLogRecord,
+            &[],
+            b"",
+            b"",
+        );
+        let commit = operation(
+            NtfsLogOperation::CommitTransaction,
+            NtfsLogOperation::Noop,
+            &[],
+            b"",
+            b"",
+        );
+
+        let records = [
+            (0x18, NtfsLogRecordType::ClientRecord, before_checkpoint),
+            (0, NtfsLogRecordType::ClientRestart, vec![0u8; 8]),
+            (0x18, NtfsLogRecordType::ClientRecord, update_value),
+            (0x30, NtfsLogRecordType::ClientRecord, uncommitted_value),
+            (0x40, NtfsLogRecordType::ClientRecord, forgotten_value),
+            (0x40, NtfsLogRecordType::ClientRecord, forget),
+            (0x18, NtfsLogRecordType::ClientRecord, extend_value),
+            (0x18, NtfsLogRecordType::ClientRecord, update_nonresident),
+            (0x18, NtfsLogRecordType::ClientRecord, commit.clone()),
+            (0x40, NtfsLogRecordType::ClientRecord, commit),
+        ];
+
+        write_log_file(testfs1.get_mut(), &records, NtfsLogRestartFlags::empty());
+        let original_image = testfs1.get_ref().clone();
+        let log_file = ntfs.log_file(&mut testfs1).unwrap().unwrap();
+        let mut overlay = NtfsOverlay::new(testfs1);
+
+        let summary = log_file.redo_committed(&ntfs, &mut overlay).unwrap();
+        assert_eq!(summary.applied_operations(), 3);
+        assert_eq!(summary.skipped_operations(), 0);
+        assert_eq!(summary.uncommitted_operations(), 2);
+
+        // A new filesystem object created on top of the overlay sees the replayed state.
+        let replayed_ntfs = Ntfs::new(&mut overlay).unwrap();
+        assert_eq!(
+            read_data(&replayed_ntfs, &mut overlay, resident_file_record_number, 9),
+            b"543216789"
+        );
+        assert_eq!(
+            read_data(&replayed_ntfs, &mut overlay, 66, 1000),
+            nonresident_data
+        );
+
+        // The attributes following the grown $DATA attribute have been moved and are still intact.
+        let file = replayed_ntfs
+            .file(&mut overlay, resident_file_record_number)
+            .unwrap();
+        let attributes = file.attributes_raw().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(attributes.len(), attribute_count);
+
+        // Replaying again skips the File Record operations, because the records carry their LSNs now.
+        let summary = log_file.redo_committed(&ntfs, &mut overlay).unwrap();
+        assert_eq!(summary.applied_operations(), 1);
+        assert_eq!(summary.skipped_operations(), 2);
+        assert_eq!(
+            read_data(&replayed_ntfs, &mut overlay, resident_file_record_number, 9),
+            b"543216789"
+        );
+
+        // The underlying image has not been modified.
+        assert_eq!(overlay.into_inner().into_inner(), original_image);
+    }
+
+    #[test]
+    fn test_redo_bitmap() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // Treat the data of "1000-bytes-file" (File Record 66, LCN 2567) as a bitmap.
+        let mut bitmap = read_data(&ntfs, &mut testfs1, 66, 1000);
+        bitmap[16] |= 0b1111_1000;
+        bitmap[17] |= 0b0001_1111;
+        bitmap[32] &= 0b1111_0000;
+
+        let bitmap_operation = |bitmap_operation: NtfsLogOperation,
+                                attribute_offset: u64,
+                                bit_offset: u32,
+                                bit_count: u32| {
+            let mut redo_data = [0u8; 8];
+            LittleEndian::write_u32(&mut redo_data[0..], bit_offset);
+            LittleEndian::write_u32(&mut redo_data[4..], bit_count);
+
+            let mut operation = operation(
+                bitmap_operation,
+                bitmap_operation,
+                &[2567],
+                &redo_data,
+                &redo_data,
+            );
+            set_offsets(&mut operation, 0, attribute_offset);
+            (0x18, NtfsLogRecordType::ClientRecord, operation)
+        };
+        let commit = operation(
+            NtfsLogOperation::CommitTransaction,
+            NtfsLogOperation::Noop,
+            &[],
+            b"",
+            b"",
+        );
+
+        let records = [
+            (0, NtfsLogRecordType::ClientRestart, vec![0u8; 8]),
+            bitmap_operation(NtfsLogOperation::SetBitsInNonresidentBitMap, 16, 3, 10),
+            bitmap_operation(NtfsLogOperation::ClearBitsInNonresidentBitMap, 32, 0, 4),
+            // A bit range beyond the target cluster is skipped.
+            bitmap_operation(NtfsLogOperation::SetBitsInNonresidentBitMap, 0, 0, u32::MAX),
+            bitmap_operation(NtfsLogOperation::ClearBitsInNonresidentBitMap, 500, 100, 1),
+            (0x18, NtfsLogRecordType::ClientRecord, commit),
+        ];
+        write_log_file(testfs1.get_mut(), &records, NtfsLogRestartFlags::empty());
+
+        let log_file = ntfs.log_file(&mut testfs1).unwrap().unwrap();
+        let mut overlay = NtfsOverlay::new(testfs1);
+        let summary = log_file.redo_committed(&ntfs, &mut overlay).unwrap();
+        assert_eq!(summary.applied_operations(), 2);
+        assert_eq!(summary.skipped_operations(), 2);
+        assert_eq!(summary.uncommitted_operations(), 0);
+
+        assert_eq!(read_data(&ntfs, &mut overlay, 66, 1000), bitmap);
+    }
+
+    #[test]
+    fn test_redo_clean_volume() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let records = nonresident_records(0x18, 0, b"abc", b"\0\0\0");
+
+        // Nothing is redone if the volume has been unmounted cleanly.
+        write_log_file(
+            testfs1.get_mut(),
+            &records,
+            NtfsLogRestartFlags::VOLUME_IS_CLEAN,
+        );
+        let log_file = ntfs.log_file(&mut testfs1).unwrap().unwrap();
+        let mut overlay = NtfsOverlay::new(testfs1.clone());
+        let summary = log_file.redo_committed(&ntfs, &mut overlay).unwrap();
+        assert_eq!(summary, NtfsLogRedoSummary::default());
+        assert!(!overlay.is_modified());
+
+        // The same log is redone if the volume is dirty.
+        write_log_file(testfs1.get_mut(), &records, NtfsLogRestartFlags::empty());
+        let log_file = ntfs.log_file(&mut testfs1).unwrap().unwrap();
+        let mut overlay = NtfsOverlay::new(testfs1);
+        let summary = log_file.redo_committed(&ntfs, &mut overlay).unwrap();
+        assert_eq!(summary.applied_operations(), 1);
+        assert!(overlay.is_modified());
+    }
+
+    #[test]
+    fn test_redo_multi_page() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // The undo data makes the log record continue on the next log record page,
+        // so the redo data must be collected from both pages.
+        let redo_data: Vec<u8> = (0..512u32).map(|i| i as u8).collect();
+        let undo_data = vec![0xaau8; 4000];
+        let records = nonresident_records(0x18, 0, &redo_data, &undo_data);
+        let lsns = write_log_file(testfs1.get_mut(), &records, NtfsLogRestartFlags::empty());
+
+        let log_file = ntfs.log_file(&mut testfs1).unwrap().unwrap();
+        let record = log_file.record(&mut testfs1, lsns[1]).unwrap();
+        assert!(record.is_multi_page());
+        assert_eq!(record.operation_record().unwrap().undo_data(), undo_data);
+
+        let mut overlay = NtfsOverlay::new(testfs1);
+        let summary = log_file.redo_committed(&ntfs, &mut overlay).unwrap();
+        assert_eq!(summary.applied_operations(), 1);
+        assert_eq!(
+            read_data(&ntfs, &mut overlay, 66, 1000)[..512],
+            redo_data[..]
+        );
+    }
+
+    #[test]
+    fn test_redo_uncommitted() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let mut data = read_data(&ntfs, &mut testfs1, 66, 1000);
+        data[..3].copy_from_slice(b"abc");
+
+        // Transaction 0x30 writes "xyz" at offset 100, but never commits.
+        let mut records = nonresident_records(0x18, 0, b"abc", b"\0\0\0");
+        let mut uncommitted = operation(
+            NtfsLogOperation::UpdateNonresidentValue,
+            NtfsLogOperation::UpdateNonresidentValue,
+            &[2567],
+            b"xyz",
+            b"\0\0\0",
+        );
+        set_offsets(&mut uncommitted, 0, 100);
+        records.insert(1, (0x30, NtfsLogRecordType::ClientRecord, uncommitted));
+        write_log_file(testfs1.get_mut(), &records, NtfsLogRestartFlags::empty());
+
+        let log_file = ntfs.log_file(&mut testfs1).unwrap().unwrap();
+        let mut overlay = NtfsOverlay::new(testfs1);
+        let summary = log_file.redo_committed(&ntfs, &mut overlay).unwrap();
+        assert_eq!(summary.applied_operations(), 1);
+        assert_eq!(summary.skipped_operations(), 0);
+        assert_eq!(summary.uncommitted_operations(), 1);
+        assert_eq!(read_data(&ntfs, &mut overlay, 66, 1000), data);
+    }
+}
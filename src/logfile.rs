@@ -0,0 +1,1680 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Reader for the NTFS transaction log stored in the $LogFile.
+
+use core::fmt;
+use core::iter::FusedIterator;
+use core::mem;
+use core::ops::Range;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek};
+use bitflags::bitflags;
+use byteorder::{ByteOrder, LittleEndian};
+use enumn::N;
+use memoffset::offset_of;
+use nt_string::u16strle::U16StrLe;
+use strum_macros::Display;
+
+use crate::attribute::NtfsAttributeType;
+use crate::error::{NtfsError, Result};
+use crate::extent_map::ExtentMap;
+use crate::file::KnownNtfsFileRecordNumber;
+use crate::ntfs::Ntfs;
+use crate::record::Record;
+use crate::types::{Lcn, NtfsPosition, Vcn};
+
+/// Signature of a restart page.
+const RESTART_PAGE_SIGNATURE: &[u8; 4] = b"RSTR";
+
+/// Signature of a restart page that has been rewritten by CHKDSK.
+const CHKDSK_PAGE_SIGNATURE: &[u8; 4] = b"CHKD";
+
+/// Signature of a log record page.
+const RECORD_PAGE_SIGNATURE: &[u8; 4] = b"RCRD";
+
+/// Signature of a page that has never been written (freshly formatted or reset $LogFile).
+const UNWRITTEN_PAGE_SIGNATURE: &[u8; 4] = b"\xff\xff\xff\xff";
+
+/// Smallest supported system and log page size, in bytes.
+const MIN_LOG_PAGE_SIZE: u32 = 512;
+
+/// Largest supported system and log page size, in bytes.
+const MAX_LOG_PAGE_SIZE: u32 = 65536;
+
+/// Assumed offset of the second restart page if the first one cannot be read.
+const DEFAULT_SYSTEM_PAGE_SIZE: u64 = 4096;
+
+/// Number of log pages between the restart pages and the first log record page,
+/// which are used as buffers by version 1.x and 2.x of the Log File Service.
+const LOG_BUFFER_PAGE_COUNT_V1: u64 = 2;
+const LOG_BUFFER_PAGE_COUNT_V2: u64 = 32;
+
+/// Terminates the linked lists of log clients.
+const LOG_NO_CLIENT: u16 = 0xffff;
+
+/// Size of a single `LOG_CLIENT_RECORD` in the client array of the restart area.
+const LOG_CLIENT_RECORD_SIZE: usize = 160;
+
+/// Maximum length of a log client name, in bytes.
+const LOG_CLIENT_NAME_MAX_SIZE: usize = 128;
+
+/// Flag of a log record that continues on the next log page.
+const LOG_RECORD_MULTI_PAGE: u16 = 0x0001;
+
+const RESTART_AREA_SIZE: usize = mem::size_of::<RestartArea>();
+const RECORD_PAGE_HEADER_SIZE: usize = mem::size_of::<RecordPageHeader>();
+const LOG_RECORD_HEADER_SIZE: usize = mem::size_of::<LogRecordHeader>();
+const OPERATION_HEADER_SIZE: usize = mem::size_of::<OperationHeader>();
+
+/// On-disk structure of the header of a restart page.
+#[repr(C, packed)]
+struct RestartPageHeader {
+    signature: [u8; 4],
+    update_sequence_offset: u16,
+    update_sequence_count: u16,
+    /// Last Log Sequence Number (LSN) found by CHKDSK, only used in `CHKD` pages.
+    chkdsk_lsn: u64,
+    /// Size of a restart page, in bytes.
+    system_page_size: u32,
+    /// Size of a log record page, in bytes.
+    log_page_size: u32,
+    /// Offset of the [`RestartArea`], in bytes from the beginning of this header.
+    restart_area_offset: u16,
+    minor_version: i16,
+    major_version: i16,
+}
+
+/// On-disk structure of the restart area following the [`RestartPageHeader`].
+#[repr(C, packed)]
+struct RestartArea {
+    /// Log Sequence Number (LSN) of the last record written when this restart area was written.
+    current_lsn: u64,
+    /// Number of entries in the client array.
+    log_clients: u16,
+    /// Index of the first free client record.
+    client_free_list: u16,
+    /// Index of the first client record in use.
+    client_in_use_list: u16,
+    /// Flags of the restart area, known flags are in [`NtfsLogRestartFlags`].
+    flags: u16,
+    /// Number of upper bits of an LSN that hold a sequence number incremented on every wrap-around.
+    sequence_number_bits: u32,
+    restart_area_length: u16,
+    /// Offset of the client array, in bytes from the beginning of this restart area.
+    client_array_offset: u16,
+    /// Usable size of the $LogFile, in bytes.
+    file_size: u64,
+    last_lsn_data_length: u32,
+    log_record_header_length: u16,
+    /// Offset of the first log record in every log record page, in bytes.
+    log_page_data_offset: u16,
+    restart_log_open_count: u32,
+    reserved: u32,
+}
+
+/// On-disk structure of a single entry of the client array.
+#[repr(C, packed)]
+struct LogClientRecord {
+    /// Oldest Log Sequence Number (LSN) still required by this client.
+    oldest_lsn: u64,
+    /// Log Sequence Number (LSN) of the last restart record (checkpoint) written by this client.
+    client_restart_lsn: u64,
+    prev_client: u16,
+    next_client: u16,
+    sequence_number: u16,
+    reserved: [u8; 6],
+    /// Length of the client name, in bytes.
+    client_name_length: u32,
+    client_name: [u16; 64],
+}
+
+/// On-disk structure of the header of a log record page.
+#[repr(C, packed)]
+struct RecordPageHeader {
+    signature: [u8; 4],
+    update_sequence_offset: u16,
+    update_sequence_count: u16,
+    last_lsn: u64,
+    flags: u32,
+    page_count: u16,
+    page_position: u16,
+    next_record_offset: u16,
+    reserved: [u8; 6],
+    last_end_lsn: u64,
+}
+
+/// On-disk structure of the header of every log record.
+#[repr(C, packed)]
+struct LogRecordHeader {
+    this_lsn: u64,
+    client_previous_lsn: u64,
+    client_undo_next_lsn: u64,
+    /// Length of the client data following this header, in bytes.
+    client_data_length: u32,
+    client_sequence_number: u16,
+    client_index: u16,
+    /// Type of the record, known types are in [`NtfsLogRecordType`].
+    record_type: u32,
+    transaction_id: u32,
+    flags: u16,
+    reserved: [u8; 6],
+}
+
+/// On-disk structure of the client data header of an NTFS log record of type [`NtfsLogRecordType::ClientRecord`].
+#[repr(C, packed)]
+struct OperationHeader {
+    redo_operation: u16,
+    undo_operation: u16,
+    /// Offset of the redo data, in bytes from the beginning of this header.
+    redo_offset: u16,
+    redo_length: u16,
+    /// Offset of the undo data, in bytes from the beginning of this header.
+    undo_offset: u16,
+    undo_length: u16,
+    /// Index into the open attribute table of the last checkpoint.
+    target_attribute: u16,
+    /// Number of LCNs following this header.
+    lcns_to_follow: u16,
+    record_offset: u16,
+    attribute_offset: u16,
+    cluster_block_offset: u16,
+    reserved: u16,
+    target_vcn: Vcn,
+}
+
+bitflags! {
+    /// Flags returned by [`NtfsLogRestartPage::flags`].
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    pub struct NtfsLogRestartFlags: u16 {
+        /// The volume has been unmounted cleanly and the log doesn't need to be replayed.
+        const VOLUME_IS_CLEAN = 0x0002;
+    }
+}
+
+impl fmt::Display for NtfsLogRestartFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Types of log records, returned by [`NtfsLogRecord::record_type`].
+#[derive(Clone, Copy, Debug, Display, Eq, N, PartialEq)]
+#[repr(u32)]
+pub enum NtfsLogRecordType {
+    /// A record describing a change through redo and undo operations, see [`NtfsLogOperationRecord`].
+    ClientRecord = 1,
+    /// A restart record (checkpoint) written by a client.
+    ClientRestart = 2,
+}
+
+/// Redo and undo operations of NTFS log records, returned by [`NtfsLogOperationRecord::redo_operation`]
+/// and [`NtfsLogOperationRecord::undo_operation`].
+///
+/// The variants are named after the operation codes used by Windows.
+#[derive(Clone, Copy, Debug, Display, Eq, N, PartialEq)]
+#[repr(u16)]
+pub enum NtfsLogOperation {
+    Noop = 0x00,
+    CompensationLogRecord = 0x01,
+    InitializeFileRecordSegment = 0x02,
+    DeallocateFileRecordSegment = 0x03,
+    WriteEndOfFileRecordSegment = 0x04,
+    CreateAttribute = 0x05,
+    DeleteAttribute = 0x06,
+    UpdateResidentValue = 0x07,
+    UpdateNonresidentValue = 0x08,
+    UpdateMappingPairs = 0x09,
+    DeleteDirtyClusters = 0x0a,
+    SetNewAttributeSizes = 0x0b,
+    AddIndexEntryRoot = 0x0c,
+    DeleteIndexEntryRoot = 0x0d,
+    AddIndexEntryAllocation = 0x0e,
+    DeleteIndexEntryAllocation = 0x0f,
+    WriteEndOfIndexBuffer = 0x10,
+    SetIndexEntryVcnRoot = 0x11,
+    SetIndexEntryVcnAllocation = 0x12,
+    UpdateFileNameRoot = 0x13,
+    UpdateFileNameAllocation = 0x14,
+    SetBitsInNonresidentBitMap = 0x15,
+    ClearBitsInNonresidentBitMap = 0x16,
+    HotFix = 0x17,
+    EndTopLevelAction = 0x18,
+    PrepareTransaction = 0x19,
+    CommitTransaction = 0x1a,
+    ForgetTransaction = 0x1b,
+    OpenNonresidentAttribute = 0x1c,
+    OpenAttributeTableDump = 0x1d,
+    AttributeNamesDump = 0x1e,
+    DirtyPageTableDump = 0x1f,
+    TransactionTableDump = 0x20,
+    UpdateRecordDataRoot = 0x21,
+    UpdateRecordDataAllocation = 0x22,
+    UpdateRelativeDataInIndex = 0x23,
+    UpdateRelativeDataInIndex2 = 0x24,
+    ZeroEndOfFileRecord = 0x25,
+}
+
+/// The NTFS transaction log stored in the $LogFile, returned from [`Ntfs::log_file`].
+///
+/// NTFS logs every metadata change as a redo/undo operation before applying it, so that an interrupted
+/// change can be completed or rolled back when the volume is mounted the next time.
+/// The $LogFile begins with two copies of a restart page (`RSTR` signature), which describe the log layout
+/// and the log clients (usually only "NTFS").
+/// They are followed by log record pages (`RCRD` signature), which are used as a circular buffer.
+///
+/// Every log record is identified by its Log Sequence Number (LSN).
+/// The lower bits of an LSN encode the byte offset of the record within the $LogFile (divided by 8),
+/// while the upper bits hold a sequence number that is incremented whenever the log wraps around.
+///
+/// Reference: <https://flatcap.github.io/linux-ntfs/ntfs/files/logfile.html>
+#[derive(Clone, Debug)]
+pub struct NtfsLogFile {
+    extent_map: ExtentMap,
+    size: u64,
+    restart_pages: Vec<NtfsLogRestartPage>,
+    current_restart_page: usize,
+}
+
+impl NtfsLogFile {
+    pub(crate) fn new<T>(ntfs: &Ntfs, fs: &mut T) -> Option<Result<Self>>
+    where
+        T: Read + Seek,
+    {
+        let file = iter_try!(ntfs.file(fs, KnownNtfsFileRecordNumber::LogFile as u64));
+        let item = iter_try!(file.find_attribute(fs, NtfsAttributeType::Data, Some("")));
+        let attribute = iter_try!(item.to_attribute());
+        let size = attribute.value_length();
+        let extent_map = iter_try!(ExtentMap::from_attribute(fs, &attribute));
+
+        // The second restart page follows the first one.
+        // If the first one cannot be read, assume the usual page size to still find the second one.
+        let first_page = NtfsLogRestartPage::read(fs, &extent_map, 0);
+        let second_page_offset = match &first_page {
+            Ok(Some(page)) => page.system_page_size() as u64,
+            _ => DEFAULT_SYSTEM_PAGE_SIZE,
+        };
+        let second_page = NtfsLogRestartPage::read(fs, &extent_map, second_page_offset);
+
+        let mut restart_pages = Vec::new();
+        let mut error = None;
+
+        for page in [first_page, second_page] {
+            match page {
+                Ok(Some(page)) => restart_pages.push(page),
+                Ok(None) => (),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+
+        if restart_pages.is_empty() {
+            // Return the first error, or `None` if the $LogFile has never been written.
+            return error.map(Err);
+        }
+
+        // Both restart pages are written alternately, so the one with the higher LSN is the current one.
+        let mut current_restart_page = 0;
+        for (i, page) in restart_pages.iter().enumerate() {
+            if page.current_lsn() > restart_pages[current_restart_page].current_lsn() {
+                current_restart_page = i;
+            }
+        }
+
+        Some(Ok(Self {
+            extent_map,
+            size,
+            restart_pages,
+            current_restart_page,
+        }))
+    }
+
+    /// Returns the offset of the first log record page within the $LogFile, in bytes.
+    fn first_log_page_offset(&self) -> u64 {
+        let restart_page = self.restart_page();
+        let buffer_page_count = if restart_page.major_version() >= 2 {
+            LOG_BUFFER_PAGE_COUNT_V2
+        } else {
+            LOG_BUFFER_PAGE_COUNT_V1
+        };
+
+        2 * restart_page.system_page_size() as u64
+            + buffer_page_count * restart_page.log_page_size() as u64
+    }
+
+    fn file_offset_bits(&self) -> u32 {
+        64 - self.restart_page().sequence_number_bits()
+    }
+
+    /// Returns the absolute filesystem position of the log record with the given LSN, in bytes.
+    ///
+    /// This is `None` if the LSN lies beyond the end of the $LogFile.
+    pub fn lsn_position(&self, lsn: u64) -> NtfsPosition {
+        self.lsn_offset(lsn)
+            .map_or(NtfsPosition::none(), |offset| self.position(offset))
+    }
+
+    /// Returns the offset of the given LSN within the $LogFile, in bytes.
+    fn lsn_offset(&self, lsn: u64) -> Option<u64> {
+        let offset = (lsn & ((1 << self.file_offset_bits()) - 1)) << 3;
+        (offset < self.size).then(|| offset)
+    }
+
+    /// Returns the offset of the log record page following the one at `page_offset`, and whether the log
+    /// wrapped around to reach it.
+    fn next_page_offset(&self, page_offset: u64) -> (u64, bool) {
+        let log_page_size = self.restart_page().log_page_size() as u64;
+        let next_page_offset = page_offset + log_page_size;
+
+        if next_page_offset + log_page_size > self.size {
+            (self.first_log_page_offset(), true)
+        } else {
+            (next_page_offset, false)
+        }
+    }
+
+    fn position(&self, offset: u64) -> NtfsPosition {
+        self.extent_map
+            .lookup(offset)
+            .map_or(NtfsPosition::none(), |(position, _)| position)
+    }
+
+    /// Reads the log record page at the given offset and applies its Update Sequence Array fixups.
+    fn read_page<T>(&self, fs: &mut T, page_offset: u64, lsn: u64) -> Result<Vec<u8>>
+    where
+        T: Read + Seek,
+    {
+        let position = self.position(page_offset);
+        let mut data = vec![0u8; self.restart_page().log_page_size() as usize];
+        self.extent_map
+            .read_exact(fs, page_offset, &mut data)
+            .ok_or(NtfsError::InvalidLsn { lsn })??;
+
+        let mut record = Record::new(data, position);
+        let signature = record.signature();
+        if &signature != RECORD_PAGE_SIGNATURE {
+            return Err(NtfsError::InvalidLogFileSignature {
+                position,
+                expected: RECORD_PAGE_SIGNATURE,
+                actual: signature,
+            });
+        }
+
+        record.fixup()?;
+
+        Ok(record.into_data())
+    }
+
+    /// Reads the log record with the given LSN, which may span multiple log record pages.
+    ///
+    /// Returns the record along with the LSN of the record following it.
+    fn read_record<T>(&self, fs: &mut T, lsn: u64) -> Result<(NtfsLogRecord, u64)>
+    where
+        T: Read + Seek,
+    {
+        let log_page_size = self.restart_page().log_page_size() as u64;
+        let data_offset = self.restart_page().log_page_data_offset() as u64;
+
+        let offset = self.lsn_offset(lsn).ok_or(NtfsError::InvalidLsn { lsn })?;
+        let mut page_offset = offset - offset % log_page_size;
+        let offset_in_page = offset - page_offset;
+
+        // A record header never crosses a page boundary.
+        if page_offset < self.first_log_page_offset()
+            || offset_in_page < data_offset
+            || offset_in_page + LOG_RECORD_HEADER_SIZE as u64 > log_page_size
+        {
+            return Err(NtfsError::InvalidLsn { lsn });
+        }
+
+        let position = self.position(offset);
+        let page = self.read_page(fs, page_offset, lsn)?;
+        let header = &page[offset_in_page as usize..];
+
+        let this_lsn = LittleEndian::read_u64(&header[offset_of!(LogRecordHeader, this_lsn)..]);
+        if this_lsn != lsn {
+            return Err(NtfsError::InvalidLogRecordLsn {
+                position,
+                expected: lsn,
+                actual: this_lsn,
+            });
+        }
+
+        let client_data_length =
+            LittleEndian::read_u32(&header[offset_of!(LogRecordHeader, client_data_length)..]);
+        let record_length = LOG_RECORD_HEADER_SIZE as u64 + client_data_length as u64;
+        if record_length > self.size {
+            return Err(NtfsError::InvalidLogRecordLength {
+                position,
+                length: client_data_length,
+            });
+        }
+
+        let record_length = record_length as usize;
+        let mut data = Vec::with_capacity(record_length);
+        let length = usize::min(record_length, (log_page_size - offset_in_page) as usize);
+        data.extend_from_slice(&header[..length]);
+        let mut end_offset = offset + length as u64;
+        let mut wrapped = false;
+
+        // Collect the remaining client data from the data areas of the following pages.
+        while data.len() < record_length {
+            let (next_page_offset, next_wrapped) = self.next_page_offset(page_offset);
+            page_offset = next_page_offset;
+            wrapped |= next_wrapped;
+
+            let page = self.read_page(fs, page_offset, lsn)?;
+            let length = usize::min(
+                record_length - data.len(),
+                (log_page_size - data_offset) as usize,
+            );
+            let start = data_offset as usize;
+            data.extend_from_slice(&page[start..start + length]);
+            end_offset = page_offset + data_offset + length as u64;
+        }
+
+        // Records are aligned to 8 bytes, and a record header must fit into the remaining page.
+        let mut next_offset = (end_offset + 7) & !7;
+        if next_offset + LOG_RECORD_HEADER_SIZE as u64 > page_offset + log_page_size {
+            let (next_page_offset, next_wrapped) = self.next_page_offset(page_offset);
+            next_offset = next_page_offset + data_offset;
+            wrapped |= next_wrapped;
+        }
+
+        let file_offset_bits = self.file_offset_bits();
+        let sequence_number = (lsn >> file_offset_bits) + wrapped as u64;
+        let next_lsn = (sequence_number << file_offset_bits) | (next_offset >> 3);
+
+        let record = NtfsLogRecord::new(data, position)?;
+        Ok((record, next_lsn))
+    }
+
+    /// Reads the log record with the given LSN.
+    ///
+    /// This can be used to follow the links between log records, e.g. via [`NtfsLogRecord::client_previous_lsn`]
+    /// or [`NtfsLogRecord::client_undo_next_lsn`].
+    pub fn record<T>(&self, fs: &mut T, lsn: u64) -> Result<NtfsLogRecord>
+    where
+        T: Read + Seek,
+    {
+        self.read_record(fs, lsn).map(|(record, _)| record)
+    }
+
+    /// Returns an iterator over all log records, starting at the oldest LSN still required by the first log client.
+    pub fn records(&self) -> NtfsLogRecords<'_> {
+        let lsn = self
+            .restart_page()
+            .clients()
+            .next()
+            .map(|client| client.oldest_lsn())
+            .filter(|lsn| *lsn != 0);
+
+        NtfsLogRecords::new(self, lsn)
+    }
+
+    /// Returns an iterator over the log records, starting at the given LSN.
+    pub fn records_from(&self, lsn: u64) -> NtfsLogRecords<'_> {
+        NtfsLogRecords::new(self, Some(lsn))
+    }
+
+    /// Returns the current [`NtfsLogRestartPage`], which is the valid restart page with the higher LSN.
+    pub fn restart_page(&self) -> &NtfsLogRestartPage {
+        &self.restart_pages[self.current_restart_page]
+    }
+
+    /// Returns all valid restart pages in on-disk order.
+    pub fn restart_pages(&self) -> &[NtfsLogRestartPage] {
+        &self.restart_pages
+    }
+
+    /// Returns the size of the $LogFile, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A restart page of the $LogFile, returned by [`NtfsLogFile::restart_page`].
+///
+/// It describes the layout of the log and contains the restart area with the registered log clients.
+#[derive(Clone, Debug)]
+pub struct NtfsLogRestartPage {
+    data: Vec<u8>,
+    position: NtfsPosition,
+}
+
+impl NtfsLogRestartPage {
+    /// Reads the restart page at the given offset, returning `None` if it has never been written.
+    fn read<T>(fs: &mut T, extent_map: &ExtentMap, offset: u64) -> Result<Option<Self>>
+    where
+        T: Read + Seek,
+    {
+        let position = extent_map
+            .lookup(offset)
+            .map_or(NtfsPosition::none(), |(position, _)| position);
+
+        // Read the first block to determine the page size.
+        let mut data = vec![0u8; MIN_LOG_PAGE_SIZE as usize];
+        match extent_map.read_exact(fs, offset, &mut data) {
+            Some(result) => result?,
+            None => return Ok(None),
+        }
+
+        let signature: [u8; 4] = data[..4].try_into().unwrap();
+        if &signature == UNWRITTEN_PAGE_SIGNATURE {
+            return Ok(None);
+        }
+
+        if &signature != RESTART_PAGE_SIGNATURE && &signature != CHKDSK_PAGE_SIGNATURE {
+            return Err(NtfsError::InvalidLogFileSignature {
+                position,
+                expected: RESTART_PAGE_SIGNATURE,
+                actual: signature,
+            });
+        }
+
+        let system_page_size =
+            LittleEndian::read_u32(&data[offset_of!(RestartPageHeader, system_page_size)..]);
+        let log_page_size =
+            LittleEndian::read_u32(&data[offset_of!(RestartPageHeader, log_page_size)..]);
+        let page_size_range = MIN_LOG_PAGE_SIZE..=MAX_LOG_PAGE_SIZE;
+
+        if !system_page_size.is_power_of_two()
+            || !page_size_range.contains(&system_page_size)
+            || !log_page_size.is_power_of_two()
+            || !page_size_range.contains(&log_page_size)
+        {
+            return Err(NtfsError::InvalidLogPageSize {
+                position,
+                system_page_size,
+                log_page_size,
+            });
+        }
+
+        data.resize(system_page_size as usize, 0);
+        let remaining_offset = offset + MIN_LOG_PAGE_SIZE as u64;
+        extent_map
+            .read_exact(
+                fs,
+                remaining_offset,
+                &mut data[MIN_LOG_PAGE_SIZE as usize..],
+            )
+            .ok_or(NtfsError::InvalidLogRestartAreaRange {
+                position,
+                range: 0..system_page_size as usize,
+                size: MIN_LOG_PAGE_SIZE as usize,
+            })??;
+
+        let mut record = Record::new(data, position);
+        record.fixup()?;
+
+        let page = Self {
+            data: record.into_data(),
+            position,
+        };
+        page.validate()?;
+
+        Ok(Some(page))
+    }
+
+    /// Returns the last LSN found by CHKDSK, which is only set if [`NtfsLogRestartPage::is_chkdsk`] returns `true`.
+    pub fn chkdsk_lsn(&self) -> u64 {
+        let start = offset_of!(RestartPageHeader, chkdsk_lsn);
+        LittleEndian::read_u64(&self.data[start..])
+    }
+
+    /// Returns an iterator over the log clients in use.
+    pub fn clients(&self) -> NtfsLogClients<'_> {
+        let start = self.restart_area_offset() + offset_of!(RestartArea, client_in_use_list);
+        let first_client = LittleEndian::read_u16(&self.data[start..]);
+
+        NtfsLogClients {
+            restart_page: self,
+            next_client: first_client,
+            remaining: self.log_clients(),
+        }
+    }
+
+    fn client_array_range(&self) -> Range<usize> {
+        let start = self.restart_area_offset() + offset_of!(RestartArea, client_array_offset);
+        let client_array_offset = LittleEndian::read_u16(&self.data[start..]) as usize;
+        let start = self.restart_area_offset() + client_array_offset;
+        let end = start + self.log_clients() as usize * LOG_CLIENT_RECORD_SIZE;
+
+        start..end
+    }
+
+    /// Returns the LSN of the last log record written when this restart page was written.
+    pub fn current_lsn(&self) -> u64 {
+        let start = self.restart_area_offset() + offset_of!(RestartArea, current_lsn);
+        LittleEndian::read_u64(&self.data[start..])
+    }
+
+    /// Returns the usable size of the $LogFile according to this restart page, in bytes.
+    pub fn file_size(&self) -> u64 {
+        let start = self.restart_area_offset() + offset_of!(RestartArea, file_size);
+        LittleEndian::read_u64(&self.data[start..])
+    }
+
+    /// Returns flags set for this restart page as specified by [`NtfsLogRestartFlags`].
+    pub fn flags(&self) -> NtfsLogRestartFlags {
+        let start = self.restart_area_offset() + offset_of!(RestartArea, flags);
+        NtfsLogRestartFlags::from_bits_truncate(LittleEndian::read_u16(&self.data[start..]))
+    }
+
+    /// Returns whether this page has been rewritten by CHKDSK (`CHKD` signature) instead of the Log File Service.
+    pub fn is_chkdsk(&self) -> bool {
+        &self.data[..4] == CHKDSK_PAGE_SIGNATURE
+    }
+
+    fn log_clients(&self) -> u16 {
+        let start = self.restart_area_offset() + offset_of!(RestartArea, log_clients);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    pub(crate) fn log_page_data_offset(&self) -> u16 {
+        let start = self.restart_area_offset() + offset_of!(RestartArea, log_page_data_offset);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    /// Returns the size of a log record page, in bytes.
+    pub fn log_page_size(&self) -> u32 {
+        let start = offset_of!(RestartPageHeader, log_page_size);
+        LittleEndian::read_u32(&self.data[start..])
+    }
+
+    /// Returns the major version of the Log File Service that has written this page.
+    pub fn major_version(&self) -> i16 {
+        let start = offset_of!(RestartPageHeader, major_version);
+        LittleEndian::read_i16(&self.data[start..])
+    }
+
+    /// Returns the minor version of the Log File Service that has written this page.
+    pub fn minor_version(&self) -> i16 {
+        let start = offset_of!(RestartPageHeader, minor_version);
+        LittleEndian::read_i16(&self.data[start..])
+    }
+
+    /// Returns the absolute position of this restart page within the filesystem, in bytes.
+    pub fn position(&self) -> NtfsPosition {
+        self.position
+    }
+
+    fn restart_area_offset(&self) -> usize {
+        let start = offset_of!(RestartPageHeader, restart_area_offset);
+        LittleEndian::read_u16(&self.data[start..]) as usize
+    }
+
+    /// Returns the number of upper bits of an LSN that hold the sequence number.
+    pub fn sequence_number_bits(&self) -> u32 {
+        let start = self.restart_area_offset() + offset_of!(RestartArea, sequence_number_bits);
+        LittleEndian::read_u32(&self.data[start..])
+    }
+
+    /// Returns the size of a restart page, in bytes.
+    pub fn system_page_size(&self) -> u32 {
+        let start = offset_of!(RestartPageHeader, system_page_size);
+        LittleEndian::read_u32(&self.data[start..])
+    }
+
+    fn validate(&self) -> Result<()> {
+        let major = self.major_version();
+        let minor = self.minor_version();
+        if !matches!(major, 1 | 2) {
+            return Err(NtfsError::UnsupportedLogFileVersion {
+                position: self.position,
+                major,
+                minor,
+            });
+        }
+
+        let size = self.data.len();
+        let restart_area_range =
+            self.restart_area_offset()..self.restart_area_offset() + RESTART_AREA_SIZE;
+        if restart_area_range.end > size {
+            return Err(NtfsError::InvalidLogRestartAreaRange {
+                position: self.position,
+                range: restart_area_range,
+                size,
+            });
+        }
+
+        let client_array_range = self.client_array_range();
+        if client_array_range.start < restart_area_range.end || client_array_range.end > size {
+            return Err(NtfsError::InvalidLogRestartAreaRange {
+                position: self.position,
+                range: client_array_range,
+                size,
+            });
+        }
+
+        let sequence_number_bits = self.sequence_number_bits();
+        if !(3..64).contains(&sequence_number_bits) {
+            return Err(NtfsError::InvalidLogSequenceNumberBits {
+                position: self.position,
+                sequence_number_bits,
+            });
+        }
+
+        // Every log record page must have room for its header and at least one log record header.
+        let data_offset = self.log_page_data_offset() as usize;
+        let data_range = data_offset..data_offset + LOG_RECORD_HEADER_SIZE;
+        let log_page_size = self.log_page_size() as usize;
+        if data_range.start < RECORD_PAGE_HEADER_SIZE || data_range.end > log_page_size {
+            return Err(NtfsError::InvalidLogRestartAreaRange {
+                position: self.position,
+                range: data_range,
+                size: log_page_size,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// A log client registered in an [`NtfsLogRestartPage`].
+///
+/// NTFS itself is usually the only client of the Log File Service.
+#[derive(Clone, Debug)]
+pub struct NtfsLogClient<'p> {
+    data: &'p [u8],
+}
+
+impl<'p> NtfsLogClient<'p> {
+    /// Returns the LSN of the last restart record (checkpoint) written by this client.
+    pub fn client_restart_lsn(&self) -> u64 {
+        let start = offset_of!(LogClientRecord, client_restart_lsn);
+        LittleEndian::read_u64(&self.data[start..])
+    }
+
+    /// Returns the name of this client.
+    pub fn name(&self) -> U16StrLe<'p> {
+        let start = offset_of!(LogClientRecord, client_name_length);
+        let length = LittleEndian::read_u32(&self.data[start..]) as usize;
+        let length = usize::min(length, LOG_CLIENT_NAME_MAX_SIZE) & !1;
+
+        let start = offset_of!(LogClientRecord, client_name);
+        U16StrLe(&self.data[start..start + length])
+    }
+
+    /// Returns the oldest LSN that is still required by this client.
+    pub fn oldest_lsn(&self) -> u64 {
+        let start = offset_of!(LogClientRecord, oldest_lsn);
+        LittleEndian::read_u64(&self.data[start..])
+    }
+
+    /// Returns the sequence number of this client, which is also stored in all of its log records.
+    pub fn sequence_number(&self) -> u16 {
+        let start = offset_of!(LogClientRecord, sequence_number);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+}
+
+/// Iterator over
+///   the log clients in use,
+///   returning an [`NtfsLogClient`] for each entry.
+///
+/// This iterator is returned from the [`NtfsLogRestartPage::clients`] function.
+#[derive(Clone, Debug)]
+pub struct NtfsLogClients<'p> {
+    restart_page: &'p NtfsLogRestartPage,
+    next_client: u16,
+    remaining: u16,
+}
+
+impl<'p> Iterator for NtfsLogClients<'p> {
+    type Item = NtfsLogClient<'p>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Limit the iteration to the size of the client array to not loop forever on a corrupted list.
+        if self.next_client == LOG_NO_CLIENT || self.remaining == 0 {
+            return None;
+        }
+
+        let client_array_range = self.restart_page.client_array_range();
+        let start = client_array_range.start + self.next_client as usize * LOG_CLIENT_RECORD_SIZE;
+        if start >= client_array_range.end {
+            self.remaining = 0;
+            return None;
+        }
+
+        let data = &self.restart_page.data[start..start + LOG_CLIENT_RECORD_SIZE];
+        self.next_client =
+            LittleEndian::read_u16(&data[offset_of!(LogClientRecord, next_client)..]);
+        self.remaining -= 1;
+
+        Some(NtfsLogClient { data })
+    }
+}
+
+impl<'p> FusedIterator for NtfsLogClients<'p> {}
+
+/// A single log record of the $LogFile, returned by [`NtfsLogFile::record`] and the [`NtfsLogRecords`] iterator.
+///
+/// The client data of a record spanning multiple log record pages has already been joined.
+#[derive(Clone, Debug)]
+pub struct NtfsLogRecord {
+    data: Vec<u8>,
+    position: NtfsPosition,
+}
+
+impl NtfsLogRecord {
+    fn new(data: Vec<u8>, position: NtfsPosition) -> Result<Self> {
+        let record = Self { data, position };
+        record.validate()?;
+        Ok(record)
+    }
+
+    /// Returns the client data following the log record header.
+    pub fn client_data(&self) -> &[u8] {
+        &self.data[LOG_RECORD_HEADER_SIZE..]
+    }
+
+    /// Returns the index of the log client that has written this record.
+    pub fn client_index(&self) -> u16 {
+        let start = offset_of!(LogRecordHeader, client_index);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    /// Returns the LSN of the previous record written by the same client, or zero if there is none.
+    pub fn client_previous_lsn(&self) -> u64 {
+        let start = offset_of!(LogRecordHeader, client_previous_lsn);
+        LittleEndian::read_u64(&self.data[start..])
+    }
+
+    /// Returns the sequence number of the log client that has written this record.
+    pub fn client_sequence_number(&self) -> u16 {
+        let start = offset_of!(LogRecordHeader, client_sequence_number);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    /// Returns the LSN of the next record to undo when rolling back the transaction of this record,
+    /// or zero if there is none.
+    pub fn client_undo_next_lsn(&self) -> u64 {
+        let start = offset_of!(LogRecordHeader, client_undo_next_lsn);
+        LittleEndian::read_u64(&self.data[start..])
+    }
+
+    /// Returns whether this record spans multiple log record pages.
+    pub fn is_multi_page(&self) -> bool {
+        let start = offset_of!(LogRecordHeader, flags);
+        LittleEndian::read_u16(&self.data[start..]) & LOG_RECORD_MULTI_PAGE != 0
+    }
+
+    /// Returns the Log Sequence Number (LSN) of this record.
+    pub fn lsn(&self) -> u64 {
+        let start = offset_of!(LogRecordHeader, this_lsn);
+        LittleEndian::read_u64(&self.data[start..])
+    }
+
+    /// Returns the redo/undo information of this record if it is of type [`NtfsLogRecordType::ClientRecord`].
+    pub fn operation_record(&self) -> Option<NtfsLogOperationRecord<'_>> {
+        (self.record_type() == NtfsLogRecordType::ClientRecord).then(|| NtfsLogOperationRecord {
+            data: self.client_data(),
+        })
+    }
+
+    /// Returns the absolute position of this record within the filesystem, in bytes.
+    pub fn position(&self) -> NtfsPosition {
+        self.position
+    }
+
+    fn raw_record_type(&self) -> u32 {
+        let start = offset_of!(LogRecordHeader, record_type);
+        LittleEndian::read_u32(&self.data[start..])
+    }
+
+    /// Returns the type of this record.
+    pub fn record_type(&self) -> NtfsLogRecordType {
+        // This unwrap is safe, because the record type has been validated in `NtfsLogRecord::new`.
+        NtfsLogRecordType::n(self.raw_record_type()).unwrap()
+    }
+
+    /// Returns the identifier of the transaction this record belongs to.
+    ///
+    /// This is an offset into the transaction table of NTFS.
+    pub fn transaction_id(&self) -> u32 {
+        let start = offset_of!(LogRecordHeader, transaction_id);
+        LittleEndian::read_u32(&self.data[start..])
+    }
+
+    fn validate(&self) -> Result<()> {
+        let record_type = self.raw_record_type();
+        let record_type =
+            NtfsLogRecordType::n(record_type).ok_or(NtfsError::UnsupportedLogRecordType {
+                position: self.position,
+                actual: record_type,
+            })?;
+
+        if record_type == NtfsLogRecordType::ClientRecord {
+            self.validate_operation_record()?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_operation_record(&self) -> Result<()> {
+        let client_data = self.client_data();
+        let size = client_data.len();
+
+        if size < OPERATION_HEADER_SIZE {
+            return Err(NtfsError::InvalidLogRecordRange {
+                position: self.position,
+                range: 0..OPERATION_HEADER_SIZE,
+                size,
+            });
+        }
+
+        let operation_record = NtfsLogOperationRecord { data: client_data };
+        let ranges = [
+            operation_record.redo_range(),
+            operation_record.undo_range(),
+            operation_record.target_lcns_range(),
+        ];
+
+        for range in ranges {
+            if !range.is_empty() && range.end > size {
+                return Err(NtfsError::InvalidLogRecordRange {
+                    position: self.position,
+                    range,
+                    size,
+                });
+            }
+        }
+
+        for operation in [
+            operation_record.raw_redo_operation(),
+            operation_record.raw_undo_operation(),
+        ] {
+            if NtfsLogOperation::n(operation).is_none() {
+                return Err(NtfsError::UnsupportedLogOperation {
+                    position: self.position,
+                    actual: operation,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Redo/undo information of an NTFS log record, returned by [`NtfsLogRecord::operation_record`].
+///
+/// The target of both operations is described by an index into the open attribute table of the last checkpoint,
+/// the VCN within that attribute, and the offsets within the addressed File Record or Index Record.
+#[derive(Clone, Debug)]
+pub struct NtfsLogOperationRecord<'r> {
+    data: &'r [u8],
+}
+
+impl<'r> NtfsLogOperationRecord<'r> {
+    /// Returns the offset of the modified attribute within the target File Record, in bytes.
+    pub fn attribute_offset(&self) -> u16 {
+        let start = offset_of!(OperationHeader, attribute_offset);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    /// Returns the offset of the target record within the target cluster(s), in units of 512 bytes.
+    pub fn cluster_block_offset(&self) -> u16 {
+        let start = offset_of!(OperationHeader, cluster_block_offset);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    fn range(&self, offset_field: usize, length_field: usize) -> Range<usize> {
+        let offset = LittleEndian::read_u16(&self.data[offset_field..]) as usize;
+        let length = LittleEndian::read_u16(&self.data[length_field..]) as usize;
+        offset..offset + length
+    }
+
+    fn raw_redo_operation(&self) -> u16 {
+        let start = offset_of!(OperationHeader, redo_operation);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    fn raw_undo_operation(&self) -> u16 {
+        let start = offset_of!(OperationHeader, undo_operation);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    /// Returns the offset of the modified data within the target record or attribute, in bytes.
+    pub fn record_offset(&self) -> u16 {
+        let start = offset_of!(OperationHeader, record_offset);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    /// Returns the data to apply when redoing this operation.
+    pub fn redo_data(&self) -> &'r [u8] {
+        let range = self.redo_range();
+        if range.is_empty() {
+            &[]
+        } else {
+            &self.data[range]
+        }
+    }
+
+    /// Returns the operation that needs to be redone to complete this change.
+    pub fn redo_operation(&self) -> NtfsLogOperation {
+        // This unwrap is safe, because the operation has been validated in `NtfsLogRecord::new`.
+        NtfsLogOperation::n(self.raw_redo_operation()).unwrap()
+    }
+
+    fn redo_range(&self) -> Range<usize> {
+        self.range(
+            offset_of!(OperationHeader, redo_offset),
+            offset_of!(OperationHeader, redo_length),
+        )
+    }
+
+    /// Returns the index of the target attribute in the open attribute table of the last checkpoint.
+    pub fn target_attribute(&self) -> u16 {
+        let start = offset_of!(OperationHeader, target_attribute);
+        LittleEndian::read_u16(&self.data[start..])
+    }
+
+    /// Returns an iterator over the Logical Cluster Numbers (LCNs) of the clusters modified by this operation.
+    pub fn target_lcns(&self) -> NtfsLogTargetLcns<'r> {
+        NtfsLogTargetLcns {
+            data: &self.data[self.target_lcns_range()],
+        }
+    }
+
+    fn target_lcns_range(&self) -> Range<usize> {
+        let start = offset_of!(OperationHeader, lcns_to_follow);
+        let lcns_to_follow = LittleEndian::read_u16(&self.data[start..]) as usize;
+        OPERATION_HEADER_SIZE..OPERATION_HEADER_SIZE + lcns_to_follow * mem::size_of::<u64>()
+    }
+
+    /// Returns the Virtual Cluster Number (VCN) of the first modified cluster within the target attribute.
+    pub fn target_vcn(&self) -> Vcn {
+        let start = offset_of!(OperationHeader, target_vcn);
+        Vcn::from(LittleEndian::read_i64(&self.data[start..]))
+    }
+
+    /// Returns the data to apply when undoing this operation.
+    pub fn undo_data(&self) -> &'r [u8] {
+        let range = self.undo_range();
+        if range.is_empty() {
+            &[]
+        } else {
+            &self.data[range]
+        }
+    }
+
+    /// Returns the operation that needs to be undone to roll back this change.
+    pub fn undo_operation(&self) -> NtfsLogOperation {
+        // This unwrap is safe, because the operation has been validated in `NtfsLogRecord::new`.
+        NtfsLogOperation::n(self.raw_undo_operation()).unwrap()
+    }
+
+    fn undo_range(&self) -> Range<usize> {
+        self.range(
+            offset_of!(OperationHeader, undo_offset),
+            offset_of!(OperationHeader, undo_length),
+        )
+    }
+}
+
+/// Iterator over
+///   the Logical Cluster Numbers (LCNs) modified by an NTFS log record,
+///   returning an [`Lcn`] for each entry.
+///
+/// This iterator is returned from the [`NtfsLogOperationRecord::target_lcns`] function.
+#[derive(Clone, Debug)]
+pub struct NtfsLogTargetLcns<'r> {
+    data: &'r [u8],
+}
+
+impl<'r> Iterator for NtfsLogTargetLcns<'r> {
+    type Item = Lcn;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < mem::size_of::<u64>() {
+            return None;
+        }
+
+        let (lcn, remainder) = self.data.split_at(mem::size_of::<u64>());
+        self.data = remainder;
+
+        Some(Lcn::from(LittleEndian::read_u64(lcn)))
+    }
+}
+
+impl<'r> FusedIterator for NtfsLogTargetLcns<'r> {}
+
+/// Iterator over
+///   the log records of the $LogFile in LSN order,
+///   returning an [`NtfsLogRecord`] for each entry.
+///
+/// This iterator is returned from the [`NtfsLogFile::records`] and [`NtfsLogFile::records_from`] functions.
+/// It ends at the first position that doesn't hold the expected log record, which marks the end of the log.
+/// The iteration also ends after the first record that cannot be read.
+///
+/// See [`NtfsLogRecordsAttached`] for an iterator that implements [`Iterator`] and [`FusedIterator`].
+#[derive(Clone, Debug)]
+pub struct NtfsLogRecords<'l> {
+    log_file: &'l NtfsLogFile,
+    next_lsn: Option<u64>,
+}
+
+impl<'l> NtfsLogRecords<'l> {
+    fn new(log_file: &'l NtfsLogFile, next_lsn: Option<u64>) -> Self {
+        Self { log_file, next_lsn }
+    }
+
+    /// Returns a variant of this iterator that implements [`Iterator`] and [`FusedIterator`]
+    /// by mutably borrowing the filesystem reader.
+    pub fn attach<'a, T>(self, fs: &'a mut T) -> NtfsLogRecordsAttached<'l, 'a, T>
+    where
+        T: Read + Seek,
+    {
+        NtfsLogRecordsAttached::new(fs, self)
+    }
+
+    /// Returns the LSN of the next log record to be read, or `None` if the iteration has ended.
+    pub fn lsn(&self) -> Option<u64> {
+        self.next_lsn
+    }
+
+    /// See [`Iterator::next`].
+    pub fn next<T>(&mut self, fs: &mut T) -> Option<Result<NtfsLogRecord>>
+    where
+        T: Read + Seek,
+    {
+        let lsn = self.next_lsn.take()?;
+
+        match self.log_file.read_record(fs, lsn) {
+            Ok((record, next_lsn)) => {
+                self.next_lsn = Some(next_lsn);
+                Some(Ok(record))
+            }
+            Err(NtfsError::InvalidLogRecordLsn { .. })
+            | Err(NtfsError::InvalidLogFileSignature { .. }) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Iterator over
+///   the log records of the $LogFile in LSN order,
+///   returning an [`NtfsLogRecord`] for each entry,
+///   implementing [`Iterator`] and [`FusedIterator`].
+///
+/// This iterator is returned from the [`NtfsLogRecords::attach`] function.
+/// Conceptually the same as [`NtfsLogRecords`], but mutably borrows the filesystem
+/// to implement aforementioned traits.
+#[derive(Debug)]
+pub struct NtfsLogRecordsAttached<'l, 'a, T: Read + Seek> {
+    fs: &'a mut T,
+    records: NtfsLogRecords<'l>,
+}
+
+impl<'l, 'a, T> NtfsLogRecordsAttached<'l, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn new(fs: &'a mut T, records: NtfsLogRecords<'l>) -> Self {
+        Self { fs, records }
+    }
+
+    /// Consumes this iterator and returns the inner [`NtfsLogRecords`].
+    pub fn detach(self) -> NtfsLogRecords<'l> {
+        self.records
+    }
+}
+
+impl<'l, 'a, T> Iterator for NtfsLogRecordsAttached<'l, 'a, T>
+where
+    T: Read + Seek,
+{
+    type Item = Result<NtfsLogRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next(self.fs)
+    }
+}
+
+impl<'l, 'a, T> FusedIterator for NtfsLogRecordsAttached<'l, 'a, T> where T: Read + Seek {}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Absolute position of the $LogFile in testfs1, which has been reset by NTFS-3G.
+    const LOG_FILE_POSITION: usize = 1052160;
+    const LOG_FILE_SIZE: u64 = 262144;
+    const PAGE_SIZE: usize = 4096;
+    const DATA_OFFSET: usize = 0x40;
+    const SEQUENCE_NUMBER_BITS: u32 = 40;
+    const FILE_OFFSET_BITS: u32 = 64 - SEQUENCE_NUMBER_BITS;
+
+    pub(crate) fn lsn(sequence_number: u64, offset: usize) -> u64 {
+        (sequence_number << FILE_OFFSET_BITS) | (offset as u64 >> 3)
+    }
+
+    /// Replaces the last 2 bytes of each sector by the Update Sequence Number, reversing `Record::fixup`.
+    fn protect(page: &mut [u8], update_sequence_offset: usize) {
+        LittleEndian::write_u16(&mut page[4..], update_sequence_offset as u16);
+        let update_sequence_count = (page.len() / 512 + 1) as u16;
+        LittleEndian::write_u16(&mut page[6..], update_sequence_count);
+        LittleEndian::write_u16(&mut page[update_sequence_offset..], 0x0007);
+
+        for sector in 0..page.len() / 512 {
+            let end = (sector + 1) * 512 - 2;
+            let array_position = update_sequence_offset + 2 + sector * 2;
+            let bytes = [page[end], page[end + 1]];
+            page[array_position..array_position + 2].copy_from_slice(&bytes);
+            LittleEndian::write_u16(&mut page[end..], 0x0007);
+        }
+    }
+
+    fn restart_page(
+        current_lsn: u64,
+        oldest_lsn: u64,
+        restart_lsn: u64,
+        flags: NtfsLogRestartFlags,
+    ) -> Vec<u8> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[..4].copy_from_slice(RESTART_PAGE_SIGNATURE);
+        LittleEndian::write_u32(&mut page[16..], PAGE_SIZE as u32);
+        LittleEndian::write_u32(&mut page[20..], PAGE_SIZE as u32);
+        LittleEndian::write_u16(&mut page[24..], 0x30);
+        LittleEndian::write_i16(&mut page[26..], 1);
+        LittleEndian::write_i16(&mut page[28..], 1);
+
+        let restart_area = &mut page[0x30..];
+        LittleEndian::write_u64(&mut restart_area[0..], current_lsn);
+        LittleEndian::write_u16(&mut restart_area[8..], 1);
+        LittleEndian::write_u16(&mut restart_area[10..], LOG_NO_CLIENT);
+        LittleEndian::write_u16(&mut restart_area[12..], 0);
+        LittleEndian::write_u16(&mut restart_area[14..], flags.bits());
+        LittleEndian::write_u32(&mut restart_area[16..], SEQUENCE_NUMBER_BITS);
+        LittleEndian::write_u16(&mut restart_area[22..], 0x30);
+        LittleEndian::write_u64(&mut restart_area[24..], LOG_FILE_SIZE);
+        LittleEndian::write_u16(&mut restart_area[36..], 0x30);
+        LittleEndian::write_u16(&mut restart_area[38..], DATA_OFFSET as u16);
+
+        let client = &mut restart_area[0x30..];
+        LittleEndian::write_u64(&mut client[0..], oldest_lsn);
+        LittleEndian::write_u64(&mut client[8..], restart_lsn);
+        LittleEndian::write_u16(&mut client[16..], LOG_NO_CLIENT);
+        LittleEndian::write_u16(&mut client[18..], LOG_NO_CLIENT);
+        LittleEndian::write_u16(&mut client[20..], 1);
+        LittleEndian::write_u32(&mut client[28..], 8);
+        for (i, c) in "NTFS".encode_utf16().enumerate() {
+            LittleEndian::write_u16(&mut client[32 + i * 2..], c);
+        }
+
+        protect(&mut page, 0x1e);
+        page
+    }
+
+    fn log_record(
+        lsn: u64,
+        previous_lsn: u64,
+        transaction_id: u32,
+        record_type: NtfsLogRecordType,
+        client_data: &[u8],
+    ) -> Vec<u8> {
+        let mut record = vec![0u8; LOG_RECORD_HEADER_SIZE];
+        LittleEndian::write_u64(&mut record[0..], lsn);
+        LittleEndian::write_u64(&mut record[8..], previous_lsn);
+        LittleEndian::write_u32(&mut record[24..], client_data.len() as u32);
+        LittleEndian::write_u16(&mut record[28..], 1);
+        LittleEndian::write_u32(&mut record[32..], record_type as u32);
+        LittleEndian::write_u32(&mut record[36..], transaction_id);
+        record.extend_from_slice(client_data);
+        record
+    }
+
+    pub(crate) fn operation(
+        redo_operation: NtfsLogOperation,
+        undo_operation: NtfsLogOperation,
+        lcns: &[u64],
+        redo_data: &[u8],
+        undo_data: &[u8],
+    ) -> Vec<u8> {
+        let redo_offset = OPERATION_HEADER_SIZE + lcns.len() * 8;
+        let undo_offset = redo_offset + redo_data.len();
+
+        let mut data = vec![0u8; OPERATION_HEADER_SIZE];
+        LittleEndian::write_u16(&mut data[0..], redo_operation as u16);
+        LittleEndian::write_u16(&mut data[2..], undo_operation as u16);
+        LittleEndian::write_u16(&mut data[4..], redo_offset as u16);
+        LittleEndian::write_u16(&mut data[6..], redo_data.len() as u16);
+        LittleEndian::write_u16(&mut data[8..], undo_offset as u16);
+        LittleEndian::write_u16(&mut data[10..], undo_data.len() as u16);
+        LittleEndian::write_u16(&mut data[12..], 0x28);
+        LittleEndian::write_u16(&mut data[14..], lcns.len() as u16);
+        LittleEndian::write_u16(&mut data[16..], 0x38);
+        LittleEndian::write_u16(&mut data[18..], 0x98);
+        LittleEndian::write_u16(&mut data[20..], 2);
+        LittleEndian::write_i64(&mut data[24..], 5);
+
+        for lcn in lcns {
+            data.extend_from_slice(&lcn.to_le_bytes());
+        }
+
+        data.extend_from_slice(redo_data);
+        data.extend_from_slice(undo_data);
+        data
+    }
+
+    fn record_page(records: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[..4].copy_from_slice(RECORD_PAGE_SIGNATURE);
+
+        for (offset, data) in records {
+            let length = usize::min(data.len(), PAGE_SIZE - offset);
+            page[*offset..*offset + length].copy_from_slice(&data[..length]);
+        }
+
+        protect(&mut page, 0x28);
+        page
+    }
+
+    /// Writes a $LogFile consisting of the given `(transaction_id, record_type, client_data)` records
+    /// into the testfs1 image and returns their LSNs.
+    ///
+    /// Records that don't fit into the remaining log record page continue on the next one.
+    /// The first [`NtfsLogRecordType::ClientRestart`] record becomes the checkpoint of the log client.
+    pub(crate) fn write_log_file(
+        image: &mut [u8],
+        records: &[(u32, NtfsLogRecordType, Vec<u8>)],
+        flags: NtfsLogRestartFlags,
+    ) -> Vec<u64> {
+        let first_page = 4 * PAGE_SIZE;
+        let mut pages = vec![Vec::new()];
+        let mut offset = DATA_OFFSET;
+        let mut previous_lsn = 0;
+        let mut lsns = Vec::new();
+
+        for (transaction_id, record_type, client_data) in records {
+            // A record header never crosses a page boundary.
+            if offset + LOG_RECORD_HEADER_SIZE > PAGE_SIZE {
+                pages.push(Vec::new());
+                offset = DATA_OFFSET;
+            }
+
+            let lsn = lsn(1, first_page + (pages.len() - 1) * PAGE_SIZE + offset);
+            let mut record = log_record(
+                lsn,
+                previous_lsn,
+                *transaction_id,
+                *record_type,
+                client_data,
+            );
+            if offset + record.len() > PAGE_SIZE {
+                LittleEndian::write_u16(&mut record[40..], LOG_RECORD_MULTI_PAGE);
+            }
+
+            let mut remaining = record.as_slice();
+            loop {
+                let length = usize::min(remaining.len(), PAGE_SIZE - offset);
+                // This unwrap is safe, because `pages` is never empty.
+                pages
+                    .last_mut()
+                    .unwrap()
+                    .push((offset, remaining[..length].to_vec()));
+                remaining = &remaining[length..];
+                offset += length;
+
+                if remaining.is_empty() {
+                    break;
+                }
+
+                pages.push(Vec::new());
+                offset = DATA_OFFSET;
+            }
+
+            offset = (offset + 7) & !7;
+            lsns.push(lsn);
+            previous_lsn = lsn;
+        }
+
+        let mut write = |offset: usize, data: &[u8]| {
+            let start = LOG_FILE_POSITION + offset;
+            image[start..start + data.len()].copy_from_slice(data);
+        };
+
+        let restart_lsn = records
+            .iter()
+            .zip(&lsns)
+            .find(|((_, record_type, _), _)| *record_type == NtfsLogRecordType::ClientRestart)
+            .map_or(0, |(_, lsn)| *lsn);
+        let page = restart_page(previous_lsn, lsns[0], restart_lsn, flags);
+        write(0, &page);
+        write(PAGE_SIZE, &page);
+
+        for (i, page_records) in pages.iter().enumerate() {
+            let page_records: Vec<_> = page_records
+                .iter()
+                .map(|(offset, data)| (*offset, data.as_slice()))
+                .collect();
+            write(first_page + i * PAGE_SIZE, &record_page(&page_records));
+        }
+
+        lsns
+    }
+
+    #[test]
+    fn test_log_file() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        assert!(ntfs.log_file(&mut testfs1).is_none());
+
+        // The log wraps around from its last page to the first log record page after the two buffer pages.
+        let last_page = LOG_FILE_SIZE as usize - PAGE_SIZE;
+        let first_page = 4 * PAGE_SIZE;
+
+        let lsn1 = lsn(1, last_page + DATA_OFFSET);
+        let lsn2 = lsn(1, last_page + DATA_OFFSET + 0x60);
+        let lsn3 = lsn(2, first_page + DATA_OFFSET + 0x70);
+
+        let record1 = log_record(
+            lsn1,
+            0,
+            0x18,
+            NtfsLogRecordType::ClientRecord,
+            &operation(
+                NtfsLogOperation::UpdateResidentValue,
+                NtfsLogOperation::UpdateResidentValue,
+                &[],
+                b"newvalue",
+                b"oldvalue",
+            ),
+        );
+        assert_eq!(record1.len(), 0x60);
+
+        // The second record spans both pages and is followed by 0x70 bytes on the first log record page.
+        let client_data: Vec<u8> = (0..4000u32).map(|i| i as u8).collect();
+        let mut record2 = log_record(
+            lsn2,
+            lsn1,
+            0x18,
+            NtfsLogRecordType::ClientRestart,
+            &client_data,
+        );
+        LittleEndian::write_u16(&mut record2[40..], LOG_RECORD_MULTI_PAGE);
+        let record2_split = PAGE_SIZE - DATA_OFFSET - 0x60;
+
+        let record3 = log_record(
+            lsn3,
+            lsn2,
+            0x18,
+            NtfsLogRecordType::ClientRecord,
+            &operation(
+                NtfsLogOperation::SetBitsInNonresidentBitMap,
+                NtfsLogOperation::ClearBitsInNonresidentBitMap,
+                &[100, 101],
+                &[0x10, 0, 0, 0, 2, 0, 0, 0],
+                &[0x10, 0, 0, 0, 2, 0, 0, 0],
+            ),
+        );
+
+        let image = testfs1.get_mut();
+        let mut write = |offset: usize, data: &[u8]| {
+            let start = LOG_FILE_POSITION + offset;
+            image[start..start + data.len()].copy_from_slice(data);
+        };
+        write(
+            0,
+            &restart_page(lsn3, lsn1, lsn2, NtfsLogRestartFlags::empty()),
+        );
+        write(
+            PAGE_SIZE,
+            &restart_page(lsn1, lsn1, 0, NtfsLogRestartFlags::empty()),
+        );
+        write(
+            last_page,
+            &record_page(&[(DATA_OFFSET, &record1), (DATA_OFFSET + 0x60, &record2)]),
+        );
+        write(
+            first_page,
+            &record_page(&[
+                (DATA_OFFSET, &record2[record2_split..]),
+                (DATA_OFFSET + 0x70, &record3),
+            ]),
+        );
+
+        let log_file = ntfs.log_file(&mut testfs1).unwrap().unwrap();
+        assert_eq!(log_file.size(), LOG_FILE_SIZE);
+        assert_eq!(log_file.restart_pages().len(), 2);
+
+        let restart_page = log_file.restart_page();
+        assert_eq!(
+            restart_page.position(),
+            NtfsPosition::new(LOG_FILE_POSITION as u64)
+        );
+        assert_eq!(restart_page.current_lsn(), lsn3);
+        assert!(!restart_page
+            .flags()
+            .contains(NtfsLogRestartFlags::VOLUME_IS_CLEAN));
+        assert!(!restart_page.is_chkdsk());
+        assert_eq!(restart_page.major_version(), 1);
+
+        let clients: Vec<_> = restart_page.clients().collect();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].name(), "NTFS");
+        assert_eq!(clients[0].oldest_lsn(), lsn1);
+        assert_eq!(clients[0].client_restart_lsn(), lsn2);
+
+        assert_eq!(
+            log_file.lsn_position(lsn1),
+            NtfsPosition::new((LOG_FILE_POSITION + last_page + DATA_OFFSET) as u64)
+        );
+
+        let records: Vec<_> = log_file
+            .records()
+            .attach(&mut testfs1)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(records.len(), 3);
+
+        let operation_record = records[0].operation_record().unwrap();
+        assert_eq!(records[0].lsn(), lsn1);
+        assert_eq!(
+            operation_record.redo_operation(),
+            NtfsLogOperation::UpdateResidentValue
+        );
+        assert_eq!(operation_record.redo_data(), b"newvalue");
+        assert_eq!(operation_record.undo_data(), b"oldvalue");
+        assert_eq!(operation_record.target_attribute(), 0x28);
+        assert_eq!(operation_record.target_vcn(), Vcn::from(5));
+        assert_eq!(operation_record.record_offset(), 0x38);
+        assert_eq!(operation_record.attribute_offset(), 0x98);
+        assert_eq!(operation_record.cluster_block_offset(), 2);
+        assert_eq!(operation_record.target_lcns().count(), 0);
+
+        assert_eq!(records[1].lsn(), lsn2);
+        assert_eq!(records[1].record_type(), NtfsLogRecordType::ClientRestart);
+        assert!(records[1].is_multi_page());
+        assert!(records[1].operation_record().is_none());
+        assert_eq!(records[1].client_data(), client_data);
+
+        let operation_record = records[2].operation_record().unwrap();
+        assert_eq!(records[2].lsn(), lsn3);
+        assert_eq!(records[2].transaction_id(), 0x18);
+        assert_eq!(
+            operation_record.undo_operation(),
+            NtfsLogOperation::ClearBitsInNonresidentBitMap
+        );
+        let lcns: Vec<_> = operation_record.target_lcns().collect();
+        assert_eq!(lcns, [Lcn::from(100), Lcn::from(101)]);
+
+        // Walk back the chain of records written by the NTFS client.
+        let mut previous_lsn = records[2].client_previous_lsn();
+        let mut chain = Vec::new();
+        while previous_lsn != 0 {
+            let record = log_file.record(&mut testfs1, previous_lsn).unwrap();
+            chain.push(record.lsn());
+            previous_lsn = record.client_previous_lsn();
+        }
+        assert_eq!(chain, [lsn2, lsn1]);
+
+        assert!(matches!(
+            log_file.record(&mut testfs1, lsn(1, first_page + DATA_OFFSET + 0x70)),
+            Err(NtfsError::InvalidLogRecordLsn { expected, actual, .. })
+                if expected == lsn(1, first_page + DATA_OFFSET + 0x70) && actual == lsn3
+        ));
+        assert!(matches!(
+            log_file.record(&mut testfs1, lsn(1, 0)),
+            Err(NtfsError::InvalidLsn { .. })
+        ));
+
+        // An unknown operation code stops the iteration.
+        let opcode_position = LOG_FILE_POSITION + last_page + DATA_OFFSET + LOG_RECORD_HEADER_SIZE;
+        testfs1.get_mut()[opcode_position] = 0x99;
+        let mut records = log_file.records();
+        assert!(matches!(
+            records.next(&mut testfs1),
+            Some(Err(NtfsError::UnsupportedLogOperation { actual: 0x99, .. }))
+        ));
+        assert!(records.next(&mut testfs1).is_none());
+    }
+
+    #[test]
+    fn test_log_file_fixup() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        let records = [
+            (0, NtfsLogRecordType::ClientRestart, vec![0u8; 8]),
+            (0x18, NtfsLogRecordType::ClientRestart, vec![0u8; 8]),
+        ];
+        let lsns = write_log_file(testfs1.get_mut(), &records, NtfsLogRestartFlags::empty());
+
+        // A restart page with a broken Update Sequence Array is ignored in favor of the other one.
+        let sector_end = LOG_FILE_POSITION + 512 - 2;
+        testfs1.get_mut()[sector_end] ^= 0xff;
+        let log_file = ntfs.log_file(&mut testfs1).unwrap().unwrap();
+        assert_eq!(log_file.restart_pages().len(), 1);
+        assert_eq!(
+            log_file.restart_page().position(),
+            NtfsPosition::new((LOG_FILE_POSITION + PAGE_SIZE) as u64)
+        );
+
+        // If both restart pages are broken, the error is returned.
+        let mut broken = testfs1.clone();
+        broken.get_mut()[sector_end + PAGE_SIZE] ^= 0xff;
+        assert!(matches!(
+            ntfs.log_file(&mut broken),
+            Some(Err(NtfsError::UpdateSequenceNumberMismatch { .. }))
+        ));
+
+        // A log record page with a broken Update Sequence Array fails to read instead of ending the log.
+        let sector_end = LOG_FILE_POSITION + 4 * PAGE_SIZE + 512 - 2;
+        testfs1.get_mut()[sector_end] ^= 0xff;
+        let mut records = log_file.records_from(lsns[0]);
+        assert!(matches!(
+            records.next(&mut testfs1),
+            Some(Err(NtfsError::UpdateSequenceNumberMismatch { .. }))
+        ));
+        assert!(records.next(&mut testfs1).is_none());
+        assert!(matches!(
+            log_file.record(&mut testfs1, lsns[1]),
+            Err(NtfsError::UpdateSequenceNumberMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,417 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::iter::FusedIterator;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek};
+
+use crate::attribute::NtfsAttributeType;
+use crate::error::{NtfsError, Result};
+use crate::file::{KnownNtfsFileRecordNumber, NtfsFile};
+use crate::ntfs::Ntfs;
+use crate::traits::NtfsReadSeek;
+
+/// Number of bytes read at once by [`NtfsMftRecords`].
+const MFT_READ_CHUNK_SIZE: u64 = 256 * 1024;
+
+/// Iterator over
+///   all File Records of the Master File Table (MFT),
+///   returning an [`NtfsMftRecord`] for each entry.
+///
+/// This iterator is returned from the [`Ntfs::mft_records`] function.
+/// File Records are returned in the order of their File Record Numbers, which is also the order on the filesystem.
+/// The MFT is read sequentially in large chunks, making this the fastest way to enumerate all files of a filesystem.
+///
+/// By default, the iterator
+///   * skips all File Records that are not marked as in use in the $BITMAP attribute of the MFT,
+///   * and stops after returning an error for the first File Record that cannot be read.
+///
+/// Use the builder-style methods to change this behavior before starting the iteration.
+///
+/// See [`NtfsMftRecordsAttached`] for an iterator that implements [`Iterator`] and [`FusedIterator`].
+#[derive(Clone, Debug)]
+pub struct NtfsMftRecords<'n> {
+    ntfs: &'n Ntfs,
+    bitmap: Vec<u8>,
+    file_record_count: u64,
+    next_file_record_number: u64,
+    chunk: Vec<u8>,
+    chunk_file_record_number: u64,
+    lenient: bool,
+    skip_unused: bool,
+}
+
+impl<'n> NtfsMftRecords<'n> {
+    pub(crate) fn new<T>(ntfs: &'n Ntfs, fs: &mut T) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let mft = ntfs.file(fs, KnownNtfsFileRecordNumber::MFT as u64)?;
+
+        // The $DATA attribute tells us how many File Records the MFT has.
+        let data_item = mft.find_attribute(fs, NtfsAttributeType::Data, Some(""))?;
+        let data_size = data_item.to_attribute()?.value_length();
+        let file_record_count = data_size / ntfs.file_record_size() as u64;
+
+        // The $BITMAP attribute has a bit for each of them, which is set if the File Record is in use.
+        // NTFS grows it in steps of 8 bytes, so it may have a few more bytes than needed, but never more than that.
+        let bitmap_item = mft.find_attribute(fs, NtfsAttributeType::Bitmap, Some(""))?;
+        let bitmap_attribute = bitmap_item.to_attribute()?;
+        let bitmap_size = bitmap_attribute.value_length();
+        let max_bitmap_size = (file_record_count + 63) / 64 * 8;
+        if bitmap_size > max_bitmap_size {
+            return Err(NtfsError::InvalidMftBitmapSize {
+                position: bitmap_attribute.position(),
+                file_record_count,
+                expected: max_bitmap_size,
+                actual: bitmap_size,
+            });
+        }
+
+        let mut bitmap_value = bitmap_attribute.value(fs)?;
+        let mut bitmap = vec![0u8; bitmap_size as usize];
+        bitmap_value.read_exact(fs, &mut bitmap)?;
+
+        Ok(Self {
+            ntfs,
+            bitmap,
+            file_record_count,
+            next_file_record_number: 0,
+            chunk: Vec::new(),
+            chunk_file_record_number: 0,
+            lenient: false,
+            skip_unused: true,
+        })
+    }
+
+    /// Returns a variant of this iterator that implements [`Iterator`] and [`FusedIterator`]
+    /// by mutably borrowing the filesystem reader.
+    pub fn attach<'a, T>(self, fs: &'a mut T) -> NtfsMftRecordsAttached<'n, 'a, T>
+    where
+        T: Read + Seek,
+    {
+        NtfsMftRecordsAttached::new(fs, self)
+    }
+
+    /// Returns the total number of File Records in the MFT, including unused ones.
+    pub fn file_record_count(&self) -> u64 {
+        self.file_record_count
+    }
+
+    fn is_in_use(&self, file_record_number: u64) -> bool {
+        let byte = (file_record_number / 8) as usize;
+        let bit = file_record_number % 8;
+
+        self.bitmap
+            .get(byte)
+            .map_or(false, |byte| byte & (1 << bit) != 0)
+    }
+
+    /// Sets whether to continue after File Records that cannot be read (default: `false`).
+    ///
+    /// In lenient mode, such File Records are returned as an [`NtfsMftRecord`] carrying the error
+    /// (see [`NtfsMftRecord::error`]), and the iteration continues with the next File Record.
+    /// Otherwise, the error is returned as such and ends the iteration.
+    ///
+    /// I/O errors of the filesystem reader always end the iteration.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// See [`Iterator::next`].
+    pub fn next<T>(&mut self, fs: &mut T) -> Option<Result<NtfsMftRecord<'n>>>
+    where
+        T: Read + Seek,
+    {
+        if self.skip_unused {
+            while self.next_file_record_number < self.file_record_count
+                && !self.is_in_use(self.next_file_record_number)
+            {
+                self.next_file_record_number += 1;
+            }
+        }
+
+        if self.next_file_record_number >= self.file_record_count {
+            return None;
+        }
+
+        let file_record_number = self.next_file_record_number;
+        self.next_file_record_number += 1;
+
+        let file_record_size = self.ntfs.file_record_size() as usize;
+        let chunk_file_record_count = (self.chunk.len() / file_record_size) as u64;
+        if file_record_number < self.chunk_file_record_number
+            || file_record_number >= self.chunk_file_record_number + chunk_file_record_count
+        {
+            if let Err(e) = self.read_chunk(fs, file_record_number) {
+                self.next_file_record_number = self.file_record_count;
+                return Some(Err(e));
+            }
+        }
+
+        let start =
+            (file_record_number - self.chunk_file_record_number) as usize * file_record_size;
+        let data = self.chunk[start..start + file_record_size].to_vec();
+
+        // This unwrap is safe, because the chunk containing this File Record has been read successfully.
+        let offset = file_record_number * file_record_size as u64;
+        let position = self
+            .ntfs
+            .mft_extents()
+            .lookup(offset)
+            .unwrap()
+            .0
+            .value()
+            .unwrap();
+
+        let file = match NtfsFile::from_vec(self.ntfs, data, position.get(), file_record_number) {
+            Err(e) if !self.lenient => {
+                self.next_file_record_number = self.file_record_count;
+                return Some(Err(e));
+            }
+            file => file,
+        };
+
+        Some(Ok(NtfsMftRecord {
+            file_record_number,
+            in_use: self.is_in_use(file_record_number),
+            file,
+        }))
+    }
+
+    fn read_chunk<T>(&mut self, fs: &mut T, file_record_number: u64) -> Result<()>
+    where
+        T: Read + Seek,
+    {
+        let file_record_size = self.ntfs.file_record_size() as u64;
+        let chunk_file_record_count = u64::max(1, MFT_READ_CHUNK_SIZE / file_record_size)
+            .min(self.file_record_count - file_record_number);
+
+        self.chunk
+            .resize((chunk_file_record_count * file_record_size) as usize, 0);
+        self.chunk_file_record_number = file_record_number;
+
+        let result = self.ntfs.mft_extents().read_exact(
+            fs,
+            file_record_number * file_record_size,
+            &mut self.chunk,
+        );
+
+        match result {
+            Some(Ok(())) => Ok(()),
+            Some(Err(e)) => {
+                self.chunk.clear();
+                Err(e)
+            }
+            None => {
+                self.chunk.clear();
+                Err(NtfsError::InvalidFileRecordNumber { file_record_number })
+            }
+        }
+    }
+
+    /// Sets whether to skip File Records that are not in use according to the $BITMAP attribute of the MFT (default: `true`).
+    ///
+    /// Unused File Records may still contain the remains of deleted files, but may just as well be uninitialized.
+    /// Consider enabling [`lenient`](Self::lenient) mode when including them.
+    pub fn skip_unused(mut self, skip: bool) -> Self {
+        self.skip_unused = skip;
+        self
+    }
+}
+
+/// Iterator over
+///   all File Records of the Master File Table (MFT),
+///   returning an [`NtfsMftRecord`] for each entry,
+///   implementing [`Iterator`] and [`FusedIterator`].
+///
+/// This iterator is returned from the [`NtfsMftRecords::attach`] function.
+/// Conceptually the same as [`NtfsMftRecords`], but mutably borrows the filesystem
+/// to implement aforementioned traits.
+#[derive(Debug)]
+pub struct NtfsMftRecordsAttached<'n, 'a, T: Read + Seek> {
+    fs: &'a mut T,
+    mft_records: NtfsMftRecords<'n>,
+}
+
+impl<'n, 'a, T> NtfsMftRecordsAttached<'n, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn new(fs: &'a mut T, mft_records: NtfsMftRecords<'n>) -> Self {
+        Self { fs, mft_records }
+    }
+
+    /// Consumes this iterator and returns the inner [`NtfsMftRecords`].
+    pub fn detach(self) -> NtfsMftRecords<'n> {
+        self.mft_records
+    }
+}
+
+impl<'n, 'a, T> Iterator for NtfsMftRecordsAttached<'n, 'a, T>
+where
+    T: Read + Seek,
+{
+    type Item = Result<NtfsMftRecord<'n>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.mft_records.next(self.fs)
+    }
+}
+
+impl<'n, 'a, T> FusedIterator for NtfsMftRecordsAttached<'n, 'a, T> where T: Read + Seek {}
+
+/// Item returned by the [`NtfsMftRecords`] iterator.
+#[derive(Debug)]
+pub struct NtfsMftRecord<'n> {
+    file_record_number: u64,
+    in_use: bool,
+    file: Result<NtfsFile<'n>>,
+}
+
+impl<'n> NtfsMftRecord<'n> {
+    /// Returns the error that occurred while reading this File Record, if any.
+    ///
+    /// This can only happen in [`lenient`](NtfsMftRecords::lenient) mode.
+    pub fn error(&self) -> Option<&NtfsError> {
+        self.file.as_ref().err()
+    }
+
+    /// Returns the [`NtfsFile`] of this File Record, unless it could not be read.
+    pub fn file(&self) -> Option<&NtfsFile<'n>> {
+        self.file.as_ref().ok()
+    }
+
+    /// Returns the NTFS File Record Number of this File Record.
+    pub fn file_record_number(&self) -> u64 {
+        self.file_record_number
+    }
+
+    /// Consumes this item and returns the [`NtfsFile`] or the error that occurred while reading it.
+    pub fn into_file(self) -> Result<NtfsFile<'n>> {
+        self.file
+    }
+
+    /// Returns whether this File Record is marked as in use in the $BITMAP attribute of the MFT.
+    pub fn is_in_use(&self) -> bool {
+        self.in_use
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binrw::io::Cursor;
+    use byteorder::{ByteOrder, LittleEndian};
+
+    #[test]
+    fn test_mft_records() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // By default, only File Records in use are returned:
+        // 16 reserved ones, 3 in $Extend, and the 5 + 512 files and directories created by `create-testfs1.sh`.
+        let mft_records = ntfs.mft_records(&mut testfs1).unwrap();
+        assert_eq!(mft_records.file_record_count(), 581);
+
+        let records = mft_records
+            .attach(&mut testfs1)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records.len(), 16 + 3 + 5 + 512);
+        assert!(records.iter().all(|record| record.is_in_use()));
+        assert_eq!(records[16].file_record_number(), 24);
+        assert_eq!(records[19].file_record_number(), 64);
+
+        // This File Record crosses the boundary between the two data runs of the MFT.
+        let record = records
+            .iter()
+            .find(|record| record.file_record_number() == 255)
+            .unwrap();
+        assert!(record.file().unwrap().is_directory());
+
+        // Corrupt the Update Sequence Array of "1000-bytes-file".
+        let position = ntfs
+            .file(&mut testfs1, 66)
+            .unwrap()
+            .position()
+            .value()
+            .unwrap();
+        testfs1.get_mut()[position.get() as usize + 510] ^= 0xff;
+
+        // Without lenient mode, the iteration stops at that File Record.
+        let records = ntfs
+            .mft_records(&mut testfs1)
+            .unwrap()
+            .attach(&mut testfs1)
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            records.last().unwrap(),
+            Err(NtfsError::UpdateSequenceNumberMismatch { .. })
+        ));
+
+        // In lenient mode, the broken File Record is reported along with its error.
+        // This also returns the File Records that are not in use.
+        let records = ntfs
+            .mft_records(&mut testfs1)
+            .unwrap()
+            .lenient(true)
+            .skip_unused(false)
+            .attach(&mut testfs1)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records.len(), 581);
+        assert!(!records[16].is_in_use());
+
+        let broken_records = records
+            .iter()
+            .filter(|record| record.error().is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(broken_records.len(), 1);
+        assert_eq!(broken_records[0].file_record_number(), 66);
+        assert!(broken_records[0].file().is_none());
+    }
+
+    #[test]
+    fn test_mft_records_invalid_bitmap_size() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // The $BITMAP attribute of the MFT is non-resident, and its value length is at offset 0x30.
+        let mft = ntfs
+            .file(&mut testfs1, KnownNtfsFileRecordNumber::MFT as u64)
+            .unwrap();
+        let bitmap_attribute = mft
+            .attributes_raw()
+            .map(|attribute| attribute.unwrap())
+            .find(|attribute| attribute.ty().unwrap() == NtfsAttributeType::Bitmap)
+            .unwrap();
+        assert!(!bitmap_attribute.is_resident());
+        let length_position = bitmap_attribute.position().value().unwrap().get() as usize + 0x30;
+
+        // 581 File Records need 73 bytes, which NTFS rounds up to 80 bytes.
+        assert_eq!(bitmap_attribute.value_length(), 80);
+
+        for (length, valid) in [(80u64, true), (81, false), (u64::MAX, false)] {
+            let mut image = testfs1.get_ref().clone();
+            LittleEndian::write_u64(&mut image[length_position..], length);
+
+            let result = ntfs.mft_records(&mut Cursor::new(image));
+            if valid {
+                assert!(result.is_ok());
+            } else {
+                assert!(matches!(
+                    result,
+                    Err(NtfsError::InvalidMftBitmapSize {
+                        file_record_count: 581,
+                        expected: 80,
+                        ..
+                    })
+                ));
+            }
+        }
+    }
+}
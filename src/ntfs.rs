@@ -1,17 +1,39 @@
 // Copyright 2021-2023 Colin Finck <colin@reactos.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use binrw::io::{Read, Seek, SeekFrom};
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek, SeekFrom, Write};
 use binrw::BinReaderExt;
+use byteorder::{ByteOrder, LittleEndian};
 
 use crate::attribute::NtfsAttributeType;
 use crate::boot_sector::BootSector;
+use crate::check::{self, NtfsCheckFinding};
+use crate::cluster_bitmap::NtfsClusterBitmap;
+use crate::cluster_owners::NtfsClusterOwners;
 use crate::error::{NtfsError, Result};
+use crate::extent_map::ExtentMap;
 use crate::file::{KnownNtfsFileRecordNumber, NtfsFile};
-use crate::structured_values::{NtfsVolumeInformation, NtfsVolumeName};
+use crate::indexes::{
+    NtfsFileNameIndex, NtfsSecurityDescriptorHeader, NtfsSecurityIdIndex,
+    SECURITY_DESCRIPTOR_HEADER_SIZE,
+};
+use crate::logfile::NtfsLogFile;
+use crate::mft::NtfsMftRecords;
+use crate::record::Record;
+#[cfg(feature = "std")]
+use crate::record_cache::{NtfsRecordCacheStats, RecordCache};
+use crate::recovery::NtfsDeletedFiles;
+use crate::structured_values::{
+    NtfsSecurityDescriptor, NtfsVolumeFlags, NtfsVolumeInformation, NtfsVolumeName,
+    VOLUME_INFORMATION_FLAGS_OFFSET,
+};
 use crate::traits::NtfsReadSeek;
 use crate::types::NtfsPosition;
 use crate::upcase_table::UpcaseTable;
+use crate::usn_journal::NtfsUsnJournal;
 
 /// Root structure describing an NTFS filesystem.
 #[derive(Debug)]
@@ -24,12 +46,23 @@ pub struct Ntfs {
     size: u64,
     /// Absolute position of the Master File Table (MFT), in bytes.
     mft_position: NtfsPosition,
+    /// Absolute position of the MFT mirror ($MFTMirr), in bytes.
+    mft_mirror_position: NtfsPosition,
     /// Size of a single File Record, in bytes.
     file_record_size: u32,
+    /// Decoded data runs of the MFT to locate File Records without reading the MFT's own File Record.
+    mft_extents: ExtentMap,
     /// Serial number of the NTFS volume.
     serial_number: u64,
     /// Table of Unicode uppercase characters (only required for case-insensitive comparisons).
     upcase_table: Option<UpcaseTable>,
+    /// Whether the backup boot sector has been used, because the primary one is damaged.
+    backup_boot_sector_used: bool,
+    /// Whether damaged File Records are read from the MFT mirror instead, if they are mirrored.
+    mft_mirror_fallback: bool,
+    /// Cache of recently read File Records and Index Records.
+    #[cfg(feature = "std")]
+    record_cache: RecordCache,
 }
 
 impl Ntfs {
@@ -37,14 +70,75 @@ impl Ntfs {
     ///
     /// The reader must cover the entire NTFS partition, not more and not less.
     /// It will be rewinded to the beginning before reading anything.
-    #[allow(clippy::seek_to_start_instead_of_rewind)]
+    ///
+    /// Check [`Ntfs::new_with_recovery`] to open a filesystem with a damaged boot sector or MFT.
     pub fn new<T>(fs: &mut T) -> Result<Self>
     where
         T: Read + Seek,
     {
-        // Read and validate the boot sector.
-        fs.seek(SeekFrom::Start(0))?;
-        let boot_sector = fs.read_le::<BootSector>()?;
+        let boot_sector = Self::read_boot_sector(fs, 0)?;
+        Self::from_boot_sector(fs, &boot_sector, false)
+    }
+
+    /// Creates a new [`Ntfs`] object like [`Ntfs::new`], but falls back to the redundant copies NTFS keeps of its
+    /// most important structures if the primary ones are damaged.
+    ///
+    /// `volume_size` is the size of the entire NTFS partition in bytes, usually the length of the reader.
+    /// It is needed to locate the backup boot sector in the last sector of the partition, which is used if the boot
+    /// sector at the beginning cannot be read or contains invalid information.
+    /// Check [`Ntfs::is_backup_boot_sector_used`] to find out which one has been used.
+    ///
+    /// Additionally, whenever one of the first File Records of the Master File Table (MFT) cannot be read
+    /// (e.g. because its update sequence numbers don't match), [`Ntfs::file`] reads the copy of that File Record
+    /// from $MFTMirr instead.
+    /// This covers the File Records of $MFT, $MFTMirr, $LogFile, and $Volume.
+    ///
+    /// If neither the primary nor the backup structures can be used, the error of the primary ones is returned.
+    pub fn new_with_recovery<T>(fs: &mut T, volume_size: u64) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let error = match Self::read_boot_sector(fs, 0)
+            .and_then(|boot_sector| Self::from_boot_sector(fs, &boot_sector, true))
+        {
+            Ok(ntfs) => return Ok(ntfs),
+            Err(error) => error,
+        };
+
+        // The sector size is unknown without a valid boot sector, so try all sector sizes supported by NTFS.
+        // A backup boot sector is only accepted if it has been found at the position implied by its own sector size.
+        for sector_size in [512u16, 1024, 2048, 4096] {
+            let position = match volume_size.checked_sub(sector_size as u64) {
+                Some(position) if position > 0 => position,
+                _ => continue,
+            };
+
+            let boot_sector = match Self::read_boot_sector(fs, position) {
+                Ok(boot_sector) => boot_sector,
+                Err(_) => continue,
+            };
+
+            if !matches!(boot_sector.bpb().sector_size(), Ok(size) if size == sector_size) {
+                continue;
+            }
+
+            if let Ok(mut ntfs) = Self::from_boot_sector(fs, &boot_sector, true) {
+                ntfs.backup_boot_sector_used = true;
+                return Ok(ntfs);
+            }
+        }
+
+        Err(error)
+    }
+
+    fn from_boot_sector<T>(
+        fs: &mut T,
+        boot_sector: &BootSector,
+        mft_mirror_fallback: bool,
+    ) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
         boot_sector.validate()?;
 
         let bpb = boot_sector.bpb();
@@ -55,34 +149,295 @@ impl Ntfs {
             .checked_mul(sector_size as u64)
             .ok_or(NtfsError::TotalSectorsTooBig { total_sectors })?;
         let mft_position = NtfsPosition::none();
+        let mft_mirror_position = NtfsPosition::none();
         let file_record_size = bpb.file_record_size()?;
         let serial_number = bpb.serial_number();
+        let mft_extents = ExtentMap::default();
         let upcase_table = None;
+        let backup_boot_sector_used = false;
 
         let mut ntfs = Self {
             cluster_size,
             sector_size,
             size,
             mft_position,
+            mft_mirror_position,
             file_record_size,
+            mft_extents,
             serial_number,
             upcase_table,
+            backup_boot_sector_used,
+            mft_mirror_fallback,
+            #[cfg(feature = "std")]
+            record_cache: RecordCache::default(),
         };
         ntfs.mft_position = bpb.mft_lcn()?.position(&ntfs)?;
 
+        // The MFT mirror is not needed for regular operation, so an invalid LCN is only reflected in its position.
+        ntfs.mft_mirror_position = bpb
+            .mft_mirror_lcn()
+            .position(&ntfs)
+            .unwrap_or_else(|_| NtfsPosition::none());
+
+        // The first File Record of the MFT describes where to find all others.
+        // If the MFT is too fragmented to fit all its data runs into that record, the record additionally has
+        // an Attribute List, which references further File Records.
+        // These are always covered by the data runs of the first File Record.
+        //
+        // This unwrap is safe, because `mft_position` has just been checked.
+        let mft = match NtfsFile::new(
+            &ntfs,
+            fs,
+            ntfs.mft_position.value().unwrap(),
+            KnownNtfsFileRecordNumber::MFT as u64,
+        ) {
+            Err(error) if ntfs.mft_mirror_fallback => ntfs
+                .mft_mirror_file(fs, KnownNtfsFileRecordNumber::MFT as u64)
+                .map_err(|_| error)?,
+            result => result?,
+        };
+        let mft_data_attribute =
+            mft.find_resident_attribute(NtfsAttributeType::Data, None, None)?;
+        let mft_extents = ExtentMap::from_attribute(fs, &mft_data_attribute)?;
+        let has_attribute_list = mft
+            .find_resident_attribute(NtfsAttributeType::AttributeList, None, None)
+            .is_ok();
+        ntfs.mft_extents = mft_extents;
+
+        if has_attribute_list {
+            let mft = ntfs.file(fs, KnownNtfsFileRecordNumber::MFT as u64)?;
+            let mft_data_item = mft.find_attribute(fs, NtfsAttributeType::Data, Some(""))?;
+            let mft_extents = ExtentMap::from_attribute(fs, &mft_data_item.to_attribute()?)?;
+            ntfs.mft_extents = mft_extents;
+        }
+
         Ok(ntfs)
     }
 
+    fn read_boot_sector<T>(fs: &mut T, position: u64) -> Result<BootSector>
+    where
+        T: Read + Seek,
+    {
+        fs.seek(SeekFrom::Start(position))?;
+        let boot_sector = fs.read_le::<BootSector>()?;
+        Ok(boot_sector)
+    }
+
+    /// Checks the consistency of the filesystem without modifying it and returns all inconsistencies found.
+    ///
+    /// This compares
+    ///   * the boot sector with the backup boot sector,
+    ///   * the first File Records of the MFT with their copies in $MFTMirr,
+    ///   * the hard link count of each file with its $FILE_NAME attributes,
+    ///   * the parent directory references of all $FILE_NAME attributes with the directories in use,
+    ///   * the order of all directory index entries with the order given by the $UpCase table,
+    ///   * and the clusters marked as allocated in $Bitmap with the clusters claimed by all Data Runs.
+    ///
+    /// Errors that prevent a check from being completed are returned as [`NtfsCheckFinding::Incomplete`],
+    /// so that the remaining checks still run.
+    /// The entire Master File Table (MFT) is scanned, so this takes a while on large filesystems.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`read_upcase_table`][Ntfs::read_upcase_table] had not been called.
+    pub fn check<T>(&self, fs: &mut T) -> Vec<NtfsCheckFinding>
+    where
+        T: Read + Seek,
+    {
+        check::check(self, fs)
+    }
+
+    /// Removes all records from the record cache, keeping its memory limit and statistics.
+    ///
+    /// Call this after the filesystem has been modified, or before passing a reader with different contents
+    /// (like an [`NtfsOverlay`]) to this [`Ntfs`] object.
+    ///
+    /// [`NtfsOverlay`]: crate::NtfsOverlay
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn clear_record_cache(&self) {
+        self.record_cache.clear();
+    }
+
+    /// Reads the $Bitmap file and returns the allocation state of all clusters of the filesystem.
+    ///
+    /// Check [`NtfsClusterBitmap`] for querying single clusters, ranges of allocated or free clusters,
+    /// and the total free space.
+    pub fn cluster_bitmap<T>(&self, fs: &mut T) -> Result<NtfsClusterBitmap>
+    where
+        T: Read + Seek,
+    {
+        NtfsClusterBitmap::new(self, fs)
+    }
+
+    /// Builds an index from clusters to the files and attributes that own them.
+    ///
+    /// This scans the entire Master File Table (MFT) via [`Ntfs::mft_records`] and decodes the Data Runs
+    /// of every non-resident attribute.
+    /// Check [`NtfsClusterOwners`] for looking up single clusters and filesystem positions.
+    pub fn cluster_owners<T>(&self, fs: &mut T) -> Result<NtfsClusterOwners>
+    where
+        T: Read + Seek,
+    {
+        NtfsClusterOwners::new(self, fs)
+    }
+
     /// Returns the size of a single cluster, in bytes.
     pub fn cluster_size(&self) -> u32 {
         self.cluster_size
     }
 
+    /// Returns an iterator over all deleted files of the filesystem whose File Records have not been reused yet.
+    ///
+    /// This scans the entire Master File Table (MFT) via [`Ntfs::mft_records`].
+    /// Check [`NtfsDeletedFile`](crate::NtfsDeletedFile) for recovering the last known name and data of each file.
+    pub fn deleted_files<'n, T>(&'n self, fs: &mut T) -> Result<NtfsDeletedFiles<'n>>
+    where
+        T: Read + Seek,
+    {
+        NtfsDeletedFiles::new(self, fs)
+    }
+
     /// Returns the [`NtfsFile`] for the given NTFS File Record Number.
     ///
     /// The first few NTFS files have fixed indexes and contain filesystem
     /// management information (see the [`KnownNtfsFileRecordNumber`] enum).
     pub fn file<'n, T>(&'n self, fs: &mut T, file_record_number: u64) -> Result<NtfsFile<'n>>
+    where
+        T: Read + Seek,
+    {
+        match self.mft_file(fs, file_record_number) {
+            Err(error)
+                if self.mft_mirror_fallback
+                    && file_record_number < self.mft_mirror_record_count() =>
+            {
+                self.mft_mirror_file(fs, file_record_number)
+                    .map_err(|_| error)
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the [`NtfsFile`] at the given path along with the name of the requested $DATA stream.
+    ///
+    /// This works like [`Ntfs::file_by_path`], but additionally accepts the `file:stream` syntax to select
+    /// an Alternate Data Stream.
+    /// The returned stream name is empty if the path does not specify one, which makes it suitable for passing
+    /// to [`NtfsFile::data`] in any case.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`read_upcase_table`][Ntfs::read_upcase_table] had not been called.
+    pub fn file_and_data_stream_by_path<'n, 'p, T>(
+        &'n self,
+        fs: &mut T,
+        path: &'p str,
+    ) -> Result<(NtfsFile<'n>, &'p str)>
+    where
+        T: Read + Seek,
+    {
+        // Colons are not allowed in NTFS filenames, so the first colon of the last component separates the stream name.
+        let last_component_start = path.rfind(['\\', '/']).map_or(0, |index| index + 1);
+        let (path, data_stream_name) = match path[last_component_start..].find(':') {
+            Some(index) => {
+                let index = last_component_start + index;
+                (&path[..index], &path[index + 1..])
+            }
+            None => (path, ""),
+        };
+
+        let file = self.file_by_path(fs, path)?;
+        Ok((file, data_stream_name))
+    }
+
+    /// Returns the [`NtfsFile`] at the given path, starting from the root directory.
+    ///
+    /// Both `\\` and `/` are accepted as path separators, and leading, trailing, or repeated separators are ignored.
+    /// Every path component is looked up case-insensitively using the filesystem's $UpCase table.
+    ///
+    /// Returns [`NtfsError::PathComponentNotFound`] if a path component does not exist and
+    /// [`NtfsError::PathComponentNotADirectory`] if a path component other than the last one is not a directory.
+    /// Both errors contain the path up to and including the offending component.
+    ///
+    /// Check [`Ntfs::file_and_data_stream_by_path`] if the path may also specify an Alternate Data Stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`read_upcase_table`][Ntfs::read_upcase_table] had not been called.
+    pub fn file_by_path<'n, T>(&'n self, fs: &mut T, path: &str) -> Result<NtfsFile<'n>>
+    where
+        T: Read + Seek,
+    {
+        let mut file = self.root_directory(fs)?;
+        let mut parent_path = "";
+        let mut end = 0;
+
+        for component in path.split(['\\', '/']) {
+            let current_path = &path[..end + component.len()];
+            end = current_path.len() + 1;
+
+            if component.is_empty() {
+                continue;
+            }
+
+            if !file.is_directory() {
+                return Err(NtfsError::PathComponentNotADirectory {
+                    path: parent_path.to_string(),
+                });
+            }
+
+            let next_file = {
+                let index = file.directory_index(fs)?;
+                let mut finder = index.finder();
+                let entry = NtfsFileNameIndex::find(&mut finder, self, fs, component).ok_or_else(
+                    || NtfsError::PathComponentNotFound {
+                        path: current_path.to_string(),
+                    },
+                )??;
+                entry.to_file(self, fs)?
+            };
+
+            file = next_file;
+            parent_path = current_path;
+        }
+
+        Ok(file)
+    }
+
+    /// Returns the size of a File Record of this NTFS filesystem, in bytes.
+    pub fn file_record_size(&self) -> u32 {
+        self.file_record_size
+    }
+
+    /// Returns whether the backup boot sector in the last sector of the partition has been used, because the
+    /// boot sector at the beginning is damaged.
+    ///
+    /// This can only be `true` for an [`Ntfs`] object created via [`Ntfs::new_with_recovery`].
+    pub fn is_backup_boot_sector_used(&self) -> bool {
+        self.backup_boot_sector_used
+    }
+
+    /// Returns an [`NtfsLogFile`] to read the transaction log stored in the $LogFile.
+    ///
+    /// The $LogFile is empty if it has never been written or has been reset (e.g. by NTFS-3G),
+    /// which is why the return value is further encapsulated in an `Option`.
+    pub fn log_file<T>(&self, fs: &mut T) -> Option<Result<NtfsLogFile>>
+    where
+        T: Read + Seek,
+    {
+        NtfsLogFile::new(self, fs)
+    }
+
+    pub(crate) fn mft_extents(&self) -> &ExtentMap {
+        &self.mft_extents
+    }
+
+    /// Reads the given File Record from the MFT, without ever falling back to $MFTMirr.
+    pub(crate) fn mft_file<'n, T>(
+        &'n self,
+        fs: &mut T,
+        file_record_number: u64,
+    ) -> Result<NtfsFile<'n>>
     where
         T: Read + Seek,
     {
@@ -90,28 +445,54 @@ impl Ntfs {
             .checked_mul(self.file_record_size as u64)
             .ok_or(NtfsError::InvalidFileRecordNumber { file_record_number })?;
 
-        // The MFT may be split into multiple data runs, referenced by its $DATA attribute.
-        // We therefore read it just like any other non-resident attribute value.
-        // However, this code assumes that the MFT does not have an Attribute List!
-        //
-        // This unwrap is safe, because `self.mft_position` has been checked in `Ntfs::new`.
-        let mft = NtfsFile::new(self, fs, self.mft_position.value().unwrap(), 0)?;
-        let mft_data_attribute =
-            mft.find_resident_attribute(NtfsAttributeType::Data, None, None)?;
-        let mut mft_data_value = mft_data_attribute.value(fs)?;
+        // A File Record may cross the boundary between two data runs of the MFT.
+        // Hence, it is read through the extent map, which splits the read as necessary.
+        let cache_position = self
+            .mft_extents
+            .lookup(offset)
+            .map_or(NtfsPosition::none(), |(position, _)| position);
 
-        mft_data_value.seek(fs, SeekFrom::Start(offset))?;
-        let position = mft_data_value
-            .data_position()
-            .value()
-            .ok_or(NtfsError::InvalidFileRecordNumber { file_record_number })?;
+        let record = self.read_record(cache_position, || {
+            let mut data = vec![0; self.file_record_size as usize];
+            self.mft_extents
+                .read_exact(fs, offset, &mut data)
+                .ok_or(NtfsError::InvalidFileRecordNumber { file_record_number })??;
+
+            // This unwrap is safe, because the read above has succeeded.
+            let position = cache_position.value().unwrap();
+
+            NtfsFile::from_vec(self, data, position.get(), file_record_number)
+                .map(NtfsFile::into_record)
+        })?;
+
+        Ok(NtfsFile::from_record(self, record, file_record_number))
+    }
+
+    /// Reads the copy of the given File Record from the MFT mirror ($MFTMirr).
+    fn mft_mirror_file<'n, T>(&'n self, fs: &mut T, file_record_number: u64) -> Result<NtfsFile<'n>>
+    where
+        T: Read + Seek,
+    {
+        // $MFTMirr is small enough to be allocated contiguously.
+        let position = self.mft_mirror_position + file_record_number * self.file_record_size as u64;
+        let position = position.value().ok_or(NtfsError::InvalidMftMirrorLcn)?;
 
         NtfsFile::new(self, fs, position, file_record_number)
     }
 
-    /// Returns the size of a File Record of this NTFS filesystem, in bytes.
-    pub fn file_record_size(&self) -> u32 {
-        self.file_record_size
+    /// Returns the absolute byte position of the MFT mirror ($MFTMirr), which holds a copy of the first
+    /// File Records of the Master File Table (MFT).
+    ///
+    /// This [`NtfsPosition`] is `None` if the boot sector contains no valid position.
+    pub fn mft_mirror_position(&self) -> NtfsPosition {
+        self.mft_mirror_position
+    }
+
+    /// Returns the number of File Records that are mirrored in $MFTMirr.
+    ///
+    /// NTFS mirrors at least 4 File Records, and as many as fit into a single cluster.
+    pub(crate) fn mft_mirror_record_count(&self) -> u64 {
+        u64::max(4, (self.cluster_size / self.file_record_size) as u64)
     }
 
     /// Returns the absolute byte position of the Master File Table (MFT).
@@ -121,6 +502,36 @@ impl Ntfs {
         self.mft_position
     }
 
+    /// Returns an iterator over all File Records of the Master File Table (MFT), in the order of their File Record Numbers.
+    ///
+    /// This reads the MFT sequentially and is much faster than traversing the directory tree when you need to
+    /// process every file of the filesystem.
+    /// The $BITMAP attribute of the MFT is read upfront to skip File Records that are not in use.
+    /// Check [`NtfsMftRecords`] for the available options.
+    pub fn mft_records<'n, T>(&'n self, fs: &mut T) -> Result<NtfsMftRecords<'n>>
+    where
+        T: Read + Seek,
+    {
+        NtfsMftRecords::new(self, fs)
+    }
+
+    /// Returns the File Record or Index Record at `position` from the record cache, or calls `read_record`
+    /// to read, fix up, and validate it.
+    pub(crate) fn read_record<F>(&self, position: NtfsPosition, read_record: F) -> Result<Record>
+    where
+        F: FnOnce() -> Result<Record>,
+    {
+        #[cfg(feature = "std")]
+        if let Some(position) = position.value() {
+            return self.record_cache.get_or_read(position.get(), read_record);
+        }
+
+        #[cfg(not(feature = "std"))]
+        let _ = position;
+
+        read_record()
+    }
+
     /// Reads the $UpCase file from the filesystem and stores it in this [`Ntfs`] object.
     ///
     /// This function only needs to be called if case-insensitive comparisons are later performed
@@ -134,6 +545,15 @@ impl Ntfs {
         Ok(())
     }
 
+    /// Returns the hit/miss statistics and the memory usage of the record cache.
+    ///
+    /// See [`Ntfs::set_record_cache_limit`].
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn record_cache_stats(&self) -> NtfsRecordCacheStats {
+        self.record_cache.stats()
+    }
+
     /// Returns the root directory of this NTFS volume as an [`NtfsFile`].
     pub fn root_directory<'n, T>(&'n self, fs: &mut T) -> Result<NtfsFile<'n>>
     where
@@ -147,11 +567,123 @@ impl Ntfs {
         self.sector_size
     }
 
+    /// Looks up the security descriptor with the given Security ID in the $Secure file.
+    ///
+    /// NTFS 3.x files reference their security descriptor through [`NtfsStandardInformation::security_id`].
+    /// The $SII index of $Secure maps this ID to the location of the security descriptor in the $SDS stream.
+    ///
+    /// Returns `None` if no security descriptor with this Security ID exists.
+    /// See [`NtfsFile::security_descriptor`] for a convenience function that also handles the security
+    /// descriptors of NTFS 1.x.
+    ///
+    /// [`NtfsStandardInformation::security_id`]: crate::structured_values::NtfsStandardInformation::security_id
+    pub fn security_descriptor<T>(
+        &self,
+        fs: &mut T,
+        security_id: u32,
+    ) -> Option<Result<NtfsSecurityDescriptor>>
+    where
+        T: Read + Seek,
+    {
+        let secure_file = iter_try!(self.file(fs, KnownNtfsFileRecordNumber::Secure as u64));
+
+        let header = {
+            let index = iter_try!(secure_file.index::<NtfsSecurityIdIndex, _>(fs, "$SII"));
+            let mut finder = index.finder();
+            let entry = iter_try!(NtfsSecurityIdIndex::find(&mut finder, fs, security_id)?);
+            iter_try!(entry.data()?)
+        };
+
+        // The $SII entry references an $SDS entry, which begins with another copy of the header.
+        let item = iter_try!(secure_file.find_attribute(fs, NtfsAttributeType::Data, Some("$SDS")));
+        let attribute = iter_try!(item.to_attribute());
+        let mut value = iter_try!(attribute.value(fs));
+        iter_try!(value.seek(fs, SeekFrom::Start(header.offset())));
+
+        let position = value.data_position();
+        let length = header.length() as u64;
+        let mut value_attached = value.attach(fs);
+        let sds_header = iter_try!(NtfsSecurityDescriptorHeader::new(
+            &mut value_attached,
+            position,
+            length
+        ));
+
+        if sds_header.security_id() != security_id {
+            return Some(Err(NtfsError::SecurityIdMismatch {
+                position,
+                expected: security_id,
+                actual: sds_header.security_id(),
+            }));
+        }
+
+        let position = position + SECURITY_DESCRIPTOR_HEADER_SIZE;
+        let length = length - SECURITY_DESCRIPTOR_HEADER_SIZE as u64;
+        Some(NtfsSecurityDescriptor::new(
+            &mut value_attached,
+            position,
+            length,
+        ))
+    }
+
     /// Returns the 64-bit serial number of this NTFS volume.
     pub fn serial_number(&self) -> u64 {
         self.serial_number
     }
 
+    /// Sets the memory limit of the record cache, in bytes.
+    ///
+    /// The record cache keeps recently read File Records and Index Records after their fixup and validation,
+    /// so that looking up the same files and directories again does not need to access the filesystem.
+    /// When the memory limit is exceeded, the least recently used records are evicted.
+    /// This mainly speeds up filesystem readers with slow random access, like network block devices or
+    /// compressed images.
+    ///
+    /// The cache is disabled by default, and a limit of 0 disables it again.
+    /// It assumes that the filesystem is not modified while this [`Ntfs`] object is in use
+    /// (see [`Ntfs::clear_record_cache`]).
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn set_record_cache_limit(&mut self, memory_limit: usize) {
+        self.record_cache.set_memory_limit(memory_limit);
+    }
+
+    /// Sets the [`NtfsVolumeFlags::IS_DIRTY`] flag of this volume and writes the $Volume File Record back
+    /// to the MFT and $MFTMirr.
+    ///
+    /// This makes Windows check the volume via `chkdsk` when mounting it the next time.
+    /// Call this after modifying the filesystem outside of Windows (e.g. via [`NtfsNonResidentAttributeValue::write`]).
+    /// Nothing is written if the flag is already set.
+    ///
+    /// [`NtfsNonResidentAttributeValue::write`]: crate::attribute_value::NtfsNonResidentAttributeValue::write
+    pub fn set_volume_dirty<T>(&self, fs: &mut T) -> Result<()>
+    where
+        T: Read + Seek + Write,
+    {
+        let mut volume_file = self.file(fs, KnownNtfsFileRecordNumber::Volume as u64)?;
+
+        let flags_offset = {
+            let attribute = volume_file.find_resident_attribute(
+                NtfsAttributeType::VolumeInformation,
+                None,
+                None,
+            )?;
+            let volume_info = attribute.resident_structured_value::<NtfsVolumeInformation>()?;
+            if volume_info.flags().contains(NtfsVolumeFlags::IS_DIRTY) {
+                return Ok(());
+            }
+
+            attribute.resident_value_range().start + VOLUME_INFORMATION_FLAGS_OFFSET
+        };
+
+        let data = volume_file.record_data_mut();
+        let flags =
+            LittleEndian::read_u16(&data[flags_offset..]) | NtfsVolumeFlags::IS_DIRTY.bits();
+        LittleEndian::write_u16(&mut data[flags_offset..], flags);
+
+        self.write_file_record(fs, &volume_file)
+    }
+
     /// Returns the partition size in bytes.
     pub fn size(&self) -> u64 {
         self.size
@@ -168,6 +700,17 @@ impl Ntfs {
             .expect("You need to call read_upcase_table first")
     }
 
+    /// Returns an [`NtfsUsnJournal`] to read the change journal stored in `$Extend\$UsnJrnl`.
+    ///
+    /// The change journal is optional and only created on demand (e.g. by Windows Search or backup software),
+    /// which is why the return value is further encapsulated in an `Option`.
+    pub fn usn_journal<'n, T>(&'n self, fs: &mut T) -> Option<Result<NtfsUsnJournal<'n>>>
+    where
+        T: Read + Seek,
+    {
+        NtfsUsnJournal::new(self, fs)
+    }
+
     /// Returns an [`NtfsVolumeInformation`] containing general information about
     /// the volume, like the NTFS version.
     pub fn volume_info<T>(&self, fs: &mut T) -> Result<NtfsVolumeInformation>
@@ -195,11 +738,50 @@ impl Ntfs {
             Err(e) => Some(Err(e)),
         }
     }
+
+    /// Writes the given File Record back to the MFT, and also to $MFTMirr if it is mirrored there.
+    pub(crate) fn write_file_record<T>(&self, fs: &mut T, file: &NtfsFile) -> Result<()>
+    where
+        T: Seek + Write,
+    {
+        let file_record_number = file.file_record_number();
+        let offset = file_record_number
+            .checked_mul(self.file_record_size as u64)
+            .ok_or(NtfsError::InvalidFileRecordNumber { file_record_number })?;
+
+        let mut record = Record::new(file.record_data().to_vec(), file.position());
+        record.protect()?;
+
+        self.mft_extents
+            .write_all(fs, offset, record.data())
+            .ok_or(NtfsError::InvalidFileRecordNumber { file_record_number })??;
+
+        if file_record_number < self.mft_mirror_record_count() {
+            // $MFTMirr is small enough to be allocated contiguously.
+            if let Some(position) = (self.mft_mirror_position + offset).value() {
+                fs.seek(SeekFrom::Start(position.get()))?;
+                fs.write_all(record.data())?;
+
+                #[cfg(feature = "std")]
+                self.record_cache.remove(position.get());
+            }
+        }
+
+        // Cached copies of the File Record are outdated now.
+        #[cfg(feature = "std")]
+        if let Some(position) = file.position().value() {
+            self.record_cache.remove(position.get());
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structured_values::NtfsAceFlags;
+    use binrw::io::Cursor;
 
     #[test]
     fn test_basics() {
@@ -210,6 +792,183 @@ mod tests {
         assert_eq!(ntfs.size(), 2096640);
     }
 
+    #[test]
+    fn test_file() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // The MFT of this filesystem consists of two data runs, and File Record 255 crosses the boundary between them.
+        let file = ntfs.file(&mut testfs1, 255).unwrap();
+        assert_eq!(file.file_record_number(), 255);
+        assert!(file.is_directory());
+
+        // File Records beyond the end of the MFT cannot be read.
+        assert!(matches!(
+            ntfs.file(&mut testfs1, 1_000_000),
+            Err(NtfsError::InvalidFileRecordNumber { .. })
+        ));
+    }
+
+    #[test]
+    fn test_file_by_path() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let mut ntfs = Ntfs::new(&mut testfs1).unwrap();
+        ntfs.read_upcase_table(&mut testfs1).unwrap();
+
+        let file = ntfs
+            .file_by_path(&mut testfs1, "many_subdirs\\256")
+            .unwrap();
+        assert!(file.is_directory());
+
+        // Mixed separators, different case, and superfluous separators.
+        let file2 = ntfs
+            .file_by_path(&mut testfs1, "/MANY_SUBDIRS//256/")
+            .unwrap();
+        assert_eq!(file.file_record_number(), file2.file_record_number());
+
+        let root_dir = ntfs.file_by_path(&mut testfs1, "\\").unwrap();
+        assert_eq!(
+            root_dir.file_record_number(),
+            KnownNtfsFileRecordNumber::RootDirectory as u64
+        );
+
+        assert!(matches!(
+            ntfs.file_by_path(&mut testfs1, "many_subdirs/1000/file"),
+            Err(NtfsError::PathComponentNotFound { path }) if path == "many_subdirs/1000"
+        ));
+        assert!(matches!(
+            ntfs.file_by_path(&mut testfs1, "file-with-12345/file"),
+            Err(NtfsError::PathComponentNotADirectory { path }) if path == "file-with-12345"
+        ));
+
+        let (file, data_stream_name) = ntfs
+            .file_and_data_stream_by_path(&mut testfs1, "file-with-12345:stream")
+            .unwrap();
+        assert!(!file.is_directory());
+        assert_eq!(data_stream_name, "stream");
+
+        let (_, data_stream_name) = ntfs
+            .file_and_data_stream_by_path(&mut testfs1, "/file-with-12345")
+            .unwrap();
+        assert_eq!(data_stream_name, "");
+    }
+
+    #[test]
+    fn test_new_with_recovery() {
+        let testfs1 = crate::helpers::tests::testfs1();
+        let volume_size = testfs1.get_ref().len() as u64;
+
+        // An intact filesystem is opened via its primary boot sector.
+        let mut fs = testfs1.clone();
+        let ntfs = Ntfs::new_with_recovery(&mut fs, volume_size).unwrap();
+        assert!(!ntfs.is_backup_boot_sector_used());
+
+        // Wipe the boot sector and damage the File Records of $MFT and $Volume by changing the
+        // last bytes of their first sectors, which makes their update sequence numbers mismatch.
+        let mft_position = ntfs.mft_position().value().unwrap().get() as usize;
+        let file_record_size = ntfs.file_record_size() as usize;
+        let sector_size = ntfs.sector_size() as usize;
+
+        let mut image = testfs1.into_inner();
+        image[..sector_size].fill(0);
+        image[mft_position + sector_size - 1] ^= 0xff;
+        image[mft_position + 3 * file_record_size + sector_size - 1] ^= 0xff;
+        let mut fs = Cursor::new(image);
+
+        assert!(matches!(
+            Ntfs::new(&mut fs),
+            Err(NtfsError::InvalidTwoByteSignature { .. })
+        ));
+
+        let ntfs = Ntfs::new_with_recovery(&mut fs, volume_size).unwrap();
+        assert!(ntfs.is_backup_boot_sector_used());
+        assert_eq!(ntfs.size(), 2096640);
+
+        // $Volume is read from $MFTMirr, while other File Records are still read from the MFT.
+        let volume = ntfs
+            .file(&mut fs, KnownNtfsFileRecordNumber::Volume as u64)
+            .unwrap();
+        assert_eq!(
+            volume.position(),
+            ntfs.mft_mirror_position() + 3 * file_record_size as u64
+        );
+        let volume_name = ntfs.volume_name(&mut fs).unwrap().unwrap();
+        assert_eq!(volume_name.name(), "mylabel");
+
+        let file = ntfs.file(&mut fs, 66).unwrap();
+        assert_eq!(file.file_record_number(), 66);
+
+        // Without a valid backup boot sector, the error of the primary boot sector is returned.
+        assert!(matches!(
+            Ntfs::new_with_recovery(&mut fs, volume_size - 512),
+            Err(NtfsError::InvalidTwoByteSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn test_security_descriptor() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // mkntfs stores the security descriptor of the root directory in a $SECURITY_DESCRIPTOR attribute.
+        let root_dir = ntfs.root_directory(&mut testfs1).unwrap();
+        let security_descriptor = root_dir.security_descriptor(&mut testfs1).unwrap().unwrap();
+        let owner = security_descriptor.owner().unwrap().unwrap();
+        assert_eq!(owner.to_string(), "S-1-5-18");
+
+        let dacl = security_descriptor.dacl().unwrap().unwrap();
+        assert_eq!(dacl.ace_count(), 8);
+        let ace = dacl.aces().nth(7).unwrap().unwrap();
+        assert_eq!(ace.sid().unwrap().unwrap().to_string(), "S-1-5-32-545");
+        assert!(ace.flags().contains(NtfsAceFlags::INHERIT_ONLY));
+
+        // Look up the first two security descriptors of $Secure via their Security IDs.
+        for security_id in [0x100, 0x101] {
+            let security_descriptor = ntfs
+                .security_descriptor(&mut testfs1, security_id)
+                .unwrap()
+                .unwrap();
+            let owner = security_descriptor.owner().unwrap().unwrap();
+            assert_eq!(owner.to_string(), "S-1-5-32-544");
+        }
+
+        assert!(ntfs.security_descriptor(&mut testfs1, 0x102).is_none());
+    }
+
+    #[test]
+    fn test_set_volume_dirty() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let mut ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // Cached File Records must not hide the modification.
+        #[cfg(feature = "std")]
+        ntfs.set_record_cache_limit(1024 * 1024);
+
+        let volume_info = ntfs.volume_info(&mut testfs1).unwrap();
+        assert!(!volume_info.flags().contains(NtfsVolumeFlags::IS_DIRTY));
+
+        ntfs.set_volume_dirty(&mut testfs1).unwrap();
+        let volume_info = ntfs.volume_info(&mut testfs1).unwrap();
+        assert!(volume_info.flags().contains(NtfsVolumeFlags::IS_DIRTY));
+        assert_eq!(volume_info.major_version(), 3);
+
+        // The File Record has been written to the MFT and $MFTMirr, with a valid Update Sequence Array.
+        let volume_file = ntfs
+            .file(&mut testfs1, KnownNtfsFileRecordNumber::Volume as u64)
+            .unwrap();
+        let mirrored_volume_file = ntfs
+            .mft_mirror_file(&mut testfs1, KnownNtfsFileRecordNumber::Volume as u64)
+            .unwrap();
+        assert_eq!(
+            volume_file.record_data(),
+            mirrored_volume_file.record_data()
+        );
+
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let volume_info = ntfs.volume_info(&mut testfs1).unwrap();
+        assert!(volume_info.flags().contains(NtfsVolumeFlags::IS_DIRTY));
+    }
+
     #[test]
     fn test_volume_info() {
         let mut testfs1 = crate::helpers::tests::testfs1();
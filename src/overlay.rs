@@ -0,0 +1,190 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Copy-on-write overlay for a filesystem reader.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io;
+use binrw::io::{Read, Seek, SeekFrom};
+
+use crate::attribute_value::seek_contiguous;
+use crate::error::Result;
+
+/// Size of the blocks in which an [`NtfsOverlay`] stores modified data, in bytes.
+const OVERLAY_BLOCK_SIZE: u64 = 512;
+
+/// A copy-on-write overlay on top of a filesystem reader.
+///
+/// Data written via [`NtfsOverlay::write_at`] is kept in memory and returned by all subsequent reads
+/// in place of the underlying data, which is never modified.
+/// As [`NtfsOverlay`] implements [`Read`] and [`Seek`], it can be passed to every function of this crate,
+/// including [`Ntfs::new`], to get a modified view of a filesystem without writing to it.
+///
+/// This is used by [`NtfsLogFile::redo_committed`] to present a volume with its committed transactions redone.
+///
+/// [`Ntfs::new`]: crate::Ntfs::new
+/// [`NtfsLogFile::redo_committed`]: crate::NtfsLogFile::redo_committed
+#[derive(Clone, Debug)]
+pub struct NtfsOverlay<T> {
+    inner: T,
+    blocks: BTreeMap<u64, Vec<u8>>,
+    stream_position: u64,
+}
+
+impl<T> NtfsOverlay<T>
+where
+    T: Read + Seek,
+{
+    /// Creates a new [`NtfsOverlay`] without any modifications on top of the given reader.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            blocks: BTreeMap::new(),
+            stream_position: 0,
+        }
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Consumes this overlay, discards all modifications, and returns the underlying reader.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns whether any data has been written to this overlay.
+    pub fn is_modified(&self) -> bool {
+        !self.blocks.is_empty()
+    }
+
+    /// Returns the number of bytes held in memory for modified data.
+    pub fn modified_size(&self) -> u64 {
+        self.blocks.len() as u64 * OVERLAY_BLOCK_SIZE
+    }
+
+    /// Copies modified data into `buf`, which has been read from the underlying reader at `position`.
+    fn patch(&self, position: u64, buf: &mut [u8]) {
+        if buf.is_empty() {
+            return;
+        }
+
+        let end = position + buf.len() as u64;
+        let first_block = position / OVERLAY_BLOCK_SIZE;
+        let last_block = (end - 1) / OVERLAY_BLOCK_SIZE;
+
+        for (block, data) in self.blocks.range(first_block..=last_block) {
+            let block_start = block * OVERLAY_BLOCK_SIZE;
+            let start = u64::max(block_start, position);
+            let end = u64::min(block_start + OVERLAY_BLOCK_SIZE, end);
+
+            let source = &data[(start - block_start) as usize..(end - block_start) as usize];
+            buf[(start - position) as usize..(end - position) as usize].copy_from_slice(source);
+        }
+    }
+
+    /// Writes `data` to the given absolute position, keeping it in memory.
+    ///
+    /// The underlying reader is never modified.
+    /// Writing beyond the end of the underlying reader fails.
+    pub fn write_at(&mut self, position: u64, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let first_block = position / OVERLAY_BLOCK_SIZE;
+        let last_block = (position + data.len() as u64 - 1) / OVERLAY_BLOCK_SIZE;
+
+        // Read all affected blocks first to not leave a partial write behind on error.
+        let mut new_blocks = Vec::new();
+        for block in first_block..=last_block {
+            if !self.blocks.contains_key(&block) {
+                let mut block_data = vec![0u8; OVERLAY_BLOCK_SIZE as usize];
+                self.inner
+                    .seek(SeekFrom::Start(block * OVERLAY_BLOCK_SIZE))?;
+                self.inner.read_exact(&mut block_data)?;
+                new_blocks.push((block, block_data));
+            }
+        }
+
+        self.blocks.extend(new_blocks);
+
+        for (block, block_data) in self.blocks.range_mut(first_block..=last_block) {
+            let block_start = block * OVERLAY_BLOCK_SIZE;
+            let start = u64::max(block_start, position);
+            let end = u64::min(
+                block_start + OVERLAY_BLOCK_SIZE,
+                position + data.len() as u64,
+            );
+
+            let source = &data[(start - position) as usize..(end - position) as usize];
+            block_data[(start - block_start) as usize..(end - block_start) as usize]
+                .copy_from_slice(source);
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Read for NtfsOverlay<T>
+where
+    T: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.seek(SeekFrom::Start(self.stream_position))?;
+        let bytes_read = self.inner.read(buf)?;
+        self.patch(self.stream_position, &mut buf[..bytes_read]);
+
+        self.stream_position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<T> Seek for NtfsOverlay<T>
+where
+    T: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let length = match pos {
+            SeekFrom::End(_) => self.inner.seek(SeekFrom::End(0))?,
+            _ => 0,
+        };
+
+        seek_contiguous(&mut self.stream_position, length, pos).map_err(io::Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binrw::io::Cursor;
+
+    #[test]
+    fn test_overlay() {
+        let data: Vec<u8> = (0..2048u32).map(|i| i as u8).collect();
+        let mut overlay = NtfsOverlay::new(Cursor::new(data.clone()));
+        assert!(!overlay.is_modified());
+
+        // Write across a block boundary.
+        overlay.write_at(510, b"abcd").unwrap();
+        assert!(overlay.is_modified());
+        assert_eq!(overlay.modified_size(), 1024);
+
+        let mut buf = [0u8; 8];
+        overlay.seek(SeekFrom::Start(508)).unwrap();
+        overlay.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [252, 253, b'a', b'b', b'c', b'd', 2, 3]);
+
+        overlay.seek(SeekFrom::End(-2)).unwrap();
+        overlay.read_exact(&mut buf[..2]).unwrap();
+        assert_eq!(buf[..2], [254, 255]);
+
+        // A failed write leaves no partial modification behind.
+        assert!(overlay.write_at(2046, b"xyz").is_err());
+        assert_eq!(overlay.modified_size(), 1024);
+        assert_eq!(overlay.into_inner().into_inner(), data);
+    }
+}
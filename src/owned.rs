@@ -0,0 +1,439 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Owned handles that share an [`Ntfs`] object and its filesystem reader instead of borrowing them.
+//!
+//! The borrowing API of this crate ties every [`NtfsFile`] and [`NtfsAttribute`] to the lifetime of the
+//! [`Ntfs`] object and passes the filesystem reader into every call.
+//! This is the most flexible and efficient way to access a filesystem, but makes it hard to keep files open
+//! in long-lived structures or to access a filesystem from multiple threads.
+//!
+//! [`NtfsShared`], [`NtfsOwnedFile`], and [`NtfsOwnedDataStream`] have no lifetimes.
+//! They keep the [`Ntfs`] object and the filesystem reader alive via [`Arc`]s, and serialize all accesses
+//! to the reader via a [`Mutex`].
+//! If the filesystem source implements [`NtfsReadAt`], using an [`NtfsReadAtReader`] per thread along with a
+//! shared [`Ntfs`] object avoids this lock altogether.
+//!
+//! [`NtfsReadAt`]: crate::NtfsReadAt
+//! [`NtfsReadAtReader`]: crate::NtfsReadAtReader
+//! [`NtfsAttribute`]: crate::NtfsAttribute
+
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::attribute::NtfsAttributeItem;
+use crate::attribute_value::{seek_contiguous, CompressedStream, NtfsAttributeValue};
+use crate::error::Result;
+use crate::extent_map::ExtentMap;
+use crate::file::NtfsFile;
+use crate::ntfs::Ntfs;
+use crate::record::Record;
+use crate::structured_values::NtfsFileName;
+
+/// An [`Ntfs`] object together with the reader of its filesystem, both shared via [`Arc`]s.
+///
+/// This is the entry point to the owned handles of this crate.
+/// Cloning an [`NtfsShared`] is cheap and yields another reference to the same filesystem, which can be
+/// moved to another thread.
+///
+/// The filesystem reader is only locked for the duration of a single lookup or read.
+/// Use [`NtfsShared::lock`] to access it for the borrowing API of this crate.
+#[derive(Debug)]
+pub struct NtfsShared<T> {
+    ntfs: Arc<Ntfs>,
+    fs: Arc<Mutex<T>>,
+}
+
+impl<T> NtfsShared<T>
+where
+    T: Read + Seek,
+{
+    /// Creates a new [`NtfsShared`] for the NTFS filesystem read by `fs`.
+    ///
+    /// This also reads the $UpCase table, which is required for [`NtfsShared::file_by_path`].
+    pub fn new(mut fs: T) -> Result<Self> {
+        let mut ntfs = Ntfs::new(&mut fs)?;
+        ntfs.read_upcase_table(&mut fs)?;
+
+        Ok(Self::from_parts(Arc::new(ntfs), Arc::new(Mutex::new(fs))))
+    }
+
+    /// Creates a new [`NtfsShared`] from an existing [`Ntfs`] object and the shared reader of its filesystem.
+    pub fn from_parts(ntfs: Arc<Ntfs>, fs: Arc<Mutex<T>>) -> Self {
+        Self { ntfs, fs }
+    }
+
+    /// Returns the [`NtfsOwnedFile`] for the given NTFS File Record Number.
+    ///
+    /// See [`Ntfs::file`].
+    pub fn file(&self, file_record_number: u64) -> Result<NtfsOwnedFile<T>> {
+        let mut fs = self.lock();
+        let file = self.ntfs.file(&mut *fs, file_record_number)?;
+        Ok(NtfsOwnedFile::new(self.clone(), file))
+    }
+
+    /// Returns the [`NtfsOwnedFile`] for the given absolute path.
+    ///
+    /// See [`Ntfs::file_by_path`].
+    pub fn file_by_path(&self, path: &str) -> Result<NtfsOwnedFile<T>> {
+        let mut fs = self.lock();
+        let file = self.ntfs.file_by_path(&mut *fs, path)?;
+        Ok(NtfsOwnedFile::new(self.clone(), file))
+    }
+
+    /// Locks the filesystem reader and returns it, e.g. to use it with the borrowing API of this crate.
+    ///
+    /// A lock poisoned by a panicking thread is taken over, because every access of this crate seeks before
+    /// reading and never relies on a previous position of the reader.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.fs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the shared [`Ntfs`] object.
+    pub fn ntfs(&self) -> &Arc<Ntfs> {
+        &self.ntfs
+    }
+
+    /// Returns the [`NtfsOwnedFile`] of the root directory.
+    ///
+    /// See [`Ntfs::root_directory`].
+    pub fn root_directory(&self) -> Result<NtfsOwnedFile<T>> {
+        let mut fs = self.lock();
+        let file = self.ntfs.root_directory(&mut *fs)?;
+        Ok(NtfsOwnedFile::new(self.clone(), file))
+    }
+}
+
+impl<T> Clone for NtfsShared<T> {
+    fn clone(&self) -> Self {
+        Self {
+            ntfs: Arc::clone(&self.ntfs),
+            fs: Arc::clone(&self.fs),
+        }
+    }
+}
+
+/// An NTFS file that shares ownership of its filesystem instead of borrowing it.
+///
+/// An [`NtfsOwnedFile`] holds a copy of the File Record and can therefore be stored and sent to other threads
+/// freely.
+/// Use [`NtfsOwnedFile::to_file`] to get an [`NtfsFile`] for all information that is not provided here.
+#[derive(Debug)]
+pub struct NtfsOwnedFile<T> {
+    shared: NtfsShared<T>,
+    record: Record,
+    file_record_number: u64,
+}
+
+impl<T> NtfsOwnedFile<T>
+where
+    T: Read + Seek,
+{
+    fn new(shared: NtfsShared<T>, file: NtfsFile) -> Self {
+        let file_record_number = file.file_record_number();
+        let record = file.into_record();
+
+        Self {
+            shared,
+            record,
+            file_record_number,
+        }
+    }
+
+    /// Returns an [`NtfsOwnedDataStream`] for the $DATA attribute of this file with the given name.
+    ///
+    /// Use `""` for the unnamed $DATA attribute of a file.
+    /// See [`NtfsFile::data`].
+    pub fn data(&self, data_stream_name: &str) -> Option<Result<NtfsOwnedDataStream<T>>> {
+        let mut fs = self.shared.lock();
+        let file = self.to_file();
+        let item = iter_try!(file.data(&mut *fs, data_stream_name)?);
+
+        Some(NtfsOwnedDataStream::new(
+            self.shared.clone(),
+            &mut *fs,
+            &item,
+        ))
+    }
+
+    /// Returns the NTFS File Record Number of this file.
+    pub fn file_record_number(&self) -> u64 {
+        self.file_record_number
+    }
+
+    /// Returns `true` if this file is a directory.
+    pub fn is_directory(&self) -> bool {
+        self.to_file().is_directory()
+    }
+
+    /// Returns the first $FILE_NAME attribute of this file.
+    ///
+    /// See [`NtfsFile::name`] to filter for a namespace or parent directory.
+    pub fn name(&self) -> Option<Result<NtfsFileName>> {
+        let mut fs = self.shared.lock();
+        self.to_file().name(&mut *fs, None, None)
+    }
+
+    /// Returns the [`NtfsShared`] this file belongs to.
+    pub fn shared(&self) -> &NtfsShared<T> {
+        &self.shared
+    }
+
+    /// Returns an [`NtfsFile`] borrowing this file, for use with the borrowing API of this crate.
+    pub fn to_file(&self) -> NtfsFile<'_> {
+        NtfsFile::from_record(
+            &self.shared.ntfs,
+            self.record.clone(),
+            self.file_record_number,
+        )
+    }
+}
+
+impl<T> Clone for NtfsOwnedFile<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            record: self.record.clone(),
+            file_record_number: self.file_record_number,
+        }
+    }
+}
+
+/// Data of an [`NtfsOwnedDataStream`], detached from the File Record it was read from.
+#[derive(Clone, Debug)]
+enum OwnedValue {
+    Resident(Vec<u8>),
+    NonResident(ExtentMap),
+    Compressed(CompressedStream),
+}
+
+/// A $DATA attribute value that shares ownership of its filesystem and implements [`Read`] and [`Seek`].
+///
+/// All Data Runs are collected when creating an [`NtfsOwnedDataStream`], so that reading only needs to lock
+/// the filesystem reader for the actual data.
+/// Sparse data is read as zeros, and compressed data is transparently decompressed.
+///
+/// Every [`NtfsOwnedDataStream`] has its own seek position, so multiple streams can read from the same
+/// filesystem concurrently.
+#[derive(Debug)]
+pub struct NtfsOwnedDataStream<T> {
+    shared: NtfsShared<T>,
+    value: OwnedValue,
+    length: u64,
+    stream_position: u64,
+}
+
+impl<T> NtfsOwnedDataStream<T>
+where
+    T: Read + Seek,
+{
+    fn new(shared: NtfsShared<T>, fs: &mut T, item: &NtfsAttributeItem) -> Result<Self> {
+        let attribute = item.to_attribute()?;
+        let length = attribute.value_length();
+
+        let value = match attribute.value(fs)? {
+            NtfsAttributeValue::Resident(value) => OwnedValue::Resident(value.data().to_vec()),
+            NtfsAttributeValue::NonResident(value) if value.is_compressed() => {
+                // This unwrap is safe, because every compressed value has a decompression state.
+                OwnedValue::Compressed(value.into_compressed_stream().unwrap())
+            }
+            NtfsAttributeValue::AttributeListNonResident(value) if value.is_compressed() => {
+                // This unwrap is safe, because every compressed value has a decompression state.
+                OwnedValue::Compressed(value.into_compressed_stream().unwrap())
+            }
+            value => {
+                OwnedValue::NonResident(ExtentMap::from_value(fs, value, attribute.position())?)
+            }
+        };
+
+        Ok(Self {
+            shared,
+            value,
+            length,
+            stream_position: 0,
+        })
+    }
+
+    /// Returns `true` if this data stream is empty.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Returns the length of this data stream, in bytes.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the [`NtfsShared`] this data stream belongs to.
+    pub fn shared(&self) -> &NtfsShared<T> {
+        &self.shared
+    }
+}
+
+impl<T> Clone for NtfsOwnedDataStream<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            value: self.value.clone(),
+            length: self.length,
+            stream_position: self.stream_position,
+        }
+    }
+}
+
+impl<T> Read for NtfsOwnedDataStream<T>
+where
+    T: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stream_position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let remaining = self.length - self.stream_position;
+        let bytes_to_read = u64::min(buf.len() as u64, remaining) as usize;
+        let buf = &mut buf[..bytes_to_read];
+
+        let bytes_read = match &mut self.value {
+            OwnedValue::Resident(data) => {
+                let start = self.stream_position as usize;
+                buf.copy_from_slice(&data[start..start + bytes_to_read]);
+                bytes_to_read
+            }
+            OwnedValue::NonResident(extent_map) => match extent_map.lookup(self.stream_position) {
+                Some((position, remaining_in_extent)) => {
+                    let bytes_to_read =
+                        u64::min(bytes_to_read as u64, remaining_in_extent) as usize;
+                    let buf = &mut buf[..bytes_to_read];
+
+                    match position.value() {
+                        Some(position) => {
+                            let mut fs = self.shared.lock();
+                            fs.seek(SeekFrom::Start(position.get()))?;
+                            fs.read(buf)?
+                        }
+                        None => {
+                            // Sparse Data Runs are read as zeros.
+                            buf.fill(0);
+                            bytes_to_read
+                        }
+                    }
+                }
+                None => {
+                    // The value data extends beyond its allocated Data Runs, which are all zeros.
+                    buf.fill(0);
+                    bytes_to_read
+                }
+            },
+            OwnedValue::Compressed(stream) => {
+                stream.seek(SeekFrom::Start(self.stream_position))?;
+                let mut fs = self.shared.lock();
+                stream.read(&mut *fs, buf)?
+            }
+        };
+
+        self.stream_position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<T> Seek for NtfsOwnedDataStream<T>
+where
+    T: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_contiguous(&mut self.stream_position, self.length, pos).map_err(io::Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::thread;
+
+    use super::*;
+
+    fn open_file(
+        shared: &NtfsShared<Cursor<Vec<u8>>>,
+        path: &str,
+    ) -> NtfsOwnedFile<Cursor<Vec<u8>>> {
+        shared.file_by_path(path).unwrap()
+    }
+
+    fn read_data(file: &NtfsOwnedFile<Cursor<Vec<u8>>>) -> Vec<u8> {
+        let mut data_stream = file.data("").unwrap().unwrap();
+        let mut buf = Vec::new();
+        data_stream.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf.len() as u64, data_stream.len());
+        buf
+    }
+
+    #[test]
+    fn test_owned_file() {
+        let shared = NtfsShared::new(crate::helpers::tests::testfs1()).unwrap();
+
+        // Owned files outlive the function that looked them up.
+        let file = open_file(&shared, "1000-bytes-file");
+        assert_eq!(file.file_record_number(), 66);
+        assert!(!file.is_directory());
+        assert_eq!(
+            file.name().unwrap().unwrap().name().to_string_lossy(),
+            "1000-bytes-file"
+        );
+        assert_eq!(read_data(&file), b"12345".repeat(200));
+
+        let mut data_stream = file.data("").unwrap().unwrap();
+        let mut buf = [0u8; 5];
+        data_stream.seek(SeekFrom::End(-5)).unwrap();
+        data_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"12345");
+        assert_eq!(data_stream.read(&mut buf).unwrap(), 0);
+
+        // Resident data.
+        let file = open_file(&shared, "file-with-12345");
+        assert_eq!(read_data(&file), b"12345");
+
+        // Sparse data.
+        let file = open_file(&shared, "sparse-file");
+        let data = read_data(&file);
+        assert_eq!(data.len(), 500005);
+        assert_eq!(&data[..5], b"12345");
+        assert!(data[5..500000].iter().all(|&b| b == 0));
+        assert_eq!(&data[500000..], b"11111");
+
+        // The root directory and the borrowing API.
+        let root = shared.root_directory().unwrap();
+        assert!(root.is_directory());
+        assert!(root.data("").is_none());
+        let mut fs = shared.lock();
+        assert!(root.to_file().directory_index(&mut *fs).is_ok());
+    }
+
+    #[test]
+    fn test_owned_threads() {
+        let shared = NtfsShared::new(crate::helpers::tests::testfs1()).unwrap();
+        let paths = ["1000-bytes-file", "file-with-12345", "sparse-file"];
+
+        let handles = paths
+            .iter()
+            .flat_map(|&path| {
+                let shared = shared.clone();
+                let expected = read_data(&open_file(&shared, path));
+
+                (0..4).map(move |_| {
+                    let shared = shared.clone();
+                    let expected = expected.clone();
+                    thread::spawn(move || {
+                        let file = open_file(&shared, path);
+                        assert_eq!(read_data(&file), expected);
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
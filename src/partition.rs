@@ -0,0 +1,944 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! MBR and GPT partition tables, for opening NTFS filesystems inside whole-disk images.
+
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io;
+use binrw::io::{Read, Seek, SeekFrom};
+use byteorder::{ByteOrder, LittleEndian};
+use nt_string::u16strle::U16StrLe;
+
+use crate::attribute_value::seek_contiguous;
+use crate::error::{NtfsError, Result};
+use crate::guid::NtfsGuid;
+use crate::types::NtfsPosition;
+
+/// Size of the Master Boot Record (MBR) and every Extended Boot Record (EBR), in bytes.
+const MBR_SIZE: usize = 512;
+/// Offset of the first of the 4 partition entries in an MBR or EBR.
+const MBR_PARTITION_ENTRIES_OFFSET: usize = 446;
+/// Size of a single partition entry in an MBR or EBR, in bytes.
+const MBR_PARTITION_ENTRY_SIZE: usize = 16;
+/// Partition type of the protective MBR entry covering a GPT disk.
+const MBR_PARTITION_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+/// Partition types of an extended partition (CHS, LBA, and Linux).
+const MBR_PARTITION_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// Partition types used for NTFS (regular, hidden, and Windows Recovery Environment).
+const MBR_PARTITION_TYPES_NTFS: [u8; 3] = [0x07, 0x17, 0x27];
+
+/// Signature at the beginning of a GPT header.
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+/// Minimum size of a GPT header, in bytes.
+const GPT_HEADER_SIZE: usize = 92;
+/// Offset of the CRC32 checksum of the GPT header, which is calculated with this field set to zero.
+const GPT_HEADER_CHECKSUM_OFFSET: usize = 16;
+/// Minimum size of a GPT partition entry, in bytes.
+const GPT_PARTITION_ENTRY_MIN_SIZE: u32 = 128;
+/// Upper limit for the size of all GPT partition entries, in bytes.
+/// The default of 128 entries only takes 16 KiB, so this leaves plenty of room while rejecting garbage.
+const GPT_PARTITION_ENTRIES_MAX_SIZE: u64 = 1024 * 1024;
+/// Maximum number of UTF-16 code units in the name of a GPT partition.
+const GPT_PARTITION_NAME_LENGTH: usize = 36;
+/// Partition type GUIDs used for NTFS (Microsoft Basic Data and Windows Recovery Environment).
+const GPT_PARTITION_TYPES_NTFS: [NtfsGuid; 2] = [
+    NtfsGuid {
+        data1: 0xebd0a0a2,
+        data2: 0xb9e5,
+        data3: 0x4433,
+        data4: [0x87, 0xc0, 0x68, 0xb6, 0xb7, 0x26, 0x99, 0xc7],
+    },
+    NtfsGuid {
+        data1: 0xde94bba4,
+        data2: 0x06d1,
+        data3: 0x4d40,
+        data4: [0xa1, 0x6a, 0xbf, 0xd5, 0x01, 0x79, 0xd6, 0xac],
+    },
+];
+
+/// Partition type GUID of an unused GPT partition entry.
+const GPT_UNUSED_PARTITION_TYPE: NtfsGuid = NtfsGuid {
+    data1: 0,
+    data2: 0,
+    data3: 0,
+    data4: [0; 8],
+};
+
+/// Logical sector sizes that are tried to find a GPT header or the partitions of an MBR.
+const SECTOR_SIZES: [u32; 2] = [512, 4096];
+/// Minimum logical sector size accepted by [`NtfsPartitionTable::new_with_sector_size`], in bytes.
+const MIN_SECTOR_SIZE: u16 = 512;
+/// Maximum logical sector size accepted by [`NtfsPartitionTable::new_with_sector_size`], in bytes.
+const MAX_SECTOR_SIZE: u16 = 4096;
+
+/// The partitioning scheme of a disk, as returned by [`NtfsPartitionTable::scheme`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NtfsPartitionScheme {
+    /// GUID Partition Table (GPT).
+    Gpt,
+    /// Master Boot Record (MBR), including logical partitions inside an extended partition.
+    Mbr,
+}
+
+/// The type of a partition as stored in its partition table entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NtfsPartitionType {
+    /// Partition type GUID of a GPT partition entry.
+    Gpt(NtfsGuid),
+    /// Partition type ID of an MBR partition entry.
+    Mbr(u8),
+}
+
+/// The partitions of a whole-disk image, read from its MBR or GPT.
+///
+/// This structure is created via [`NtfsPartitionTable::new`].
+/// Use [`NtfsPartition::reader`] to get a reader for a single partition, which can be passed to [`Ntfs::new`].
+///
+/// [`Ntfs::new`]: crate::Ntfs::new
+#[derive(Clone, Debug)]
+pub struct NtfsPartitionTable {
+    scheme: NtfsPartitionScheme,
+    sector_size: u32,
+    partitions: Vec<NtfsPartition>,
+}
+
+impl NtfsPartitionTable {
+    /// Reads the partition table of the disk covered by the given reader.
+    ///
+    /// A GPT is recognized by the protective MBR entry covering it, and its header is searched for at logical
+    /// sector sizes of 512 and 4096 bytes.
+    /// If the primary GPT header or its partition entries are damaged (i.e. fail the signature or CRC32 checks),
+    /// the backup GPT header in the last sector of the disk is used.
+    ///
+    /// Otherwise, the primary partitions of the MBR are returned, along with all logical partitions found by
+    /// following the chain of Extended Boot Records (EBRs) in an extended partition.
+    /// As an MBR doesn't store the logical sector size, the partitions are calculated for 512 and 4096 bytes, and the
+    /// first sector size where a partition begins with an NTFS boot sector is used (falling back to 512 bytes).
+    /// Use [`NtfsPartitionTable::new_with_sector_size`] if the logical sector size of the disk is known.
+    ///
+    /// The first sector of every partition is additionally read to check for an NTFS boot sector.
+    pub fn new<T>(fs: &mut T) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        Self::read(fs, &SECTOR_SIZES)
+    }
+
+    /// Reads the partition table of the disk covered by the given reader, which has the given logical sector size
+    /// (in bytes).
+    ///
+    /// This works like [`NtfsPartitionTable::new`], but doesn't try other sector sizes.
+    pub fn new_with_sector_size<T>(fs: &mut T, sector_size: u32) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let actual = u16::try_from(sector_size).unwrap_or(u16::MAX);
+        if !(MIN_SECTOR_SIZE..=MAX_SECTOR_SIZE).contains(&actual) || !actual.is_power_of_two() {
+            return Err(NtfsError::UnsupportedSectorSize {
+                min: MIN_SECTOR_SIZE,
+                max: MAX_SECTOR_SIZE,
+                actual,
+            });
+        }
+
+        Self::read(fs, &[sector_size])
+    }
+
+    fn read<T>(fs: &mut T, sector_sizes: &[u32]) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let mut mbr = [0u8; MBR_SIZE];
+        fs.seek(SeekFrom::Start(0))?;
+        fs.read_exact(&mut mbr)?;
+
+        let mbr_result = validate_mbr_signature(&mbr, 0);
+        let is_gpt = mbr_partition_entries(&mbr)
+            .any(|entry| entry.partition_type == MBR_PARTITION_TYPE_GPT_PROTECTIVE);
+
+        match mbr_result {
+            Ok(()) if !is_gpt => Self::read_mbr(fs, &mbr, sector_sizes),
+            _ => match Self::read_gpt(fs, sector_sizes) {
+                Ok(mut table) => {
+                    table.detect_ntfs_boot_sectors(fs);
+                    Ok(table)
+                }
+                Err(error) => Err(mbr_result.err().unwrap_or(error)),
+            },
+        }
+    }
+
+    fn detect_ntfs_boot_sectors<T>(&mut self, fs: &mut T)
+    where
+        T: Read + Seek,
+    {
+        for partition in &mut self.partitions {
+            partition.ntfs_boot_sector = has_ntfs_boot_sector(fs, partition.offset);
+        }
+    }
+
+    fn read_gpt<T>(fs: &mut T, sector_sizes: &[u32]) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let mut first_error = None;
+
+        // Try the primary GPT header in the second sector first.
+        // The backup GPT header in the last sector needs the size of the disk.
+        let disk_size = fs.seek(SeekFrom::End(0))?;
+        let primary_positions = sector_sizes
+            .iter()
+            .map(|&sector_size| (sector_size as u64, sector_size));
+        let backup_positions = sector_sizes
+            .iter()
+            .map(|&sector_size| (disk_size.saturating_sub(sector_size as u64), sector_size));
+
+        for (position, sector_size) in primary_positions.chain(backup_positions) {
+            match Self::read_gpt_at(fs, position, sector_size) {
+                Ok(table) => return Ok(table),
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+
+        // This unwrap is safe, because `sector_sizes` is not empty and every failed attempt has set an error.
+        Err(first_error.unwrap())
+    }
+
+    fn read_gpt_at<T>(fs: &mut T, position: u64, sector_size: u32) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let mut header = vec![0u8; GPT_HEADER_SIZE];
+        fs.seek(SeekFrom::Start(position))?;
+        fs.read_exact(&mut header)?;
+
+        let signature = &header[..GPT_SIGNATURE.len()];
+        if signature != GPT_SIGNATURE {
+            return Err(NtfsError::InvalidGptSignature {
+                position: NtfsPosition::new(position),
+                expected: GPT_SIGNATURE,
+                // This unwrap is safe, because the signature has exactly 8 bytes.
+                actual: signature.try_into().unwrap(),
+            });
+        }
+
+        // The header may be larger than the fields known to us, but always fits into a sector.
+        let header_size = LittleEndian::read_u32(&header[12..]);
+        if header_size as usize > GPT_HEADER_SIZE && header_size <= sector_size {
+            header.resize(header_size as usize, 0);
+            fs.read_exact(&mut header[GPT_HEADER_SIZE..])?;
+        }
+
+        let header_checksum = LittleEndian::read_u32(&header[GPT_HEADER_CHECKSUM_OFFSET..]);
+        header[GPT_HEADER_CHECKSUM_OFFSET..GPT_HEADER_CHECKSUM_OFFSET + 4].fill(0);
+        let calculated_checksum = crc32(&header);
+        if header.len() != header_size as usize || calculated_checksum != header_checksum {
+            return Err(NtfsError::InvalidGptChecksum {
+                structure: "header",
+                position: NtfsPosition::new(position),
+                expected: calculated_checksum,
+                actual: header_checksum,
+            });
+        }
+
+        let entries_lba = LittleEndian::read_u64(&header[72..]);
+        let entry_count = LittleEndian::read_u32(&header[80..]);
+        let entry_size = LittleEndian::read_u32(&header[84..]);
+
+        let entries_size = entry_count as u64 * entry_size as u64;
+        let entries_position = entries_lba.checked_mul(sector_size as u64);
+        if entry_size < GPT_PARTITION_ENTRY_MIN_SIZE
+            || entry_size % 8 != 0
+            || entries_size > GPT_PARTITION_ENTRIES_MAX_SIZE
+            || entries_position.is_none()
+        {
+            return Err(NtfsError::InvalidGptPartitionEntries {
+                position: NtfsPosition::new(position),
+                count: entry_count,
+                size: entry_size,
+            });
+        }
+
+        // This unwrap is safe, because `entries_position` has just been checked.
+        let entries_position = entries_position.unwrap();
+        let mut entries = vec![0u8; entries_size as usize];
+        fs.seek(SeekFrom::Start(entries_position))?;
+        fs.read_exact(&mut entries)?;
+
+        let entries_checksum = LittleEndian::read_u32(&header[88..]);
+        let calculated_checksum = crc32(&entries);
+        if calculated_checksum != entries_checksum {
+            return Err(NtfsError::InvalidGptChecksum {
+                structure: "partition entry array",
+                position: NtfsPosition::new(entries_position),
+                expected: calculated_checksum,
+                actual: entries_checksum,
+            });
+        }
+
+        let mut partitions = Vec::new();
+
+        for (index, entry) in entries.chunks_exact(entry_size as usize).enumerate() {
+            let partition_type = NtfsGuid::from_bytes(&entry[..16]);
+            if partition_type == GPT_UNUSED_PARTITION_TYPE {
+                continue;
+            }
+
+            let first_lba = LittleEndian::read_u64(&entry[32..]);
+            let last_lba = LittleEndian::read_u64(&entry[40..]);
+            if last_lba < first_lba {
+                continue;
+            }
+
+            let name = &entry[56..56 + 2 * GPT_PARTITION_NAME_LENGTH];
+            let name_length = name
+                .chunks_exact(2)
+                .position(|character| character == [0, 0])
+                .unwrap_or(GPT_PARTITION_NAME_LENGTH);
+
+            partitions.push(NtfsPartition {
+                number: index as u32 + 1,
+                offset: first_lba.saturating_mul(sector_size as u64),
+                size: (last_lba - first_lba + 1).saturating_mul(sector_size as u64),
+                partition_type: NtfsPartitionType::Gpt(partition_type),
+                name: name[..2 * name_length].to_vec(),
+                ntfs_boot_sector: false,
+            });
+        }
+
+        Ok(Self {
+            scheme: NtfsPartitionScheme::Gpt,
+            sector_size,
+            partitions,
+        })
+    }
+
+    fn read_mbr<T>(fs: &mut T, mbr: &[u8; MBR_SIZE], sector_sizes: &[u32]) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let mut first_result = None;
+
+        for &sector_size in sector_sizes {
+            let result = Self::read_mbr_with_sector_size(fs, mbr, sector_size as u64);
+
+            if let Ok(mut table) = result {
+                table.detect_ntfs_boot_sectors(fs);
+                if table
+                    .partitions
+                    .iter()
+                    .any(NtfsPartition::has_ntfs_boot_sector)
+                {
+                    return Ok(table);
+                }
+
+                first_result.get_or_insert(Ok(table));
+            } else {
+                first_result.get_or_insert(result);
+            }
+        }
+
+        // This unwrap is safe, because `sector_sizes` is not empty.
+        first_result.unwrap()
+    }
+
+    fn read_mbr_with_sector_size<T>(
+        fs: &mut T,
+        mbr: &[u8; MBR_SIZE],
+        sector_size: u64,
+    ) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let mut partitions = Vec::new();
+        let mut extended_partitions = Vec::new();
+
+        // Primary partitions are numbered 1 to 4 by their slot in the MBR, logical partitions from 5 on.
+        for (index, entry) in mbr_partition_entries(mbr).enumerate() {
+            if entry.partition_type == 0 || entry.sector_count == 0 {
+                continue;
+            }
+
+            if MBR_PARTITION_TYPES_EXTENDED.contains(&entry.partition_type) {
+                extended_partitions.push(entry.start_lba as u64);
+                continue;
+            }
+
+            partitions.push(entry.to_partition(index as u32 + 1, 0, sector_size));
+        }
+
+        let mut number = 5;
+        let mut visited = BTreeSet::new();
+
+        for extended_partition_lba in extended_partitions {
+            let mut ebr_lba = extended_partition_lba;
+
+            // Each EBR describes one logical partition relative to itself and links to the next EBR
+            // relative to the start of the extended partition.
+            loop {
+                let position = ebr_lba.saturating_mul(sector_size);
+                if !visited.insert(ebr_lba) {
+                    return Err(NtfsError::ExtendedBootRecordLoop {
+                        position: NtfsPosition::new(position),
+                    });
+                }
+
+                let mut ebr = [0u8; MBR_SIZE];
+                fs.seek(SeekFrom::Start(position))?;
+                fs.read_exact(&mut ebr)?;
+                validate_mbr_signature(&ebr, position)?;
+
+                let mut entries = mbr_partition_entries(&ebr);
+
+                // This unwrap is safe, because every EBR has 4 partition entries.
+                let entry = entries.next().unwrap();
+                if entry.partition_type != 0 && entry.sector_count != 0 {
+                    partitions.push(entry.to_partition(number, ebr_lba, sector_size));
+                    number += 1;
+                }
+
+                // This unwrap is safe, because every EBR has 4 partition entries.
+                let link = entries.next().unwrap();
+                if !MBR_PARTITION_TYPES_EXTENDED.contains(&link.partition_type)
+                    || link.start_lba == 0
+                {
+                    break;
+                }
+
+                ebr_lba = extended_partition_lba + link.start_lba as u64;
+            }
+        }
+
+        Ok(Self {
+            scheme: NtfsPartitionScheme::Mbr,
+            sector_size: sector_size as u32,
+            partitions,
+        })
+    }
+
+    /// Returns `true` if the partition table contains no partitions.
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+
+    /// Returns an iterator over all partitions, in the order of their partition numbers.
+    pub fn iter(&self) -> core::slice::Iter<'_, NtfsPartition> {
+        self.partitions.iter()
+    }
+
+    /// Returns the number of partitions.
+    pub fn len(&self) -> usize {
+        self.partitions.len()
+    }
+
+    /// Returns an iterator over all partitions that contain an NTFS filesystem according to
+    /// [`NtfsPartition::is_ntfs`].
+    pub fn ntfs_partitions(&self) -> impl Iterator<Item = &NtfsPartition> {
+        self.partitions
+            .iter()
+            .filter(|partition| partition.is_ntfs())
+    }
+
+    /// Returns the partitioning scheme of the disk.
+    pub fn scheme(&self) -> NtfsPartitionScheme {
+        self.scheme
+    }
+
+    /// Returns the logical sector size of the disk, in bytes.
+    pub fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+}
+
+/// A single partition of an [`NtfsPartitionTable`].
+#[derive(Clone, Debug)]
+pub struct NtfsPartition {
+    number: u32,
+    offset: u64,
+    size: u64,
+    partition_type: NtfsPartitionType,
+    name: Vec<u8>,
+    ntfs_boot_sector: bool,
+}
+
+impl NtfsPartition {
+    /// Returns whether the first sector of the partition is a boot sector with the "NTFS" OEM ID
+    /// and the 0x55 0xAA signature.
+    pub fn has_ntfs_boot_sector(&self) -> bool {
+        self.ntfs_boot_sector
+    }
+
+    /// Returns whether the partition type is one that Windows uses for NTFS filesystems.
+    ///
+    /// These types are shared with other filesystems (like exFAT and FAT), so this is only a hint.
+    pub fn has_ntfs_partition_type(&self) -> bool {
+        match &self.partition_type {
+            NtfsPartitionType::Gpt(guid) => GPT_PARTITION_TYPES_NTFS.contains(guid),
+            NtfsPartitionType::Mbr(id) => MBR_PARTITION_TYPES_NTFS.contains(id),
+        }
+    }
+
+    /// Returns whether the partition contains an NTFS filesystem, i.e. has both an NTFS partition type and
+    /// an NTFS boot sector.
+    ///
+    /// Check [`NtfsPartition::has_ntfs_boot_sector`] alone to also find NTFS filesystems in mislabeled partitions.
+    pub fn is_ntfs(&self) -> bool {
+        self.has_ntfs_partition_type() && self.has_ntfs_boot_sector()
+    }
+
+    /// Returns the name of a GPT partition, or an empty string for an MBR partition.
+    pub fn name(&self) -> U16StrLe<'_> {
+        U16StrLe(&self.name)
+    }
+
+    /// Returns the partition number.
+    ///
+    /// GPT partitions are numbered by their entry in the partition table, starting at 1.
+    /// Primary MBR partitions are numbered 1 to 4 by their slot in the MBR, and logical partitions are numbered
+    /// from 5 on in the order of their Extended Boot Records.
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    /// Returns the byte offset of the partition from the beginning of the disk.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the type of the partition.
+    pub fn partition_type(&self) -> &NtfsPartitionType {
+        &self.partition_type
+    }
+
+    /// Returns an [`NtfsPartitionReader`] for this partition on top of the given reader of the entire disk.
+    ///
+    /// Pass a mutable reference as `fs` to keep using the disk reader afterwards.
+    pub fn reader<T>(&self, fs: T) -> NtfsPartitionReader<T>
+    where
+        T: Read + Seek,
+    {
+        NtfsPartitionReader::new(fs, self.offset, self.size)
+    }
+
+    /// Returns the size of the partition, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A reader for a window of an underlying reader, usually a single partition of a disk.
+///
+/// Position 0 of this reader corresponds to the given offset of the underlying reader, and reading stops
+/// at the end of the window.
+/// As [`NtfsPartitionReader`] implements [`Read`] and [`Seek`], it can be passed to [`Ntfs::new`] and all
+/// other functions of this crate.
+///
+/// [`Ntfs::new`]: crate::Ntfs::new
+#[derive(Clone, Debug)]
+pub struct NtfsPartitionReader<T> {
+    inner: T,
+    offset: u64,
+    size: u64,
+    stream_position: u64,
+}
+
+impl<T> NtfsPartitionReader<T>
+where
+    T: Read + Seek,
+{
+    /// Creates a new [`NtfsPartitionReader`] for `size` bytes starting at byte `offset` of the given reader.
+    pub fn new(inner: T, offset: u64, size: u64) -> Self {
+        Self {
+            inner,
+            offset,
+            size,
+            stream_position: 0,
+        }
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Consumes this reader and returns the underlying reader.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Read for NtfsPartitionReader<T>
+where
+    T: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.stream_position);
+        let bytes_to_read = u64::min(buf.len() as u64, remaining) as usize;
+        if bytes_to_read == 0 {
+            return Ok(0);
+        }
+
+        let position = self
+            .offset
+            .checked_add(self.stream_position)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "position exceeds the underlying reader",
+                )
+            })?;
+        self.inner.seek(SeekFrom::Start(position))?;
+        let bytes_read = self.inner.read(&mut buf[..bytes_to_read])?;
+
+        self.stream_position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<T> Seek for NtfsPartitionReader<T>
+where
+    T: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_contiguous(&mut self.stream_position, self.size, pos).map_err(io::Error::from)
+    }
+}
+
+/// A partition entry of an MBR or EBR.
+struct MbrPartitionEntry {
+    partition_type: u8,
+    start_lba: u32,
+    sector_count: u32,
+}
+
+impl MbrPartitionEntry {
+    /// Converts this entry into an [`NtfsPartition`], with `start_lba` being relative to `base_lba`.
+    fn to_partition(&self, number: u32, base_lba: u64, sector_size: u64) -> NtfsPartition {
+        NtfsPartition {
+            number,
+            offset: (base_lba + self.start_lba as u64) * sector_size,
+            size: self.sector_count as u64 * sector_size,
+            partition_type: NtfsPartitionType::Mbr(self.partition_type),
+            name: Vec::new(),
+            ntfs_boot_sector: false,
+        }
+    }
+}
+
+/// Calculates the CRC32 checksum (IEEE 802.3 polynomial) used throughout GPT.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+/// Reads the first sector of a partition and checks whether it is an NTFS boot sector.
+fn has_ntfs_boot_sector<T>(fs: &mut T, offset: u64) -> bool
+where
+    T: Read + Seek,
+{
+    let mut boot_sector = [0u8; MBR_SIZE];
+    let result = fs
+        .seek(SeekFrom::Start(offset))
+        .and_then(|_| fs.read_exact(&mut boot_sector));
+
+    result.is_ok() && &boot_sector[3..11] == b"NTFS    " && boot_sector[510..] == [0x55, 0xaa]
+}
+
+fn mbr_partition_entries(mbr: &[u8; MBR_SIZE]) -> impl Iterator<Item = MbrPartitionEntry> + '_ {
+    mbr[MBR_PARTITION_ENTRIES_OFFSET..MBR_SIZE - 2]
+        .chunks_exact(MBR_PARTITION_ENTRY_SIZE)
+        .map(|entry| MbrPartitionEntry {
+            partition_type: entry[4],
+            start_lba: LittleEndian::read_u32(&entry[8..]),
+            sector_count: LittleEndian::read_u32(&entry[12..]),
+        })
+}
+
+fn validate_mbr_signature(mbr: &[u8; MBR_SIZE], position: u64) -> Result<()> {
+    let expected_signature = &[0x55, 0xaa];
+    let actual_signature = [mbr[MBR_SIZE - 2], mbr[MBR_SIZE - 1]];
+
+    if &actual_signature != expected_signature {
+        return Err(NtfsError::InvalidTwoByteSignature {
+            position: NtfsPosition::new(position + MBR_SIZE as u64 - 2),
+            expected: expected_signature,
+            actual: actual_signature,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::Ntfs;
+    use binrw::io::Cursor;
+
+    /// Size of the testfs1 image, in sectors of 512 bytes.
+    const TESTFS1_SECTORS: u32 = 4096;
+
+    fn write_mbr_entry(
+        sector: &mut [u8],
+        index: usize,
+        partition_type: u8,
+        start: u32,
+        count: u32,
+    ) {
+        let entry = &mut sector[MBR_PARTITION_ENTRIES_OFFSET + index * MBR_PARTITION_ENTRY_SIZE..];
+        entry[4] = partition_type;
+        LittleEndian::write_u32(&mut entry[8..], start);
+        LittleEndian::write_u32(&mut entry[12..], count);
+        sector[510..512].copy_from_slice(&[0x55, 0xaa]);
+    }
+
+    fn write_sector(image: &mut [u8], lba: u64, data: &[u8]) {
+        let start = lba as usize * 512;
+        image[start..start + data.len()].copy_from_slice(data);
+    }
+
+    fn assert_testfs1(partition: &NtfsPartition, disk: &mut Cursor<Vec<u8>>) {
+        let mut reader = partition.reader(disk);
+        let ntfs = Ntfs::new(&mut reader).unwrap();
+        assert_eq!(ntfs.size(), partition.size() - 512);
+
+        // "1000-bytes-file" can be read through the partition reader.
+        let file = ntfs.file(&mut reader, 66).unwrap();
+        let data_item = file.data(&mut reader, "").unwrap().unwrap();
+        assert_eq!(data_item.to_attribute().unwrap().value_length(), 1000);
+    }
+
+    #[test]
+    fn test_mbr() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+
+        // A primary NTFS partition at LBA 63, followed by an extended partition with two logical partitions.
+        let extended_lba = 63 + TESTFS1_SECTORS;
+        let mut image = vec![0u8; (extended_lba as usize + 8) * 512];
+
+        let mut mbr = [0u8; 512];
+        write_mbr_entry(&mut mbr, 0, 0x07, 63, TESTFS1_SECTORS);
+        write_mbr_entry(&mut mbr, 1, 0x0f, extended_lba, 8);
+        write_sector(&mut image, 0, &mbr);
+        write_sector(&mut image, 63, &testfs1);
+
+        let mut ebr = [0u8; 512];
+        write_mbr_entry(&mut ebr, 0, 0x0c, 1, 2);
+        write_mbr_entry(&mut ebr, 1, 0x05, 4, 4);
+        write_sector(&mut image, extended_lba as u64, &ebr);
+
+        let mut ebr = [0u8; 512];
+        write_mbr_entry(&mut ebr, 0, 0x07, 1, 3);
+        write_sector(&mut image, extended_lba as u64 + 4, &ebr);
+
+        let mut disk = Cursor::new(image);
+        let table = NtfsPartitionTable::new(&mut disk).unwrap();
+        assert_eq!(table.scheme(), NtfsPartitionScheme::Mbr);
+        assert_eq!(table.sector_size(), 512);
+
+        let partitions = table.iter().collect::<Vec<_>>();
+        assert_eq!(partitions.len(), 3);
+
+        assert_eq!(partitions[0].number(), 1);
+        assert_eq!(partitions[0].offset(), 63 * 512);
+        assert_eq!(partitions[0].size(), TESTFS1_SECTORS as u64 * 512);
+        assert!(partitions[0].is_ntfs());
+        assert_eq!(partitions[0].name(), "");
+
+        assert_eq!(partitions[1].number(), 5);
+        assert_eq!(partitions[1].offset(), (extended_lba as u64 + 1) * 512);
+        assert_eq!(
+            *partitions[1].partition_type(),
+            NtfsPartitionType::Mbr(0x0c)
+        );
+        assert!(!partitions[1].has_ntfs_partition_type());
+
+        // The second logical partition has an NTFS partition type, but no NTFS filesystem.
+        assert_eq!(partitions[2].number(), 6);
+        assert_eq!(partitions[2].offset(), (extended_lba as u64 + 5) * 512);
+        assert!(partitions[2].has_ntfs_partition_type());
+        assert!(!partitions[2].has_ntfs_boot_sector());
+
+        let ntfs_partitions = table.ntfs_partitions().collect::<Vec<_>>();
+        assert_eq!(ntfs_partitions.len(), 1);
+        assert_testfs1(ntfs_partitions[0], &mut disk);
+
+        // An EBR linking back to itself is detected.
+        let mut image = disk.into_inner();
+        let mut ebr = [0u8; 512];
+        write_mbr_entry(&mut ebr, 0, 0x07, 1, 3);
+        write_mbr_entry(&mut ebr, 1, 0x05, 4, 4);
+        write_sector(&mut image, extended_lba as u64 + 4, &ebr);
+        assert!(matches!(
+            NtfsPartitionTable::new(&mut Cursor::new(image)),
+            Err(NtfsError::ExtendedBootRecordLoop { .. })
+        ));
+    }
+
+    /// Writes a GPT header with 128 partition entries at `entries_lba` and the given entry array checksum.
+    fn write_gpt_header(image: &mut [u8], lba: u64, entries_lba: u64, entries_checksum: u32) {
+        let mut header = [0u8; 512];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        LittleEndian::write_u32(&mut header[12..], GPT_HEADER_SIZE as u32);
+        LittleEndian::write_u64(&mut header[72..], entries_lba);
+        LittleEndian::write_u32(&mut header[80..], 128);
+        LittleEndian::write_u32(&mut header[84..], 128);
+        LittleEndian::write_u32(&mut header[88..], entries_checksum);
+        let header_checksum = crc32(&header[..GPT_HEADER_SIZE]);
+        LittleEndian::write_u32(&mut header[GPT_HEADER_CHECKSUM_OFFSET..], header_checksum);
+        write_sector(image, lba, &header);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_gpt() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+
+        // Protective MBR, GPT header at LBA 1, 128 partition entries at LBA 2 to 33,
+        // the NTFS partition at LBA 34, followed by a backup of the partition entries
+        // and the backup GPT header in the last sector.
+        let backup_entries_lba = 34 + TESTFS1_SECTORS as u64;
+        let last_lba = backup_entries_lba + 32;
+        let mut image = vec![0u8; (last_lba as usize + 1) * 512];
+
+        let mut mbr = [0u8; 512];
+        write_mbr_entry(
+            &mut mbr,
+            0,
+            MBR_PARTITION_TYPE_GPT_PROTECTIVE,
+            1,
+            last_lba as u32,
+        );
+        write_sector(&mut image, 0, &mbr);
+
+        let mut entries = vec![0u8; 128 * 128];
+        entries[..16].copy_from_slice(&[
+            0xa2, 0xa0, 0xd0, 0xeb, 0xe5, 0xb9, 0x33, 0x44, 0x87, 0xc0, 0x68, 0xb6, 0xb7, 0x26,
+            0x99, 0xc7,
+        ]);
+        LittleEndian::write_u64(&mut entries[32..], 34);
+        LittleEndian::write_u64(&mut entries[40..], 34 + TESTFS1_SECTORS as u64 - 1);
+        for (i, character) in "Basic data".encode_utf16().enumerate() {
+            LittleEndian::write_u16(&mut entries[56 + 2 * i..], character);
+        }
+        let entries_checksum = crc32(&entries);
+        write_sector(&mut image, 2, &entries);
+        write_sector(&mut image, backup_entries_lba, &entries);
+        write_sector(&mut image, 34, &testfs1);
+
+        write_gpt_header(&mut image, 1, 2, entries_checksum);
+        write_gpt_header(&mut image, last_lba, backup_entries_lba, entries_checksum);
+
+        let mut disk = Cursor::new(image);
+        let table = NtfsPartitionTable::new(&mut disk).unwrap();
+        assert_eq!(table.scheme(), NtfsPartitionScheme::Gpt);
+        assert_eq!(table.sector_size(), 512);
+        assert_eq!(table.len(), 1);
+
+        let partition = table.iter().next().unwrap();
+        assert_eq!(partition.number(), 1);
+        assert_eq!(partition.offset(), 34 * 512);
+        assert_eq!(partition.name(), "Basic data");
+        assert_eq!(
+            partition.partition_type(),
+            &NtfsPartitionType::Gpt(GPT_PARTITION_TYPES_NTFS[0].clone())
+        );
+        assert!(partition.is_ntfs());
+        assert_testfs1(partition, &mut disk);
+
+        // With damaged primary partition entries, the backup GPT header and its partition entries are used.
+        let mut image = disk.into_inner();
+        image[2 * 512 + 128 + 16] ^= 0xff;
+        let table = NtfsPartitionTable::new(&mut Cursor::new(image.clone())).unwrap();
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.iter().next().unwrap().name(), "Basic data");
+
+        // The same happens with a damaged primary GPT header whose signature is still intact.
+        write_sector(&mut image, 2, &entries);
+        image[512 + 72] ^= 0xff;
+        let table = NtfsPartitionTable::new(&mut Cursor::new(image.clone())).unwrap();
+        assert_eq!(table.len(), 1);
+
+        // Without a valid backup GPT header, the error of the primary one is returned.
+        image[last_lba as usize * 512 + 72] ^= 0xff;
+        assert!(matches!(
+            NtfsPartitionTable::new(&mut Cursor::new(image.clone())),
+            Err(NtfsError::InvalidGptChecksum { structure: "header", position, .. }) if position == NtfsPosition::new(512)
+        ));
+
+        // Without any GPT header, the error of the primary one is returned.
+        write_sector(&mut image, 1, &[0u8; 512]);
+        write_sector(&mut image, last_lba, &[0u8; 512]);
+        assert!(matches!(
+            NtfsPartitionTable::new(&mut Cursor::new(image)),
+            Err(NtfsError::InvalidGptSignature { position, .. }) if position == NtfsPosition::new(512)
+        ));
+    }
+
+    #[test]
+    fn test_mbr_sector_size() {
+        let testfs1 = crate::helpers::tests::testfs1().into_inner();
+
+        // A disk with 4096-byte sectors and an NTFS partition at LBA 1.
+        let mut image = vec![0u8; 4096 + testfs1.len()];
+        let mut mbr = [0u8; 512];
+        write_mbr_entry(&mut mbr, 0, 0x07, 1, TESTFS1_SECTORS / 8);
+        write_sector(&mut image, 0, &mbr);
+        image[4096..].copy_from_slice(&testfs1);
+        let mut disk = Cursor::new(image);
+
+        // The sector size is detected through the NTFS boot sector.
+        let table = NtfsPartitionTable::new(&mut disk).unwrap();
+        assert_eq!(table.sector_size(), 4096);
+        let partition = table.ntfs_partitions().next().unwrap();
+        assert_eq!(partition.offset(), 4096);
+        assert_testfs1(partition, &mut disk);
+
+        // An explicitly given sector size is always used.
+        let table = NtfsPartitionTable::new_with_sector_size(&mut disk, 512).unwrap();
+        assert_eq!(table.sector_size(), 512);
+        let partition = table.iter().next().unwrap();
+        assert_eq!(partition.offset(), 512);
+        assert!(!partition.has_ntfs_boot_sector());
+
+        assert!(matches!(
+            NtfsPartitionTable::new_with_sector_size(&mut disk, 1000),
+            Err(NtfsError::UnsupportedSectorSize { actual: 1000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_partition_reader() {
+        let data: Vec<u8> = (0..64u8).collect();
+        let mut reader = NtfsPartitionReader::new(Cursor::new(data), 16, 32);
+
+        let mut buf = [0u8; 8];
+        reader.seek(SeekFrom::End(-4)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(buf[..4], [44, 45, 46, 47]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [16, 17, 18, 19, 20, 21, 22, 23]);
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use binrw::io;
+use binrw::io::{Read, Seek, SeekFrom};
+
+use crate::attribute_value::seek_contiguous;
+
+/// Trait for a filesystem source that can be read at arbitrary positions through a shared reference.
+///
+/// Unlike [`Read`] and [`Seek`], reading via [`NtfsReadAt::read_at`] does not change any state of the source.
+/// Hence, a single source can be read by multiple threads at the same time without any locking.
+/// Wrap it in an [`NtfsReadAtReader`] to pass it to any function of this crate that expects a filesystem reader.
+///
+/// This trait is implemented for byte slices and vectors, for [`std::fs::File`] (using positional reads
+/// of the operating system), as well as for shared references and [`Arc`]s of any implementor.
+pub trait NtfsReadAt {
+    /// Reads bytes at the given `offset` into `buf`, returning the number of bytes read.
+    ///
+    /// Like [`Read::read`], this may read fewer bytes than requested, and returns `0` at the end of the source.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Returns the total size of the source, in bytes.
+    fn size(&self) -> io::Result<u64>;
+}
+
+impl NtfsReadAt for [u8] {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let start = usize::min(offset.try_into().unwrap_or(usize::MAX), self.len());
+        let bytes_to_read = usize::min(buf.len(), self.len() - start);
+
+        buf[..bytes_to_read].copy_from_slice(&self[start..start + bytes_to_read]);
+        Ok(bytes_to_read)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl NtfsReadAt for Vec<u8> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.as_slice().read_at(offset, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.as_slice().size()
+    }
+}
+
+impl<R> NtfsReadAt for &R
+where
+    R: NtfsReadAt + ?Sized,
+{
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<R> NtfsReadAt for Arc<R>
+where
+    R: NtfsReadAt + ?Sized,
+{
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+#[cfg(all(feature = "std", any(unix, windows)))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "std", any(unix, windows)))))]
+impl NtfsReadAt for std::fs::File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::FileExt::read_at(self, buf, offset)
+        }
+
+        #[cfg(windows)]
+        {
+            // `seek_read` also moves the file cursor, which is irrelevant for positional reads.
+            std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+        }
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        let length = self.metadata()?.len();
+        if length > 0 {
+            return Ok(length);
+        }
+
+        // Block devices report a size of zero in their metadata, and only seeking to the end tells their size.
+        // Restore the file cursor afterwards, as it is shared with all `Read` and `Seek` calls on this file.
+        let mut file = self;
+        let position = file.stream_position()?;
+        let size = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(position))?;
+        Ok(size)
+    }
+}
+
+/// A reader with its own seek position over a shared [`NtfsReadAt`] source.
+///
+/// This implements [`Read`] and [`Seek`], so it can be passed to every function of this crate.
+/// Creating an [`NtfsReadAtReader`] is cheap: Use one per thread over the same source
+/// (e.g. an `&File` or `Arc<File>`) to read from a single [`Ntfs`] object in parallel.
+///
+/// [`Ntfs`]: crate::Ntfs
+#[derive(Clone, Debug)]
+pub struct NtfsReadAtReader<R> {
+    inner: R,
+    stream_position: u64,
+}
+
+impl<R> NtfsReadAtReader<R>
+where
+    R: NtfsReadAt,
+{
+    /// Creates a new [`NtfsReadAtReader`] over the given source, starting at position 0.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            stream_position: 0,
+        }
+    }
+
+    /// Returns a reference to the underlying source.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Consumes this reader and returns the underlying source.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Read for NtfsReadAtReader<R>
+where
+    R: NtfsReadAt,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read_at(self.stream_position, buf)?;
+        self.stream_position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R> Seek for NtfsReadAtReader<R>
+where
+    R: NtfsReadAt,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        // The size of the source is only needed when seeking relative to its end.
+        let length = match pos {
+            SeekFrom::End(_) => self.inner.size()?,
+            _ => 0,
+        };
+
+        seek_contiguous(&mut self.stream_position, length, pos).map_err(io::Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::thread;
+
+    use super::*;
+    use crate::ntfs::Ntfs;
+
+    fn read_file<T>(ntfs: &Ntfs, fs: &mut T, path: &str) -> Vec<u8>
+    where
+        T: Read + Seek,
+    {
+        let file = ntfs.file_by_path(fs, path).unwrap();
+        let data_item = file.data(fs, "").unwrap().unwrap();
+        let data_attribute = data_item.to_attribute().unwrap();
+        let mut data_value = data_attribute.value(fs).unwrap().attach(fs);
+
+        let mut buf = Vec::new();
+        data_value.read_to_end(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_read_at_slice() {
+        let data = b"0123456789".as_slice();
+        let mut buf = [0u8; 4];
+
+        assert_eq!(data.read_at(3, &mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"3456");
+        assert_eq!(data.read_at(8, &mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"89");
+        assert_eq!(data.read_at(10, &mut buf).unwrap(), 0);
+        assert_eq!(data.read_at(u64::MAX, &mut buf).unwrap(), 0);
+
+        let mut reader = NtfsReadAtReader::new(data);
+        reader.seek(SeekFrom::End(-3)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"789");
+        assert!(reader.seek(SeekFrom::Current(-20)).is_err());
+    }
+
+    #[test]
+    fn test_read_at_threads() {
+        let testfs1 = Arc::new(crate::helpers::tests::testfs1().into_inner());
+
+        let mut ntfs = Ntfs::new(&mut NtfsReadAtReader::new(&*testfs1)).unwrap();
+        ntfs.read_upcase_table(&mut NtfsReadAtReader::new(&*testfs1))
+            .unwrap();
+        let ntfs = Arc::new(ntfs);
+
+        // Every thread reads through its own reader over the same image, without any locking.
+        let handles = ["1000-bytes-file", "file-with-12345", "sparse-file"]
+            .iter()
+            .map(|&path| {
+                let ntfs = Arc::clone(&ntfs);
+                let mut fs = NtfsReadAtReader::new(Arc::clone(&testfs1));
+                thread::spawn(move || read_file(&ntfs, &mut fs, path))
+            })
+            .collect::<Vec<_>>();
+
+        let data = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(data[0], b"12345".repeat(200));
+        assert_eq!(data[1], b"12345");
+        assert_eq!(data[2].len(), 500005);
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn test_read_at_file() {
+        let mut file = File::open("testdata/testfs1").unwrap();
+
+        // Getting the size doesn't move the file cursor.
+        file.seek(SeekFrom::Start(42)).unwrap();
+        assert_eq!(file.size().unwrap(), 2 * 1024 * 1024);
+        assert_eq!(file.stream_position().unwrap(), 42);
+
+        let mut fs = NtfsReadAtReader::new(&file);
+        let mut ntfs = Ntfs::new(&mut fs).unwrap();
+        ntfs.read_upcase_table(&mut fs).unwrap();
+
+        // Reading through a second reader is not affected by the position of the first one.
+        let mut other_fs = NtfsReadAtReader::new(&file);
+        other_fs.seek(SeekFrom::Start(12345)).unwrap();
+        assert_eq!(
+            read_file(&ntfs, &mut fs, "1000-bytes-file"),
+            b"12345".repeat(200)
+        );
+        assert_eq!(read_file(&ntfs, &mut other_fs, "file-with-12345"), b"12345");
+    }
+}
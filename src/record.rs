@@ -35,22 +35,14 @@ impl Record {
         &self.data
     }
 
+    pub(crate) fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
     pub(crate) fn fixup(&mut self) -> Result<()> {
         let update_sequence_number = self.update_sequence_number()?;
-        let array_count = self.update_sequence_array_count()?;
-
         let mut array_position = self.update_sequence_array_start() as usize;
-        let array_end =
-            self.update_sequence_offset() as usize + self.update_sequence_size() as usize;
-        let sectors_end = array_count as usize * NTFS_BLOCK_SIZE;
-
-        if array_end > self.data.len() || sectors_end > self.data.len() {
-            return Err(NtfsError::UpdateSequenceArrayExceedsRecordSize {
-                position: self.position,
-                array_count,
-                record_size: self.data.len(),
-            });
-        }
+        let array_end = self.update_sequence_array_end()?;
 
         // The Update Sequence Number (USN) is written to the last 2 bytes of each sector.
         let mut sector_position = NTFS_BLOCK_SIZE - mem::size_of::<u16>();
@@ -86,6 +78,34 @@ impl Record {
         Ok(())
     }
 
+    /// Reverses [`Record::fixup`] before the record is written back to the filesystem.
+    ///
+    /// The last 2 bytes of each sector are saved in the Update Sequence Array and replaced
+    /// by the Update Sequence Number (USN).
+    pub(crate) fn protect(&mut self) -> Result<()> {
+        let update_sequence_number = self.update_sequence_number()?;
+        let mut array_position = self.update_sequence_array_start() as usize;
+        let array_end = self.update_sequence_array_end()?;
+        let mut sector_position = NTFS_BLOCK_SIZE - mem::size_of::<u16>();
+
+        while array_position < array_end {
+            let array_position_end = array_position + mem::size_of::<u16>();
+            let sector_position_end = sector_position + mem::size_of::<u16>();
+
+            let sector_bytes: [u8; 2] = self.data[sector_position..sector_position_end]
+                .try_into()
+                .unwrap();
+            self.data[array_position..array_position_end].copy_from_slice(&sector_bytes);
+            self.data[sector_position..sector_position_end]
+                .copy_from_slice(&update_sequence_number);
+
+            array_position += mem::size_of::<u16>();
+            sector_position += NTFS_BLOCK_SIZE;
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn into_data(self) -> Vec<u8> {
         self.data
     }
@@ -119,6 +139,25 @@ impl Record {
             })
     }
 
+    /// Returns the end of the Update Sequence Array after validating that the array and all sectors
+    /// it protects fit into the record.
+    fn update_sequence_array_end(&self) -> Result<usize> {
+        let array_count = self.update_sequence_array_count()?;
+        let array_end =
+            self.update_sequence_offset() as usize + self.update_sequence_size() as usize;
+        let sectors_end = array_count as usize * NTFS_BLOCK_SIZE;
+
+        if array_end > self.data.len() || sectors_end > self.data.len() {
+            return Err(NtfsError::UpdateSequenceArrayExceedsRecordSize {
+                position: self.position,
+                array_count,
+                record_size: self.data.len(),
+            });
+        }
+
+        Ok(array_end)
+    }
+
     fn update_sequence_array_start(&self) -> u16 {
         // The Update Sequence Number (USN) comes first and the array begins right after that.
         self.update_sequence_offset() + mem::size_of::<u16>() as u16
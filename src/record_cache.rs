@@ -0,0 +1,266 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::error::Result;
+use crate::record::Record;
+
+/// Statistics of the record cache of an [`Ntfs`] object, as returned by [`Ntfs::record_cache_stats`].
+///
+/// [`Ntfs`]: crate::Ntfs
+/// [`Ntfs::record_cache_stats`]: crate::Ntfs::record_cache_stats
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NtfsRecordCacheStats {
+    entries: usize,
+    evictions: u64,
+    hits: u64,
+    memory_limit: usize,
+    memory_usage: usize,
+    misses: u64,
+}
+
+impl NtfsRecordCacheStats {
+    /// Returns the number of records currently held by the cache.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// Returns the number of records that have been evicted to stay within the memory limit.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Returns the number of record reads that have been served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns the configured memory limit of the cache, in bytes.
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// Returns the total size of all records currently held by the cache, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    /// Returns the number of record reads that had to go to the filesystem.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    record: Record,
+    /// Key of this entry in [`RecordCacheState::lru`].
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct RecordCacheState {
+    /// Cached records by their filesystem position.
+    entries: HashMap<u64, CacheEntry>,
+    /// Filesystem positions of the cached records, ordered from the least to the most recently used.
+    lru: BTreeMap<u64, u64>,
+    next_use: u64,
+    stats: NtfsRecordCacheStats,
+}
+
+impl RecordCacheState {
+    fn evict_to(&mut self, memory_limit: usize) {
+        while self.stats.memory_usage > memory_limit {
+            // This unwrap is safe, because a non-zero memory usage implies a cached record.
+            let (&last_used, &position) = self.lru.iter().next().unwrap();
+            self.lru.remove(&last_used);
+            let entry = self.entries.remove(&position).unwrap();
+
+            self.stats.memory_usage -= entry.record.data().len();
+            self.stats.evictions += 1;
+        }
+
+        self.stats.entries = self.entries.len();
+    }
+
+    fn touch(&mut self, position: u64) -> Option<Record> {
+        let next_use = self.next_use;
+        let entry = self.entries.get_mut(&position)?;
+
+        self.lru.remove(&entry.last_used);
+        self.lru.insert(next_use, position);
+        entry.last_used = next_use;
+        self.next_use += 1;
+
+        Some(entry.record.clone())
+    }
+}
+
+/// Least-recently-used cache of fixed-up and validated File Records and Index Records, keyed by their
+/// filesystem position.
+///
+/// The cache is disabled until a memory limit is set.
+#[derive(Debug, Default)]
+pub(crate) struct RecordCache {
+    state: Mutex<RecordCacheState>,
+}
+
+impl RecordCache {
+    pub(crate) fn clear(&self) {
+        let mut state = self.lock();
+        state.entries.clear();
+        state.lru.clear();
+        state.stats.memory_usage = 0;
+        state.stats.entries = 0;
+    }
+
+    /// Returns the record at `position` from the cache, or calls `read_record` to read it from the filesystem
+    /// and adds it to the cache.
+    ///
+    /// Errors are returned as-is and never cached.
+    pub(crate) fn get_or_read<F>(&self, position: u64, read_record: F) -> Result<Record>
+    where
+        F: FnOnce() -> Result<Record>,
+    {
+        {
+            let mut state = self.lock();
+            if state.stats.memory_limit == 0 {
+                drop(state);
+                return read_record();
+            }
+
+            if let Some(record) = state.touch(position) {
+                state.stats.hits += 1;
+                return Ok(record);
+            }
+
+            state.stats.misses += 1;
+        }
+
+        // Don't hold the lock while reading, so that other threads can use the cache in the meantime.
+        let record = read_record()?;
+
+        let mut state = self.lock();
+        let memory_limit = state.stats.memory_limit;
+        if record.data().len() <= memory_limit && !state.entries.contains_key(&position) {
+            let last_used = state.next_use;
+            state.next_use += 1;
+            state.lru.insert(last_used, position);
+            state.stats.memory_usage += record.data().len();
+            state.entries.insert(
+                position,
+                CacheEntry {
+                    record: record.clone(),
+                    last_used,
+                },
+            );
+            state.evict_to(memory_limit);
+        }
+
+        Ok(record)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RecordCacheState> {
+        // The cache state is never left inconsistent by a panic, so a poisoned lock can be taken over.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Removes the record at `position` from the cache, because it has been modified on the filesystem.
+    pub(crate) fn remove(&self, position: u64) {
+        let mut state = self.lock();
+
+        if let Some(entry) = state.entries.remove(&position) {
+            state.lru.remove(&entry.last_used);
+            state.stats.memory_usage -= entry.record.data().len();
+            state.stats.entries = state.entries.len();
+        }
+    }
+
+    pub(crate) fn set_memory_limit(&self, memory_limit: usize) {
+        let mut state = self.lock();
+        state.stats.memory_limit = memory_limit;
+        state.evict_to(memory_limit);
+    }
+
+    pub(crate) fn stats(&self) -> NtfsRecordCacheStats {
+        self.lock().stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ntfs::Ntfs;
+
+    #[test]
+    fn test_record_cache() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let mut ntfs = Ntfs::new(&mut testfs1).unwrap();
+        ntfs.read_upcase_table(&mut testfs1).unwrap();
+        let file_record_size = ntfs.file_record_size() as usize;
+
+        // The cache is disabled by default.
+        ntfs.file(&mut testfs1, 66).unwrap();
+        assert_eq!(ntfs.record_cache_stats().misses(), 0);
+
+        // Leave room for exactly 2 File Records.
+        ntfs.set_record_cache_limit(2 * file_record_size);
+        let uncached = ntfs.file(&mut testfs1, 66).unwrap();
+        let cached = ntfs.file(&mut testfs1, 66).unwrap();
+        assert_eq!(cached.record_data(), uncached.record_data());
+
+        let stats = ntfs.record_cache_stats();
+        assert_eq!(stats.hits(), 1);
+        assert_eq!(stats.misses(), 1);
+        assert_eq!(stats.entries(), 1);
+        assert_eq!(stats.memory_usage(), file_record_size);
+
+        // File Record 64 becomes the least recently used one and is evicted when reading File Record 67.
+        ntfs.file(&mut testfs1, 64).unwrap();
+        ntfs.file(&mut testfs1, 66).unwrap();
+        ntfs.file(&mut testfs1, 67).unwrap();
+        ntfs.file(&mut testfs1, 66).unwrap();
+
+        let stats = ntfs.record_cache_stats();
+        assert_eq!(stats.hits(), 3);
+        assert_eq!(stats.misses(), 3);
+        assert_eq!(stats.evictions(), 1);
+        assert_eq!(stats.entries(), 2);
+
+        ntfs.file(&mut testfs1, 64).unwrap();
+        assert_eq!(ntfs.record_cache_stats().misses(), 4);
+
+        // Index Records are cached as well.
+        ntfs.set_record_cache_limit(1024 * 1024);
+        ntfs.clear_record_cache();
+        assert_eq!(ntfs.record_cache_stats().entries(), 0);
+
+        let mut entry_counts = Vec::new();
+        for _ in 0..2 {
+            let dir = ntfs.file_by_path(&mut testfs1, "many_subdirs").unwrap();
+            let dir_index = dir.directory_index(&mut testfs1).unwrap();
+            let mut entries = dir_index.entries();
+            let mut count = 0;
+
+            while let Some(entry) = entries.next(&mut testfs1) {
+                entry.unwrap();
+                count += 1;
+            }
+
+            entry_counts.push(count);
+        }
+
+        // The second listing is entirely served from the cache.
+        let stats = ntfs.record_cache_stats();
+        assert_eq!(entry_counts[0], entry_counts[1]);
+        assert!(stats.memory_usage() > stats.entries() * file_record_size);
+        assert_eq!(stats.misses() - 4, stats.entries() as u64);
+        assert_eq!(stats.hits() - 3, stats.entries() as u64);
+
+        // A limit of 0 disables the cache again.
+        ntfs.set_record_cache_limit(0);
+        assert_eq!(ntfs.record_cache_stats().entries(), 0);
+        assert_eq!(ntfs.record_cache_stats().memory_usage(), 0);
+    }
+}
@@ -0,0 +1,655 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Recovery of deleted files from File Records that are no longer in use.
+
+use core::iter::FusedIterator;
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use binrw::io;
+use binrw::io::{Read, Seek, SeekFrom};
+
+use crate::attribute::{NtfsAttribute, NtfsAttributeType};
+use crate::attribute_value::{NtfsAttributeValue, NtfsDataRun};
+use crate::cluster_bitmap::NtfsClusterBitmap;
+use crate::error::Result;
+use crate::file::{NtfsFile, NtfsFileFlags};
+use crate::file_reference::NtfsFileReference;
+use crate::mft::NtfsMftRecords;
+use crate::ntfs::Ntfs;
+use crate::structured_values::{NtfsFileName, NtfsFileNamespace};
+use crate::traits::NtfsReadSeek;
+use crate::types::NtfsPosition;
+
+/// Iterator over
+///   all deleted files whose File Records have not been reused yet,
+///   returning an [`NtfsDeletedFile`] for each entry.
+///
+/// This iterator is returned from the [`Ntfs::deleted_files`] function.
+/// It scans the entire MFT via [`NtfsMftRecords`] and returns every File Record that does not have the
+/// [`NtfsFileFlags::IN_USE`] flag set, but still has a $FILE_NAME attribute.
+/// File Records that cannot be read are skipped.
+///
+/// See [`NtfsDeletedFilesAttached`] for an iterator that implements [`Iterator`] and [`FusedIterator`].
+#[derive(Clone, Debug)]
+pub struct NtfsDeletedFiles<'n> {
+    mft_records: NtfsMftRecords<'n>,
+}
+
+impl<'n> NtfsDeletedFiles<'n> {
+    pub(crate) fn new<T>(ntfs: &'n Ntfs, fs: &mut T) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let mft_records = ntfs.mft_records(fs)?.lenient(true).skip_unused(false);
+        Ok(Self { mft_records })
+    }
+
+    /// Returns a variant of this iterator that implements [`Iterator`] and [`FusedIterator`]
+    /// by mutably borrowing the filesystem reader.
+    pub fn attach<'a, T>(self, fs: &'a mut T) -> NtfsDeletedFilesAttached<'n, 'a, T>
+    where
+        T: Read + Seek,
+    {
+        NtfsDeletedFilesAttached::new(fs, self)
+    }
+
+    /// See [`Iterator::next`].
+    pub fn next<T>(&mut self, fs: &mut T) -> Option<Result<NtfsDeletedFile<'n>>>
+    where
+        T: Read + Seek,
+    {
+        loop {
+            let record = iter_try!(self.mft_records.next(fs)?);
+            let file = match record.into_file() {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+
+            if file.flags().contains(NtfsFileFlags::IN_USE) {
+                continue;
+            }
+
+            // Never used File Records don't have a $FILE_NAME attribute.
+            if let Some(file_name) = Self::last_file_name(fs, &file) {
+                let file_name = iter_try!(file_name);
+                return Some(Ok(NtfsDeletedFile { file, file_name }));
+            }
+        }
+    }
+
+    /// Returns the $FILE_NAME attribute of `file`, preferring a long name over a DOS (8.3) name.
+    ///
+    /// This only looks at the top-level attributes, because the File Records referenced by an Attribute List
+    /// of a deleted file may have been reused already.
+    fn last_file_name<T>(fs: &mut T, file: &NtfsFile<'n>) -> Option<Result<NtfsFileName>>
+    where
+        T: Read + Seek,
+    {
+        let mut dos_file_name = None;
+
+        for attribute in file.attributes_raw() {
+            let attribute = iter_try!(attribute);
+            if iter_try!(attribute.ty()) != NtfsAttributeType::FileName {
+                continue;
+            }
+
+            let file_name = iter_try!(attribute.structured_value::<_, NtfsFileName>(fs));
+            if file_name.namespace() != NtfsFileNamespace::Dos {
+                return Some(Ok(file_name));
+            }
+
+            dos_file_name.get_or_insert(file_name);
+        }
+
+        dos_file_name.map(Ok)
+    }
+}
+
+/// Iterator over
+///   all deleted files whose File Records have not been reused yet,
+///   returning an [`NtfsDeletedFile`] for each entry,
+///   implementing [`Iterator`] and [`FusedIterator`].
+///
+/// This iterator is returned from the [`NtfsDeletedFiles::attach`] function.
+/// Conceptually the same as [`NtfsDeletedFiles`], but mutably borrows the filesystem
+/// to implement aforementioned traits.
+#[derive(Debug)]
+pub struct NtfsDeletedFilesAttached<'n, 'a, T: Read + Seek> {
+    fs: &'a mut T,
+    deleted_files: NtfsDeletedFiles<'n>,
+}
+
+impl<'n, 'a, T> NtfsDeletedFilesAttached<'n, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn new(fs: &'a mut T, deleted_files: NtfsDeletedFiles<'n>) -> Self {
+        Self { fs, deleted_files }
+    }
+
+    /// Consumes this iterator and returns the inner [`NtfsDeletedFiles`].
+    pub fn detach(self) -> NtfsDeletedFiles<'n> {
+        self.deleted_files
+    }
+}
+
+impl<'n, 'a, T> Iterator for NtfsDeletedFilesAttached<'n, 'a, T>
+where
+    T: Read + Seek,
+{
+    type Item = Result<NtfsDeletedFile<'n>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.deleted_files.next(self.fs)
+    }
+}
+
+impl<'n, 'a, T> FusedIterator for NtfsDeletedFilesAttached<'n, 'a, T> where T: Read + Seek {}
+
+/// Item returned by the [`NtfsDeletedFiles`] iterator.
+///
+/// NTFS only clears the [`NtfsFileFlags::IN_USE`] flag of a File Record when deleting a file and frees its
+/// clusters in the $Bitmap file.
+/// Until the File Record is reused, its attributes still describe the last known state of the file.
+/// The clusters of its data may have been reallocated to other files in the meantime though.
+#[derive(Clone, Debug)]
+pub struct NtfsDeletedFile<'n> {
+    file: NtfsFile<'n>,
+    file_name: NtfsFileName,
+}
+
+impl<'n> NtfsDeletedFile<'n> {
+    /// Returns the data runs of a non-resident attribute of this file, along with the information whether
+    /// their clusters have been reallocated since the file was deleted.
+    ///
+    /// `attribute` is usually the $DATA attribute returned by [`NtfsFile::data`] on [`NtfsDeletedFile::file`].
+    /// `bitmap` is the allocation state of all clusters returned by [`Ntfs::cluster_bitmap`].
+    /// Read it once and pass it for all deleted files, because it covers the entire filesystem.
+    ///
+    /// An empty list is returned for a resident attribute, whose value is entirely stored in the File Record
+    /// and can always be recovered.
+    ///
+    /// [`Ntfs::cluster_bitmap`]: crate::Ntfs::cluster_bitmap
+    pub fn data_runs<T>(
+        &self,
+        fs: &mut T,
+        attribute: &NtfsAttribute<'n, '_>,
+        bitmap: &NtfsClusterBitmap,
+    ) -> Result<Vec<NtfsRecoverableDataRun>>
+    where
+        T: Read + Seek,
+    {
+        let mut data_runs = Vec::new();
+
+        match attribute.value(fs)? {
+            NtfsAttributeValue::Resident(_) => return Ok(Vec::new()),
+            NtfsAttributeValue::NonResident(value) => {
+                for data_run in value.data_runs() {
+                    data_runs.push(data_run?);
+                }
+            }
+            NtfsAttributeValue::AttributeListNonResident(value) => {
+                value.for_each_data_run(fs, |data_run| data_runs.push(data_run.clone()))?;
+            }
+        }
+
+        // Check the clusters of each Data Run against the $Bitmap file.
+        let cluster_size = self.file.ntfs().cluster_size() as u64;
+        let mut offset = 0;
+        let mut recoverable_data_runs = Vec::with_capacity(data_runs.len());
+
+        for data_run in data_runs {
+            let reallocated_clusters = match data_run.start_position() {
+                Some(position) => {
+                    let first_lcn = position / cluster_size;
+                    let last_lcn = first_lcn + data_run.allocated_size() / cluster_size;
+                    bitmap
+                        .allocated_ranges_within(first_lcn..last_lcn)
+                        .map(|lcns| lcns.start.value() - first_lcn..lcns.end.value() - first_lcn)
+                        .collect()
+                }
+                None => Vec::new(),
+            };
+
+            let allocated_size = data_run.allocated_size();
+            recoverable_data_runs.push(NtfsRecoverableDataRun {
+                data_run,
+                offset,
+                reallocated_clusters,
+            });
+            offset += allocated_size;
+        }
+
+        Ok(recoverable_data_runs)
+    }
+
+    /// Returns the [`NtfsFile`] of the deleted file.
+    pub fn file(&self) -> &NtfsFile<'n> {
+        &self.file
+    }
+
+    /// Returns the last known [`NtfsFileName`] of the deleted file.
+    ///
+    /// If the file has multiple names, a long name is preferred over a DOS (8.3) name.
+    pub fn file_name(&self) -> &NtfsFileName {
+        &self.file_name
+    }
+
+    /// Consumes this item and returns the [`NtfsFile`] of the deleted file.
+    pub fn into_file(self) -> NtfsFile<'n> {
+        self.file
+    }
+
+    /// Returns the parent directory of the deleted file if it still exists.
+    ///
+    /// This returns `None` if the File Record of the parent directory has been deleted or reused in the meantime.
+    pub fn parent_directory<T>(&self, fs: &mut T) -> Result<Option<NtfsFile<'n>>>
+    where
+        T: Read + Seek,
+    {
+        let parent_reference = self.parent_directory_reference();
+        let parent = parent_reference.to_file(self.file.ntfs(), fs)?;
+
+        if parent.flags().contains(NtfsFileFlags::IN_USE)
+            && parent.is_directory()
+            && parent.sequence_number() == parent_reference.sequence_number()
+        {
+            Ok(Some(parent))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the [`NtfsFileReference`] of the last known parent directory of the deleted file.
+    pub fn parent_directory_reference(&self) -> NtfsFileReference {
+        self.file_name.parent_directory_reference()
+    }
+
+    /// Returns an [`NtfsRecoveredData`] reader over the value of an attribute of this file.
+    ///
+    /// `attribute` and `bitmap` are the same as for [`NtfsDeletedFile::data_runs`].
+    /// All bytes stored in clusters that have been reallocated since the file was deleted are returned as zeros.
+    /// For compressed values, this affects entire compression units.
+    pub fn recovered_data<'f, T>(
+        &self,
+        fs: &mut T,
+        attribute: &NtfsAttribute<'n, 'f>,
+        bitmap: &NtfsClusterBitmap,
+    ) -> Result<NtfsRecoveredData<'n, 'f>>
+    where
+        T: Read + Seek,
+    {
+        let value = attribute.value(fs)?;
+        let mut unrecoverable_ranges = Vec::new();
+
+        if !attribute.is_resident() {
+            let cluster_size = self.file.ntfs().cluster_size() as u64;
+            let unit_size = attribute
+                .non_resident_value_compression_unit_size()?
+                .map_or(1, |unit_size| unit_size as u64);
+
+            for data_run in self.data_runs(fs, attribute, bitmap)? {
+                for clusters in &data_run.reallocated_clusters {
+                    let start = data_run.offset + clusters.start * cluster_size;
+                    let end = data_run.offset + clusters.end * cluster_size;
+
+                    // A reallocated cluster corrupts the entire compression unit it belongs to.
+                    let start = start / unit_size * unit_size;
+                    let end = (end + unit_size - 1) / unit_size * unit_size;
+
+                    // Clusters in the slack space after the value data don't affect it.
+                    if start >= value.len() {
+                        continue;
+                    }
+
+                    unrecoverable_ranges.push(start..u64::min(end, value.len()));
+                }
+            }
+        }
+
+        Ok(NtfsRecoveredData {
+            value,
+            unrecoverable_ranges,
+        })
+    }
+}
+
+/// A Data Run of a deleted file, returned by [`NtfsDeletedFile::data_runs`].
+#[derive(Clone, Debug)]
+pub struct NtfsRecoverableDataRun {
+    data_run: NtfsDataRun,
+    offset: u64,
+    reallocated_clusters: Vec<Range<u64>>,
+}
+
+impl NtfsRecoverableDataRun {
+    /// Returns the allocated size of the Data Run, in bytes.
+    pub fn allocated_size(&self) -> u64 {
+        self.data_run.allocated_size()
+    }
+
+    /// Returns the absolute position of the Data Run within the filesystem, in bytes.
+    /// This is `None` for a "sparse" Data Run.
+    pub fn data_position(&self) -> NtfsPosition {
+        self.data_run.data_position()
+    }
+
+    /// Returns `true` if none of the clusters of this Data Run has been reallocated since the file was deleted.
+    ///
+    /// Sparse Data Runs are always recoverable.
+    pub fn is_recoverable(&self) -> bool {
+        self.reallocated_clusters.is_empty()
+    }
+
+    /// Returns the offset of this Data Run within the attribute value, in bytes.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the number of clusters of this Data Run that are marked as allocated in the $Bitmap file,
+    /// i.e. that have been reallocated to another file since the file was deleted.
+    pub fn reallocated_cluster_count(&self) -> u64 {
+        self.reallocated_clusters
+            .iter()
+            .map(|clusters| clusters.end - clusters.start)
+            .sum()
+    }
+}
+
+/// Reader for the recoverable data of a deleted file, returned by [`NtfsDeletedFile::recovered_data`].
+///
+/// This reads the attribute value just like [`NtfsAttributeValue`], but returns zeros for all bytes that are
+/// stored in reallocated clusters.
+#[derive(Clone, Debug)]
+pub struct NtfsRecoveredData<'n, 'f> {
+    value: NtfsAttributeValue<'n, 'f>,
+    unrecoverable_ranges: Vec<Range<u64>>,
+}
+
+impl<'n, 'f> NtfsRecoveredData<'n, 'f> {
+    /// Returns a variant of this reader that implements [`Read`] and [`Seek`]
+    /// by mutably borrowing the filesystem reader.
+    pub fn attach<'a, T>(self, fs: &'a mut T) -> NtfsRecoveredDataAttached<'n, 'f, 'a, T>
+    where
+        T: Read + Seek,
+    {
+        NtfsRecoveredDataAttached::new(fs, self)
+    }
+
+    /// Returns `true` if the attribute value contains no data.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if all data of the attribute value can be recovered.
+    pub fn is_fully_recoverable(&self) -> bool {
+        self.unrecoverable_ranges.is_empty()
+    }
+
+    /// Returns the total length of the attribute value data, in bytes.
+    pub fn len(&self) -> u64 {
+        self.value.len()
+    }
+
+    /// Returns the byte ranges of the attribute value that cannot be recovered and are read as zeros.
+    pub fn unrecoverable_ranges(&self) -> &[Range<u64>] {
+        &self.unrecoverable_ranges
+    }
+}
+
+impl<'n, 'f> NtfsReadSeek for NtfsRecoveredData<'n, 'f> {
+    fn read<T>(&mut self, fs: &mut T, buf: &mut [u8]) -> Result<usize>
+    where
+        T: Read + Seek,
+    {
+        let start = self.value.stream_position();
+        let bytes_read = self.value.read(fs, buf)?;
+        let end = start + bytes_read as u64;
+
+        for range in &self.unrecoverable_ranges {
+            let zero_start = u64::max(range.start, start);
+            let zero_end = u64::min(range.end, end);
+
+            if zero_start < zero_end {
+                buf[(zero_start - start) as usize..(zero_end - start) as usize].fill(0);
+            }
+        }
+
+        Ok(bytes_read)
+    }
+
+    fn seek<T>(&mut self, fs: &mut T, pos: SeekFrom) -> Result<u64>
+    where
+        T: Read + Seek,
+    {
+        self.value.seek(fs, pos)
+    }
+
+    fn stream_position(&self) -> u64 {
+        self.value.stream_position()
+    }
+}
+
+/// A variant of [`NtfsRecoveredData`] that implements [`Read`] and [`Seek`]
+/// by mutably borrowing the filesystem reader.
+#[derive(Debug)]
+pub struct NtfsRecoveredDataAttached<'n, 'f, 'a, T: Read + Seek> {
+    fs: &'a mut T,
+    data: NtfsRecoveredData<'n, 'f>,
+}
+
+impl<'n, 'f, 'a, T> NtfsRecoveredDataAttached<'n, 'f, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn new(fs: &'a mut T, data: NtfsRecoveredData<'n, 'f>) -> Self {
+        Self { fs, data }
+    }
+
+    /// Consumes this reader and returns the inner [`NtfsRecoveredData`].
+    pub fn detach(self) -> NtfsRecoveredData<'n, 'f> {
+        self.data
+    }
+
+    /// Returns `true` if the attribute value contains no data.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the total length of the attribute value data, in bytes.
+    pub fn len(&self) -> u64 {
+        self.data.len()
+    }
+}
+
+impl<'n, 'f, 'a, T> Read for NtfsRecoveredDataAttached<'n, 'f, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(self.fs, buf).map_err(io::Error::from)
+    }
+}
+
+impl<'n, 'f, 'a, T> Seek for NtfsRecoveredDataAttached<'n, 'f, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(self.fs, pos).map_err(io::Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::KnownNtfsFileRecordNumber;
+    use alloc::vec;
+    use binrw::io::Cursor;
+    use byteorder::{ByteOrder, LittleEndian};
+
+    #[test]
+    fn test_deleted_files() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // No file has been deleted on this filesystem.
+        assert_eq!(
+            ntfs.deleted_files(&mut testfs1)
+                .unwrap()
+                .attach(&mut testfs1)
+                .count(),
+            0
+        );
+
+        // Read the original data of "1000-bytes-file", which occupies 2 clusters.
+        let file = ntfs.file(&mut testfs1, 66).unwrap();
+        let data_item = file.data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_item.to_attribute().unwrap();
+        let mut data_value = data_attribute.value(&mut testfs1).unwrap();
+        let data_position = data_value.data_position().value().unwrap().get();
+        let mut original_data = vec![0u8; 1000];
+        data_value
+            .read_exact(&mut testfs1, &mut original_data)
+            .unwrap();
+
+        // Mark its File Record as not in use by clearing the IN_USE flag.
+        let file_position = file.position().value().unwrap().get() as usize;
+        testfs1.get_mut()[file_position + 22] &= !(NtfsFileFlags::IN_USE.bits() as u8);
+
+        let deleted_files = ntfs
+            .deleted_files(&mut testfs1)
+            .unwrap()
+            .attach(&mut testfs1)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(deleted_files.len(), 1);
+
+        let deleted_file = &deleted_files[0];
+        assert_eq!(deleted_file.file().file_record_number(), 66);
+        assert_eq!(deleted_file.file_name().name(), "1000-bytes-file");
+        assert_ne!(deleted_file.file_name().namespace(), NtfsFileNamespace::Dos);
+
+        let parent = deleted_file
+            .parent_directory(&mut testfs1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            parent.file_record_number(),
+            KnownNtfsFileRecordNumber::RootDirectory as u64
+        );
+
+        // Its clusters are still allocated in $Bitmap, so they are considered reallocated.
+        let data_item = deleted_file.file().data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_item.to_attribute().unwrap();
+        let bitmap = ntfs.cluster_bitmap(&mut testfs1).unwrap();
+        let data_runs = deleted_file
+            .data_runs(&mut testfs1, &data_attribute, &bitmap)
+            .unwrap();
+        assert_eq!(data_runs.len(), 1);
+        assert!(!data_runs[0].is_recoverable());
+        assert_eq!(data_runs[0].reallocated_cluster_count(), 2);
+
+        let mut recovered_data = deleted_file
+            .recovered_data(&mut testfs1, &data_attribute, &bitmap)
+            .unwrap();
+        assert!(!recovered_data.is_fully_recoverable());
+        let mut data = vec![0xffu8; 1000];
+        recovered_data.read_exact(&mut testfs1, &mut data).unwrap();
+        assert!(data.iter().all(|byte| *byte == 0));
+
+        // Free the second cluster in $Bitmap, which makes the second half of the data recoverable.
+        let cluster_size = ntfs.cluster_size() as u64;
+        let second_lcn = data_position / cluster_size + 1;
+        free_cluster(&ntfs, &mut testfs1, second_lcn);
+
+        let bitmap = ntfs.cluster_bitmap(&mut testfs1).unwrap();
+        let data_runs = deleted_file
+            .data_runs(&mut testfs1, &data_attribute, &bitmap)
+            .unwrap();
+        assert_eq!(data_runs[0].reallocated_cluster_count(), 1);
+
+        let mut recovered_data = deleted_file
+            .recovered_data(&mut testfs1, &data_attribute, &bitmap)
+            .unwrap();
+        assert_eq!(recovered_data.unrecoverable_ranges().len(), 1);
+        assert_eq!(recovered_data.unrecoverable_ranges()[0], 0..cluster_size);
+        recovered_data.read_exact(&mut testfs1, &mut data).unwrap();
+        let cluster_size = cluster_size as usize;
+        assert!(data[..cluster_size].iter().all(|byte| *byte == 0));
+        assert_eq!(&data[cluster_size..], &original_data[cluster_size..]);
+    }
+
+    /// Clears the bit of the given cluster in $Bitmap.
+    fn free_cluster(ntfs: &Ntfs, testfs1: &mut Cursor<Vec<u8>>, lcn: u64) {
+        let bitmap_file = ntfs
+            .file(testfs1, KnownNtfsFileRecordNumber::Bitmap as u64)
+            .unwrap();
+        let bitmap_item = bitmap_file.data(testfs1, "").unwrap().unwrap();
+        let bitmap_attribute = bitmap_item.to_attribute().unwrap();
+        let mut bitmap_value = bitmap_attribute.value(testfs1).unwrap();
+        bitmap_value
+            .seek(testfs1, SeekFrom::Start(lcn / 8))
+            .unwrap();
+        let bitmap_position = bitmap_value.data_position().value().unwrap().get() as usize;
+        testfs1.get_mut()[bitmap_position] &= !(1 << (lcn % 8));
+    }
+
+    #[test]
+    fn test_recovered_data_slack() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // Shrink "1000-bytes-file" to 500 bytes, so that its second cluster only contains slack space,
+        // and mark its File Record as not in use.
+        let file = ntfs.file(&mut testfs1, 66).unwrap();
+        let data_item = file.data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_item.to_attribute().unwrap();
+        let data_position = data_attribute
+            .value(&mut testfs1)
+            .unwrap()
+            .data_position()
+            .value()
+            .unwrap()
+            .get();
+        let attribute_position = data_attribute.position().value().unwrap().get() as usize;
+        LittleEndian::write_u64(&mut testfs1.get_mut()[attribute_position + 0x30..], 500);
+        LittleEndian::write_u64(&mut testfs1.get_mut()[attribute_position + 0x38..], 500);
+
+        let file_position = file.position().value().unwrap().get() as usize;
+        testfs1.get_mut()[file_position + 22] &= !(NtfsFileFlags::IN_USE.bits() as u8);
+
+        // Only the second cluster in the slack space has been reallocated.
+        let first_lcn = data_position / ntfs.cluster_size() as u64;
+        free_cluster(&ntfs, &mut testfs1, first_lcn);
+
+        let deleted_file = ntfs
+            .deleted_files(&mut testfs1)
+            .unwrap()
+            .next(&mut testfs1)
+            .unwrap()
+            .unwrap();
+        let data_item = deleted_file.file().data(&mut testfs1, "").unwrap().unwrap();
+        let data_attribute = data_item.to_attribute().unwrap();
+        let bitmap = ntfs.cluster_bitmap(&mut testfs1).unwrap();
+
+        let data_runs = deleted_file
+            .data_runs(&mut testfs1, &data_attribute, &bitmap)
+            .unwrap();
+        assert_eq!(data_runs[0].reallocated_cluster_count(), 1);
+
+        // That doesn't affect the data.
+        let mut recovered_data = deleted_file
+            .recovered_data(&mut testfs1, &data_attribute, &bitmap)
+            .unwrap();
+        assert_eq!(recovered_data.len(), 500);
+        assert!(recovered_data.is_fully_recoverable());
+        assert!(recovered_data.unrecoverable_ranges().is_empty());
+
+        let mut data = vec![0u8; 500];
+        recovered_data.read_exact(&mut testfs1, &mut data).unwrap();
+        assert_eq!(data, b"12345".repeat(100));
+    }
+}
@@ -57,6 +57,7 @@ struct AttributeListEntryHeader {
 /// An $ATTRIBUTE_LIST attribute can hence be resident or non-resident.
 ///
 /// Reference: <https://flatcap.github.io/linux-ntfs/ntfs/attributes/attribute_list.html>
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug)]
 pub enum NtfsAttributeList<'n, 'f> {
     /// A resident $ATTRIBUTE_LIST attribute.
@@ -229,7 +230,7 @@ impl NtfsAttributeListEntry {
     }
 
     /// Gets the attribute name and returns it wrapped in a [`U16StrLe`].
-    pub fn name(&self) -> U16StrLe {
+    pub fn name(&self) -> U16StrLe<'_> {
         U16StrLe(&self.name)
     }
 
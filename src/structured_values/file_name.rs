@@ -207,7 +207,7 @@ impl NtfsFileName {
     }
 
     /// Gets the file name and returns it wrapped in a [`U16StrLe`].
-    pub fn name(&self) -> U16StrLe {
+    pub fn name(&self) -> U16StrLe<'_> {
         U16StrLe(&self.name)
     }
 
@@ -65,7 +65,7 @@ impl<'n, 'f> NtfsIndexAllocation<'n, 'f> {
         }
 
         // Get the record.
-        let record = NtfsIndexRecord::new(fs, value, index_record_size)?;
+        let record = NtfsIndexRecord::new(self.ntfs, fs, value, index_record_size)?;
 
         // Validate that the VCN in the record is the requested one.
         if record.vcn() != vcn {
@@ -148,6 +148,7 @@ impl<'n, 'f> NtfsIndexRecords<'n, 'f> {
 
         // Get the current record.
         let record = iter_try!(NtfsIndexRecord::new(
+            self.index_allocation.ntfs,
             fs,
             self.index_allocation.value.clone(),
             self.index_record_size
@@ -8,6 +8,8 @@ mod file_name;
 mod index_allocation;
 mod index_root;
 mod object_id;
+mod reparse_point;
+mod security_descriptor;
 mod standard_information;
 mod volume_information;
 mod volume_name;
@@ -19,6 +21,8 @@ pub use file_name::*;
 pub use index_allocation::*;
 pub use index_root::*;
 pub use object_id::*;
+pub use reparse_point::*;
+pub use security_descriptor::*;
 pub use standard_information::*;
 pub use volume_information::*;
 pub use volume_name::*;
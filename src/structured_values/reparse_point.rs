@@ -0,0 +1,591 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::mem;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek};
+use binrw::BinReaderExt;
+use byteorder::{ByteOrder, LittleEndian};
+use nt_string::u16strle::U16StrLe;
+
+use crate::attribute::NtfsAttributeType;
+use crate::attribute_value::NtfsAttributeValue;
+use crate::error::{NtfsError, Result};
+use crate::guid::{NtfsGuid, GUID_SIZE};
+use crate::structured_values::NtfsStructuredValue;
+use crate::types::NtfsPosition;
+
+/// Size of the reparse point header (tag, data length, and reserved field).
+const REPARSE_POINT_HEADER_SIZE: usize = 8;
+
+/// Maximum size of the reparse point data, as enforced by Windows (`MAXIMUM_REPARSE_DATA_BUFFER_SIZE`).
+const REPARSE_POINT_MAX_DATA_SIZE: usize = 16 * 1024;
+
+/// Set in the tag of every reparse point defined by Microsoft.
+const REPARSE_TAG_MICROSOFT_FLAG: u32 = 0x8000_0000;
+/// Set in the tag of reparse points that redirect to another file or directory.
+const REPARSE_TAG_NAME_SURROGATE_FLAG: u32 = 0x2000_0000;
+/// Set in the tag of reparse points that may be set on non-empty directories.
+const REPARSE_TAG_DIRECTORY_FLAG: u32 = 0x1000_0000;
+
+const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+const IO_REPARSE_TAG_DEDUP: u32 = 0x8000_0013;
+const IO_REPARSE_TAG_SYMLINK: u32 = 0xA000_000C;
+const IO_REPARSE_TAG_WOF: u32 = 0x8000_0017;
+const IO_REPARSE_TAG_APPEXECLINK: u32 = 0x8000_001B;
+const IO_REPARSE_TAG_AF_UNIX: u32 = 0x8000_0023;
+
+/// All cloud file tags (`IO_REPARSE_TAG_CLOUD` up to `IO_REPARSE_TAG_CLOUD_F`) match this value
+/// after applying [`IO_REPARSE_TAG_CLOUD_MASK`].
+const IO_REPARSE_TAG_CLOUD: u32 = 0x9000_001A;
+const IO_REPARSE_TAG_CLOUD_MASK: u32 = 0xFFFF_0FFF;
+
+/// Flag of a symbolic link denoting that the substitute name is a relative path.
+const SYMLINK_FLAG_RELATIVE: u32 = 0x0000_0001;
+
+/// Structure of a $REPARSE_POINT attribute.
+///
+/// A reparse point tells the filesystem driver (or a filter driver) to process the file in a special way.
+/// Each reparse point has a tag denoting its type (and therefore the owner of its data), followed by the
+/// tag-specific data.
+/// Reparse points with a non-Microsoft tag additionally carry a GUID identifying their owner.
+///
+/// Use [`NtfsReparsePoint::data`] to get the decoded data of the most common reparse point types.
+/// This never follows links or junctions, it only tells where they point to.
+///
+/// A $REPARSE_POINT attribute can be resident or non-resident.
+///
+/// Reference: <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-fscc/c8e77b37-3909-4fe6-a4ea-2b9d423b1ee4>
+#[derive(Clone, Debug)]
+pub struct NtfsReparsePoint {
+    tag: u32,
+    guid: Option<NtfsGuid>,
+    data: Vec<u8>,
+    data_position: NtfsPosition,
+}
+
+impl NtfsReparsePoint {
+    fn new<T>(r: &mut T, position: NtfsPosition, value_length: u64) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        if value_length < REPARSE_POINT_HEADER_SIZE as u64 {
+            return Err(NtfsError::InvalidStructuredValueSize {
+                position,
+                ty: NtfsAttributeType::ReparsePoint,
+                expected: REPARSE_POINT_HEADER_SIZE as u64,
+                actual: value_length,
+            });
+        }
+
+        let tag = r.read_le::<u32>()?;
+        let data_length = r.read_le::<u16>()? as usize;
+        let _reserved = r.read_le::<u16>()?;
+
+        let guid_size = if tag & REPARSE_TAG_MICROSOFT_FLAG == 0 {
+            GUID_SIZE
+        } else {
+            0
+        };
+
+        let expected = (REPARSE_POINT_HEADER_SIZE + guid_size + data_length) as u64;
+        if value_length < expected || data_length > REPARSE_POINT_MAX_DATA_SIZE {
+            return Err(NtfsError::InvalidStructuredValueSize {
+                position,
+                ty: NtfsAttributeType::ReparsePoint,
+                expected,
+                actual: value_length,
+            });
+        }
+
+        let guid = if guid_size > 0 {
+            Some(r.read_le::<NtfsGuid>()?)
+        } else {
+            None
+        };
+
+        let mut data = vec![0u8; data_length];
+        r.read_exact(&mut data)?;
+
+        let data_position = position + (REPARSE_POINT_HEADER_SIZE + guid_size) as u64;
+
+        Ok(Self {
+            tag,
+            guid,
+            data,
+            data_position,
+        })
+    }
+
+    /// Returns the decoded reparse point data.
+    ///
+    /// Symbolic links, mount points (junctions), WOF, Data Deduplication, AppExecLink, cloud files,
+    /// and AF_UNIX sockets are recognized by their tag.
+    /// The data of any other reparse point is returned as [`NtfsReparsePointData::Unknown`].
+    pub fn data(&self) -> Result<NtfsReparsePointData<'_>> {
+        let data = match self.tag {
+            IO_REPARSE_TAG_SYMLINK => NtfsReparsePointData::SymbolicLink(
+                NtfsSymbolicLinkReparseData::new(&self.data, self.data_position)?,
+            ),
+            IO_REPARSE_TAG_MOUNT_POINT => NtfsReparsePointData::MountPoint(
+                NtfsMountPointReparseData::new(&self.data, self.data_position)?,
+            ),
+            IO_REPARSE_TAG_WOF => {
+                NtfsReparsePointData::Wof(NtfsWofReparseData::new(&self.data, self.data_position)?)
+            }
+            IO_REPARSE_TAG_DEDUP => NtfsReparsePointData::Dedup(&self.data),
+            IO_REPARSE_TAG_APPEXECLINK => NtfsReparsePointData::AppExecLink(
+                NtfsAppExecLinkReparseData::new(&self.data, self.data_position)?,
+            ),
+            IO_REPARSE_TAG_AF_UNIX => NtfsReparsePointData::AfUnix,
+            tag if tag & IO_REPARSE_TAG_CLOUD_MASK == IO_REPARSE_TAG_CLOUD => {
+                NtfsReparsePointData::Cloud(&self.data)
+            }
+            _ => NtfsReparsePointData::Unknown(&self.data),
+        };
+
+        Ok(data)
+    }
+
+    /// Returns the GUID of the reparse point owner.
+    ///
+    /// Only reparse points with a non-Microsoft tag have a GUID.
+    pub fn guid(&self) -> Option<&NtfsGuid> {
+        self.guid.as_ref()
+    }
+
+    /// Returns `true` if this reparse point may be set on a non-empty directory.
+    pub fn is_directory(&self) -> bool {
+        self.tag & REPARSE_TAG_DIRECTORY_FLAG != 0
+    }
+
+    /// Returns `true` if the tag of this reparse point has been defined by Microsoft.
+    pub fn is_microsoft(&self) -> bool {
+        self.tag & REPARSE_TAG_MICROSOFT_FLAG != 0
+    }
+
+    /// Returns `true` if this reparse point redirects to another file or directory
+    /// (like symbolic links and mount points do).
+    pub fn is_name_surrogate(&self) -> bool {
+        self.tag & REPARSE_TAG_NAME_SURROGATE_FLAG != 0
+    }
+
+    /// Returns the raw reparse point data (without the header and GUID).
+    pub fn raw_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the reparse tag, which denotes the type of this reparse point.
+    pub fn tag(&self) -> u32 {
+        self.tag
+    }
+}
+
+impl<'n, 'f> NtfsStructuredValue<'n, 'f> for NtfsReparsePoint {
+    const TY: NtfsAttributeType = NtfsAttributeType::ReparsePoint;
+
+    fn from_attribute_value<T>(fs: &mut T, value: NtfsAttributeValue<'n, 'f>) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let position = value.data_position();
+        let value_length = value.len();
+
+        let mut value_attached = value.attach(fs);
+        Self::new(&mut value_attached, position, value_length)
+    }
+}
+
+/// Decoded data of an [`NtfsReparsePoint`], returned by [`NtfsReparsePoint::data`].
+#[derive(Clone, Debug)]
+pub enum NtfsReparsePointData<'r> {
+    /// A symbolic link (`IO_REPARSE_TAG_SYMLINK`).
+    SymbolicLink(NtfsSymbolicLinkReparseData<'r>),
+    /// A mount point or directory junction (`IO_REPARSE_TAG_MOUNT_POINT`).
+    MountPoint(NtfsMountPointReparseData<'r>),
+    /// A file compressed by the Windows Overlay Filter (`IO_REPARSE_TAG_WOF`).
+    Wof(NtfsWofReparseData<'r>),
+    /// A file managed by Data Deduplication (`IO_REPARSE_TAG_DEDUP`).
+    ///
+    /// The format of this data is undocumented, hence it is returned as raw bytes.
+    Dedup(&'r [u8]),
+    /// An execution alias of a packaged app (`IO_REPARSE_TAG_APPEXECLINK`).
+    AppExecLink(NtfsAppExecLinkReparseData<'r>),
+    /// A placeholder of the Cloud Files API, e.g. used by OneDrive (`IO_REPARSE_TAG_CLOUD` up to `IO_REPARSE_TAG_CLOUD_F`).
+    ///
+    /// This data is owned by the Cloud Files filter driver and undocumented, hence it is returned as raw bytes.
+    Cloud(&'r [u8]),
+    /// A Unix domain socket (`IO_REPARSE_TAG_AF_UNIX`), which has no data.
+    AfUnix,
+    /// Raw data of a reparse point with any other tag.
+    Unknown(&'r [u8]),
+}
+
+/// Returns the bytes of the UTF-16 name at `offset` and with `length` bytes within `buffer`,
+/// which is located at `position` within the filesystem.
+fn reparse_name(buffer: &[u8], position: NtfsPosition, offset: u16, length: u16) -> Result<&[u8]> {
+    let range = offset as usize..offset as usize + length as usize;
+    buffer
+        .get(range.clone())
+        .ok_or(NtfsError::InvalidReparsePointNameRange {
+            position,
+            range,
+            size: buffer.len(),
+        })
+}
+
+/// Checks that `data` has at least `expected` bytes.
+fn validate_data_size(data: &[u8], position: NtfsPosition, expected: usize) -> Result<()> {
+    if data.len() < expected {
+        return Err(NtfsError::InvalidStructuredValueSize {
+            position,
+            ty: NtfsAttributeType::ReparsePoint,
+            expected: expected as u64,
+            actual: data.len() as u64,
+        });
+    }
+
+    Ok(())
+}
+
+/// Size of the fixed fields of a symbolic link reparse point.
+const SYMLINK_HEADER_SIZE: usize = 12;
+
+/// Decoded data of a symbolic link reparse point.
+#[derive(Clone, Debug)]
+pub struct NtfsSymbolicLinkReparseData<'r> {
+    substitute_name: &'r [u8],
+    print_name: &'r [u8],
+    flags: u32,
+}
+
+impl<'r> NtfsSymbolicLinkReparseData<'r> {
+    fn new(data: &'r [u8], position: NtfsPosition) -> Result<Self> {
+        validate_data_size(data, position, SYMLINK_HEADER_SIZE)?;
+
+        let flags = LittleEndian::read_u32(&data[8..]);
+        let buffer = &data[SYMLINK_HEADER_SIZE..];
+        let buffer_position = position + SYMLINK_HEADER_SIZE as u64;
+        let (substitute_name, print_name) = read_names(data, buffer, buffer_position)?;
+
+        Ok(Self {
+            substitute_name,
+            print_name,
+            flags,
+        })
+    }
+
+    /// Returns `true` if the substitute name is a path relative to the directory containing the link.
+    pub fn is_relative(&self) -> bool {
+        self.flags & SYMLINK_FLAG_RELATIVE != 0
+    }
+
+    /// Returns the raw flags of this symbolic link.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Returns the user-friendly name of the link target (e.g. `C:\Target`).
+    pub fn print_name(&self) -> U16StrLe<'r> {
+        U16StrLe(self.print_name)
+    }
+
+    /// Returns the name of the link target that is actually used by the filesystem driver
+    /// (e.g. `\??\C:\Target` for absolute paths).
+    pub fn substitute_name(&self) -> U16StrLe<'r> {
+        U16StrLe(self.substitute_name)
+    }
+}
+
+/// Size of the fixed fields of a mount point reparse point.
+const MOUNT_POINT_HEADER_SIZE: usize = 8;
+
+/// Decoded data of a mount point (or directory junction) reparse point.
+#[derive(Clone, Debug)]
+pub struct NtfsMountPointReparseData<'r> {
+    substitute_name: &'r [u8],
+    print_name: &'r [u8],
+}
+
+impl<'r> NtfsMountPointReparseData<'r> {
+    fn new(data: &'r [u8], position: NtfsPosition) -> Result<Self> {
+        validate_data_size(data, position, MOUNT_POINT_HEADER_SIZE)?;
+
+        let buffer = &data[MOUNT_POINT_HEADER_SIZE..];
+        let buffer_position = position + MOUNT_POINT_HEADER_SIZE as u64;
+        let (substitute_name, print_name) = read_names(data, buffer, buffer_position)?;
+
+        Ok(Self {
+            substitute_name,
+            print_name,
+        })
+    }
+
+    /// Returns the user-friendly name of the mount point target (e.g. `C:\Target`).
+    /// This may be empty for volume mount points.
+    pub fn print_name(&self) -> U16StrLe<'r> {
+        U16StrLe(self.print_name)
+    }
+
+    /// Returns the name of the mount point target that is actually used by the filesystem driver
+    /// (e.g. `\??\C:\Target` or `\??\Volume{...}\`).
+    pub fn substitute_name(&self) -> U16StrLe<'r> {
+        U16StrLe(self.substitute_name)
+    }
+}
+
+/// Reads the substitute name and print name, whose offsets and lengths are at the beginning of `data`
+/// and refer to the path buffer `buffer`.
+fn read_names<'r>(
+    data: &[u8],
+    buffer: &'r [u8],
+    buffer_position: NtfsPosition,
+) -> Result<(&'r [u8], &'r [u8])> {
+    let substitute_name_offset = LittleEndian::read_u16(&data[0..]);
+    let substitute_name_length = LittleEndian::read_u16(&data[2..]);
+    let print_name_offset = LittleEndian::read_u16(&data[4..]);
+    let print_name_length = LittleEndian::read_u16(&data[6..]);
+
+    let substitute_name = reparse_name(
+        buffer,
+        buffer_position,
+        substitute_name_offset,
+        substitute_name_length,
+    )?;
+    let print_name = reparse_name(
+        buffer,
+        buffer_position,
+        print_name_offset,
+        print_name_length,
+    )?;
+
+    Ok((substitute_name, print_name))
+}
+
+/// Size of the `WOF_EXTERNAL_INFO` structure.
+const WOF_EXTERNAL_INFO_SIZE: usize = 8;
+
+/// WOF provider that stores compressed file data in the file itself.
+pub(crate) const WOF_PROVIDER_FILE: u32 = 2;
+
+/// Decoded data of a Windows Overlay Filter (WOF) reparse point.
+///
+/// See [`NtfsFile::wof_compressed_data`] for reading the data of such files.
+///
+/// [`NtfsFile::wof_compressed_data`]: crate::NtfsFile::wof_compressed_data
+#[derive(Clone, Debug)]
+pub struct NtfsWofReparseData<'r> {
+    version: u32,
+    provider: u32,
+    provider_data: &'r [u8],
+}
+
+impl<'r> NtfsWofReparseData<'r> {
+    fn new(data: &'r [u8], position: NtfsPosition) -> Result<Self> {
+        validate_data_size(data, position, WOF_EXTERNAL_INFO_SIZE)?;
+
+        let version = LittleEndian::read_u32(&data[0..]);
+        let provider = LittleEndian::read_u32(&data[4..]);
+        let provider_data = &data[WOF_EXTERNAL_INFO_SIZE..];
+
+        Ok(Self {
+            version,
+            provider,
+            provider_data,
+        })
+    }
+
+    /// Returns the compression algorithm if this file is handled by the file provider
+    /// (which stores the compressed data in the `WofCompressedData` stream).
+    ///
+    /// Known values are listed in [`NtfsWofAlgorithm`].
+    ///
+    /// [`NtfsWofAlgorithm`]: crate::NtfsWofAlgorithm
+    pub fn algorithm(&self) -> Option<u32> {
+        // FILE_PROVIDER_EXTERNAL_INFO_V1 starts with a version field, followed by the algorithm.
+        if self.provider == WOF_PROVIDER_FILE && self.provider_data.len() >= 8 {
+            Some(LittleEndian::read_u32(&self.provider_data[4..]))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the WOF provider (1 for a file backed by a WIM file, 2 for a compressed file).
+    pub fn provider(&self) -> u32 {
+        self.provider
+    }
+
+    /// Returns the raw provider-specific data.
+    pub fn provider_data(&self) -> &'r [u8] {
+        self.provider_data
+    }
+
+    /// Returns the version of the WOF reparse data structure.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+/// Decoded data of an AppExecLink reparse point.
+///
+/// Windows creates these for the execution aliases of packaged (Store) apps in `%LOCALAPPDATA%\Microsoft\WindowsApps`.
+/// The data consists of a version field, followed by a list of NUL-terminated UTF-16 strings.
+#[derive(Clone, Debug)]
+pub struct NtfsAppExecLinkReparseData<'r> {
+    version: u32,
+    strings: Vec<&'r [u8]>,
+}
+
+impl<'r> NtfsAppExecLinkReparseData<'r> {
+    fn new(data: &'r [u8], position: NtfsPosition) -> Result<Self> {
+        validate_data_size(data, position, mem::size_of::<u32>())?;
+
+        let version = LittleEndian::read_u32(data);
+        let mut strings = Vec::new();
+        let mut remaining = &data[mem::size_of::<u32>()..];
+
+        while remaining.len() >= mem::size_of::<u16>() {
+            let end = remaining
+                .chunks_exact(mem::size_of::<u16>())
+                .position(|c| c == [0, 0])
+                .map(|index| index * mem::size_of::<u16>())
+                .unwrap_or(remaining.len() & !1);
+
+            strings.push(&remaining[..end]);
+            remaining = remaining.get(end + mem::size_of::<u16>()..).unwrap_or(&[]);
+        }
+
+        Ok(Self { version, strings })
+    }
+
+    /// Returns the App User Model ID of the app.
+    pub fn app_user_model_id(&self) -> Option<U16StrLe<'r>> {
+        self.strings.get(1).map(|string| U16StrLe(string))
+    }
+
+    /// Returns the Package Family Name of the app.
+    pub fn package_id(&self) -> Option<U16StrLe<'r>> {
+        self.strings.first().map(|string| U16StrLe(string))
+    }
+
+    /// Returns all strings of this reparse point.
+    ///
+    /// The first three are the Package Family Name, App User Model ID, and target path.
+    /// Further strings depend on the version.
+    pub fn strings(&self) -> impl Iterator<Item = U16StrLe<'r>> + '_ {
+        self.strings.iter().map(|string| U16StrLe(string))
+    }
+
+    /// Returns the path of the executable that is started via this execution alias.
+    pub fn target_path(&self) -> Option<U16StrLe<'r>> {
+        self.strings.get(2).map(|string| U16StrLe(string))
+    }
+
+    /// Returns the version of the AppExecLink data structure.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binrw::io::Cursor;
+
+    use super::*;
+
+    fn build_reparse_point(tag: u32, data: &[u8]) -> NtfsReparsePoint {
+        let mut value = Vec::new();
+        value.extend_from_slice(&tag.to_le_bytes());
+        value.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        value.extend_from_slice(&[0, 0]);
+        value.extend_from_slice(data);
+
+        let value_length = value.len() as u64;
+        NtfsReparsePoint::new(&mut Cursor::new(value), NtfsPosition::none(), value_length).unwrap()
+    }
+
+    fn utf16(string: &str) -> Vec<u8> {
+        string.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_symbolic_link() {
+        let substitute_name = utf16("\\??\\C:\\Target");
+        let print_name = utf16("C:\\Target");
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&(substitute_name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(substitute_name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(print_name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&substitute_name);
+        data.extend_from_slice(&print_name);
+
+        let reparse_point = build_reparse_point(IO_REPARSE_TAG_SYMLINK, &data);
+        assert!(reparse_point.is_microsoft());
+        assert!(reparse_point.is_name_surrogate());
+        assert!(reparse_point.guid().is_none());
+
+        let link = match reparse_point.data().unwrap() {
+            NtfsReparsePointData::SymbolicLink(link) => link,
+            data => panic!("Unexpected reparse point data {data:?}"),
+        };
+        assert_eq!(link.substitute_name(), "\\??\\C:\\Target");
+        assert_eq!(link.print_name(), "C:\\Target");
+        assert!(!link.is_relative());
+
+        // A print name exceeding the path buffer must be rejected.
+        data[6] = 0xff;
+        let reparse_point = build_reparse_point(IO_REPARSE_TAG_SYMLINK, &data);
+        assert!(matches!(
+            reparse_point.data(),
+            Err(NtfsError::InvalidReparsePointNameRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_app_exec_link() {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        for string in ["Package_id", "Package_id!App", "C:\\app.exe", "0"] {
+            data.extend_from_slice(&utf16(string));
+            data.extend_from_slice(&[0, 0]);
+        }
+
+        let reparse_point = build_reparse_point(IO_REPARSE_TAG_APPEXECLINK, &data);
+        let link = match reparse_point.data().unwrap() {
+            NtfsReparsePointData::AppExecLink(link) => link,
+            data => panic!("Unexpected reparse point data {data:?}"),
+        };
+        assert_eq!(link.version(), 3);
+        assert_eq!(link.strings().count(), 4);
+        assert_eq!(link.package_id().unwrap(), "Package_id");
+        assert_eq!(link.app_user_model_id().unwrap(), "Package_id!App");
+        assert_eq!(link.target_path().unwrap(), "C:\\app.exe");
+    }
+
+    #[test]
+    fn test_unknown_tag() {
+        // Non-Microsoft tags come with a GUID.
+        let mut data = vec![0x11u8; GUID_SIZE];
+        data.extend_from_slice(b"raw");
+
+        let mut value = 0x0000_1234u32.to_le_bytes().to_vec();
+        value.extend_from_slice(&3u16.to_le_bytes());
+        value.extend_from_slice(&[0, 0]);
+        value.extend_from_slice(&data);
+        let value_length = value.len() as u64;
+
+        let reparse_point =
+            NtfsReparsePoint::new(&mut Cursor::new(value), NtfsPosition::none(), value_length)
+                .unwrap();
+        assert!(!reparse_point.is_microsoft());
+        assert_eq!(reparse_point.guid().unwrap().data1, 0x1111_1111);
+        assert!(matches!(
+            reparse_point.data().unwrap(),
+            NtfsReparsePointData::Unknown(b"raw")
+        ));
+    }
+}
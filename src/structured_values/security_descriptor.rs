@@ -0,0 +1,770 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::fmt;
+use core::iter::FusedIterator;
+use core::mem;
+use core::ops::Range;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Cursor, Read, Seek};
+use binrw::BinReaderExt;
+use bitflags::bitflags;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use enumn::N;
+use strum_macros::Display;
+
+use crate::attribute::NtfsAttributeType;
+use crate::attribute_value::{NtfsAttributeValue, NtfsResidentAttributeValue};
+use crate::error::{NtfsError, Result};
+use crate::guid::{NtfsGuid, GUID_SIZE};
+use crate::structured_values::{
+    NtfsStructuredValue, NtfsStructuredValueFromResidentAttributeValue,
+};
+use crate::types::NtfsPosition;
+
+/// Size of the header of a self-relative security descriptor (`SECURITY_DESCRIPTOR_RELATIVE`).
+const SECURITY_DESCRIPTOR_HEADER_SIZE: usize = 20;
+
+/// Maximum size of a security descriptor: Two ACLs of up to 65535 bytes each, two SIDs, and the header.
+const SECURITY_DESCRIPTOR_MAX_SIZE: u64 = 0x2_0000 + 2 * SID_MAX_SIZE as u64;
+
+/// Size of the fixed fields of a SID (revision, sub-authority count, and identifier authority).
+const SID_HEADER_SIZE: usize = 8;
+
+/// Maximum size of a SID, which has up to 15 sub-authorities.
+const SID_MAX_SIZE: usize = SID_HEADER_SIZE + 15 * mem::size_of::<u32>();
+
+/// Size of an ACL header.
+const ACL_HEADER_SIZE: usize = 8;
+
+/// Size of an ACE header (type, flags, and size) plus the access mask.
+const ACE_HEADER_SIZE: usize = 8;
+
+/// Set in the flags of an object ACE if the `ObjectType` GUID is present.
+const ACE_OBJECT_TYPE_PRESENT: u32 = 0x1;
+/// Set in the flags of an object ACE if the `InheritedObjectType` GUID is present.
+const ACE_INHERITED_OBJECT_TYPE_PRESENT: u32 = 0x2;
+
+bitflags! {
+    /// Flags returned by [`NtfsSecurityDescriptor::control`].
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    pub struct NtfsSecurityDescriptorControl: u16 {
+        /// The owner SID has been set by a default mechanism.
+        const OWNER_DEFAULTED = 0x0001;
+        /// The group SID has been set by a default mechanism.
+        const GROUP_DEFAULTED = 0x0002;
+        /// The security descriptor has a DACL.
+        /// If this flag is set and the DACL offset is zero, the DACL is a NULL DACL granting full access to everyone.
+        const DACL_PRESENT = 0x0004;
+        /// The DACL has been set by a default mechanism.
+        const DACL_DEFAULTED = 0x0008;
+        /// The security descriptor has a SACL.
+        const SACL_PRESENT = 0x0010;
+        /// The SACL has been set by a default mechanism.
+        const SACL_DEFAULTED = 0x0020;
+        const DACL_UNTRUSTED = 0x0040;
+        const SERVER_SECURITY = 0x0080;
+        const DACL_AUTO_INHERIT_REQ = 0x0100;
+        const SACL_AUTO_INHERIT_REQ = 0x0200;
+        /// The DACL has been set up to support automatic propagation of inheritable ACEs to child objects.
+        const DACL_AUTO_INHERITED = 0x0400;
+        /// The SACL has been set up to support automatic propagation of inheritable ACEs to child objects.
+        const SACL_AUTO_INHERITED = 0x0800;
+        /// The DACL does not inherit any ACEs from the parent object.
+        const DACL_PROTECTED = 0x1000;
+        /// The SACL does not inherit any ACEs from the parent object.
+        const SACL_PROTECTED = 0x2000;
+        const RM_CONTROL_VALID = 0x4000;
+        /// The security descriptor is in self-relative format (always the case for NTFS).
+        const SELF_RELATIVE = 0x8000;
+    }
+}
+
+impl fmt::Display for NtfsSecurityDescriptorControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+bitflags! {
+    /// Inheritance and auditing flags of an ACE, returned by [`NtfsAce::flags`].
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    pub struct NtfsAceFlags: u8 {
+        /// Non-container child objects (files) inherit this ACE.
+        const OBJECT_INHERIT = 0x01;
+        /// Container child objects (directories) inherit this ACE.
+        const CONTAINER_INHERIT = 0x02;
+        /// Child objects inherit this ACE, but do not propagate it further to their own children.
+        const NO_PROPAGATE_INHERIT = 0x04;
+        /// This ACE does not apply to the object itself, only to its children.
+        const INHERIT_ONLY = 0x08;
+        /// This ACE has been inherited from the parent object.
+        const INHERITED = 0x10;
+        /// Audit ACEs only: Generate audit messages for successful access attempts.
+        const SUCCESSFUL_ACCESS = 0x40;
+        /// Audit ACEs only: Generate audit messages for failed access attempts.
+        const FAILED_ACCESS = 0x80;
+    }
+}
+
+impl fmt::Display for NtfsAceFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+bitflags! {
+    /// Access rights of an ACE, returned by [`NtfsAce::access_mask`].
+    ///
+    /// The file-specific rights have different names for directories, which are given in the documentation of each flag.
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    pub struct NtfsAccessMask: u32 {
+        /// Read the file data (`FILE_LIST_DIRECTORY` for directories).
+        const FILE_READ_DATA = 0x0000_0001;
+        /// Write the file data (`FILE_ADD_FILE` for directories).
+        const FILE_WRITE_DATA = 0x0000_0002;
+        /// Append to the file data (`FILE_ADD_SUBDIRECTORY` for directories).
+        const FILE_APPEND_DATA = 0x0000_0004;
+        /// Read extended attributes.
+        const FILE_READ_EA = 0x0000_0008;
+        /// Write extended attributes.
+        const FILE_WRITE_EA = 0x0000_0010;
+        /// Execute the file (`FILE_TRAVERSE` for directories).
+        const FILE_EXECUTE = 0x0000_0020;
+        /// Delete the directory and all files it contains.
+        const FILE_DELETE_CHILD = 0x0000_0040;
+        /// Read the file attributes.
+        const FILE_READ_ATTRIBUTES = 0x0000_0080;
+        /// Write the file attributes.
+        const FILE_WRITE_ATTRIBUTES = 0x0000_0100;
+        /// Delete the object.
+        const DELETE = 0x0001_0000;
+        /// Read the security descriptor (except for the SACL).
+        const READ_CONTROL = 0x0002_0000;
+        /// Modify the DACL.
+        const WRITE_DAC = 0x0004_0000;
+        /// Change the owner.
+        const WRITE_OWNER = 0x0008_0000;
+        /// Use the object for synchronization.
+        const SYNCHRONIZE = 0x0010_0000;
+        /// Read or modify the SACL.
+        const ACCESS_SYSTEM_SECURITY = 0x0100_0000;
+        const MAXIMUM_ALLOWED = 0x0200_0000;
+        /// All possible access rights.
+        const GENERIC_ALL = 0x1000_0000;
+        /// Generic execute access.
+        const GENERIC_EXECUTE = 0x2000_0000;
+        /// Generic write access.
+        const GENERIC_WRITE = 0x4000_0000;
+        /// Generic read access.
+        const GENERIC_READ = 0x8000_0000;
+    }
+}
+
+impl fmt::Display for NtfsAccessMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Type of an [`NtfsAce`], returned by [`NtfsAce::ace_type`].
+///
+/// Reference: <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/628ebb1d-c509-4ea0-a10f-77ef97ca4586>
+#[derive(Clone, Copy, Debug, Display, Eq, N, PartialEq)]
+#[repr(u8)]
+pub enum NtfsAceType {
+    AccessAllowed = 0x00,
+    AccessDenied = 0x01,
+    SystemAudit = 0x02,
+    SystemAlarm = 0x03,
+    AccessAllowedCompound = 0x04,
+    AccessAllowedObject = 0x05,
+    AccessDeniedObject = 0x06,
+    SystemAuditObject = 0x07,
+    SystemAlarmObject = 0x08,
+    AccessAllowedCallback = 0x09,
+    AccessDeniedCallback = 0x0A,
+    AccessAllowedCallbackObject = 0x0B,
+    AccessDeniedCallbackObject = 0x0C,
+    SystemAuditCallback = 0x0D,
+    SystemAlarmCallback = 0x0E,
+    SystemAuditCallbackObject = 0x0F,
+    SystemAlarmCallbackObject = 0x10,
+    SystemMandatoryLabel = 0x11,
+    SystemResourceAttribute = 0x12,
+    SystemScopedPolicyId = 0x13,
+    SystemProcessTrustLabel = 0x14,
+    SystemAccessFilter = 0x15,
+}
+
+impl NtfsAceType {
+    /// Returns `true` if ACEs of this type have the object-specific fields (flags, `ObjectType`, and
+    /// `InheritedObjectType`) in front of the SID.
+    pub fn is_object_ace(&self) -> bool {
+        matches!(
+            self,
+            Self::AccessAllowedObject
+                | Self::AccessDeniedObject
+                | Self::SystemAuditObject
+                | Self::SystemAlarmObject
+                | Self::AccessAllowedCallbackObject
+                | Self::AccessDeniedCallbackObject
+                | Self::SystemAuditCallbackObject
+                | Self::SystemAlarmCallbackObject
+        )
+    }
+}
+
+/// Structure of a security descriptor, stored either in a $SECURITY_DESCRIPTOR attribute (NTFS 1.x)
+/// or in the $SDS stream of the $Secure file (NTFS 3.x).
+///
+/// The security descriptor is stored in self-relative format: A header with offsets to the owner SID,
+/// group SID, SACL, and DACL, all of which are optional.
+///
+/// NTFS 3.x files usually only reference a security descriptor through [`NtfsStandardInformation::security_id`].
+/// Use [`NtfsFile::security_descriptor`] or [`Ntfs::security_descriptor`] to look it up.
+///
+/// A $SECURITY_DESCRIPTOR attribute can be resident or non-resident.
+///
+/// Reference: <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/7d4dac05-9cef-4563-a058-f108abecce1d>
+///
+/// [`Ntfs::security_descriptor`]: crate::Ntfs::security_descriptor
+/// [`NtfsFile::security_descriptor`]: crate::NtfsFile::security_descriptor
+/// [`NtfsStandardInformation::security_id`]: crate::structured_values::NtfsStandardInformation::security_id
+#[derive(Clone, Debug)]
+pub struct NtfsSecurityDescriptor {
+    data: Vec<u8>,
+    position: NtfsPosition,
+}
+
+impl NtfsSecurityDescriptor {
+    pub(crate) fn new<T>(r: &mut T, position: NtfsPosition, value_length: u64) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        if value_length < SECURITY_DESCRIPTOR_HEADER_SIZE as u64 {
+            return Err(NtfsError::InvalidStructuredValueSize {
+                position,
+                ty: NtfsAttributeType::SecurityDescriptor,
+                expected: SECURITY_DESCRIPTOR_HEADER_SIZE as u64,
+                actual: value_length,
+            });
+        }
+
+        if value_length > SECURITY_DESCRIPTOR_MAX_SIZE {
+            return Err(NtfsError::InvalidStructuredValueSize {
+                position,
+                ty: NtfsAttributeType::SecurityDescriptor,
+                expected: SECURITY_DESCRIPTOR_MAX_SIZE,
+                actual: value_length,
+            });
+        }
+
+        let mut data = vec![0u8; value_length as usize];
+        r.read_exact(&mut data)?;
+
+        Ok(Self { data, position })
+    }
+
+    /// Returns flags describing the contents of this security descriptor, as specified by
+    /// [`NtfsSecurityDescriptorControl`].
+    pub fn control(&self) -> NtfsSecurityDescriptorControl {
+        NtfsSecurityDescriptorControl::from_bits_truncate(LittleEndian::read_u16(&self.data[2..]))
+    }
+
+    /// Returns the Discretionary Access Control List (DACL), which controls access to the file.
+    ///
+    /// Returns `None` if the security descriptor has no DACL or a NULL DACL.
+    /// Check [`NtfsSecurityDescriptorControl::DACL_PRESENT`] to tell the two cases apart:
+    /// A missing DACL denies access to everyone (except the owner), whereas a NULL DACL grants
+    /// access to everyone.
+    pub fn dacl(&self) -> Option<Result<NtfsAcl<'_>>> {
+        if !self
+            .control()
+            .contains(NtfsSecurityDescriptorControl::DACL_PRESENT)
+        {
+            return None;
+        }
+
+        self.acl_at(LittleEndian::read_u32(&self.data[16..]))
+    }
+
+    /// Returns the primary group SID, if any.
+    pub fn group(&self) -> Option<Result<NtfsSid<'_>>> {
+        self.sid_at(LittleEndian::read_u32(&self.data[8..]))
+    }
+
+    /// Returns the owner SID, if any.
+    pub fn owner(&self) -> Option<Result<NtfsSid<'_>>> {
+        self.sid_at(LittleEndian::read_u32(&self.data[4..]))
+    }
+
+    /// Returns the absolute position of this security descriptor within the filesystem, in bytes.
+    pub fn position(&self) -> NtfsPosition {
+        self.position
+    }
+
+    /// Returns the raw bytes of this self-relative security descriptor.
+    pub fn raw_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the revision of the security descriptor format (currently always 1).
+    pub fn revision(&self) -> u8 {
+        self.data[0]
+    }
+
+    /// Returns the System Access Control List (SACL), which controls auditing of accesses to the file.
+    ///
+    /// Returns `None` if the security descriptor has no SACL.
+    pub fn sacl(&self) -> Option<Result<NtfsAcl<'_>>> {
+        if !self
+            .control()
+            .contains(NtfsSecurityDescriptorControl::SACL_PRESENT)
+        {
+            return None;
+        }
+
+        self.acl_at(LittleEndian::read_u32(&self.data[12..]))
+    }
+
+    fn acl_at(&self, offset: u32) -> Option<Result<NtfsAcl<'_>>> {
+        if offset == 0 {
+            return None;
+        }
+
+        let offset = offset as usize;
+        let header = iter_try!(self.slice(offset..offset + ACL_HEADER_SIZE));
+        let acl_size = LittleEndian::read_u16(&header[2..]) as usize;
+        let slice = iter_try!(self.slice(offset..offset + acl_size));
+
+        Some(NtfsAcl::new(slice, self.position + offset))
+    }
+
+    fn sid_at(&self, offset: u32) -> Option<Result<NtfsSid<'_>>> {
+        if offset == 0 {
+            return None;
+        }
+
+        let offset = offset as usize;
+        let slice = iter_try!(self.slice(offset..self.data.len()));
+        Some(NtfsSid::new(slice, self.position + offset))
+    }
+
+    fn slice(&self, range: Range<usize>) -> Result<&[u8]> {
+        self.data
+            .get(range.clone())
+            .ok_or(NtfsError::InvalidSecurityDescriptorRange {
+                position: self.position,
+                range,
+                size: self.data.len(),
+            })
+    }
+}
+
+impl<'n, 'f> NtfsStructuredValue<'n, 'f> for NtfsSecurityDescriptor {
+    const TY: NtfsAttributeType = NtfsAttributeType::SecurityDescriptor;
+
+    fn from_attribute_value<T>(fs: &mut T, value: NtfsAttributeValue<'n, 'f>) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let position = value.data_position();
+        let value_length = value.len();
+
+        let mut value_attached = value.attach(fs);
+        Self::new(&mut value_attached, position, value_length)
+    }
+}
+
+impl<'n, 'f> NtfsStructuredValueFromResidentAttributeValue<'n, 'f> for NtfsSecurityDescriptor {
+    fn from_resident_attribute_value(value: NtfsResidentAttributeValue<'f>) -> Result<Self> {
+        let position = value.data_position();
+        let value_length = value.len();
+
+        let mut cursor = Cursor::new(value.data());
+        Self::new(&mut cursor, position, value_length)
+    }
+}
+
+/// A Security Identifier (SID), which identifies a user, group, or computer account.
+///
+/// Use the [`fmt::Display`] implementation to get the well-known string representation (e.g. `S-1-5-32-544`).
+///
+/// Reference: <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/f992ad60-0fe4-4b87-9fed-beb478836861>
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NtfsSid<'s> {
+    slice: &'s [u8],
+}
+
+impl<'s> NtfsSid<'s> {
+    fn new(slice: &'s [u8], position: NtfsPosition) -> Result<Self> {
+        let sub_authority_count = slice.get(1).copied().unwrap_or_default() as usize;
+        let size = SID_HEADER_SIZE + sub_authority_count * mem::size_of::<u32>();
+        let slice = slice
+            .get(..size)
+            .ok_or(NtfsError::InvalidSecurityDescriptorRange {
+                position,
+                range: 0..size,
+                size: slice.len(),
+            })?;
+
+        Ok(Self { slice })
+    }
+
+    /// Returns the 48-bit identifier authority (e.g. 5 for `SECURITY_NT_AUTHORITY`).
+    pub fn identifier_authority(&self) -> u64 {
+        BigEndian::read_u48(&self.slice[2..])
+    }
+
+    /// Returns the raw bytes of this SID.
+    pub fn raw_data(&self) -> &'s [u8] {
+        self.slice
+    }
+
+    /// Returns the revision of the SID format (currently always 1).
+    pub fn revision(&self) -> u8 {
+        self.slice[0]
+    }
+
+    /// Returns the number of sub-authorities of this SID.
+    pub fn sub_authority_count(&self) -> u8 {
+        self.slice[1]
+    }
+
+    /// Returns an iterator over the sub-authorities of this SID.
+    /// The last one is the Relative Identifier (RID).
+    pub fn sub_authorities(&self) -> impl Iterator<Item = u32> + 's {
+        self.slice[SID_HEADER_SIZE..]
+            .chunks_exact(mem::size_of::<u32>())
+            .map(LittleEndian::read_u32)
+    }
+}
+
+impl<'s> fmt::Display for NtfsSid<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "S-{}-", self.revision())?;
+
+        // Identifier authorities that don't fit into 32 bits are written in hexadecimal.
+        let identifier_authority = self.identifier_authority();
+        if identifier_authority > u32::MAX as u64 {
+            write!(f, "{identifier_authority:#014X}")?;
+        } else {
+            write!(f, "{identifier_authority}")?;
+        }
+
+        for sub_authority in self.sub_authorities() {
+            write!(f, "-{sub_authority}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An Access Control List (ACL), returned by [`NtfsSecurityDescriptor::dacl`] and [`NtfsSecurityDescriptor::sacl`].
+///
+/// Reference: <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/20233ed8-a6c6-4097-aafa-dd545ed24428>
+#[derive(Clone, Debug)]
+pub struct NtfsAcl<'s> {
+    slice: &'s [u8],
+    position: NtfsPosition,
+}
+
+impl<'s> NtfsAcl<'s> {
+    fn new(slice: &'s [u8], position: NtfsPosition) -> Result<Self> {
+        if slice.len() < ACL_HEADER_SIZE {
+            return Err(NtfsError::InvalidSecurityDescriptorRange {
+                position,
+                range: 0..ACL_HEADER_SIZE,
+                size: slice.len(),
+            });
+        }
+
+        Ok(Self { slice, position })
+    }
+
+    /// Returns the number of ACEs in this ACL.
+    pub fn ace_count(&self) -> u16 {
+        LittleEndian::read_u16(&self.slice[4..])
+    }
+
+    /// Returns an iterator over all ACEs of this ACL in their stored order
+    /// (which is also the order in which Windows evaluates them).
+    pub fn aces(&self) -> NtfsAces<'s> {
+        NtfsAces {
+            slice: self.slice,
+            position: self.position,
+            offset: ACL_HEADER_SIZE,
+            remaining: self.ace_count(),
+        }
+    }
+
+    /// Returns the absolute position of this ACL within the filesystem, in bytes.
+    pub fn position(&self) -> NtfsPosition {
+        self.position
+    }
+
+    /// Returns the revision of the ACL format (2, or 4 if the ACL contains object ACEs).
+    pub fn revision(&self) -> u8 {
+        self.slice[0]
+    }
+}
+
+/// Iterator over
+///   all ACEs of an [`NtfsAcl`],
+///   returning an [`NtfsAce`] for each entry,
+///   implementing [`Iterator`] and [`FusedIterator`].
+///
+/// This iterator is returned from the [`NtfsAcl::aces`] function.
+#[derive(Clone, Debug)]
+pub struct NtfsAces<'s> {
+    slice: &'s [u8],
+    position: NtfsPosition,
+    offset: usize,
+    remaining: u16,
+}
+
+impl<'s> Iterator for NtfsAces<'s> {
+    type Item = Result<NtfsAce<'s>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let position = self.position + self.offset;
+        let ace_size = self
+            .slice
+            .get(self.offset + 2..self.offset + 4)
+            .map(LittleEndian::read_u16)
+            .unwrap_or_default() as usize;
+        let range = self.offset..self.offset + ace_size;
+
+        let slice = self
+            .slice
+            .get(range.clone())
+            .filter(|_| ace_size >= ACE_HEADER_SIZE);
+        let slice = match slice {
+            Some(slice) => slice,
+            None => {
+                // Ensure that any further call returns `None`.
+                self.remaining = 0;
+
+                return Some(Err(NtfsError::InvalidSecurityDescriptorRange {
+                    position: self.position,
+                    range,
+                    size: self.slice.len(),
+                }));
+            }
+        };
+
+        self.offset += ace_size;
+        self.remaining -= 1;
+
+        Some(Ok(NtfsAce { slice, position }))
+    }
+}
+
+impl<'s> FusedIterator for NtfsAces<'s> {}
+
+/// An Access Control Entry (ACE) of an [`NtfsAcl`].
+///
+/// Reference: <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/d06e5a81-176e-46c6-9cf7-9137aad3cc85>
+#[derive(Clone, Debug)]
+pub struct NtfsAce<'s> {
+    slice: &'s [u8],
+    position: NtfsPosition,
+}
+
+impl<'s> NtfsAce<'s> {
+    /// Returns the access rights allowed, denied, or audited by this ACE.
+    pub fn access_mask(&self) -> NtfsAccessMask {
+        NtfsAccessMask::from_bits_retain(LittleEndian::read_u32(&self.slice[4..]))
+    }
+
+    /// Returns the type of this ACE.
+    pub fn ace_type(&self) -> Result<NtfsAceType> {
+        let ace_type = self.slice[0];
+
+        NtfsAceType::n(ace_type).ok_or(NtfsError::UnsupportedAceType {
+            position: self.position,
+            actual: ace_type,
+        })
+    }
+
+    /// Returns the inheritance and auditing flags of this ACE.
+    pub fn flags(&self) -> NtfsAceFlags {
+        NtfsAceFlags::from_bits_truncate(self.slice[1])
+    }
+
+    /// Returns the GUID of the object type (or property set) that an object ACE inherits to,
+    /// or `None` if this is no object ACE or the GUID is not present.
+    pub fn inherited_object_type(&self) -> Option<NtfsGuid> {
+        let object_flags = self.object_flags()?;
+        if object_flags & ACE_INHERITED_OBJECT_TYPE_PRESENT == 0 {
+            return None;
+        }
+
+        let mut offset = ACE_HEADER_SIZE + mem::size_of::<u32>();
+        if object_flags & ACE_OBJECT_TYPE_PRESENT != 0 {
+            offset += GUID_SIZE;
+        }
+
+        self.guid_at(offset)
+    }
+
+    /// Returns the GUID of the object type (or property set) that an object ACE applies to,
+    /// or `None` if this is no object ACE or the GUID is not present.
+    pub fn object_type(&self) -> Option<NtfsGuid> {
+        let object_flags = self.object_flags()?;
+        if object_flags & ACE_OBJECT_TYPE_PRESENT == 0 {
+            return None;
+        }
+
+        self.guid_at(ACE_HEADER_SIZE + mem::size_of::<u32>())
+    }
+
+    /// Returns the absolute position of this ACE within the filesystem, in bytes.
+    pub fn position(&self) -> NtfsPosition {
+        self.position
+    }
+
+    /// Returns the raw bytes of this ACE.
+    pub fn raw_data(&self) -> &'s [u8] {
+        self.slice
+    }
+
+    /// Returns the SID of the trustee (user or group) that this ACE applies to.
+    ///
+    /// Returns `None` for unknown ACE types and `AccessAllowedCompound` ACEs, whose layout differs.
+    pub fn sid(&self) -> Option<Result<NtfsSid<'s>>> {
+        let ace_type = self.ace_type().ok()?;
+        if ace_type == NtfsAceType::AccessAllowedCompound {
+            return None;
+        }
+
+        let mut offset = ACE_HEADER_SIZE;
+        if let Some(object_flags) = self.object_flags() {
+            offset += mem::size_of::<u32>();
+
+            if object_flags & ACE_OBJECT_TYPE_PRESENT != 0 {
+                offset += GUID_SIZE;
+            }
+            if object_flags & ACE_INHERITED_OBJECT_TYPE_PRESENT != 0 {
+                offset += GUID_SIZE;
+            }
+        }
+
+        let slice = self.slice.get(offset..).unwrap_or_default();
+        Some(NtfsSid::new(slice, self.position + offset))
+    }
+
+    fn guid_at(&self, offset: usize) -> Option<NtfsGuid> {
+        let slice = self.slice.get(offset..offset + GUID_SIZE)?;
+        Cursor::new(slice).read_le::<NtfsGuid>().ok()
+    }
+
+    fn object_flags(&self) -> Option<u32> {
+        if !self.ace_type().ok()?.is_object_ace() {
+            return None;
+        }
+
+        self.slice
+            .get(ACE_HEADER_SIZE..ACE_HEADER_SIZE + mem::size_of::<u32>())
+            .map(LittleEndian::read_u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    fn sid(sub_authorities: &[u32]) -> Vec<u8> {
+        let mut sid = vec![1, sub_authorities.len() as u8, 0, 0, 0, 0, 0, 5];
+        for sub_authority in sub_authorities {
+            sid.extend_from_slice(&sub_authority.to_le_bytes());
+        }
+
+        sid
+    }
+
+    #[test]
+    fn test_security_descriptor() {
+        // Owner: BUILTIN\Administrators, Group: SYSTEM
+        let owner = sid(&[32, 544]);
+        let group = sid(&[18]);
+
+        // ACE: Allow BUILTIN\Users read and execute, inherited to files and directories.
+        let mut ace = vec![
+            NtfsAceType::AccessAllowed as u8,
+            (NtfsAceFlags::OBJECT_INHERIT | NtfsAceFlags::CONTAINER_INHERIT).bits(),
+        ];
+        let users = sid(&[32, 545]);
+        ace.extend_from_slice(&((ACE_HEADER_SIZE + users.len()) as u16).to_le_bytes());
+        ace.extend_from_slice(&0x0012_00A9u32.to_le_bytes());
+        ace.extend_from_slice(&users);
+
+        let mut dacl = vec![2, 0];
+        dacl.extend_from_slice(&((ACL_HEADER_SIZE + ace.len()) as u16).to_le_bytes());
+        dacl.extend_from_slice(&1u16.to_le_bytes());
+        dacl.extend_from_slice(&[0, 0]);
+        dacl.extend_from_slice(&ace);
+
+        let owner_offset = SECURITY_DESCRIPTOR_HEADER_SIZE;
+        let group_offset = owner_offset + owner.len();
+        let dacl_offset = group_offset + group.len();
+        let control = NtfsSecurityDescriptorControl::SELF_RELATIVE
+            | NtfsSecurityDescriptorControl::DACL_PRESENT;
+
+        let mut data = vec![1, 0];
+        data.extend_from_slice(&control.bits().to_le_bytes());
+        data.extend_from_slice(&(owner_offset as u32).to_le_bytes());
+        data.extend_from_slice(&(group_offset as u32).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(dacl_offset as u32).to_le_bytes());
+        data.extend_from_slice(&owner);
+        data.extend_from_slice(&group);
+        data.extend_from_slice(&dacl);
+
+        let value_length = data.len() as u64;
+        let security_descriptor =
+            NtfsSecurityDescriptor::new(&mut Cursor::new(data), NtfsPosition::none(), value_length)
+                .unwrap();
+
+        assert_eq!(security_descriptor.revision(), 1);
+        assert_eq!(
+            security_descriptor.owner().unwrap().unwrap().to_string(),
+            "S-1-5-32-544"
+        );
+        assert_eq!(
+            security_descriptor.group().unwrap().unwrap().to_string(),
+            "S-1-5-18"
+        );
+        assert!(security_descriptor.sacl().is_none());
+
+        let dacl = security_descriptor.dacl().unwrap().unwrap();
+        assert_eq!(dacl.ace_count(), 1);
+
+        let mut aces = dacl.aces();
+        let ace = aces.next().unwrap().unwrap();
+        assert_eq!(ace.ace_type().unwrap(), NtfsAceType::AccessAllowed);
+        assert_eq!(
+            ace.flags(),
+            NtfsAceFlags::OBJECT_INHERIT | NtfsAceFlags::CONTAINER_INHERIT
+        );
+        assert!(ace.access_mask().contains(
+            NtfsAccessMask::FILE_READ_DATA
+                | NtfsAccessMask::FILE_EXECUTE
+                | NtfsAccessMask::READ_CONTROL
+                | NtfsAccessMask::SYNCHRONIZE
+        ));
+        assert!(!ace.access_mask().contains(NtfsAccessMask::FILE_WRITE_DATA));
+        assert_eq!(ace.sid().unwrap().unwrap().to_string(), "S-1-5-32-545");
+        assert!(ace.object_type().is_none());
+        assert!(aces.next().is_none());
+    }
+}
@@ -19,6 +19,12 @@ const STANDARD_INFORMATION_SIZE_NTFS1: usize = 48;
 /// Size of all [`StandardInformationData`] plus [`StandardInformationDataNtfs3`] fields.
 const STANDARD_INFORMATION_SIZE_NTFS3: usize = 72;
 
+/// Offset of [`StandardInformationDataNtfs1::modification_time`] within the attribute value.
+pub(crate) const STANDARD_INFORMATION_MODIFICATION_TIME_OFFSET: usize = 8;
+
+/// Offset of [`StandardInformationDataNtfs1::mft_record_modification_time`] within the attribute value.
+pub(crate) const STANDARD_INFORMATION_MFT_RECORD_MODIFICATION_TIME_OFFSET: usize = 16;
+
 #[derive(BinRead, Clone, Debug)]
 struct StandardInformationDataNtfs1 {
     creation_time: NtfsTime,
@@ -18,6 +18,9 @@ use crate::types::NtfsPosition;
 /// Size of all [`VolumeInformationData`] fields.
 const VOLUME_INFORMATION_SIZE: usize = 12;
 
+/// Offset of [`VolumeInformationData::flags`] within the attribute value.
+pub(crate) const VOLUME_INFORMATION_FLAGS_OFFSET: usize = 10;
+
 #[derive(BinRead, Clone, Debug)]
 struct VolumeInformationData {
     _reserved: u64,
@@ -57,7 +57,7 @@ impl NtfsVolumeName {
     }
 
     /// Gets the volume name and returns it wrapped in a [`U16StrLe`].
-    pub fn name(&self) -> U16StrLe {
+    pub fn name(&self) -> U16StrLe<'_> {
         U16StrLe(&self.name)
     }
 
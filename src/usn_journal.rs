@@ -0,0 +1,969 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Reader for the NTFS change journal in `$Extend\$UsnJrnl`.
+
+use core::fmt;
+use core::iter::FusedIterator;
+use core::mem;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek};
+use bitflags::bitflags;
+use byteorder::{ByteOrder, LittleEndian};
+use nt_string::u16strle::U16StrLe;
+
+use crate::attribute::NtfsAttributeType;
+use crate::error::{NtfsError, Result};
+use crate::extent_map::ExtentMap;
+use crate::file::{KnownNtfsFileRecordNumber, NtfsFile};
+use crate::file_reference::NtfsFileReference;
+use crate::ntfs::Ntfs;
+use crate::structured_values::NtfsFileAttributeFlags;
+use crate::time::NtfsTime;
+use crate::traits::NtfsReadSeek;
+use crate::types::NtfsPosition;
+
+/// Name of the change journal file in the $Extend directory.
+const USN_JOURNAL_FILE_NAME: &str = "$UsnJrnl";
+
+/// Name of the $DATA stream holding the journal parameters.
+const USN_JOURNAL_MAX_STREAM_NAME: &str = "$Max";
+
+/// Name of the sparse $DATA stream holding the USN records.
+const USN_JOURNAL_J_STREAM_NAME: &str = "$J";
+
+/// Size of the `USN_JOURNAL_DATA` structure in the $Max stream.
+const USN_JOURNAL_MAX_SIZE: usize = 32;
+
+/// USN records never cross a page boundary. The remainder of a page is filled with zeros instead.
+const USN_PAGE_SIZE: u64 = 4096;
+
+/// Number of bytes read at once by [`NtfsUsnRecords`].
+const USN_READ_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Size of the `RecordLength`, `MajorVersion`, and `MinorVersion` fields common to all USN record versions.
+const USN_RECORD_COMMON_HEADER_SIZE: usize = 8;
+
+/// Sizes of the fixed parts of `USN_RECORD_V2`, `USN_RECORD_V3`, and `USN_RECORD_V4`.
+const USN_RECORD_V2_HEADER_SIZE: usize = 60;
+const USN_RECORD_V3_HEADER_SIZE: usize = 76;
+const USN_RECORD_V4_HEADER_SIZE: usize = 64;
+
+/// Size of a `USN_RECORD_EXTENT` structure.
+const USN_RECORD_EXTENT_SIZE: usize = 16;
+
+bitflags! {
+    /// Reasons for a change, returned by [`NtfsUsnRecord::reason`].
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    pub struct NtfsUsnReason: u32 {
+        /// The unnamed data stream has been overwritten.
+        const DATA_OVERWRITE = 0x0000_0001;
+        /// The unnamed data stream has been extended.
+        const DATA_EXTEND = 0x0000_0002;
+        /// The unnamed data stream has been truncated.
+        const DATA_TRUNCATION = 0x0000_0004;
+        /// A named data stream has been overwritten.
+        const NAMED_DATA_OVERWRITE = 0x0000_0010;
+        /// A named data stream has been extended.
+        const NAMED_DATA_EXTEND = 0x0000_0020;
+        /// A named data stream has been truncated.
+        const NAMED_DATA_TRUNCATION = 0x0000_0040;
+        /// The file or directory has been created.
+        const FILE_CREATE = 0x0000_0100;
+        /// The file or directory has been deleted.
+        const FILE_DELETE = 0x0000_0200;
+        /// The extended attributes have changed.
+        const EA_CHANGE = 0x0000_0400;
+        /// The security descriptor has changed.
+        const SECURITY_CHANGE = 0x0000_0800;
+        /// The file or directory has been renamed, and this is the old name.
+        const RENAME_OLD_NAME = 0x0000_1000;
+        /// The file or directory has been renamed, and this is the new name.
+        const RENAME_NEW_NAME = 0x0000_2000;
+        /// The `NOT_CONTENT_INDEXED` attribute has changed.
+        const INDEXABLE_CHANGE = 0x0000_4000;
+        /// Basic file attributes or timestamps have changed.
+        const BASIC_INFO_CHANGE = 0x0000_8000;
+        /// A hard link has been added or removed.
+        const HARD_LINK_CHANGE = 0x0001_0000;
+        /// The compression state has changed.
+        const COMPRESSION_CHANGE = 0x0002_0000;
+        /// The encryption state has changed.
+        const ENCRYPTION_CHANGE = 0x0004_0000;
+        /// The object identifier has changed.
+        const OBJECT_ID_CHANGE = 0x0008_0000;
+        /// The reparse point has changed.
+        const REPARSE_POINT_CHANGE = 0x0010_0000;
+        /// A named data stream has been added, removed, or renamed.
+        const STREAM_CHANGE = 0x0020_0000;
+        /// The change has been made as part of a transaction.
+        const TRANSACTED_CHANGE = 0x0040_0000;
+        /// The integrity state has changed.
+        const INTEGRITY_CHANGE = 0x0080_0000;
+        /// The desired storage class has changed.
+        const DESIRED_STORAGE_CLASS_CHANGE = 0x0100_0000;
+        /// The file or directory has been closed, finishing a sequence of changes.
+        const CLOSE = 0x8000_0000;
+    }
+}
+
+impl fmt::Display for NtfsUsnReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+bitflags! {
+    /// Information about the source of a change, returned by [`NtfsUsnRecord::source_info`].
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    pub struct NtfsUsnSourceInfo: u32 {
+        /// The change has been made by the operating system and doesn't change the application data.
+        const DATA_MANAGEMENT = 0x0000_0001;
+        /// The change has been made to auxiliary data, e.g. an alternate data stream, without changing the application data.
+        const AUXILIARY_DATA = 0x0000_0002;
+        /// The change has been made by file replication.
+        const REPLICATION_MANAGEMENT = 0x0000_0004;
+        /// The change has been made to a file replicated from the cloud.
+        const CLIENT_REPLICATION_MANAGEMENT = 0x0000_0008;
+    }
+}
+
+impl fmt::Display for NtfsUsnSourceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// The NTFS change journal, which records changes to files and directories as a sequence of USN records.
+///
+/// It is stored in the `$Extend\$UsnJrnl` file and returned from [`Ntfs::usn_journal`].
+/// The journal parameters are read from the $Max stream, while the records are stored in the sparse $J stream.
+/// Every record is identified by its Update Sequence Number (USN), which is the byte offset of the record within
+/// the $J stream.
+/// Windows frees old records by deallocating the beginning of that stream, which therefore usually starts with
+/// a large sparse region.
+///
+/// Reference: <https://learn.microsoft.com/en-us/windows/win32/fileio/change-journals>
+#[derive(Clone, Debug)]
+pub struct NtfsUsnJournal<'n> {
+    file: NtfsFile<'n>,
+    max_data: [u8; USN_JOURNAL_MAX_SIZE],
+    j_size: u64,
+    j_extent_map: ExtentMap,
+}
+
+impl<'n> NtfsUsnJournal<'n> {
+    pub(crate) fn new<T>(ntfs: &'n Ntfs, fs: &mut T) -> Option<Result<Self>>
+    where
+        T: Read + Seek,
+    {
+        // Look up $UsnJrnl without relying on the $UpCase table. Windows always uses the same name.
+        let extend = iter_try!(ntfs.file(fs, KnownNtfsFileRecordNumber::Extend as u64));
+        let index = iter_try!(extend.directory_index(fs));
+        let mut iter = index.entries();
+
+        while let Some(entry) = iter.next(fs) {
+            let entry = iter_try!(entry);
+            let file_name = match entry.key() {
+                Some(key) => iter_try!(key),
+                None => continue,
+            };
+
+            if file_name.name() == USN_JOURNAL_FILE_NAME {
+                let file = iter_try!(entry.to_file(ntfs, fs));
+                return Some(Self::from_file(fs, file));
+            }
+        }
+
+        None
+    }
+
+    fn from_file<T>(fs: &mut T, file: NtfsFile<'n>) -> Result<Self>
+    where
+        T: Read + Seek,
+    {
+        let max_item = file.find_attribute(
+            fs,
+            NtfsAttributeType::Data,
+            Some(USN_JOURNAL_MAX_STREAM_NAME),
+        )?;
+        let max_attribute = max_item.to_attribute()?;
+        if max_attribute.value_length() < USN_JOURNAL_MAX_SIZE as u64 {
+            return Err(NtfsError::InvalidStructuredValueSize {
+                position: max_attribute.position(),
+                ty: NtfsAttributeType::Data,
+                expected: USN_JOURNAL_MAX_SIZE as u64,
+                actual: max_attribute.value_length(),
+            });
+        }
+
+        let mut max_data = [0u8; USN_JOURNAL_MAX_SIZE];
+        max_attribute.value(fs)?.read_exact(fs, &mut max_data)?;
+
+        let j_item =
+            file.find_attribute(fs, NtfsAttributeType::Data, Some(USN_JOURNAL_J_STREAM_NAME))?;
+        let j_attribute = j_item.to_attribute()?;
+        let j_size = j_attribute.value_length();
+        let j_extent_map = ExtentMap::from_attribute(fs, &j_attribute)?;
+
+        Ok(Self {
+            file,
+            max_data,
+            j_size,
+            j_extent_map,
+        })
+    }
+
+    /// Returns the number of bytes by which the journal is extended and truncated at once.
+    pub fn allocation_delta(&self) -> u64 {
+        LittleEndian::read_u64(&self.max_data[8..])
+    }
+
+    /// Returns the [`NtfsFile`] of `$Extend\$UsnJrnl`.
+    pub fn file(&self) -> &NtfsFile<'n> {
+        &self.file
+    }
+
+    /// Returns the identifier of this journal instance.
+    ///
+    /// It changes whenever the journal is deleted and recreated, which invalidates all previously recorded USNs.
+    pub fn journal_id(&self) -> u64 {
+        LittleEndian::read_u64(&self.max_data[16..])
+    }
+
+    /// Returns the lowest USN that is still guaranteed to be part of the journal.
+    pub fn lowest_valid_usn(&self) -> u64 {
+        LittleEndian::read_u64(&self.max_data[24..])
+    }
+
+    /// Returns the maximum size of the journal, in bytes, before Windows starts freeing old records.
+    pub fn maximum_size(&self) -> u64 {
+        LittleEndian::read_u64(&self.max_data[0..])
+    }
+
+    /// Returns the USN that will be assigned to the next record, which is the size of the $J stream.
+    pub fn next_usn(&self) -> u64 {
+        self.j_size
+    }
+
+    /// Returns an iterator over all records of the journal, starting at [`NtfsUsnJournal::lowest_valid_usn`].
+    pub fn records(&self) -> NtfsUsnRecords<'_> {
+        self.records_from(self.lowest_valid_usn())
+    }
+
+    /// Returns an iterator over the records of the journal, starting at the given USN.
+    ///
+    /// `usn` must be the USN of a record, e.g. one that has previously been returned by
+    /// [`NtfsUsnRecord::usn`] or [`NtfsStandardInformation::usn`].
+    /// Sparse regions of the journal are skipped without reading them.
+    ///
+    /// [`NtfsStandardInformation::usn`]: crate::structured_values::NtfsStandardInformation::usn
+    pub fn records_from(&self, usn: u64) -> NtfsUsnRecords<'_> {
+        NtfsUsnRecords::new(&self.j_extent_map, self.j_size, usn)
+    }
+
+    /// Returns the absolute filesystem position of the record with the given USN, in bytes.
+    ///
+    /// This is `None` if the USN lies beyond the end of the journal or in a sparse region that has already been freed.
+    pub fn usn_position(&self, usn: u64) -> NtfsPosition {
+        if usn >= self.j_size {
+            return NtfsPosition::none();
+        }
+
+        self.j_extent_map
+            .lookup(usn)
+            .map_or(NtfsPosition::none(), |(position, _)| position)
+    }
+}
+
+/// Iterator over
+///   the records of the NTFS change journal,
+///   returning an [`NtfsUsnRecord`] for each entry.
+///
+/// This iterator is returned from the [`NtfsUsnJournal::records`] and [`NtfsUsnJournal::records_from`] functions.
+/// It reads the $J stream sequentially in large chunks and skips sparse regions without reading them.
+/// The iteration ends after the first record that cannot be parsed.
+///
+/// See [`NtfsUsnRecordsAttached`] for an iterator that implements [`Iterator`] and [`FusedIterator`].
+#[derive(Clone, Debug)]
+pub struct NtfsUsnRecords<'j> {
+    extent_map: &'j ExtentMap,
+    data_size: u64,
+    usn: u64,
+    chunk: Vec<u8>,
+    chunk_usn: u64,
+}
+
+impl<'j> NtfsUsnRecords<'j> {
+    pub(crate) fn new(extent_map: &'j ExtentMap, data_size: u64, usn: u64) -> Self {
+        Self {
+            extent_map,
+            data_size,
+            usn,
+            chunk: Vec::new(),
+            chunk_usn: 0,
+        }
+    }
+
+    /// Returns a variant of this iterator that implements [`Iterator`] and [`FusedIterator`]
+    /// by mutably borrowing the filesystem reader.
+    pub fn attach<'a, T>(self, fs: &'a mut T) -> NtfsUsnRecordsAttached<'j, 'a, T>
+    where
+        T: Read + Seek,
+    {
+        NtfsUsnRecordsAttached::new(fs, self)
+    }
+
+    /// Returns the slice of the current chunk starting at the current USN.
+    fn chunk_remainder(&self) -> &[u8] {
+        let chunk_end = self.chunk_usn + self.chunk.len() as u64;
+        if self.usn < self.chunk_usn || self.usn >= chunk_end {
+            return &[];
+        }
+
+        &self.chunk[(self.usn - self.chunk_usn) as usize..]
+    }
+
+    /// See [`Iterator::next`].
+    pub fn next<T>(&mut self, fs: &mut T) -> Option<Result<NtfsUsnRecord>>
+    where
+        T: Read + Seek,
+    {
+        loop {
+            if self.usn >= self.data_size {
+                return None;
+            }
+
+            if self.usn % 8 != 0 {
+                let usn = self.usn;
+                self.usn = self.data_size;
+                return Some(Err(NtfsError::InvalidUsn { usn }));
+            }
+
+            if self.chunk_remainder().len() < USN_RECORD_COMMON_HEADER_SIZE {
+                let (position, remaining) = self.extent_map.lookup(self.usn)?;
+
+                if position.value().is_none() {
+                    // Skip the entire sparse region at once.
+                    self.usn += remaining;
+                    continue;
+                }
+
+                if let Err(e) = self.read_chunk(fs, remaining) {
+                    self.usn = self.data_size;
+                    return Some(Err(e));
+                }
+            }
+
+            let remainder_length = self.chunk_remainder().len();
+            if remainder_length < USN_RECORD_COMMON_HEADER_SIZE {
+                // The $J stream ends in the middle of a record header.
+                let position = self.extent_map.lookup(self.usn)?.0;
+                self.usn = self.data_size;
+                return Some(Err(NtfsError::InvalidUsnRecordLength {
+                    position,
+                    length: remainder_length as u32,
+                }));
+            }
+
+            let record_length = LittleEndian::read_u32(self.chunk_remainder());
+            if record_length == 0 {
+                // The rest of this page is padding.
+                self.usn = (self.usn / USN_PAGE_SIZE + 1) * USN_PAGE_SIZE;
+                continue;
+            }
+
+            let usn = self.usn;
+            let position = self.extent_map.lookup(usn)?.0;
+            if record_length as u64 % 8 != 0
+                || (record_length as usize) < USN_RECORD_COMMON_HEADER_SIZE
+                || record_length as u64 > USN_PAGE_SIZE - usn % USN_PAGE_SIZE
+            {
+                self.usn = self.data_size;
+                return Some(Err(NtfsError::InvalidUsnRecordLength {
+                    position,
+                    length: record_length,
+                }));
+            }
+
+            let data = if self.chunk_remainder().len() >= record_length as usize {
+                self.chunk_remainder()[..record_length as usize].to_vec()
+            } else {
+                // The record crosses the boundary between two Data Runs.
+                let mut data = vec![0u8; record_length as usize];
+                match self.extent_map.read_exact(fs, usn, &mut data) {
+                    Some(Ok(())) => data,
+                    Some(Err(e)) => {
+                        self.usn = self.data_size;
+                        return Some(Err(e));
+                    }
+                    None => {
+                        self.usn = self.data_size;
+                        return Some(Err(NtfsError::InvalidUsnRecordLength {
+                            position,
+                            length: record_length,
+                        }));
+                    }
+                }
+            };
+
+            self.usn += record_length as u64;
+
+            let record = NtfsUsnRecord::new(data, position);
+            if record.is_err() {
+                self.usn = self.data_size;
+            }
+
+            return Some(record);
+        }
+    }
+
+    /// Reads the next chunk starting at the current USN, which is `remaining` bytes away from the end of its Data Run.
+    fn read_chunk<T>(&mut self, fs: &mut T, remaining: u64) -> Result<()>
+    where
+        T: Read + Seek,
+    {
+        let length = USN_READ_CHUNK_SIZE
+            .min(remaining)
+            .min(self.data_size - self.usn);
+
+        self.chunk.resize(length as usize, 0);
+        self.chunk_usn = self.usn;
+
+        // This unwrap is safe, because the caller has just checked that the current USN is in a non-sparse Data Run.
+        self.extent_map
+            .read_exact(fs, self.usn, &mut self.chunk)
+            .unwrap()
+            .map_err(|e| {
+                self.chunk.clear();
+                e
+            })
+    }
+
+    /// Returns the USN of the next record to be read.
+    pub fn usn(&self) -> u64 {
+        self.usn
+    }
+}
+
+/// Iterator over
+///   the records of the NTFS change journal,
+///   returning an [`NtfsUsnRecord`] for each entry,
+///   implementing [`Iterator`] and [`FusedIterator`].
+///
+/// This iterator is returned from the [`NtfsUsnRecords::attach`] function.
+/// Conceptually the same as [`NtfsUsnRecords`], but mutably borrows the filesystem
+/// to implement aforementioned traits.
+#[derive(Debug)]
+pub struct NtfsUsnRecordsAttached<'j, 'a, T: Read + Seek> {
+    fs: &'a mut T,
+    records: NtfsUsnRecords<'j>,
+}
+
+impl<'j, 'a, T> NtfsUsnRecordsAttached<'j, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn new(fs: &'a mut T, records: NtfsUsnRecords<'j>) -> Self {
+        Self { fs, records }
+    }
+
+    /// Consumes this iterator and returns the inner [`NtfsUsnRecords`].
+    pub fn detach(self) -> NtfsUsnRecords<'j> {
+        self.records
+    }
+}
+
+impl<'j, 'a, T> Iterator for NtfsUsnRecordsAttached<'j, 'a, T>
+where
+    T: Read + Seek,
+{
+    type Item = Result<NtfsUsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next(self.fs)
+    }
+}
+
+impl<'j, 'a, T> FusedIterator for NtfsUsnRecordsAttached<'j, 'a, T> where T: Read + Seek {}
+
+/// A single record of the NTFS change journal, returned by the [`NtfsUsnRecords`] iterator.
+///
+/// Versions 2, 3, and 4 of the record structure are supported.
+/// Version 3 uses 128-bit file identifiers, which are always 64-bit [`NtfsFileReference`]s on NTFS.
+/// Version 4 records describe the changed ranges of a file instead of its metadata and don't have a name.
+///
+/// Reference: <https://learn.microsoft.com/en-us/windows/win32/api/winioctl/ns-winioctl-usn_record_v2>
+#[derive(Clone, Debug)]
+pub struct NtfsUsnRecord {
+    data: Vec<u8>,
+    position: NtfsPosition,
+}
+
+impl NtfsUsnRecord {
+    pub(crate) fn new(data: Vec<u8>, position: NtfsPosition) -> Result<Self> {
+        let record = Self { data, position };
+        record.validate()?;
+        Ok(record)
+    }
+
+    /// Returns an iterator over the changed ranges of the file, as reported by a version 4 record.
+    ///
+    /// The iterator is empty for all other versions.
+    pub fn extents(&self) -> NtfsUsnRecordExtents<'_> {
+        let data = match self.extents_range() {
+            Some((start, count)) => &self.data[start..start + count * USN_RECORD_EXTENT_SIZE],
+            None => &[],
+        };
+
+        NtfsUsnRecordExtents { data }
+    }
+
+    /// Returns the start and count of the extents of a version 4 record.
+    fn extents_range(&self) -> Option<(usize, usize)> {
+        if self.major_version() != 4 {
+            return None;
+        }
+
+        let count = LittleEndian::read_u16(&self.data[60..]) as usize;
+        Some((USN_RECORD_V4_HEADER_SIZE, count))
+    }
+
+    /// Returns the attributes of the file at the time of the change.
+    ///
+    /// This is `None` for version 4 records.
+    pub fn file_attributes(&self) -> Option<NtfsFileAttributeFlags> {
+        let offset = match self.major_version() {
+            2 => 52,
+            3 => 68,
+            _ => return None,
+        };
+
+        Some(NtfsFileAttributeFlags::from_bits_truncate(
+            LittleEndian::read_u32(&self.data[offset..]),
+        ))
+    }
+
+    /// Returns the [`NtfsFileReference`] of the changed file.
+    pub fn file_reference(&self) -> NtfsFileReference {
+        // Both 64-bit references and the lower half of 128-bit identifiers start at offset 8.
+        NtfsFileReference::new(self.data[8..16].try_into().unwrap())
+    }
+
+    /// Returns the major version of this record structure (2, 3, or 4).
+    pub fn major_version(&self) -> u16 {
+        LittleEndian::read_u16(&self.data[4..])
+    }
+
+    /// Returns the minor version of this record structure.
+    pub fn minor_version(&self) -> u16 {
+        LittleEndian::read_u16(&self.data[6..])
+    }
+
+    /// Returns the name of the changed file.
+    ///
+    /// This is `None` for version 4 records.
+    pub fn name(&self) -> Option<U16StrLe<'_>> {
+        let range = self.name_range()?;
+        Some(U16StrLe(&self.data[range]))
+    }
+
+    fn name_range(&self) -> Option<core::ops::Range<usize>> {
+        let offset = match self.major_version() {
+            2 => 56,
+            3 => 72,
+            _ => return None,
+        };
+
+        let name_length = LittleEndian::read_u16(&self.data[offset..]) as usize;
+        let name_offset = LittleEndian::read_u16(&self.data[offset + 2..]) as usize;
+        Some(name_offset..name_offset + name_length)
+    }
+
+    /// Returns the [`NtfsFileReference`] of the parent directory of the changed file.
+    pub fn parent_directory_reference(&self) -> NtfsFileReference {
+        let offset = match self.major_version() {
+            2 => 16,
+            _ => 24,
+        };
+
+        NtfsFileReference::new(self.data[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// Returns the absolute position of this record within the filesystem, in bytes.
+    pub fn position(&self) -> NtfsPosition {
+        self.position
+    }
+
+    /// Returns the reasons for the change.
+    ///
+    /// All reasons accumulated since the file was last closed are included.
+    pub fn reason(&self) -> NtfsUsnReason {
+        let offset = match self.major_version() {
+            2 => 40,
+            3 => 56,
+            _ => 48,
+        };
+
+        NtfsUsnReason::from_bits_truncate(LittleEndian::read_u32(&self.data[offset..]))
+    }
+
+    /// Returns the length of this record, in bytes.
+    pub fn record_length(&self) -> u32 {
+        LittleEndian::read_u32(&self.data[0..])
+    }
+
+    /// Returns the number of extents reported in further version 4 records for the same change.
+    ///
+    /// This is `None` for all other versions.
+    pub fn remaining_extents(&self) -> Option<u32> {
+        (self.major_version() == 4).then(|| LittleEndian::read_u32(&self.data[56..]))
+    }
+
+    /// Returns the Security ID of the changed file, which can be passed to [`Ntfs::security_descriptor`].
+    ///
+    /// This is `None` for version 4 records.
+    pub fn security_id(&self) -> Option<u32> {
+        let offset = match self.major_version() {
+            2 => 48,
+            3 => 64,
+            _ => return None,
+        };
+
+        Some(LittleEndian::read_u32(&self.data[offset..]))
+    }
+
+    /// Returns information about the source of the change.
+    pub fn source_info(&self) -> NtfsUsnSourceInfo {
+        let offset = match self.major_version() {
+            2 => 44,
+            3 => 60,
+            _ => 52,
+        };
+
+        NtfsUsnSourceInfo::from_bits_truncate(LittleEndian::read_u32(&self.data[offset..]))
+    }
+
+    /// Returns the time of the change.
+    ///
+    /// This is `None` for version 4 records.
+    pub fn timestamp(&self) -> Option<NtfsTime> {
+        let offset = match self.major_version() {
+            2 => 32,
+            3 => 48,
+            _ => return None,
+        };
+
+        Some(NtfsTime::from(LittleEndian::read_u64(&self.data[offset..])))
+    }
+
+    /// Returns the Update Sequence Number (USN) of this record.
+    pub fn usn(&self) -> u64 {
+        let offset = match self.major_version() {
+            2 => 24,
+            _ => 40,
+        };
+
+        LittleEndian::read_u64(&self.data[offset..])
+    }
+
+    fn validate(&self) -> Result<()> {
+        let size = self.data.len();
+        if size < USN_RECORD_COMMON_HEADER_SIZE {
+            return Err(NtfsError::InvalidUsnRecordLength {
+                position: self.position,
+                length: size as u32,
+            });
+        }
+
+        let header_size = match self.major_version() {
+            2 => USN_RECORD_V2_HEADER_SIZE,
+            3 => USN_RECORD_V3_HEADER_SIZE,
+            4 => USN_RECORD_V4_HEADER_SIZE,
+            actual => {
+                return Err(NtfsError::UnsupportedUsnRecordVersion {
+                    position: self.position,
+                    actual,
+                })
+            }
+        };
+
+        if size < header_size || self.record_length() as usize != size {
+            return Err(NtfsError::InvalidUsnRecordLength {
+                position: self.position,
+                length: self.record_length(),
+            });
+        }
+
+        let range = match (self.name_range(), self.extents_range()) {
+            (Some(range), _) => range,
+            (None, Some((start, count))) => start..start + count * USN_RECORD_EXTENT_SIZE,
+            (None, None) => return Ok(()),
+        };
+
+        if range.start < header_size || range.end > size || range.len() % mem::size_of::<u16>() != 0
+        {
+            return Err(NtfsError::InvalidUsnRecordRange {
+                position: self.position,
+                range,
+                size,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// A range of a file changed, as reported by a version 4 [`NtfsUsnRecord`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NtfsUsnRecordExtent {
+    offset: u64,
+    length: u64,
+}
+
+impl NtfsUsnRecordExtent {
+    /// Returns the length of the changed range, in bytes.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the offset of the changed range within the file, in bytes.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// Iterator over
+///   the changed ranges of a version 4 [`NtfsUsnRecord`],
+///   returning an [`NtfsUsnRecordExtent`] for each entry.
+///
+/// This iterator is returned from the [`NtfsUsnRecord::extents`] function.
+#[derive(Clone, Debug)]
+pub struct NtfsUsnRecordExtents<'r> {
+    data: &'r [u8],
+}
+
+impl<'r> Iterator for NtfsUsnRecordExtents<'r> {
+    type Item = NtfsUsnRecordExtent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < USN_RECORD_EXTENT_SIZE {
+            return None;
+        }
+
+        let (extent, remainder) = self.data.split_at(USN_RECORD_EXTENT_SIZE);
+        self.data = remainder;
+
+        Some(NtfsUsnRecordExtent {
+            offset: LittleEndian::read_u64(&extent[0..]),
+            length: LittleEndian::read_u64(&extent[8..]),
+        })
+    }
+}
+
+impl<'r> FusedIterator for NtfsUsnRecordExtents<'r> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribute_value::NtfsDataRun;
+    use binrw::io::Cursor;
+
+    fn usn_record_v2(usn: u64, name: &str) -> Vec<u8> {
+        let name_length = name.len() * 2;
+        let record_length = (USN_RECORD_V2_HEADER_SIZE + name_length + 7) & !7;
+
+        let mut data = vec![0u8; record_length];
+        LittleEndian::write_u32(&mut data[0..], record_length as u32);
+        LittleEndian::write_u16(&mut data[4..], 2);
+        LittleEndian::write_u64(&mut data[8..], 0x0001_0000_0000_0042);
+        LittleEndian::write_u64(&mut data[16..], 0x0005_0000_0000_0005);
+        LittleEndian::write_u64(&mut data[24..], usn);
+        LittleEndian::write_u64(&mut data[32..], 132_000_000_000_000_000);
+        LittleEndian::write_u32(&mut data[40..], 0x8000_0100);
+        LittleEndian::write_u32(&mut data[44..], 0x2);
+        LittleEndian::write_u32(&mut data[48..], 0x101);
+        LittleEndian::write_u32(&mut data[52..], 0x20);
+        LittleEndian::write_u16(&mut data[56..], name_length as u16);
+        LittleEndian::write_u16(&mut data[58..], USN_RECORD_V2_HEADER_SIZE as u16);
+
+        for (i, c) in name.encode_utf16().enumerate() {
+            LittleEndian::write_u16(&mut data[USN_RECORD_V2_HEADER_SIZE + i * 2..], c);
+        }
+
+        data
+    }
+
+    #[test]
+    fn test_usn_record_versions() {
+        let position = NtfsPosition::new(4096);
+
+        let record = NtfsUsnRecord::new(usn_record_v2(0x1000, "file.txt"), position).unwrap();
+        assert_eq!(record.major_version(), 2);
+        assert_eq!(record.record_length(), 80);
+        assert_eq!(record.file_reference().file_record_number(), 0x42);
+        assert_eq!(record.file_reference().sequence_number(), 1);
+        assert_eq!(record.parent_directory_reference().file_record_number(), 5);
+        assert_eq!(record.usn(), 0x1000);
+        assert_eq!(
+            record.timestamp().unwrap().nt_timestamp(),
+            132_000_000_000_000_000
+        );
+        assert_eq!(
+            record.reason(),
+            NtfsUsnReason::FILE_CREATE | NtfsUsnReason::CLOSE
+        );
+        assert_eq!(record.source_info(), NtfsUsnSourceInfo::AUXILIARY_DATA);
+        assert_eq!(record.security_id(), Some(0x101));
+        assert_eq!(
+            record.file_attributes(),
+            Some(NtfsFileAttributeFlags::ARCHIVE)
+        );
+        assert_eq!(record.name().unwrap(), "file.txt");
+        assert_eq!(record.extents().count(), 0);
+        assert_eq!(record.position(), position);
+
+        // USN_RECORD_V3 with 128-bit file identifiers.
+        let mut data = vec![0u8; 88];
+        LittleEndian::write_u32(&mut data[0..], 88);
+        LittleEndian::write_u16(&mut data[4..], 3);
+        LittleEndian::write_u64(&mut data[8..], 0x0002_0000_0000_0043);
+        LittleEndian::write_u64(&mut data[24..], 0x0005_0000_0000_0005);
+        LittleEndian::write_u64(&mut data[40..], 0x2000);
+        LittleEndian::write_u32(&mut data[56..], 0x200);
+        LittleEndian::write_u16(&mut data[72..], 4);
+        LittleEndian::write_u16(&mut data[74..], 76);
+        LittleEndian::write_u16(&mut data[76..], u16::from(b'a'));
+        LittleEndian::write_u16(&mut data[78..], u16::from(b'b'));
+
+        let record = NtfsUsnRecord::new(data, position).unwrap();
+        assert_eq!(record.major_version(), 3);
+        assert_eq!(record.file_reference().file_record_number(), 0x43);
+        assert_eq!(record.parent_directory_reference().file_record_number(), 5);
+        assert_eq!(record.usn(), 0x2000);
+        assert_eq!(record.reason(), NtfsUsnReason::FILE_DELETE);
+        assert_eq!(record.name().unwrap(), "ab");
+
+        // USN_RECORD_V4 with two changed ranges.
+        let mut data = vec![0u8; 96];
+        LittleEndian::write_u32(&mut data[0..], 96);
+        LittleEndian::write_u16(&mut data[4..], 4);
+        LittleEndian::write_u64(&mut data[8..], 0x0001_0000_0000_0044);
+        LittleEndian::write_u64(&mut data[40..], 0x3000);
+        LittleEndian::write_u32(&mut data[48..], 0x1);
+        LittleEndian::write_u16(&mut data[60..], 2);
+        LittleEndian::write_u16(&mut data[62..], 16);
+        LittleEndian::write_u64(&mut data[64..], 0);
+        LittleEndian::write_u64(&mut data[72..], 4096);
+        LittleEndian::write_u64(&mut data[80..], 65536);
+        LittleEndian::write_u64(&mut data[88..], 8192);
+
+        let record = NtfsUsnRecord::new(data, position).unwrap();
+        assert_eq!(record.major_version(), 4);
+        assert_eq!(record.usn(), 0x3000);
+        assert_eq!(record.reason(), NtfsUsnReason::DATA_OVERWRITE);
+        assert_eq!(record.remaining_extents(), Some(0));
+        assert!(record.name().is_none());
+        assert!(record.timestamp().is_none());
+
+        let extents: Vec<_> = record
+            .extents()
+            .map(|extent| (extent.offset(), extent.length()))
+            .collect();
+        assert_eq!(extents, [(0, 4096), (65536, 8192)]);
+
+        // Unknown versions and names beyond the record are rejected.
+        let mut data = usn_record_v2(0, "x");
+        LittleEndian::write_u16(&mut data[4..], 5);
+        assert!(matches!(
+            NtfsUsnRecord::new(data, position),
+            Err(NtfsError::UnsupportedUsnRecordVersion { actual: 5, .. })
+        ));
+
+        let mut data = usn_record_v2(0, "x");
+        LittleEndian::write_u16(&mut data[56..], 100);
+        assert!(matches!(
+            NtfsUsnRecord::new(data, position),
+            Err(NtfsError::InvalidUsnRecordRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_usn_records() {
+        // A $J stream of 3 pages, whose first page has been freed and is sparse.
+        // The other two pages are stored at filesystem position 0x10000.
+        let mut extent_map = ExtentMap::default();
+        extent_map.push_data_run(&NtfsDataRun::new(None, 4096));
+        extent_map.push_data_run(&NtfsDataRun::new(Some(0x10000), 8192));
+
+        let mut image = vec![0u8; 0x10000 + 8192];
+        let first = usn_record_v2(4096, "first");
+        let second = usn_record_v2(4096 + first.len() as u64, "second");
+        let third = usn_record_v2(8192, "third");
+        image[0x10000..0x10000 + first.len()].copy_from_slice(&first);
+        image[0x10000 + first.len()..0x10000 + first.len() + second.len()].copy_from_slice(&second);
+        image[0x11000..0x11000 + third.len()].copy_from_slice(&third);
+        let mut fs = Cursor::new(image);
+
+        let data_size = 8192 + third.len() as u64;
+        let records = NtfsUsnRecords::new(&extent_map, data_size, 0);
+        let records: Vec<_> = records.attach(&mut fs).map(Result::unwrap).collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].name().unwrap(), "first");
+        assert_eq!(records[0].position(), NtfsPosition::new(0x10000));
+        assert_eq!(records[1].usn(), 4096 + first.len() as u64);
+        assert_eq!(records[2].name().unwrap(), "third");
+        assert_eq!(records[2].position(), NtfsPosition::new(0x11000));
+
+        // Start at the USN of the second record.
+        let mut records = NtfsUsnRecords::new(&extent_map, data_size, records[1].usn());
+        assert_eq!(
+            records.next(&mut fs).unwrap().unwrap().name().unwrap(),
+            "second"
+        );
+        assert_eq!(records.usn(), 4096 + (first.len() + second.len()) as u64);
+
+        // A record length crossing the page boundary ends the iteration.
+        LittleEndian::write_u32(&mut fs.get_mut()[0x10000..], 8192);
+        let mut records = NtfsUsnRecords::new(&extent_map, data_size, 0);
+        assert!(matches!(
+            records.next(&mut fs),
+            Some(Err(NtfsError::InvalidUsnRecordLength { length: 8192, .. }))
+        ));
+        assert!(records.next(&mut fs).is_none());
+    }
+
+    #[test]
+    fn test_usn_records_truncated() {
+        let mut extent_map = ExtentMap::default();
+        extent_map.push_data_run(&NtfsDataRun::new(Some(0x1000), 4096));
+        let mut fs = Cursor::new(vec![0xffu8; 0x2000]);
+
+        // A USN that is not aligned to 8 bytes is rejected.
+        let mut records = NtfsUsnRecords::new(&extent_map, 4096, 4094);
+        assert!(matches!(
+            records.next(&mut fs),
+            Some(Err(NtfsError::InvalidUsn { usn: 4094 }))
+        ));
+        assert!(records.next(&mut fs).is_none());
+
+        // A $J stream ending in the middle of a record header ends the iteration.
+        let mut records = NtfsUsnRecords::new(&extent_map, 4092, 4088);
+        assert!(matches!(
+            records.next(&mut fs),
+            Some(Err(NtfsError::InvalidUsnRecordLength { length: 4, .. }))
+        ));
+        assert!(records.next(&mut fs).is_none());
+    }
+
+    #[test]
+    fn test_usn_journal() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+
+        // mkntfs doesn't create a change journal.
+        assert!(ntfs.usn_journal(&mut testfs1).is_none());
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Recursive traversal of directory trees.
+
+use core::iter::FusedIterator;
+
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use binrw::io::{Read, Seek};
+
+use crate::error::{NtfsError, Result};
+use crate::file::{KnownNtfsFileRecordNumber, NtfsFile};
+use crate::file_reference::NtfsFileReference;
+use crate::ntfs::Ntfs;
+use crate::structured_values::{NtfsFileName, NtfsFileNamespace};
+
+/// Number of File Records reserved for NTFS metadata files ($MFT up to $Extend and some reserved records).
+const METADATA_FILE_RECORD_COUNT: u64 = 16;
+
+/// Order in which an [`NtfsDirectoryWalker`] visits the entries of a directory tree.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NtfsWalkOrder {
+    /// Visit the contents of a subdirectory right after the subdirectory itself (pre-order).
+    DepthFirst,
+    /// Visit all entries of a directory level before descending to the next level.
+    BreadthFirst,
+}
+
+/// Iterator over
+///   all files and directories below a directory,
+///   returning an [`NtfsDirectoryWalkerEntry`] for each entry.
+///
+/// This iterator is returned from the [`NtfsFile::walk`] function.
+/// Within each directory, entries are returned in the order of the directory index (sorted by name).
+///
+/// The walker keeps track of all directories it has descended into.
+/// A corrupt directory index referencing one of its ancestors therefore can't send it into an endless loop.
+/// If a subdirectory cannot be read, the error is returned after the subdirectory entry and the walk continues
+/// with the next entry.
+///
+/// By default, the walker
+///   * traverses the tree depth-first,
+///   * has no depth limit,
+///   * skips DOS (8.3) names, which would otherwise report files with a long name twice,
+///   * and reports the NTFS metadata files (like $MFT and the "." entry of the root directory).
+///
+/// Use the builder-style methods to change this behavior before starting the iteration.
+///
+/// See [`NtfsDirectoryWalkerAttached`] for an iterator that implements [`Iterator`] and [`FusedIterator`].
+#[derive(Debug)]
+pub struct NtfsDirectoryWalker<'n> {
+    ntfs: &'n Ntfs,
+    start_file_record_number: Option<u64>,
+    pending: VecDeque<NtfsDirectoryWalkerEntry>,
+    pending_error: Option<NtfsError>,
+    visited_directories: BTreeSet<u64>,
+    order: NtfsWalkOrder,
+    max_depth: Option<usize>,
+    skip_dos_names: bool,
+    skip_metadata_files: bool,
+}
+
+impl<'n> NtfsDirectoryWalker<'n> {
+    pub(crate) fn new(ntfs: &'n Ntfs, file_record_number: u64) -> Self {
+        Self {
+            ntfs,
+            start_file_record_number: Some(file_record_number),
+            pending: VecDeque::new(),
+            pending_error: None,
+            visited_directories: BTreeSet::new(),
+            order: NtfsWalkOrder::DepthFirst,
+            max_depth: None,
+            skip_dos_names: true,
+            skip_metadata_files: false,
+        }
+    }
+
+    /// Returns a variant of this iterator that implements [`Iterator`] and [`FusedIterator`]
+    /// by mutably borrowing the filesystem reader.
+    pub fn attach<'a, T>(self, fs: &'a mut T) -> NtfsDirectoryWalkerAttached<'n, 'a, T>
+    where
+        T: Read + Seek,
+    {
+        NtfsDirectoryWalkerAttached::new(fs, self)
+    }
+
+    /// Limits the depth of the traversal.
+    ///
+    /// Entries of the start directory have a depth of 1.
+    /// A `max_depth` of 1 therefore only returns the entries of the start directory without descending
+    /// into any subdirectory.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// See [`Iterator::next`].
+    pub fn next<T>(&mut self, fs: &mut T) -> Option<Result<NtfsDirectoryWalkerEntry>>
+    where
+        T: Read + Seek,
+    {
+        if let Some(error) = self.pending_error.take() {
+            return Some(Err(error));
+        }
+
+        if let Some(file_record_number) = self.start_file_record_number.take() {
+            self.visited_directories.insert(file_record_number);
+            iter_try!(self.read_directory(fs, file_record_number, "", 1));
+        }
+
+        let entry = self.pending.pop_front()?;
+
+        let descend = entry.is_directory()
+            && self
+                .max_depth
+                .map_or(true, |max_depth| entry.depth < max_depth)
+            && self
+                .visited_directories
+                .insert(entry.file_reference.file_record_number());
+
+        if descend {
+            // Return the directory entry in any case and any error reading its contents on the next call.
+            self.pending_error = self
+                .read_directory(
+                    fs,
+                    entry.file_reference.file_record_number(),
+                    &entry.path,
+                    entry.depth + 1,
+                )
+                .err();
+        }
+
+        Some(Ok(entry))
+    }
+
+    /// Sets the order in which the directory tree is traversed.
+    pub fn order(mut self, order: NtfsWalkOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Sets whether to skip file names in the DOS namespace (default: `true`).
+    ///
+    /// Files with a long name usually have an additional 8.3 name in the DOS namespace.
+    /// Both names are stored in the directory index, so the file would be reported twice if DOS names were not skipped.
+    pub fn skip_dos_names(mut self, skip: bool) -> Self {
+        self.skip_dos_names = skip;
+        self
+    }
+
+    /// Sets whether to skip the NTFS metadata files (default: `false`).
+    ///
+    /// This skips the files in the reserved File Records of the root directory (like $MFT, $Secure, or $Extend)
+    /// as well as the "." entry of the root directory, which refers to the root directory itself.
+    /// As $Extend is skipped, none of its contents (like $UsnJrnl) is reported either.
+    pub fn skip_metadata_files(mut self, skip: bool) -> Self {
+        self.skip_metadata_files = skip;
+        self
+    }
+
+    fn read_directory<T>(
+        &mut self,
+        fs: &mut T,
+        file_record_number: u64,
+        path: &str,
+        depth: usize,
+    ) -> Result<()>
+    where
+        T: Read + Seek,
+    {
+        let directory = self.ntfs.file(fs, file_record_number)?;
+        let index = directory.directory_index(fs)?;
+        let mut iter = index.entries();
+        let mut entries = Vec::new();
+
+        while let Some(entry) = iter.next(fs) {
+            let entry = entry?;
+            let file_name = match entry.key() {
+                Some(key) => key?,
+                None => continue,
+            };
+            let file_reference = entry.file_reference();
+
+            if self.skip_dos_names && file_name.namespace() == NtfsFileNamespace::Dos {
+                continue;
+            }
+
+            if self.skip_metadata_files
+                && file_record_number == KnownNtfsFileRecordNumber::RootDirectory as u64
+                && file_reference.file_record_number() < METADATA_FILE_RECORD_COUNT
+            {
+                continue;
+            }
+
+            let mut entry_path = String::from(path);
+            if !entry_path.is_empty() {
+                entry_path.push('\\');
+            }
+            entry_path.push_str(&file_name.name().to_string_lossy());
+
+            entries.push(NtfsDirectoryWalkerEntry {
+                path: entry_path,
+                file_name,
+                file_reference,
+                depth,
+            });
+        }
+
+        match self.order {
+            NtfsWalkOrder::DepthFirst => {
+                // Put the entries in front of the queue, keeping their order.
+                for entry in entries.into_iter().rev() {
+                    self.pending.push_front(entry);
+                }
+            }
+            NtfsWalkOrder::BreadthFirst => self.pending.extend(entries),
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterator over
+///   all files and directories below a directory,
+///   returning an [`NtfsDirectoryWalkerEntry`] for each entry,
+///   implementing [`Iterator`] and [`FusedIterator`].
+///
+/// This iterator is returned from the [`NtfsDirectoryWalker::attach`] function.
+/// Conceptually the same as [`NtfsDirectoryWalker`], but mutably borrows the filesystem
+/// to implement aforementioned traits.
+#[derive(Debug)]
+pub struct NtfsDirectoryWalkerAttached<'n, 'a, T: Read + Seek> {
+    fs: &'a mut T,
+    walker: NtfsDirectoryWalker<'n>,
+}
+
+impl<'n, 'a, T> NtfsDirectoryWalkerAttached<'n, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn new(fs: &'a mut T, walker: NtfsDirectoryWalker<'n>) -> Self {
+        Self { fs, walker }
+    }
+
+    /// Consumes this iterator and returns the inner [`NtfsDirectoryWalker`].
+    pub fn detach(self) -> NtfsDirectoryWalker<'n> {
+        self.walker
+    }
+}
+
+impl<'n, 'a, T> Iterator for NtfsDirectoryWalkerAttached<'n, 'a, T>
+where
+    T: Read + Seek,
+{
+    type Item = Result<NtfsDirectoryWalkerEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.walker.next(self.fs)
+    }
+}
+
+impl<'n, 'a, T> FusedIterator for NtfsDirectoryWalkerAttached<'n, 'a, T> where T: Read + Seek {}
+
+/// Item returned by the [`NtfsDirectoryWalker`] iterator.
+#[derive(Clone, Debug)]
+pub struct NtfsDirectoryWalkerEntry {
+    path: String,
+    file_name: NtfsFileName,
+    file_reference: NtfsFileReference,
+    depth: usize,
+}
+
+impl NtfsDirectoryWalkerEntry {
+    /// Returns the depth of this entry relative to the start directory.
+    /// Entries of the start directory have a depth of 1.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the [`NtfsFileName`] structure of this entry, as stored in the directory index.
+    pub fn file_name(&self) -> &NtfsFileName {
+        &self.file_name
+    }
+
+    /// Returns the [`NtfsFileReference`] of the file referenced by this entry.
+    pub fn file_reference(&self) -> NtfsFileReference {
+        self.file_reference
+    }
+
+    /// Returns whether this entry refers to a directory.
+    pub fn is_directory(&self) -> bool {
+        self.file_name.is_directory()
+    }
+
+    /// Returns the path of this entry relative to the start directory, with components separated by `\`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns an [`NtfsFile`] for the file referenced by this entry.
+    pub fn to_file<'n, T>(&self, ntfs: &'n Ntfs, fs: &mut T) -> Result<NtfsFile<'n>>
+    where
+        T: Read + Seek,
+    {
+        self.file_reference.to_file(ntfs, fs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walker() {
+        let mut testfs1 = crate::helpers::tests::testfs1();
+        let ntfs = Ntfs::new(&mut testfs1).unwrap();
+        let root_dir = ntfs.root_directory(&mut testfs1).unwrap();
+
+        // Skip the metadata files and compare with the files created by `create-testfs1.sh`.
+        // The subdirectories of "many_subdirs" are empty, so we don't need to descend into them.
+        let entries = root_dir
+            .walk()
+            .max_depth(2)
+            .skip_metadata_files(true)
+            .attach(&mut testfs1)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(entries.len(), 5 + 512);
+        assert_eq!(entries[0].path(), "1000-bytes-file");
+        assert_eq!(entries[3].path(), "many_subdirs");
+        assert_eq!(entries[4].path(), "many_subdirs\\1");
+        assert_eq!(entries[4].depth(), 2);
+        assert_eq!(entries.last().unwrap().path(), "sparse-file");
+
+        // A breadth-first walk returns all entries of the root directory first.
+        let entries = root_dir
+            .walk()
+            .max_depth(2)
+            .skip_metadata_files(true)
+            .order(NtfsWalkOrder::BreadthFirst)
+            .attach(&mut testfs1)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(entries.len(), 5 + 512);
+        assert_eq!(entries[4].path(), "sparse-file");
+        assert_eq!(entries[5].path(), "many_subdirs\\1");
+
+        // Limiting the depth only returns the entries of the root directory.
+        // This includes the metadata files and the "." entry, but the latter must not cause an endless loop.
+        let entries = root_dir
+            .walk()
+            .max_depth(1)
+            .attach(&mut testfs1)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(entries.iter().all(|entry| entry.depth() == 1));
+        assert!(entries.iter().any(|entry| entry.path() == "$MFT"));
+        assert!(entries.iter().any(|entry| entry.path() == "."));
+
+        // With a higher depth limit, the "." entry must not be descended into again.
+        let deeper_entries = root_dir
+            .walk()
+            .max_depth(2)
+            .attach(&mut testfs1)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(!deeper_entries
+            .iter()
+            .any(|entry| entry.path().starts_with(".\\")));
+        assert_eq!(
+            deeper_entries.len(),
+            entries.len()
+                + 512
+                + deeper_entries
+                    .iter()
+                    .filter(|entry| entry.path().starts_with("$Extend\\"))
+                    .count()
+        );
+    }
+}
@@ -0,0 +1,532 @@
+// Copyright 2023 Colin Finck <colin@reactos.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+//! Support for files compressed by the Windows Overlay Filter (WOF), also known as "CompactOS" compression.
+//!
+//! `compact /exe` (and Windows setup in CompactOS mode) does not use NTFS compression.
+//! Instead, it moves the file data into a `WofCompressedData` alternate data stream and leaves the
+//! unnamed $DATA attribute empty (except for its size, which still reports the uncompressed size).
+//! A reparse point with the tag `IO_REPARSE_TAG_WOF` marks such files and specifies the compression algorithm.
+//!
+//! The `WofCompressedData` stream starts with a chunk table, followed by the independently compressed chunks.
+//! The table contains the end offset of every chunk except the last one (relative to the end of the table).
+//! Entries are 32-bit values, or 64-bit values if the uncompressed size exceeds 4 GiB.
+//! A chunk whose compressed size equals its uncompressed size is stored uncompressed.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use binrw::io;
+use binrw::io::{Read, Seek, SeekFrom};
+use byteorder::{ByteOrder, LittleEndian};
+use enumn::N;
+use strum_macros::Display;
+
+use crate::attribute::{NtfsAttributeItem, NtfsAttributeType};
+use crate::attribute_value::{seek_contiguous, NtfsAttributeValue};
+use crate::compression::{lzx, xpress_huffman};
+use crate::error::{NtfsError, Result};
+use crate::file::NtfsFile;
+use crate::structured_values::{NtfsReparsePoint, NtfsReparsePointData, WOF_PROVIDER_FILE};
+use crate::traits::NtfsReadSeek;
+
+/// Name of the alternate data stream that holds the compressed data.
+const WOF_COMPRESSED_DATA_STREAM_NAME: &str = "WofCompressedData";
+
+/// Size of `FILE_PROVIDER_EXTERNAL_INFO_V1`, the provider data of a WOF reparse point with the file provider.
+const WOF_PROVIDER_DATA_SIZE: u64 = 8;
+
+/// Compression algorithms supported by the Windows Overlay Filter.
+///
+/// Reference: <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/ntifs/ns-ntifs-_file_provider_external_info_v1>
+#[derive(Clone, Copy, Debug, Display, Eq, N, PartialEq)]
+#[repr(u32)]
+pub enum NtfsWofAlgorithm {
+    /// XPRESS Huffman with 4 KiB chunks.
+    Xpress4K = 0,
+    /// LZX with 32 KiB chunks.
+    Lzx = 1,
+    /// XPRESS Huffman with 8 KiB chunks.
+    Xpress8K = 2,
+    /// XPRESS Huffman with 16 KiB chunks.
+    Xpress16K = 3,
+}
+
+impl NtfsWofAlgorithm {
+    /// Returns the size of an uncompressed chunk, in bytes.
+    pub fn chunk_size(&self) -> u32 {
+        match self {
+            Self::Xpress4K => 4096,
+            Self::Lzx => 32768,
+            Self::Xpress8K => 8192,
+            Self::Xpress16K => 16384,
+        }
+    }
+}
+
+/// Reader for the data of a file compressed by the Windows Overlay Filter (WOF).
+///
+/// This reader decompresses the `WofCompressedData` alternate data stream and is returned from
+/// [`NtfsFile::wof_compressed_data`].
+/// Decompressed data is only buffered for the chunk at the current seek position.
+#[derive(Clone, Debug)]
+pub struct NtfsWofCompressedData<'n, 'f> {
+    /// The `WofCompressedData` attribute.
+    item: NtfsAttributeItem<'n, 'f>,
+    /// Compression algorithm of all chunks.
+    algorithm: NtfsWofAlgorithm,
+    /// Total length of the uncompressed data, in bytes.
+    data_size: u64,
+    /// Current seek position within the uncompressed data, in bytes.
+    stream_position: u64,
+    /// Index of the chunk currently held in `chunk_data`.
+    chunk_index: Option<u64>,
+    /// Uncompressed data of the current chunk.
+    chunk_data: Vec<u8>,
+}
+
+impl<'n, 'f> NtfsWofCompressedData<'n, 'f> {
+    pub(crate) fn new<T>(fs: &mut T, file: &'f NtfsFile<'n>) -> Option<Result<Self>>
+    where
+        T: Read + Seek,
+    {
+        let algorithm = iter_try!(Self::read_algorithm(fs, file))?;
+
+        // The unnamed $DATA attribute reports the uncompressed size.
+        let data_item = match file.data(fs, "") {
+            Some(data_item) => iter_try!(data_item),
+            None => {
+                return Some(Err(NtfsError::AttributeNotFound {
+                    position: file.position(),
+                    ty: NtfsAttributeType::Data,
+                }))
+            }
+        };
+        let data_size = iter_try!(data_item.to_attribute()).value_length();
+
+        // Look up the stream without relying on the $UpCase table. Windows always uses the same name.
+        let mut iter = file.attributes();
+        while let Some(item) = iter.next(fs) {
+            let item = iter_try!(item);
+            let attribute = iter_try!(item.to_attribute());
+
+            if iter_try!(attribute.ty()) != NtfsAttributeType::Data
+                || iter_try!(attribute.name()) != WOF_COMPRESSED_DATA_STREAM_NAME
+            {
+                continue;
+            }
+
+            return Some(Ok(Self {
+                item,
+                algorithm,
+                data_size,
+                stream_position: 0,
+                chunk_index: None,
+                chunk_data: Vec::new(),
+            }));
+        }
+
+        Some(Err(NtfsError::AttributeNotFound {
+            position: file.position(),
+            ty: NtfsAttributeType::Data,
+        }))
+    }
+
+    /// Returns the compression algorithm used for this file.
+    pub fn algorithm(&self) -> NtfsWofAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns a variant of this reader that implements [`Read`] and [`Seek`]
+    /// by mutably borrowing the filesystem reader.
+    pub fn attach<'a, T>(self, fs: &'a mut T) -> NtfsWofCompressedDataAttached<'n, 'f, 'a, T>
+    where
+        T: Read + Seek,
+    {
+        NtfsWofCompressedDataAttached::new(fs, self)
+    }
+
+    /// Returns `true` if the uncompressed data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the total length of the uncompressed data, in bytes.
+    pub fn len(&self) -> u64 {
+        self.data_size
+    }
+
+    /// Reads the chunk with the given index into `chunk_data`.
+    fn load_chunk<T>(&mut self, fs: &mut T, chunk_index: u64) -> Result<()>
+    where
+        T: Read + Seek,
+    {
+        if self.chunk_index == Some(chunk_index) {
+            return Ok(());
+        }
+
+        let attribute = self.item.to_attribute()?;
+        let mut value = attribute.value(fs)?;
+        self.chunk_data = read_chunk(fs, &mut value, self.algorithm, self.data_size, chunk_index)?;
+
+        self.chunk_index = Some(chunk_index);
+        Ok(())
+    }
+
+    /// Reads the WOF reparse point of `file` and returns the compression algorithm,
+    /// or `None` if `file` is not compressed by WOF.
+    fn read_algorithm<T>(fs: &mut T, file: &NtfsFile<'n>) -> Result<Option<NtfsWofAlgorithm>>
+    where
+        T: Read + Seek,
+    {
+        let mut iter = file.attributes();
+        while let Some(item) = iter.next(fs) {
+            let item = item?;
+            let attribute = item.to_attribute()?;
+            if attribute.ty()? != NtfsAttributeType::ReparsePoint {
+                continue;
+            }
+
+            let position = attribute.position();
+            let reparse_point = attribute.structured_value::<_, NtfsReparsePoint>(fs)?;
+            let wof = match reparse_point.data()? {
+                NtfsReparsePointData::Wof(wof) => wof,
+                _ => return Ok(None),
+            };
+
+            if wof.provider() != WOF_PROVIDER_FILE {
+                return Err(NtfsError::UnsupportedWofProvider {
+                    position,
+                    actual: wof.provider(),
+                });
+            }
+
+            let algorithm = wof
+                .algorithm()
+                .ok_or(NtfsError::InvalidStructuredValueSize {
+                    position,
+                    ty: NtfsAttributeType::ReparsePoint,
+                    expected: WOF_PROVIDER_DATA_SIZE,
+                    actual: wof.provider_data().len() as u64,
+                })?;
+            let algorithm =
+                NtfsWofAlgorithm::n(algorithm).ok_or(NtfsError::UnsupportedWofAlgorithm {
+                    position,
+                    actual: algorithm,
+                })?;
+
+            return Ok(Some(algorithm));
+        }
+
+        Ok(None)
+    }
+}
+
+impl<'n, 'f> NtfsReadSeek for NtfsWofCompressedData<'n, 'f> {
+    fn read<T>(&mut self, fs: &mut T, buf: &mut [u8]) -> Result<usize>
+    where
+        T: Read + Seek,
+    {
+        let chunk_size = self.algorithm.chunk_size() as u64;
+        let mut bytes_read = 0usize;
+
+        while bytes_read < buf.len() && self.stream_position < self.data_size {
+            let chunk_index = self.stream_position / chunk_size;
+            self.load_chunk(fs, chunk_index)?;
+
+            // Copy up to the end of the chunk or the end of the buffer, whatever comes first.
+            let offset_in_chunk = (self.stream_position % chunk_size) as usize;
+            let remaining_in_chunk = self.chunk_data.len() - offset_in_chunk;
+            let bytes_to_copy = usize::min(remaining_in_chunk, buf.len() - bytes_read);
+
+            buf[bytes_read..bytes_read + bytes_to_copy].copy_from_slice(
+                &self.chunk_data[offset_in_chunk..offset_in_chunk + bytes_to_copy],
+            );
+            bytes_read += bytes_to_copy;
+            self.stream_position += bytes_to_copy as u64;
+        }
+
+        Ok(bytes_read)
+    }
+
+    fn seek<T>(&mut self, _fs: &mut T, pos: SeekFrom) -> Result<u64>
+    where
+        T: Read + Seek,
+    {
+        seek_contiguous(&mut self.stream_position, self.data_size, pos)
+    }
+
+    fn stream_position(&self) -> u64 {
+        self.stream_position
+    }
+}
+
+/// Reads the chunk with the given index from the `WofCompressedData` stream `value` and returns its uncompressed data.
+///
+/// `data_size` is the total length of the uncompressed data, in bytes.
+fn read_chunk<T>(
+    fs: &mut T,
+    value: &mut NtfsAttributeValue,
+    algorithm: NtfsWofAlgorithm,
+    data_size: u64,
+    chunk_index: u64,
+) -> Result<Vec<u8>>
+where
+    T: Read + Seek,
+{
+    let chunk_size = algorithm.chunk_size() as u64;
+    let chunk_count = (data_size + chunk_size - 1) / chunk_size;
+    let entry_size = if data_size > u32::MAX as u64 { 8 } else { 4 };
+    let table_size = (chunk_count - 1) * entry_size;
+    let compressed_size = value.len();
+
+    let mut read_entry = |fs: &mut T, index: u64| -> Result<u64> {
+        value.seek(fs, SeekFrom::Start(index * entry_size))?;
+        let mut buf = [0u8; 8];
+        value.read_exact(fs, &mut buf[..entry_size as usize])?;
+        Ok(LittleEndian::read_u64(&buf))
+    };
+
+    let start = if chunk_index == 0 {
+        0
+    } else {
+        read_entry(fs, chunk_index - 1)?
+    };
+    let end = if chunk_index == chunk_count - 1 {
+        compressed_size.saturating_sub(table_size)
+    } else {
+        read_entry(fs, chunk_index)?
+    };
+
+    let uncompressed_size = u64::min(chunk_size, data_size - chunk_index * chunk_size);
+
+    value.seek(fs, SeekFrom::Start(table_size + start))?;
+    let position = value.data_position();
+
+    if start > end || end - start > uncompressed_size || table_size + end > compressed_size {
+        return Err(NtfsError::InvalidCompressedData { position });
+    }
+
+    let mut compressed_data = vec![0u8; (end - start) as usize];
+    value.read_exact(fs, &mut compressed_data)?;
+
+    if compressed_data.len() as u64 == uncompressed_size {
+        return Ok(compressed_data);
+    }
+
+    let mut chunk_data = vec![0u8; uncompressed_size as usize];
+
+    match algorithm {
+        NtfsWofAlgorithm::Lzx => lzx::decompress(&compressed_data, &mut chunk_data, position)?,
+        _ => xpress_huffman::decompress(&compressed_data, &mut chunk_data, position)?,
+    }
+
+    Ok(chunk_data)
+}
+
+/// A variant of [`NtfsWofCompressedData`] that implements [`Read`] and [`Seek`]
+/// by mutably borrowing the filesystem reader.
+#[derive(Debug)]
+pub struct NtfsWofCompressedDataAttached<'n, 'f, 'a, T: Read + Seek> {
+    fs: &'a mut T,
+    data: NtfsWofCompressedData<'n, 'f>,
+}
+
+impl<'n, 'f, 'a, T> NtfsWofCompressedDataAttached<'n, 'f, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn new(fs: &'a mut T, data: NtfsWofCompressedData<'n, 'f>) -> Self {
+        Self { fs, data }
+    }
+
+    /// Consumes this reader and returns the inner [`NtfsWofCompressedData`].
+    pub fn detach(self) -> NtfsWofCompressedData<'n, 'f> {
+        self.data
+    }
+
+    /// Returns `true` if the uncompressed data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the total length of the uncompressed data, in bytes.
+    pub fn len(&self) -> u64 {
+        self.data.len()
+    }
+}
+
+impl<'n, 'f, 'a, T> Read for NtfsWofCompressedDataAttached<'n, 'f, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(self.fs, buf).map_err(io::Error::from)
+    }
+}
+
+impl<'n, 'f, 'a, T> Seek for NtfsWofCompressedDataAttached<'n, 'f, 'a, T>
+where
+    T: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(self.fs, pos).map_err(io::Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binrw::io::Cursor;
+
+    use super::*;
+    use crate::attribute_value::NtfsResidentAttributeValue;
+    use crate::compression::{lzx, xpress_huffman};
+
+    fn abc(length: usize) -> Vec<u8> {
+        b"abc".iter().cycle().take(length).copied().collect()
+    }
+
+    /// Builds a `WofCompressedData` stream with a chunk table of `entry_size`-byte entries.
+    fn wof_stream(chunks: &[Vec<u8>], entry_size: usize) -> Vec<u8> {
+        let mut stream = Vec::new();
+        let mut end = 0u64;
+
+        for chunk in &chunks[..chunks.len() - 1] {
+            end += chunk.len() as u64;
+            stream.extend_from_slice(&end.to_le_bytes()[..entry_size]);
+        }
+
+        for chunk in chunks {
+            stream.extend_from_slice(chunk);
+        }
+
+        stream
+    }
+
+    fn read_chunks(
+        stream: &[u8],
+        algorithm: NtfsWofAlgorithm,
+        data_size: u64,
+        chunk_indexes: &[u64],
+    ) -> Result<Vec<Vec<u8>>> {
+        let mut fs = Cursor::new(Vec::new());
+        let mut value =
+            NtfsAttributeValue::Resident(NtfsResidentAttributeValue::from_bytes(stream, 0x1000));
+
+        chunk_indexes
+            .iter()
+            .map(|&chunk_index| read_chunk(&mut fs, &mut value, algorithm, data_size, chunk_index))
+            .collect()
+    }
+
+    #[test]
+    fn test_wof_xpress() {
+        for algorithm in [
+            NtfsWofAlgorithm::Xpress4K,
+            NtfsWofAlgorithm::Xpress8K,
+            NtfsWofAlgorithm::Xpress16K,
+        ] {
+            // A compressed chunk, an uncompressed chunk (whose compressed size equals the chunk size),
+            // and a shorter compressed last chunk, whose end is only given by the stream size.
+            let chunk_size = algorithm.chunk_size() as usize;
+            let stored_chunk = (0..chunk_size).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+            let stream = wof_stream(
+                &[
+                    xpress_huffman::tests::compress_abc(chunk_size),
+                    stored_chunk.clone(),
+                    xpress_huffman::tests::compress_abc(300),
+                ],
+                4,
+            );
+
+            let data_size = 2 * chunk_size as u64 + 300;
+            let chunks = read_chunks(&stream, algorithm, data_size, &[2, 0, 1]).unwrap();
+            assert_eq!(chunks[0], abc(300));
+            assert_eq!(chunks[1], abc(chunk_size));
+            assert_eq!(chunks[2], stored_chunk);
+        }
+    }
+
+    #[test]
+    fn test_wof_lzx() {
+        let chunk_size = NtfsWofAlgorithm::Lzx.chunk_size() as usize;
+        let stream = wof_stream(
+            &[
+                lzx::tests::compress_abc(chunk_size),
+                lzx::tests::compress_abc(5000),
+            ],
+            4,
+        );
+
+        let data_size = chunk_size as u64 + 5000;
+        let chunks = read_chunks(&stream, NtfsWofAlgorithm::Lzx, data_size, &[0, 1]).unwrap();
+        assert_eq!(chunks[0], abc(chunk_size));
+        assert_eq!(chunks[1], abc(5000));
+    }
+
+    #[test]
+    fn test_wof_single_chunk() {
+        // A file that fits into a single chunk has no chunk table at all.
+        let stream = xpress_huffman::tests::compress_abc(1000);
+        let chunks = read_chunks(&stream, NtfsWofAlgorithm::Xpress4K, 1000, &[0]).unwrap();
+        assert_eq!(chunks[0], abc(1000));
+    }
+
+    #[test]
+    fn test_wof_large_file() {
+        // The chunk table of a file larger than 4 GiB consists of 64-bit entries.
+        // Only the entries of the requested chunk are read, so most of the table can be left zeroed.
+        let algorithm = NtfsWofAlgorithm::Xpress16K;
+        let chunk_size = algorithm.chunk_size() as u64;
+        let data_size = u32::MAX as u64 + 1 + 100;
+        let chunk_count = (data_size + chunk_size - 1) / chunk_size;
+
+        let first_chunk = xpress_huffman::tests::compress_abc(chunk_size as usize);
+        let mut stream = vec![0u8; (chunk_count - 1) as usize * 8];
+        stream[..8].copy_from_slice(&(first_chunk.len() as u64).to_le_bytes());
+        stream.extend_from_slice(&first_chunk);
+
+        let chunks = read_chunks(&stream, algorithm, data_size, &[0]).unwrap();
+        assert_eq!(chunks[0], abc(chunk_size as usize));
+    }
+
+    #[test]
+    fn test_wof_invalid_chunk_table() {
+        let algorithm = NtfsWofAlgorithm::Xpress4K;
+        let chunk_size = algorithm.chunk_size() as usize;
+        let data_size = 2 * chunk_size as u64;
+        let chunk = xpress_huffman::tests::compress_abc(chunk_size);
+        let valid_stream = wof_stream(&[chunk.clone(), chunk], 4);
+
+        // The first chunk ends beyond the stream.
+        let mut stream = valid_stream.clone();
+        stream[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            read_chunks(&stream, algorithm, data_size, &[0]),
+            Err(NtfsError::InvalidCompressedData { .. })
+        ));
+
+        // The second chunk would start after its end.
+        assert!(matches!(
+            read_chunks(&stream, algorithm, data_size, &[1]),
+            Err(NtfsError::InvalidCompressedData { .. })
+        ));
+
+        // A chunk must not be larger than its uncompressed data.
+        let mut stream = valid_stream.clone();
+        stream.extend_from_slice(&[0u8; 4096]);
+        let result = read_chunks(&stream, algorithm, data_size, &[1]);
+        assert!(matches!(
+            result,
+            Err(NtfsError::InvalidCompressedData { position }) if position.value().is_some()
+        ));
+
+        // Corrupted compressed data is detected by the decompressor.
+        let mut stream = valid_stream;
+        stream.truncate(stream.len() - 3);
+        assert!(matches!(
+            read_chunks(&stream, algorithm, data_size, &[1]),
+            Err(NtfsError::InvalidCompressedData { .. })
+        ));
+    }
+}